@@ -7,6 +7,8 @@ use csv::WriterBuilder;
 use iirs::{Config, SearchParams};
 use itertools::iproduct;
 use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
 use std::fs;
 use std::fs::File;
@@ -97,6 +99,10 @@ pub struct Runner {
     /// The first arg is the size of the sequence, then the number of tests.
     #[clap(long, num_args = 2)]
     pub random_bench: Vec<usize>,
+
+    /// Seed of the random sequences, for reproducible benches.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
 }
 
 impl Runner {
@@ -108,17 +114,16 @@ impl Runner {
     }
 }
 
-fn generate_random_fasta(size_seq: usize) -> String {
-    let mut rng = rand::thread_rng();
+fn generate_random_fasta(size_seq: usize, rng: &mut StdRng) -> String {
     let random_sequence: String = (0..size_seq)
-        .map(|_| *SYMBOLS.choose(&mut rng).unwrap())
+        .map(|_| *SYMBOLS.choose(rng).unwrap())
         .collect();
 
     format!(">seq0\n{}", random_sequence)
 }
 
-fn write_random_fasta(size_seq: usize) -> Result<()> {
-    let fasta = generate_random_fasta(size_seq);
+fn write_random_fasta(size_seq: usize, rng: &mut StdRng) -> Result<()> {
+    let fasta = generate_random_fasta(size_seq, rng);
     let mut file = File::create(RANDOM_FILE_PATH).unwrap();
     file.write_all(fasta.as_bytes())?;
 
@@ -181,6 +186,7 @@ fn main() -> Result<()> {
 
     let runner = Runner::parse();
     let test_suite = runner.get_test_suite();
+    let mut rng = StdRng::seed_from_u64(runner.seed);
 
     let mut writer = if runner.write {
         Some(WriterBuilder::new().from_writer(File::create("bench/results.csv")?))
@@ -209,7 +215,7 @@ fn main() -> Result<()> {
                 continue;
             }
             for _ in 0..test_suite.n_test {
-                write_random_fasta(*size_seq)?;
+                write_random_fasta(*size_seq, &mut rng)?;
                 let ctiming = run_command(CPP_BINARY_PATH, &config);
                 let rtiming = run_command(RUST_BINARY_PATH, &config);
 
@@ -30,7 +30,7 @@ impl SearchParams {
 #[pyfunction]
 pub fn find_irs(params: &SearchParams, seq: &str) -> PyResult<Vec<(usize, usize, usize)>> {
     match _iirs::find_irs(&params.inner, seq.as_bytes()) {
-        Ok(result) => Ok(result),
+        Ok(result) => Ok(result.iter().map(_iirs::IrMatch::span).collect()),
         Err(e) => Err(PyErr::new::<PyValueError, _>(format!("Error: {:?}", e))),
     }
 }
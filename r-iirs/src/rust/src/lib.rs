@@ -0,0 +1,32 @@
+use ::iirs as _iirs;
+use extendr_api::prelude::*;
+
+/// Find all the inverted repeats (IRs) of a sequence.
+///
+/// Returns a data.frame with one row per IR: the (1-based, inclusive) start and
+/// end positions and the gap size.
+/// @export
+#[extendr]
+fn find_irs(seq: &str, min_len: i32, max_len: i32, max_gap: i32, mismatches: i32) -> Result<Robj> {
+    let params = _iirs::SearchParams::new(
+        min_len as usize,
+        max_len as usize,
+        max_gap as usize,
+        mismatches as usize,
+    )
+    .map_err(|e| Error::Other(format!("Invalid search parameters: {:?}", e)))?;
+
+    let irs = _iirs::find_irs(&params, seq.as_bytes())
+        .map_err(|e| Error::Other(format!("Error: {:?}", e)))?;
+
+    let start: Vec<i32> = irs.iter().map(|ir| (ir.start + 1) as i32).collect();
+    let end: Vec<i32> = irs.iter().map(|ir| (ir.end + 1) as i32).collect();
+    let gap: Vec<i32> = irs.iter().map(|ir| ir.gap as i32).collect();
+
+    data_frame!(start = start, end = end, gap = gap)
+}
+
+extendr_module! {
+    mod iirs;
+    fn find_irs;
+}
@@ -1,27 +1,121 @@
 #![allow(clippy::many_single_char_names)]
 
+use anyhow::Result;
 use rmq::Rmq;
-
-use crate::{config::SearchParams, matrix::MatchMatrix};
-
-pub fn lcp_array(s: &[u8], s_n: usize, sa: &[i32], inv_sa: &[usize]) -> Vec<usize> {
-    let mut lcp: Vec<usize> = vec![0; s_n];
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::{
+    IndexInt,
+    bed::{contains, overlaps},
+    complement::complement_array,
+    complexity::{ambiguous_fraction, arms, dust_score, homopolymer_fraction},
+    config::{SearchParams, SymmetryMode},
+    control::{MaxHitsAction, SearchSummary, StopHandle, StopReason},
+    matrix::{MatchMatrix, Matcher, PlainMatcher},
+    observer::{AlgoEvent, NoObserver, Observer},
+    score::{arm_len, count_mismatches, ir_score, mismatched_pairs},
+    seeds::{Centers, prefilter_centers, seed_centers},
+    simd::dna_pairs,
+    thermo::hairpin_thermo,
+};
+
+/// Fill `lcp` (cleared first) with the LCP array of `s`.
+pub fn lcp_array(s: &[u8], s_n: usize, sa: &[i32], inv_sa: &[IndexInt], lcp: &mut Vec<IndexInt>) {
+    lcp.clear();
+    lcp.resize(s_n, 0);
     let mut j: usize;
 
     for i in 1..s_n {
-        if inv_sa[i] != 0 {
-            let l = lcp[inv_sa[i - 1]];
+        let rank = inv_sa[i] as usize;
+        if rank != 0 {
+            let l = lcp[inv_sa[i - 1] as usize] as usize;
             j = if l > 1 { l - 1 } else { 0 };
 
-            while s[i + j] == s[sa[inv_sa[i] - 1] as usize + j] {
+            while s[i + j] == s[sa[rank - 1] as usize + j] {
                 j += 1;
             }
 
-            lcp[inv_sa[i]] = j;
+            lcp[rank] = j as IndexInt;
+        }
+    }
+}
+
+/// Longest common extension (LCE) queries over the doubled sequence.
+pub trait Lce: Sync {
+    /// Length of the common prefix of the suffixes at `i` < `j`, or `None` when it is not
+    /// answered in one query (the characters are then compared one by one).
+    fn lce(&self, i: usize, j: usize) -> Option<usize>;
+
+    /// Number of characters past which the extensions are not followed: they are taken to
+    /// stop there, with a mismatch.
+    fn limit(&self) -> usize {
+        usize::MAX
+    }
+}
+
+/// LCE queries through the inverse suffix array and the RMQ over the LCP array.
+pub struct IndexLce<'a, R> {
+    pub inv_sa: &'a [IndexInt],
+    pub rmq: &'a R,
+}
+
+impl<R: Rmq + Sync> Lce for IndexLce<'_, R> {
+    fn lce(&self, i: usize, j: usize) -> Option<usize> {
+        let ii = self.inv_sa[i] as usize;
+        let jj = self.inv_sa[j] as usize;
+        (ii < jj).then(|| self.rmq.rmq(ii + 1, jj + 1).unwrap_or(0))
+    }
+}
+
+/// LCE queries by comparing the characters, 8 at a time, without any index.
+///
+/// Without mismatches, the extensions are a few characters long on most centers: scanning
+/// them is cheaper than building the suffix array. In runs (of N, or of a repeat that is its
+/// own reverse complement like (AT)n) they are not, so they are only scanned up to the
+/// `max_len + max_gap / 2 + 1` characters of the longest IR: past them, the IRs of a center
+/// are truncated to `max_len` the same way wherever its extension stops.
+pub struct ScanLce<'a> {
+    pub s: &'a [u8],
+    pub limit: usize,
+}
+
+impl<'a> ScanLce<'a> {
+    pub const fn new(s: &'a [u8], params: &SearchParams) -> Self {
+        Self {
+            s,
+            limit: params.max_len + params.max_gap / 2 + 1,
         }
     }
+}
 
-    lcp
+impl Lce for ScanLce<'_> {
+    fn lce(&self, i: usize, j: usize) -> Option<usize> {
+        let s = &self.s[..self.s.len().min(j + self.limit)];
+        let mut k = 0;
+        while j + k + 8 <= s.len() {
+            let a = u64::from_le_bytes(s[i + k..i + k + 8].try_into().unwrap());
+            let b = u64::from_le_bytes(s[j + k..j + k + 8].try_into().unwrap());
+            if a != b {
+                return Some(k + (a ^ b).trailing_zeros() as usize / 8);
+            }
+            k += 8;
+        }
+        while j + k < s.len() && s[i + k] == s[j + k] {
+            k += 1;
+        }
+        Some(k)
+    }
+    fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+/// Work done by one call to `real_lce_mismatches`.
+#[derive(Default)]
+struct LceCost {
+    queries: usize,
+    steps: usize,
 }
 
 // Calculates a list of Longest Common Extensions, corresponding to 0, 1, 2, etc. allowed mismatches,
@@ -38,28 +132,30 @@ pub fn lcp_array(s: &[u8], s_n: usize, sa: &[i32], inv_sa: &[usize]) -> Vec<usiz
 // - For the BANANA case, the given (i, j) will be:
 //     (1, 13), (1, 12), (2, 12), (2, 11), (3, 11) ... (6, 8)
 //
-#[allow(clippy::too_many_arguments)]
-fn real_lce_mismatches<R: Rmq>(
+fn real_lce_mismatches<L: Lce, M: Matcher>(
     s: &[u8],
     i: usize,
     j: usize,
-    inv_sa: &[usize],
-    rmq: &R,
+    lce: &L,
     mut mismatches: i32,
     initial_gap: usize,
-    matrix: &MatchMatrix,
-) -> Vec<u32> {
+    matrix: &M,
+) -> (Vec<u32>, LceCost) {
     let s_n = s.len();
     let mut mismatch_locs = vec![0];
     let mut real_lce = 0;
+    let mut cost = LceCost::default();
 
     while mismatches >= 0 && j + real_lce != s_n {
         // LCE function in the original
-        let ii = inv_sa[i + real_lce];
-        let jj = inv_sa[j + real_lce];
-
-        if ii < jj {
-            real_lce += rmq.rmq(ii + 1, jj + 1).unwrap_or(0);
+        cost.steps += 1;
+        if let Some(extension) = lce.lce(i + real_lce, j + real_lce) {
+            cost.queries += 1;
+            real_lce += extension;
+        }
+        if real_lce >= lce.limit() {
+            mismatch_locs.push((lce.limit() + 1) as u32);
+            break;
         }
 
         let ni = i + real_lce;
@@ -80,7 +176,7 @@ fn real_lce_mismatches<R: Rmq>(
         real_lce += 1;
     }
 
-    mismatch_locs
+    (mismatch_locs, cost)
 }
 
 // TODO: Clear this
@@ -96,42 +192,393 @@ fn real_lce_mismatches<R: Rmq>(
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
 
-pub fn add_irs<R: Rmq + std::marker::Sync>(
+/// Number of centers examined between two checks of the stop handle.
+const CENTER_WINDOW: usize = 1 << 14;
+
+/// Size of the L2 cache assumed for the granularity of the parallel search (per core on most
+/// current CPUs).
+#[cfg(feature = "parallel")]
+const L2_CACHE_BYTES: usize = 1 << 18;
+
+/// Smallest number of consecutive centers handed to a thread by the parallel search.
+///
+/// This only sets the granularity of the work stealing: the extensions themselves are not
+/// reordered. Without a lower bound, a window can be split down to single centers, whose
+/// threads interleave over the same cache lines of `s` and `inv_sa` (consecutive centers start
+/// at consecutive positions, on both sides of the doubled sequence). A block of centers touches
+/// `1 + size_of::<IndexInt>()` bytes per position on both sides before its first jumps, which
+/// is kept under a quarter of the L2 cache. The sequential search is not affected.
+#[cfg(feature = "parallel")]
+const CENTER_BLOCK: usize = L2_CACHE_BYTES / 4 / (2 * (size_of::<IndexInt>() + 1));
+
+#[cfg(feature = "parallel")]
+pub type ThreadPool = rayon::ThreadPool;
+/// Without the `parallel` feature, every search runs in the calling thread.
+#[cfg(not(feature = "parallel"))]
+pub enum ThreadPool {}
+
+/// Dedicated pool of `threads` worker threads, or `None` (0) to run in the current pool (the
+/// global one, with a thread per CPU, unless called from another pool). The pools are kept for
+/// the next searches, so that the threads of each size are only started once.
+#[cfg(feature = "parallel")]
+pub fn thread_pool(threads: usize) -> Result<Option<Arc<ThreadPool>>> {
+    static POOLS: std::sync::Mutex<Vec<(usize, Arc<ThreadPool>)>> =
+        std::sync::Mutex::new(Vec::new());
+    if threads == 0 {
+        return Ok(None);
+    }
+    let mut pools = POOLS
+        .lock()
+        .map_err(|_| anyhow::anyhow!("A search thread panicked."))?;
+    if let Some((_, pool)) = pools.iter().find(|(size, _)| *size == threads) {
+        return Ok(Some(Arc::clone(pool)));
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map(Arc::new)
+        .map_err(|err| anyhow::anyhow!("Could not start {threads} threads: {err}"))?;
+    pools.push((threads, Arc::clone(&pool)));
+    Ok(Some(pool))
+}
+
+#[cfg(not(feature = "parallel"))]
+pub const fn thread_pool(_threads: usize) -> Result<Option<Arc<ThreadPool>>> {
+    Ok(None)
+}
+
+/// Run `f` in `pool`, or in the current pool.
+#[cfg(feature = "parallel")]
+pub fn in_pool<R: Send>(pool: Option<&ThreadPool>, f: impl FnOnce() -> R + Send) -> R {
+    match pool {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn in_pool<R: Send>(_pool: Option<&ThreadPool>, f: impl FnOnce() -> R + Send) -> R {
+    f()
+}
+
+/// Examines the centers window by window, checking the stop handle in between.
+///
+/// The IRs of every window are handed to `emit` (unsorted) together with the end of the window,
+/// so that callers can stream them without waiting for the whole search. The windows are
+/// searched in `pool` (see [`thread_pool`]), or in the current pool.
+pub fn add_irs<L, O, F>(
     s: &[u8],
-    inv_sa: &[usize],
-    rmq: &R,
+    lce: &L,
+    params: &SearchParams,
+    pool: Option<&ThreadPool>,
+    stop: &StopHandle,
+    observer: &O,
+    mut emit: F,
+) -> Result<SearchSummary>
+where
+    L: Lce,
+    O: Observer,
+    F: FnMut(Vec<(usize, usize, usize)>, usize) -> Result<()>,
+{
+    let n = s.len() / 2 - 1;
+    let Range {
+        start: mut window_start,
+        end: centers_end,
+    } = center_range(s.len(), params);
+    let mut kept = 0;
+    let mut found = 0;
+    let mut stopped = None;
+    let matrix = &MatchMatrix::from_params(params);
+    let complement = complement_array(params);
+    // Most sequences only have plain bases and N, which are matched without table lookups
+    // when their complements are plain bases too.
+    let plain = matrix.exact_bases() && dna_pairs(&complement) && PlainMatcher::accepts(&s[..n]);
+    let seeds = match params.seed_len {
+        Some(k) => {
+            // The seed must lie in the gap or in an arm of at most max_len.
+            let reach = (params.max_gap / 2 + 1 + params.max_len).saturating_sub(k);
+            Some(seed_centers(s, k, reach)?)
+        }
+        None if params.prefilter => prefilter_centers(s, params, matrix.exact_bases()),
+        None => None,
+    };
+
+    while window_start < centers_end {
+        stopped = stop.check();
+        if stopped.is_some() {
+            break;
+        }
+        let window_end = (window_start + CENTER_WINDOW).min(centers_end);
+        let window = window_start..window_end;
+        let mut window_irs = in_pool(pool, || {
+            let seeds = seeds.as_ref();
+            if plain {
+                add_irs_in_window(s, lce, params, &PlainMatcher, observer, seeds, window)
+            } else {
+                add_irs_in_window(s, lce, params, matrix, observer, seeds, window)
+            }
+        });
+        window_start = window_end;
+        // The first n characters of s are the sequence itself.
+        window_irs = post_filter(window_irs, &s[..n], params, matrix, &complement);
+        found += window_irs.len();
+
+        if let Some((max_hits, action)) = stop.max_hits()
+            && found > max_hits
+        {
+            match action {
+                // Keep the whole window so that the covered positions stay complete.
+                MaxHitsAction::Abort => stopped = Some(StopReason::MaxHits),
+                MaxHitsAction::Count => window_irs.truncate(max_hits.saturating_sub(kept)),
+            }
+        }
+
+        kept += window_irs.len();
+        if !window_irs.is_empty() {
+            emit(window_irs, window_end)?;
+        }
+        if stopped.is_some() {
+            break;
+        }
+    }
+
+    // The centers examined so far all lie in the first window_start / 2 positions of seq.
+    Ok(SearchSummary {
+        kept,
+        found,
+        covered: if stopped.is_none() {
+            n
+        } else {
+            (window_start / 2).min(n)
+        },
+        stopped,
+    })
+}
+
+/// Centers examined for a doubled sequence of length `s_n`.
+pub fn center_range(s_n: usize, params: &SearchParams) -> Range<usize> {
+    let mut start = params.min_len;
+    let mut end = s_n - 1 - params.min_len;
+    if let Some(anchor) = params.anchor {
+        // The IRs of the center c satisfy left + right = c.
+        start = start.max((2 * anchor).saturating_sub(1));
+        end = end.min(2 * anchor + 2);
+    }
+    start..end
+}
+
+/// Centers of `range` with at least one IR before the post-filters (see [`post_filter`]).
+pub fn candidate_centers<L: Lce>(
+    s: &[u8],
+    lce: &L,
+    params: &SearchParams,
+    matrix: &MatchMatrix,
+    range: Range<usize>,
+) -> Vec<usize> {
+    let n = s.len() / 2 - 1;
+    range
+        .filter(|&c| !add_irs_at_this_center(s, n, lce, params, matrix, &NoObserver, c).is_empty())
+        .collect()
+}
+
+/// IRs of the center `c`, post-filtered.
+pub fn irs_at_center<L: Lce>(
+    s: &[u8],
+    lce: &L,
     params: &SearchParams,
     matrix: &MatchMatrix,
+    c: usize,
 ) -> Vec<(usize, usize, usize)> {
-    let s_n = s.len();
-    let n = s_n / 2 - 1;
+    let n = s.len() / 2 - 1;
+    let irs = add_irs_at_this_center(s, n, lce, params, matrix, &NoObserver, c);
+    post_filter(irs, &s[..n], params, matrix, &complement_array(params))
+}
+
+/// Apply the mismatch rate and the other post-filters of the search to the IRs of `seq`.
+fn post_filter(
+    mut irs: Vec<(usize, usize, usize)>,
+    seq: &[u8],
+    params: &SearchParams,
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+) -> Vec<(usize, usize, usize)> {
+    if let Some(rate) = params.max_mismatch_rate {
+        irs = irs
+            .into_iter()
+            .filter_map(|ir| trim_to_mismatch_rate(&ir, seq, rate, params, matrix, complement))
+            .filter(|ir| arm_len(ir) >= params.min_len)
+            .collect();
+    }
+    irs.retain(|ir| passes_filters(ir, seq, params, matrix, complement));
+    irs
+}
+
+/// Extend the arms of an IR again from its gap (from the end of the arms for the direct
+/// modes), as long as the mismatches can stay within `rate` times the length extended, so that
+/// the allowance grows with the arm length. The IR is shortened to the longest extension that
+/// ends in a matching pair within the allowance (`None` if there is none), so that a mismatch
+/// next to the gap does not stop the extension.
+fn trim_to_mismatch_rate(
+    ir: &(usize, usize, usize),
+    seq: &[u8],
+    rate: f64,
+    params: &SearchParams,
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+) -> Option<(usize, usize, usize)> {
+    let &(left, right, gap) = ir;
+    let len = arm_len(ir);
+    let mode = &params.symmetry_mode;
+    // The mismatched pairs, counted from the start of the extension.
+    let mut mismatched: Vec<usize> = mismatched_pairs(ir, seq, mode, matrix, complement)
+        .into_iter()
+        .map(|(l, _)| left + len - l)
+        .collect();
+    mismatched.sort_unstable();
+    let mut mismatches = 0;
+    let mut trimmed = 0;
+    for k in 1..=len {
+        if mismatched.get(mismatches) == Some(&k) {
+            mismatches += 1;
+        } else if mismatches as f64 <= rate * k as f64 {
+            trimmed = k;
+        }
+    }
+    let outer = len - trimmed;
+    (trimmed > 0).then(|| match mode {
+        SymmetryMode::Inverted | SymmetryMode::InvertedComplementary => {
+            (left + outer, right - outer, gap)
+        }
+        // The arms keep their ends: the right one is trimmed into the gap.
+        SymmetryMode::Direct | SymmetryMode::DirectComplementary => {
+            (left + outer, right, gap + outer)
+        }
+    })
+}
+
+/// Post-filters of the search, that only depend on the IR itself.
+pub(crate) fn passes_filters(
+    ir: &(usize, usize, usize),
+    seq: &[u8],
+    params: &SearchParams,
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+) -> bool {
+    if params
+        .min_score
+        .is_some_and(|min_score| ir_score(ir, seq, params, matrix, complement) < min_score)
+    {
+        return false;
+    }
+    if let Some(max_dust) = params.max_dust {
+        let (left_arm, right_arm) = arms(ir, seq);
+        if dust_score(left_arm).max(dust_score(right_arm)) > max_dust {
+            return false;
+        }
+    }
+    if let Some(max_homopolymer) = params.max_homopolymer {
+        let (left_arm, right_arm) = arms(ir, seq);
+        let fraction = homopolymer_fraction(left_arm).max(homopolymer_fraction(right_arm));
+        if fraction > max_homopolymer {
+            return false;
+        }
+    }
+    if let Some(max_n_fraction) = params.max_n_fraction {
+        let &(left, right, _) = ir;
+        if ambiguous_fraction(&seq[left..=right]) > max_n_fraction {
+            return false;
+        }
+    }
+    if let Some((left_regions, right_regions)) = &params.arm_regions {
+        let &(left, right, _) = ir;
+        let len = arm_len(ir);
+        if !contains(left_regions, left, left + len)
+            || !contains(right_regions, right + 1 - len, right + 1)
+        {
+            return false;
+        }
+    }
+    if params.min_tm.is_some() || params.max_tm.is_some() {
+        let tm = hairpin_thermo(ir, seq, &params.thermo).tm;
+        if params.min_tm.is_some_and(|min_tm| tm < min_tm)
+            || params.max_tm.is_some_and(|max_tm| tm > max_tm)
+        {
+            return false;
+        }
+    }
+    if params.min_mismatches_used.is_some() || params.max_mismatches_used.is_some() {
+        let mismatches = count_mismatches(ir, seq, &params.symmetry_mode, matrix, complement);
+        if params
+            .min_mismatches_used
+            .is_some_and(|min| mismatches < min)
+            || params
+                .max_mismatches_used
+                .is_some_and(|max| mismatches > max)
+        {
+            return false;
+        }
+    }
+    if !params.masked.is_empty() {
+        let &(left, right, _) = ir;
+        let len = arm_len(ir);
+        if overlaps(&params.masked, left, left + len)
+            || overlaps(&params.masked, right + 1 - len, right + 1)
+        {
+            return false;
+        }
+    }
+    #[cfg(feature = "wasm")]
+    if let Some(plugin) = &params.plugin
+        && !plugin.accepts(ir, seq)
+    {
+        return false;
+    }
+    true
+}
+
+/// IRs of the centers of `window`, only those of `seeds` if given.
+fn add_irs_in_window<L: Lce, M: Matcher, O: Observer>(
+    s: &[u8],
+    lce: &L,
+    params: &SearchParams,
+    matrix: &M,
+    observer: &O,
+    seeds: Option<&Centers>,
+    window: Range<usize>,
+) -> Vec<(usize, usize, usize)> {
+    let n = s.len() / 2 - 1;
+    let seeded = |&c: &usize| seeds.is_none_or(|seeds| seeds.contains(c));
 
     // Conditional compilation for parallel execution
     #[cfg(feature = "parallel")]
-    let result: Vec<_> = (params.min_len..(s_n - 1 - params.min_len))
+    let result: Vec<_> = window
         .into_par_iter()
-        .flat_map(|c| add_irs_at_this_center(s, n, inv_sa, rmq, params, matrix, c))
+        .with_min_len(CENTER_BLOCK)
+        .filter(seeded)
+        .flat_map(|c| add_irs_at_this_center(s, n, lce, params, matrix, observer, c))
         .collect();
 
     // Conditional compilation for sequential execution
     #[cfg(not(feature = "parallel"))]
-    let result: Vec<_> = (params.min_len..(s_n - 1 - params.min_len))
-        .flat_map(|c| add_irs_at_this_center(s, n, inv_sa, rmq, params, matrix, c))
+    let result: Vec<_> = window
+        .filter(seeded)
+        .flat_map(|c| add_irs_at_this_center(s, n, lce, params, matrix, observer, c))
         .collect();
 
     result
 }
 
-fn add_irs_at_this_center<R: Rmq>(
+fn add_irs_at_this_center<L: Lce, M: Matcher, O: Observer>(
     s: &[u8],
     n: usize,
-    inv_sa: &[usize],
-    rmq: &R,
+    lce: &L,
     params: &SearchParams,
-    matrix: &MatchMatrix,
+    matrix: &M,
+    observer: &O,
     c: usize,
 ) -> Vec<(usize, usize, usize)> {
     let mut irs_at_this_center = Vec::new();
+    let center = c;
 
     // This could be computed outside of the loop.
     // It is done inside to ease the parallel / sequential structure.
@@ -153,16 +600,27 @@ fn add_irs_at_this_center<R: Rmq>(
     let i = (1.0 + c - margin) as usize;
     let j = (behind - c - margin) as usize;
 
-    let mismatch_locs = real_lce_mismatches(
-        s,
-        i,
-        j,
-        inv_sa,
-        rmq,
-        params.mismatches as i32,
-        initial_gap,
-        matrix,
-    );
+    let (mismatch_locs, cost) =
+        real_lce_mismatches(s, i, j, lce, params.mismatches as i32, initial_gap, matrix);
+
+    // The first location is a sentinel, the others are the mismatches along the extension.
+    let extension = *mismatch_locs.last().unwrap() as usize;
+    observer.on_event(AlgoEvent::Seed {
+        center,
+        extension,
+        lce_queries: cost.queries,
+        steps: cost.steps,
+    });
+    let budget_used = mismatch_locs[1..]
+        .iter()
+        .filter(|&&loc| loc as usize >= initial_gap)
+        .count();
+    if budget_used > params.mismatches {
+        observer.on_event(AlgoEvent::ExtensionTruncated {
+            center,
+            offset: extension,
+        });
+    }
 
     // Get a list of valid start and end mismatch locations
     // (that could mark the potential start or end of an IR)
@@ -234,6 +692,7 @@ fn add_irs_at_this_center<R: Rmq>(
         } else {
             // IR is too long, so attempt truncation
             let overshoot = ir_length - params.max_len;
+            observer.on_event(AlgoEvent::MaxLenTruncated { center, overshoot });
 
             // 0 if end_it_ptr <= 2
             let prev_ptr = end_it_ptr.saturating_sub(2);
@@ -254,6 +713,7 @@ fn add_irs_at_this_center<R: Rmq>(
             }
         };
 
+        observer.on_event(AlgoEvent::Hit { center, ir });
         irs_at_this_center.push(ir);
 
         start_it_ptr += 1;
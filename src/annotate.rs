@@ -0,0 +1,284 @@
+//! Nearest-feature annotation from BED or GFF files (origins, IS elements, genes...).
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashMap;
+use std::fs;
+
+use crate::IrSpan;
+use crate::bed::rotate_interval;
+
+/// GFF feature types spanning whole sequences, which would be the nearest feature of everything.
+const IGNORED_GFF_TYPES: [&str; 3] = ["region", "chromosome", "source"];
+
+/// A named feature, as a 0-based half-open interval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feature {
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Features of one sequence, indexed for nearest-feature queries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Features {
+    /// Sorted by start.
+    features: Vec<Feature>,
+    /// `reach[i]` is the index of the feature ending the furthest among the first `i + 1`.
+    reach: Vec<usize>,
+}
+
+impl Features {
+    pub fn new(mut features: Vec<Feature>) -> Self {
+        features.sort_unstable_by_key(|feature| (feature.start, feature.end));
+        let mut reach: Vec<usize> = Vec::with_capacity(features.len());
+        for (idx, feature) in features.iter().enumerate() {
+            match reach.last() {
+                Some(&best) if features[best].end >= feature.end => reach.push(best),
+                _ => reach.push(idx),
+            }
+        }
+        Self { features, reach }
+    }
+
+    /// The features of a circular sequence of length `len`, once rotated so that `origin`
+    /// becomes position 0. Features spanning the origin are split in two.
+    pub fn rotate(&self, origin: usize, len: usize) -> Self {
+        let features = self
+            .features
+            .iter()
+            .flat_map(|feature| {
+                rotate_interval(feature.start, feature.end, origin, len)
+                    .into_iter()
+                    .map(|(start, end)| Feature {
+                        name: feature.name.clone(),
+                        start,
+                        end,
+                    })
+            })
+            .collect();
+        Self::new(features)
+    }
+
+    pub fn len(&self) -> usize {
+        self.features.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    /// Nearest feature to an IR, with the signed distance from the IR to it, following
+    /// `bedtools closest -D ref`: 0 if they overlap, 1 if they are adjacent, positive if the
+    /// feature lies after the IR and negative if it lies before. Ties go to the feature before.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use iirs::{Feature, Features};
+    ///
+    /// let feature = |name: &str, start, end| Feature { name: name.to_string(), start, end };
+    /// let features = Features::new(vec![feature("oriC", 0, 10), feature("dnaA", 50, 60)]);
+    /// let (nearest, distance) = features.nearest(&(30, 44, 2)).unwrap();
+    /// assert_eq!((nearest.name.as_str(), distance), ("dnaA", 6));
+    /// ```
+    pub fn nearest(&self, ir: &IrSpan) -> Option<(&Feature, i64)> {
+        let &(left, right, _) = ir;
+        // Features starting before the end of the IR
+        let idx = self
+            .features
+            .partition_point(|feature| feature.start <= right);
+
+        let before = idx.checked_sub(1).map(|i| {
+            let feature = &self.features[self.reach[i]];
+            if feature.end > left {
+                (feature, 0)
+            } else {
+                (feature, -((left + 1 - feature.end) as i64))
+            }
+        });
+        let after = self
+            .features
+            .get(idx)
+            .map(|feature| (feature, (feature.start - right) as i64));
+
+        match (before, after) {
+            (Some(before), Some(after)) if after.1 < -before.1 => Some(after),
+            (before, after) => before.or(after),
+        }
+    }
+
+    /// Features overlapping an IR (its whole span), sorted by start.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use iirs::{Feature, Features};
+    ///
+    /// let feature = |name: &str, start, end| Feature { name: name.to_string(), start, end };
+    /// let features = Features::new(vec![feature("oriC", 0, 10), feature("dnaA", 50, 60)]);
+    /// let names: Vec<_> = features.overlapping(&(5, 54, 0)).map(|f| f.name.as_str()).collect();
+    /// assert_eq!(names, vec!["oriC", "dnaA"]);
+    /// assert_eq!(features.overlapping(&(10, 49, 0)).count(), 0);
+    /// ```
+    pub fn overlapping(&self, ir: &IrSpan) -> impl Iterator<Item = &Feature> {
+        let &(left, right, _) = ir;
+        // Features starting before the end of the IR
+        let idx = self
+            .features
+            .partition_point(|feature| feature.start <= right);
+        // None of the first `i + 1` features reach the IR if the furthest one does not.
+        let from = self.reach[..idx].partition_point(|&best| self.features[best].end <= left);
+        self.features[from..idx]
+            .iter()
+            .filter(move |feature| feature.end > left)
+    }
+}
+
+/// Read the features of a BED or GFF/GTF file per sequence name. GFF files are recognized by
+/// their extension or their `##gff-version` header.
+///
+/// BED features are named after their 4th column (or their coordinates if there is none), and
+/// GFF features after their `Name`, `gene_name`, `gene` or `ID` attribute (or their type).
+pub fn read_features(path: &str) -> Result<HashMap<String, Features>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Could not read feature file {path}"))?;
+    let lower = path.to_ascii_lowercase();
+    let is_gff = [".gff", ".gff3", ".gtf"]
+        .iter()
+        .any(|extension| lower.ends_with(extension))
+        || content.starts_with("##gff-version");
+
+    let mut features: HashMap<String, Vec<Feature>> = HashMap::new();
+    for (line_number, line) in content.lines().enumerate() {
+        if line.trim().is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        let parse = |field: &str| {
+            field
+                .trim()
+                .parse::<usize>()
+                .with_context(|| format!("{}:{}: invalid position", path, line_number + 1))
+        };
+
+        let (seq_name, feature) = if is_gff {
+            if fields.len() < 9 {
+                return Err(anyhow!("{}:{}: expected 9 columns", path, line_number + 1));
+            }
+            if IGNORED_GFF_TYPES.contains(&fields[2]) {
+                continue;
+            }
+            // GFF is 1-based and inclusive
+            let feature = Feature {
+                name: gff_name(fields[8]).unwrap_or(fields[2]).to_string(),
+                start: parse(fields[3])?.saturating_sub(1),
+                end: parse(fields[4])?,
+            };
+            (fields[0], feature)
+        } else {
+            if fields.len() < 3 {
+                return Err(anyhow!(
+                    "{}:{}: expected at least 3 columns",
+                    path,
+                    line_number + 1
+                ));
+            }
+            let (start, end) = (parse(fields[1])?, parse(fields[2])?);
+            let name = match fields.get(3) {
+                Some(name) if !name.trim().is_empty() => name.trim().to_string(),
+                _ => format!("{}:{}-{}", fields[0], start, end),
+            };
+            (fields[0], Feature { name, start, end })
+        };
+        features
+            .entry(seq_name.to_string())
+            .or_default()
+            .push(feature);
+    }
+
+    Ok(features
+        .into_iter()
+        .map(|(seq_name, features)| (seq_name, Features::new(features)))
+        .collect())
+}
+
+/// Name of a GFF3 (`key=value`) or GTF (`key "value"`) feature from its attributes.
+fn gff_name(attributes: &str) -> Option<&str> {
+    let pairs: Vec<(&str, &str)> = attributes
+        .split(';')
+        .filter_map(|pair| {
+            let pair = pair.trim();
+            pair.split_once('=')
+                .or_else(|| pair.split_once(' '))
+                .map(|(key, value)| (key.trim(), value.trim().trim_matches('"')))
+        })
+        .collect();
+    ["Name", "gene_name", "gene", "ID", "gene_id"]
+        .iter()
+        .find_map(|key| pairs.iter().find(|(k, _)| k == key).map(|&(_, v)| v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feature(name: &str, start: usize, end: usize) -> Feature {
+        Feature {
+            name: name.to_string(),
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn test_nearest() {
+        // "long" ends after "short", which starts later
+        let features = Features::new(vec![
+            feature("long", 0, 100),
+            feature("short", 10, 20),
+            feature("far", 300, 310),
+        ]);
+        let nearest = |ir| {
+            features
+                .nearest(&ir)
+                .map(|(feature, distance)| (feature.name.clone(), distance))
+        };
+        assert_eq!(nearest((50, 60, 0)), Some((String::from("long"), 0)));
+        assert_eq!(nearest((100, 110, 0)), Some((String::from("long"), -1)));
+        assert_eq!(nearest((250, 299, 0)), Some((String::from("far"), 1)));
+        assert_eq!(Features::default().nearest(&(0, 10, 0)), None);
+    }
+
+    #[test]
+    fn test_overlapping() {
+        let features = Features::new(vec![
+            feature("long", 0, 100),
+            feature("short", 10, 20),
+            feature("mid", 40, 60),
+            feature("far", 300, 310),
+        ]);
+        let names = |ir| {
+            features
+                .overlapping(&ir)
+                .map(|feature| feature.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names((15, 45, 1)), vec!["long", "short", "mid"]);
+        assert_eq!(names((60, 99, 0)), vec!["long"]);
+        assert_eq!(names((100, 299, 0)), Vec::<&str>::new());
+        assert_eq!(names((305, 400, 0)), vec!["far"]);
+    }
+
+    #[test]
+    fn test_gff_name() {
+        assert_eq!(gff_name("ID=gene0;Name=dnaA;gbkey=Gene"), Some("dnaA"));
+        assert_eq!(
+            gff_name("gene_id \"g1\"; gene_name \"oriC\";"),
+            Some("oriC")
+        );
+        assert_eq!(gff_name("Note=none"), None);
+    }
+}
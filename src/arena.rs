@@ -0,0 +1,37 @@
+//! Reusable storage for the temporary structures of a search.
+//!
+//! A search allocates several arrays proportional to the length of the sequence. When
+//! searching millions of short sequences, reusing them avoids most of the allocator
+//! overhead and fragmentation.
+use crate::IndexInt;
+
+/// Buffers of the sanitized sequence, the doubled sequence (`seq + '$' + revcomp + '#'`),
+/// the inverse suffix array and the LCP array. They only grow, and are cleared before use.
+/// The last two are 32-bit with the `index32` feature.
+///
+/// The suffix array and the range minimum query structure are still allocated by every
+/// search, by their respective crates.
+#[derive(Debug, Default)]
+pub struct SearchArena {
+    #[cfg(feature = "arena")]
+    pub(crate) seq: Vec<u8>,
+    pub(crate) s: Vec<u8>,
+    pub(crate) inv_sa: Vec<IndexInt>,
+    pub(crate) lcp: Vec<IndexInt>,
+    /// Back the buffers with transparent huge pages (see `SearchParams::huge_pages`).
+    pub(crate) huge_pages: bool,
+}
+
+#[cfg(feature = "arena")]
+impl SearchArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bytes currently reserved by the buffers.
+    pub fn capacity(&self) -> usize {
+        self.seq.capacity()
+            + self.s.capacity()
+            + (self.inv_sa.capacity() + self.lcp.capacity()) * size_of::<IndexInt>()
+    }
+}
@@ -0,0 +1,129 @@
+//! Minimal BED reader, for region-based options.
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashMap;
+use std::fs;
+
+/// Sorted, non-overlapping, 0-based half-open intervals of one sequence.
+pub type Intervals = Vec<(usize, usize)>;
+
+/// Read the first three columns of a BED file (e.g. from TRF or ULTRA) into merged intervals
+/// per sequence name. Header, `track` and `browser` lines are ignored.
+pub fn read_bed(path: &str) -> Result<HashMap<String, Intervals>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Could not read BED file {path}"))?;
+
+    let mut regions: HashMap<String, Intervals> = HashMap::new();
+    for (line_number, line) in content.lines().enumerate() {
+        if line.trim().is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let mut fields = line.split('\t');
+        let (Some(name), Some(start), Some(end)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(anyhow!(
+                "{}:{}: expected at least 3 columns",
+                path,
+                line_number + 1
+            ));
+        };
+        let parse = |field: &str| {
+            field
+                .trim()
+                .parse::<usize>()
+                .with_context(|| format!("{}:{}: invalid position", path, line_number + 1))
+        };
+        regions
+            .entry(name.to_string())
+            .or_default()
+            .push((parse(start)?, parse(end)?));
+    }
+
+    for intervals in regions.values_mut() {
+        merge(intervals);
+    }
+    Ok(regions)
+}
+
+/// Sort and merge overlapping (or touching) intervals.
+pub fn merge(intervals: &mut Intervals) {
+    intervals.sort_unstable();
+    let mut merged: Intervals = Vec::with_capacity(intervals.len());
+    for &(start, end) in intervals.iter() {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    *intervals = merged;
+}
+
+/// Whether `start..end` intersects any of the (merged) `intervals`.
+pub fn overlaps(intervals: &[(usize, usize)], start: usize, end: usize) -> bool {
+    // First interval ending after start
+    let idx = intervals.partition_point(|&(_, e)| e <= start);
+    idx < intervals.len() && intervals[idx].0 < end
+}
+
+/// Whether `start..end` lies inside one of the (merged) `intervals`.
+pub fn contains(intervals: &[(usize, usize)], start: usize, end: usize) -> bool {
+    let idx = intervals.partition_point(|&(_, e)| e < end);
+    idx < intervals.len() && intervals[idx].0 <= start
+}
+
+/// The 0-based half-open interval `start..end` of a circular sequence of length `len`, once
+/// rotated so that `origin` becomes position 0: one interval, or two if it spans the origin.
+pub fn rotate_interval(start: usize, end: usize, origin: usize, len: usize) -> Intervals {
+    let end = end.min(len);
+    let start = start.min(end);
+    if start >= origin {
+        vec![(start - origin, end - origin)]
+    } else if end <= origin {
+        vec![(start + len - origin, end + len - origin)]
+    } else {
+        vec![(start + len - origin, len), (0, end - origin)]
+    }
+}
+
+/// `intervals` of a circular sequence of length `len`, once rotated so that `origin` becomes
+/// position 0.
+pub fn rotate_intervals(intervals: &[(usize, usize)], origin: usize, len: usize) -> Intervals {
+    let mut rotated: Intervals = intervals
+        .iter()
+        .flat_map(|&(start, end)| rotate_interval(start, end, origin, len))
+        .collect();
+    merge(&mut rotated);
+    rotated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_and_overlaps() {
+        let mut intervals = vec![(10, 20), (0, 5), (15, 30)];
+        merge(&mut intervals);
+        assert_eq!(intervals, vec![(0, 5), (10, 30)]);
+        assert!(overlaps(&intervals, 4, 6));
+        assert!(!overlaps(&intervals, 5, 10));
+        assert!(overlaps(&intervals, 29, 40));
+        assert!(!overlaps(&intervals, 30, 40));
+        assert!(contains(&intervals, 10, 30));
+        assert!(contains(&intervals, 0, 3));
+        assert!(!contains(&intervals, 4, 6));
+        assert!(!contains(&intervals, 29, 31));
+    }
+
+    #[test]
+    fn test_rotate_intervals() {
+        let intervals = vec![(0, 5), (10, 30), (90, 100)];
+        // The second interval spans the new origin, and the last and first ones become adjacent
+        let rotated = rotate_intervals(&intervals, 20, 100);
+        assert_eq!(rotated, vec![(0, 10), (70, 85), (90, 100)]);
+        assert_eq!(rotate_intervals(&intervals, 0, 100), intervals);
+    }
+}
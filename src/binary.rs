@@ -0,0 +1,202 @@
+//! Compact binary serialization of the IRs of a sequence, with the metadata of the run.
+//!
+//! Layout (version 1): the magic bytes `IIRSB`, a version byte, then LEB128 varints: the length
+//! and bytes of the sequence name, the sequence length, `min_len`, `max_len`, `max_gap`,
+//! `mismatches`, the number of IRs, and for every IR (sorted) the distance from the start of
+//! the previous IR to its start, its span minus one and its gap.
+use anyhow::{Result, anyhow, bail};
+use std::io::{self, Read, Write};
+
+use crate::IrSpan;
+use crate::config::SearchParams;
+
+pub const BINARY_MAGIC: &[u8; 5] = b"IIRSB";
+pub const BINARY_VERSION: u8 = 1;
+
+/// The IRs of a sequence, with the metadata of the run that found them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryResults {
+    pub seq_name: String,
+    pub seq_len: usize,
+    pub min_len: usize,
+    pub max_len: usize,
+    pub max_gap: usize,
+    pub mismatches: usize,
+    /// Sorted by (left, right, gap).
+    pub irs: Vec<IrSpan>,
+}
+
+impl BinaryResults {
+    pub fn new(
+        seq_name: &str,
+        seq_len: usize,
+        params: &SearchParams,
+        mut irs: Vec<IrSpan>,
+    ) -> Self {
+        irs.sort_unstable();
+        Self {
+            seq_name: seq_name.to_string(),
+            seq_len,
+            min_len: params.min_len,
+            max_len: params.max_len,
+            max_gap: params.max_gap,
+            mismatches: params.mismatches,
+            irs,
+        }
+    }
+
+    /// Write the results in the binary format.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use iirs::{BinaryResults, SearchParams};
+    ///
+    /// let params = SearchParams::new(3, 6, 2, 0).unwrap();
+    /// let results = BinaryResults::new("seq0", 6, &params, vec![(0, 5, 0)]);
+    /// let mut bytes = Vec::new();
+    /// results.write(&mut bytes).unwrap();
+    /// assert_eq!(BinaryResults::read(&mut bytes.as_slice()).unwrap(), results);
+    /// ```
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(32 + 4 * self.irs.len());
+        buf.extend_from_slice(BINARY_MAGIC);
+        buf.push(BINARY_VERSION);
+        write_varint(&mut buf, self.seq_name.len() as u64);
+        buf.extend_from_slice(self.seq_name.as_bytes());
+        for value in [
+            self.seq_len,
+            self.min_len,
+            self.max_len,
+            self.max_gap,
+            self.mismatches,
+            self.irs.len(),
+        ] {
+            write_varint(&mut buf, value as u64);
+        }
+
+        let mut previous = 0;
+        for &(left, right, gap) in &self.irs {
+            write_varint(&mut buf, (left - previous) as u64);
+            write_varint(&mut buf, (right - left) as u64);
+            write_varint(&mut buf, gap as u64);
+            previous = left;
+        }
+        out.write_all(&buf)
+    }
+
+    /// Read results in the binary format.
+    pub fn read<R: Read>(input: &mut R) -> Result<Self> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        if !is_binary_results(&bytes) {
+            bail!("not an iirs binary result file");
+        }
+        let version = bytes[BINARY_MAGIC.len()];
+        if version != BINARY_VERSION {
+            bail!("unsupported binary result version {version} (expected {BINARY_VERSION})");
+        }
+
+        let mut cursor = &bytes[BINARY_MAGIC.len() + 1..];
+        let name_len = read_varint(&mut cursor)?;
+        if name_len > cursor.len() {
+            bail!("truncated binary result file");
+        }
+        let (name, rest) = cursor.split_at(name_len);
+        let seq_name = String::from_utf8(name.to_vec())?;
+        cursor = rest;
+
+        let mut header = [0; 6];
+        for value in &mut header {
+            *value = read_varint(&mut cursor)?;
+        }
+        let [seq_len, min_len, max_len, max_gap, mismatches, n_irs] = header;
+
+        // Every IR takes at least 3 bytes
+        let mut irs = Vec::with_capacity(n_irs.min(cursor.len() / 3));
+        let mut left: usize = 0;
+        for _ in 0..n_irs {
+            let overflow = || anyhow!("invalid position in binary result file");
+            left = left
+                .checked_add(read_varint(&mut cursor)?)
+                .ok_or_else(overflow)?;
+            let right = left
+                .checked_add(read_varint(&mut cursor)?)
+                .ok_or_else(overflow)?;
+            let gap = read_varint(&mut cursor)?;
+            irs.push((left, right, gap));
+        }
+        if !cursor.is_empty() {
+            bail!("trailing bytes after the IRs");
+        }
+
+        Ok(Self {
+            seq_name,
+            seq_len,
+            min_len,
+            max_len,
+            max_gap,
+            mismatches,
+            irs,
+        })
+    }
+}
+
+/// Whether `bytes` start like a binary result file.
+pub fn is_binary_results(bytes: &[u8]) -> bool {
+    bytes.len() > BINARY_MAGIC.len() && bytes.starts_with(BINARY_MAGIC)
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_varint(cursor: &mut &[u8]) -> Result<usize> {
+    let mut value: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = cursor
+            .split_first()
+            .ok_or_else(|| anyhow!("truncated binary result file"))?;
+        *cursor = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(usize::try_from(value)?);
+        }
+    }
+    Err(anyhow!("invalid varint in binary result file"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_roundtrip() {
+        let params = SearchParams::new(10, 100, 50, 2).unwrap();
+        let irs = vec![
+            (300, 1000, 7),
+            (5, 40, 0),
+            (5, 60, 20),
+            (1 << 40, (1 << 40) + 30, 1),
+        ];
+        let results = BinaryResults::new("chr1", 1 << 41, &params, irs);
+        assert_eq!(results.irs[0], (5, 40, 0));
+
+        let mut bytes = Vec::new();
+        results.write(&mut bytes).unwrap();
+        assert!(is_binary_results(&bytes));
+        assert_eq!(BinaryResults::read(&mut bytes.as_slice()).unwrap(), results);
+
+        // Truncated, wrong version and not binary
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(BinaryResults::read(&mut &truncated[..]).is_err());
+        let mut newer = bytes.clone();
+        newer[BINARY_MAGIC.len()] = BINARY_VERSION + 1;
+        assert!(BinaryResults::read(&mut newer.as_slice()).is_err());
+        assert!(BinaryResults::read(&mut &b"start_n,end_n"[..]).is_err());
+    }
+}
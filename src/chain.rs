@@ -0,0 +1,154 @@
+//! Chaining of neighbouring IRs into higher-order structures, such as arrays of cruciforms
+//! or the breakpoints of inverted duplications.
+use anyhow::Result;
+use std::fmt;
+
+use crate::IrSpan;
+use crate::complement::complement_array;
+use crate::config::SearchParams;
+use crate::matrix::MatchMatrix;
+use crate::score::ir_score;
+use crate::utils::sanitize_sequence;
+
+/// Successive non-overlapping IRs, each starting at most the chaining distance after the end
+/// of the previous one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IrChain {
+    /// Sorted by position.
+    pub irs: Vec<IrSpan>,
+    /// Sum of the scores of the IRs (with the score scheme of the search).
+    pub score: f64,
+}
+
+impl IrChain {
+    /// 0-based inclusive span of the chain.
+    pub fn span(&self) -> (usize, usize) {
+        (self.irs[0].0, self.irs[self.irs.len() - 1].1)
+    }
+}
+
+pub fn fmt_chains_header() -> String {
+    String::from("chain_start,chain_end,irs,score,members")
+}
+
+/// `chain_start,chain_end,irs,score,members`, where members are the (1-based) spans of
+/// the IRs of the chain, such as `1-6;11-20`.
+impl fmt::Display for IrChain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (start, end) = self.span();
+        let members: Vec<String> = self
+            .irs
+            .iter()
+            .map(|&(left, right, _)| format!("{}-{}", left + 1, right + 1))
+            .collect();
+        write!(
+            f,
+            "{},{},{},{:.2},{}",
+            start + 1,
+            end + 1,
+            self.irs.len(),
+            self.score,
+            members.join(";")
+        )
+    }
+}
+
+/// Chain the IRs of `seq` into the highest scoring chains of at least two IRs, where every IR
+/// starts at most `max_distance` positions after the end of the previous one. Every IR belongs
+/// to at most one chain, and chains are returned by position.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{SearchParams, chain_irs};
+///
+/// // Two hairpins 3 positions apart, and a third one far away
+/// let seq = "acgcgtaaaccgcggttttttttttttttttttttttttttgggccc".as_bytes();
+/// let params = SearchParams::new(3, 6, 0, 0).unwrap();
+/// let irs = vec![(0, 5, 0), (9, 14, 0), (41, 46, 0)];
+/// let chains = chain_irs(&irs, seq, &params, 5).unwrap();
+/// assert_eq!(chains.len(), 1);
+/// assert_eq!(chains[0].irs, vec![(0, 5, 0), (9, 14, 0)]);
+/// ```
+pub fn chain_irs(
+    irs: &[IrSpan],
+    seq: &[u8],
+    params: &SearchParams,
+    max_distance: usize,
+) -> Result<Vec<IrChain>> {
+    let seq = sanitize_sequence(seq)?;
+    let matrix = MatchMatrix::from_params(params);
+    let complement = complement_array(params);
+
+    let mut irs = irs.to_vec();
+    irs.sort_unstable_by_key(|&(left, right, _)| (right, left));
+    let scores: Vec<f64> = irs
+        .iter()
+        .map(|ir| ir_score(ir, &seq, params, &matrix, &complement))
+        .collect();
+
+    // Best chain ending with every IR (sorted by end), as (score, previous IR).
+    let mut best: Vec<(f64, Option<usize>)> = Vec::with_capacity(irs.len());
+    for (i, &(left, _, _)) in irs.iter().enumerate() {
+        // IRs ending before `left`, and at most `max_distance` before it
+        let first = irs[..i].partition_point(|other| other.1 + max_distance + 1 < left);
+        let last = irs[..i].partition_point(|other| other.1 < left);
+        let previous = (first..last)
+            .filter(|&j| best[j].0 > 0.0)
+            .max_by(|&a, &b| best[a].0.total_cmp(&best[b].0));
+        let score = scores[i] + previous.map_or(0.0, |j| best[j].0);
+        best.push((score, previous));
+    }
+
+    // Trace back the best chains first, each IR being used once.
+    let mut order: Vec<usize> = (0..irs.len()).collect();
+    order.sort_by(|&a, &b| best[b].0.total_cmp(&best[a].0));
+    let mut used = vec![false; irs.len()];
+    let mut chains = Vec::new();
+    for end in order {
+        let mut members = Vec::new();
+        let mut current = Some(end);
+        while let Some(i) = current.filter(|&i| !used[i]) {
+            used[i] = true;
+            members.push(i);
+            current = best[i].1;
+        }
+        if members.len() >= 2 {
+            members.reverse();
+            chains.push(IrChain {
+                irs: members.iter().map(|&i| irs[i]).collect(),
+                score: members.iter().map(|&i| scores[i]).sum(),
+            });
+        }
+    }
+    chains.sort_by_key(IrChain::span);
+
+    Ok(chains)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_irs() {
+        let seq = "acgcgtaaaccgcggttttttttttttttttttttttttttgggccc".as_bytes();
+        let params = SearchParams::new(3, 6, 0, 0).unwrap();
+        let irs = [(0, 5, 0), (9, 14, 0), (41, 46, 0)];
+
+        // Every IR is close enough to the previous one
+        let chains = chain_irs(&irs, seq, &params, 26).unwrap();
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].irs, irs);
+        assert_eq!(chains[0].span(), (0, 46));
+        assert_eq!(chains[0].to_string(), "1,47,3,27.00,1-6;10-15;42-47");
+
+        assert!(chain_irs(&irs, seq, &params, 2).unwrap().is_empty());
+        // Overlapping IRs are never chained
+        assert!(
+            chain_irs(&[(0, 5, 0), (1, 4, 0)], seq, &params, 10)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
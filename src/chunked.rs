@@ -0,0 +1,508 @@
+//! Search of a single long sequence (e.g. a chromosome) in overlapping chunks, whose indexes
+//! are built and searched on several threads.
+//!
+//! Every chunk owns a range of centers and is extended on both sides by half the overlap, so
+//! that the IRs of its centers lie inside it. The IRs found around the centers owned by the
+//! neighbouring chunks are dropped, so that the IRs of the overlaps are only reported once.
+//!
+//! With mismatches, the extension from a center can go past `max_len`, which decides how its
+//! IRs are truncated. The centers whose extension reaches an end of their chunk are searched
+//! again, all the ones of an end together, in wider parts of the sequence (without reporting
+//! their events again), so that the IRs are the same as the ones of a search of the whole
+//! sequence. In a run of N crossing a chunk, these are most of the centers of the run.
+use anyhow::{Result, anyhow};
+use std::ops::Range;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::bed::Intervals;
+use crate::config::{SearchParams, SymmetryMode};
+use crate::control::{MaxHitsAction, SearchSummary, StopHandle, StopReason};
+use crate::observer::{AlgoEvent, NoObserver, Observer};
+use crate::{IrSpan, search_all};
+
+#[cfg(feature = "parallel")]
+use crate::algo::{in_pool, thread_pool};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Overlap between consecutive chunks: the span of the longest IR (both arms and the gap).
+pub const fn chunk_overlap(params: &SearchParams) -> usize {
+    2 * params.max_len + params.max_gap
+}
+
+/// Positions added on both sides of a chunk: the arms of an IR end at most one position
+/// further than half the overlap from its center.
+pub(crate) const fn margin(params: &SearchParams) -> usize {
+    chunk_overlap(params) / 2 + 1
+}
+
+/// Part of the sequence searched for the IRs of the centers of `owned` (positions).
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Chunk {
+    owned: Range<usize>,
+    context: Range<usize>,
+}
+
+/// Chunks of `chunk_len` positions (the last one takes the remainder) covering `n` positions.
+fn split(n: usize, chunk_len: usize, margin: usize) -> Vec<Chunk> {
+    let count = (n / chunk_len).max(1);
+    (0..count)
+        .map(|idx| {
+            let start = idx * chunk_len;
+            let end = if idx + 1 == count {
+                n
+            } else {
+                start + chunk_len
+            };
+            Chunk {
+                owned: start..end,
+                context: start.saturating_sub(margin)..(end + margin).min(n),
+            }
+        })
+        .collect()
+}
+
+/// Part of `intervals` inside `context`, relative to its start.
+fn clip(intervals: &[(usize, usize)], context: &Range<usize>) -> Intervals {
+    intervals
+        .iter()
+        .filter(|&&(start, end)| start < context.end && end > context.start)
+        .map(|&(start, end)| {
+            (
+                start.max(context.start) - context.start,
+                end.min(context.end) - context.start,
+            )
+        })
+        .collect()
+}
+
+/// Same as `search`, chunk by chunk (see the module documentation). The IRs of every chunk are
+/// handed to `emit` in the order of the sequence, once the chunks searched with it are done.
+pub fn search_chunked<O, F>(
+    params: &SearchParams,
+    chunk_len: usize,
+    sanitized_seq: &[u8],
+    stop: &StopHandle,
+    observer: &O,
+    mut emit: F,
+) -> Result<SearchSummary>
+where
+    O: Observer,
+    F: FnMut(Vec<IrSpan>, usize) -> Result<()>,
+{
+    // The arms of direct repeats are not paired around a center.
+    if matches!(
+        params.symmetry_mode,
+        SymmetryMode::Direct | SymmetryMode::DirectComplementary
+    ) {
+        return Err(anyhow!(
+            "Chunked searches are only available for the inverted symmetry modes."
+        ));
+    }
+    let overlap = chunk_overlap(params);
+    if chunk_len < overlap {
+        return Err(anyhow!(
+            "chunk_len={} must not be less than 2 * max_len + max_gap = {}.",
+            chunk_len,
+            overlap
+        ));
+    }
+    let n = sanitized_seq.len();
+    let chunks = split(n, chunk_len, margin(params));
+
+    // The guard on the number of IRs applies to the IRs kept from every chunk.
+    let chunk_stop = stop.without_max_hits();
+    #[cfg(feature = "parallel")]
+    let pool = thread_pool(params.threads)?;
+    #[cfg(feature = "parallel")]
+    let batch_len = in_pool(pool.as_deref(), rayon::current_num_threads);
+    #[cfg(not(feature = "parallel"))]
+    let batch_len = 1;
+
+    let mut summary = SearchSummary {
+        kept: 0,
+        found: 0,
+        covered: n,
+        stopped: None,
+    };
+    'batches: for batch in chunks.chunks(batch_len) {
+        #[cfg(feature = "parallel")]
+        let results: Vec<_> = in_pool(pool.as_deref(), || {
+            batch
+                .par_iter()
+                .map(|chunk| search_chunk(params, sanitized_seq, chunk, &chunk_stop, observer))
+                .collect()
+        });
+        #[cfg(not(feature = "parallel"))]
+        let results: Vec<_> = batch
+            .iter()
+            .map(|chunk| search_chunk(params, sanitized_seq, chunk, &chunk_stop, observer))
+            .collect();
+
+        for (chunk, result) in batch.iter().zip(results) {
+            let (mut irs, stopped) = result?;
+            if stopped.is_some() {
+                // The IRs of a partially searched chunk are not all in its first positions.
+                summary.stopped = stopped;
+                summary.covered = chunk.owned.start;
+                break 'batches;
+            }
+            summary.found += irs.len();
+            if let Some((max_hits, action)) = stop.max_hits()
+                && summary.found > max_hits
+            {
+                match action {
+                    // Keep the whole chunk so that the covered positions stay complete.
+                    MaxHitsAction::Abort => summary.stopped = Some(StopReason::MaxHits),
+                    MaxHitsAction::Count => irs.truncate(max_hits.saturating_sub(summary.kept)),
+                }
+            }
+            summary.kept += irs.len();
+            if !irs.is_empty() {
+                // The IRs of the center c satisfy left + right = c.
+                emit(irs, 2 * chunk.owned.end)?;
+            }
+            if summary.stopped.is_some() {
+                summary.covered = chunk.owned.end;
+                break 'batches;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// IRs centered in the positions owned by `chunk`, in the coordinates of the whole sequence.
+fn search_chunk<O: Observer>(
+    params: &SearchParams,
+    sanitized_seq: &[u8],
+    chunk: &Chunk,
+    stop: &StopHandle,
+    observer: &O,
+) -> Result<(Vec<IrSpan>, Option<StopReason>)> {
+    let centers = 2 * chunk.owned.start..2 * chunk.owned.end;
+    let mut found = search_context(
+        params,
+        sanitized_seq,
+        chunk.context.clone(),
+        centers,
+        stop,
+        Some(observer),
+    )?;
+    if found.stopped.is_some() || found.cut.is_empty() {
+        return Ok((found.irs, found.stopped));
+    }
+    // The extension of these centers was cut by an end of the chunk, which changes how their
+    // IRs are truncated to max_len.
+    found
+        .irs
+        .retain(|&(left, right, _)| found.cut.binary_search(&(left + right)).is_err());
+    // The centers of the first half of the chunk are searched again towards its start, the
+    // others towards its end.
+    let middle = chunk.owned.start + chunk.owned.end;
+    let (first, last): (Vec<usize>, Vec<usize>) =
+        found.cut.into_iter().partition(|&center| center < middle);
+    for centers in [first, last] {
+        let (irs, stopped) = search_centers(params, sanitized_seq, centers, stop)?;
+        found.irs.extend(irs);
+        if stopped.is_some() {
+            return Ok((found.irs, stopped));
+        }
+    }
+    Ok((found.irs, None))
+}
+
+/// IRs of the (sorted) `centers`, searched together in wider and wider parts of the sequence
+/// until their extensions stop inside.
+fn search_centers(
+    params: &SearchParams,
+    sanitized_seq: &[u8],
+    mut centers: Vec<usize>,
+    stop: &StopHandle,
+) -> Result<(Vec<IrSpan>, Option<StopReason>)> {
+    let n = sanitized_seq.len();
+    let mut margin = margin(params);
+    let mut irs = Vec::new();
+    while let (Some(&first), Some(&last)) = (centers.first(), centers.last()) {
+        margin *= 2;
+        let context = (first / 2).saturating_sub(margin)..(last / 2 + margin + 1).min(n);
+        let whole = context == (0..n);
+        let found = search_context(
+            params,
+            sanitized_seq,
+            context,
+            first..last + 1,
+            stop,
+            None::<&NoObserver>,
+        )?;
+        if found.stopped.is_some() {
+            return Ok((irs, found.stopped));
+        }
+        let done = |center: usize| {
+            centers.binary_search(&center).is_ok()
+                && (whole || found.cut.binary_search(&center).is_err())
+        };
+        irs.extend(
+            found
+                .irs
+                .into_iter()
+                .filter(|&(left, right, _)| done(left + right)),
+        );
+        centers.retain(|&center| !whole && found.cut.binary_search(&center).is_ok());
+    }
+    Ok((irs, None))
+}
+
+/// IRs of a part of a sequence.
+struct ContextIrs {
+    /// IRs of the requested centers, in the coordinates of the whole sequence.
+    irs: Vec<IrSpan>,
+    /// Requested centers whose extension reached an end of the part inside the sequence.
+    cut: Vec<usize>,
+    stopped: Option<StopReason>,
+}
+
+/// IRs of the centers of `centers` (of the whole sequence) found in the `context` part of
+/// `sanitized_seq`.
+fn search_context<O: Observer>(
+    params: &SearchParams,
+    sanitized_seq: &[u8],
+    context: Range<usize>,
+    centers: Range<usize>,
+    stop: &StopHandle,
+    observer: Option<&O>,
+) -> Result<ContextIrs> {
+    let offset = context.start;
+    let mut context_params = params.clone();
+    context_params.chunk_len = None;
+    // Searched in the threads of the chunks.
+    context_params.threads = 0;
+    context_params.anchor = None;
+    context_params.masked = clip(&params.masked, &context);
+    context_params.arm_regions = params
+        .arm_regions
+        .as_ref()
+        .map(|(left, right)| (clip(left, &context), clip(right, &context)));
+
+    let context_observer = ContextObserver {
+        inner: observer,
+        offset,
+        len: context.len(),
+        open_start: context.start > 0,
+        open_end: context.end < sanitized_seq.len(),
+        centers: centers.clone(),
+        cut: Mutex::new(Vec::new()),
+    };
+    let mut irs = Vec::new();
+    let mut summaries = search_all(
+        std::slice::from_ref(&context_params),
+        &sanitized_seq[context],
+        stop,
+        &context_observer,
+        |_, window_irs, _| {
+            irs.extend(
+                window_irs
+                    .into_iter()
+                    .map(|(left, right, gap)| (left + offset, right + offset, gap))
+                    // The IRs of the center c satisfy left + right = c.
+                    .filter(|&(left, right, _)| centers.contains(&(left + right))),
+            );
+            Ok(())
+        },
+    )?;
+    let mut cut = context_observer
+        .cut
+        .into_inner()
+        .map_err(|_| anyhow!("A search thread panicked."))?;
+    cut.sort_unstable();
+    Ok(ContextIrs {
+        irs,
+        cut,
+        stopped: summaries.remove(0).stopped,
+    })
+}
+
+/// Forwards the events of the requested centers to `inner`, in the coordinates of the whole
+/// sequence, and records the ones whose extension reached an end of the part searched that
+/// is not an end of the sequence.
+struct ContextObserver<'a, O> {
+    inner: Option<&'a O>,
+    offset: usize,
+    len: usize,
+    open_start: bool,
+    open_end: bool,
+    centers: Range<usize>,
+    cut: Mutex<Vec<usize>>,
+}
+
+impl<O: Observer> Observer for ContextObserver<'_, O> {
+    fn on_event(&self, event: AlgoEvent) {
+        let shift = 2 * self.offset;
+        let event = match event {
+            AlgoEvent::Seed {
+                center,
+                extension,
+                lce_queries,
+                steps,
+            } => {
+                // The ends of the part count as mismatches, one position past the last
+                // character (a little slack does not hurt: the center is searched again).
+                let position = center / 2;
+                if ((self.open_start && extension + 2 > position)
+                    || (self.open_end && position + extension + 2 >= self.len))
+                    && self.centers.contains(&(center + shift))
+                    && let Ok(mut cut) = self.cut.lock()
+                {
+                    cut.push(center + shift);
+                }
+                AlgoEvent::Seed {
+                    center: center + shift,
+                    extension,
+                    lce_queries,
+                    steps,
+                }
+            }
+            AlgoEvent::ExtensionTruncated { center, offset } => AlgoEvent::ExtensionTruncated {
+                center: center + shift,
+                offset,
+            },
+            AlgoEvent::MaxLenTruncated { center, overshoot } => AlgoEvent::MaxLenTruncated {
+                center: center + shift,
+                overshoot,
+            },
+            AlgoEvent::Hit {
+                center,
+                ir: (left, right, gap),
+            } => AlgoEvent::Hit {
+                center: center + shift,
+                ir: (left + self.offset, right + self.offset, gap),
+            },
+        };
+        if let Some(inner) = self.inner {
+            let center = match event {
+                AlgoEvent::Seed { center, .. }
+                | AlgoEvent::ExtensionTruncated { center, .. }
+                | AlgoEvent::MaxLenTruncated { center, .. }
+                | AlgoEvent::Hit { center, .. } => center,
+            };
+            if self.centers.contains(&center) {
+                inner.on_event(event);
+            }
+        }
+    }
+
+    fn on_index_built(&self, seq_len: usize, elapsed: Duration) {
+        if let Some(inner) = self.inner {
+            inner.on_index_built(seq_len, elapsed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::find_spans;
+    use crate::libtests::random_seq;
+
+    #[test]
+    fn test_split() {
+        let chunks = split(25, 10, 3);
+        assert_eq!(
+            chunks,
+            vec![
+                Chunk {
+                    owned: 0..10,
+                    context: 0..13
+                },
+                Chunk {
+                    owned: 10..25,
+                    context: 7..25
+                },
+            ]
+        );
+        assert_eq!(split(8, 10, 3).len(), 1);
+
+        // Positions past u32::MAX (polyploid genomes, pan-genomes)
+        let n = 5_000_000_007;
+        let chunks = split(n, 100_000_000, 1000);
+        assert_eq!(chunks.len(), 50);
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[0].owned.end, pair[1].owned.start);
+            assert_eq!(pair[1].context.start, pair[1].owned.start - 1000);
+        }
+        let last = chunks.last().unwrap();
+        assert_eq!(last.owned, 4_900_000_000..n);
+        assert_eq!(last.context, 4_899_999_000..n);
+    }
+
+    #[test]
+    fn test_chunked_search_matches_whole_search() {
+        // Random, with palindromic stretches crossing the chunk boundaries
+        let mut seq = random_seq(11, 5000, b"acgt");
+        for start in [990, 1995, 3000] {
+            seq[start..start + 30].copy_from_slice(b"atatatatatatatatatatatatatatat");
+        }
+        for (min_len, max_len, max_gap, mismatches) in [(4, 10, 3, 0), (5, 12, 6, 2), (3, 5, 7, 2)]
+        {
+            let mut params = SearchParams::new(min_len, max_len, max_gap, mismatches).unwrap();
+            params.masked = vec![(1500, 1520), (2495, 2505)];
+            let mut expected = find_spans(&params, &seq).unwrap();
+            expected.sort_unstable();
+
+            params.chunk_len = Some(500);
+            let mut irs = find_spans(&params, &seq).unwrap();
+            irs.sort_unstable();
+            assert_eq!(irs, expected);
+        }
+    }
+
+    #[test]
+    fn test_chunked_extrusion_scores() {
+        // The supercoiling domain is the whole sequence, not the chunk.
+        let seq = "gcgcggccatcgatttttatcgatggccgcgc".repeat(100).into_bytes();
+        let mut params = SearchParams::new(12, 16, 4, 0).unwrap();
+        params.score_scheme = crate::ScoreScheme::Extrusion;
+        params.supercoiling.density = -0.07;
+        params.min_score = Some(0.5);
+        let expected = find_spans(&params, &seq).unwrap();
+        assert!(!expected.is_empty());
+
+        params.chunk_len = Some(500);
+        assert_eq!(find_spans(&params, &seq).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_chunked_search_in_long_n_run() {
+        let mut seq = random_seq(17, 4000, b"acgt");
+        // Crosses the chunk boundaries: the extensions of its centers reach the chunk ends.
+        seq.splice(2000..2000, b"n".repeat(100_000));
+        let mut params = SearchParams::new(5, 12, 6, 2).unwrap();
+        let mut expected = find_spans(&params, &seq).unwrap();
+        expected.sort_unstable();
+
+        params.chunk_len = Some(5000);
+        let stop = StopHandle::new().with_time_limit(Duration::from_secs(30));
+        let mut irs = Vec::new();
+        let summary = crate::search(&params, &seq, &stop, &NoObserver, |window_irs, _| {
+            irs.extend(window_irs);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(summary.stopped, None);
+        irs.sort_unstable();
+        assert_eq!(irs, expected);
+    }
+
+    #[test]
+    fn test_chunked_search_errors() {
+        let mut params = SearchParams::new(4, 10, 3, 0).unwrap();
+        params.chunk_len = Some(22);
+        assert!(find_spans(&params, &[b'a'; 100]).is_err());
+
+        params.chunk_len = Some(50);
+        assert!(find_spans(&params, &[b'a'; 100]).is_ok());
+        params.symmetry_mode = SymmetryMode::Direct;
+        assert!(find_spans(&params, &[b'a'; 100]).is_err());
+    }
+}
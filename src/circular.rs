@@ -0,0 +1,143 @@
+//! Search of a circular sequence (plasmid, organellar genome), whose IRs may span the origin.
+//!
+//! The sequence is searched between two copies of itself, so that the arms extend across the
+//! origin as on the circle, also when the mismatches let the extension from a center go past
+//! `max_len` (which decides how its IRs are truncated). Only the IRs centered in the middle
+//! copy are kept, so that every IR is reported once, with its start modulo the length `n` of
+//! the sequence. The end of an IR spanning the origin is past the end of the sequence
+//! (`end - n` on the circle), like the circular features of GenBank, so that the arm lengths
+//! and gaps of the spans stay `end + 1 - start` apart. BED and GFF3, whose records must stay
+//! within the sequence, split them in two at the origin instead (see [`at_origin`]).
+use std::borrow::Cow;
+
+use crate::IrSpan;
+use crate::bed::Intervals;
+use crate::chunked::chunk_overlap;
+use crate::config::SearchParams;
+
+/// Sequence searched for the IRs of the circular `seq`: `seq` between two copies of itself.
+pub fn wrapped(seq: &[u8]) -> Vec<u8> {
+    seq.repeat(3)
+}
+
+/// `seq` followed by its first positions, so that the IRs spanning the origin can be read
+/// (and scored) at their reported positions. Borrowed when the search is not circular.
+pub fn unrolled<'a>(params: &SearchParams, seq: &'a [u8]) -> Cow<'a, [u8]> {
+    if !params.circular {
+        return Cow::Borrowed(seq);
+    }
+    // The span of the longest IR.
+    let extra = chunk_overlap(params).min(seq.len());
+    Cow::Owned([seq, &seq[..extra]].concat())
+}
+
+/// Length of the circular sequence whose [`unrolled`] sequence has `len` positions.
+pub fn rolled_len(params: &SearchParams, len: usize) -> usize {
+    let extra = chunk_overlap(params);
+    // Only the sequences shorter than the longest IR are unrolled by all their positions.
+    if len >= 2 * extra {
+        len - extra
+    } else {
+        len / 2
+    }
+}
+
+/// 0-based half-open `[start, end)`, with `start` less than `2n`, as intervals of the circular
+/// sequence of `n` positions if `n` is given: split in two at the origin when it spans it.
+pub fn at_origin(start: usize, end: usize, n: Option<usize>) -> Vec<(usize, usize)> {
+    match n {
+        Some(n) if end > n && start >= n => vec![(start - n, end - n)],
+        Some(n) if end > n => vec![(start, n), (0, end - n)],
+        _ => vec![(start, end)],
+    }
+}
+
+/// `intervals` in each of the three copies of the wrapped sequence.
+fn copies(intervals: &[(usize, usize)], n: usize) -> Intervals {
+    (0..3)
+        .flat_map(|copy| {
+            intervals
+                .iter()
+                .map(move |&(start, end)| (start + copy * n, end + copy * n))
+        })
+        .collect()
+}
+
+/// Parameters of the search of the wrapped sequence, whose positions are shifted by `n`.
+pub fn wrapped_params(params: &SearchParams, n: usize) -> SearchParams {
+    let mut wrapped = params.clone();
+    wrapped.circular = false;
+    wrapped.anchor = params.anchor.map(|anchor| anchor + n);
+    wrapped.masked = copies(&params.masked, n);
+    wrapped.arm_regions = params
+        .arm_regions
+        .as_ref()
+        .map(|(left, right)| (copies(left, n), copies(right, n)));
+    wrapped
+}
+
+/// The IRs of `irs` (found in the wrapped sequence) centered in the middle copy, in the
+/// positions of the circular sequence of `n` positions. The IRs longer than the circle are
+/// dropped.
+pub fn unwrap_irs(irs: Vec<IrSpan>, n: usize) -> Vec<IrSpan> {
+    irs.into_iter()
+        // The IRs of the center c satisfy left + right = c.
+        .filter(|&(left, right, _)| (2 * n..4 * n).contains(&(left + right)) && right - left < n)
+        .map(|(left, right, gap)| {
+            let start = left % n;
+            (start, start + right - left, gap)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwrap_irs() {
+        // n = 10: the wrapped sequence is 0 .. 9 | 0 .. 9 | 0 .. 9
+        let irs = vec![
+            (5, 12, 0),
+            (8, 13, 0),
+            (10, 15, 0),
+            (17, 22, 0),
+            (18, 21, 1),
+            (5, 25, 0),
+        ];
+        assert_eq!(
+            unwrap_irs(irs, 10),
+            vec![(8, 13, 0), (0, 5, 0), (7, 12, 0), (8, 11, 1)]
+        );
+    }
+
+    #[test]
+    fn test_rolled_len() {
+        let params = SearchParams::new(3, 6, 2, 0).unwrap();
+        for n in [5, 14, 15, 100] {
+            let seq = vec![b'a'; n];
+            let mut circular = params.clone();
+            circular.circular = true;
+            assert_eq!(rolled_len(&params, unrolled(&circular, &seq).len()), n);
+        }
+        assert_eq!(at_origin(4, 8, Some(10)), vec![(4, 8)]);
+        assert_eq!(at_origin(8, 13, Some(10)), vec![(8, 10), (0, 3)]);
+        assert_eq!(at_origin(11, 13, Some(10)), vec![(1, 3)]);
+        assert_eq!(at_origin(8, 13, None), vec![(8, 13)]);
+    }
+
+    #[test]
+    fn test_wrapped_params() {
+        let mut params = SearchParams::new(3, 6, 2, 0).unwrap();
+        params.circular = true;
+        params.masked = vec![(0, 2), (8, 10)];
+        params.anchor = Some(4);
+        let wrapped = wrapped_params(&params, 10);
+        assert!(!wrapped.circular);
+        assert_eq!(wrapped.anchor, Some(14));
+        assert_eq!(
+            wrapped.masked,
+            vec![(0, 2), (8, 10), (10, 12), (18, 20), (20, 22), (28, 30)]
+        );
+    }
+}
@@ -1,63 +1,739 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Result, bail};
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
 
 use crate::OutputFormat;
 use crate::SymmetryMode;
-use crate::config::{Config, SearchParams};
+use crate::annotate::{Features, read_features};
+use crate::bed::{Intervals, merge, read_bed, rotate_intervals};
+use crate::complement::ComplementTable;
+use crate::config::{
+    Config, GapStrata, ParamSet, RegionCap, RmqBackend, SaBackend, SearchParams, SortBy,
+    StrataOutput,
+};
 use crate::constants::{
-    DEFAULT_INPUT_FILE, DEFAULT_MAX_GAP, DEFAULT_MAX_LEN, DEFAULT_MIN_LEN, DEFAULT_MISMATCHES,
-    DEFAULT_OUTPUT_FILE, DEFAULT_SEQ_NAME,
+    ALL_SEQUENCES, DEFAULT_INPUT_FILE, DEFAULT_MAX_GAP, DEFAULT_MAX_LEN, DEFAULT_MIN_LEN,
+    DEFAULT_MISMATCHES, DEFAULT_OUTPUT_FILE, DEFAULT_SEQ_NAME, Profile, ProfilePreset,
 };
-use crate::utils::safe_extract_records;
+use crate::control::MaxHitsAction;
+use crate::mask::MaskMode;
+use crate::memory::parse_memory;
+#[cfg(feature = "wasm")]
+use crate::plugin::WasmPlugin;
+use crate::progress::ProgressFormat;
+use crate::score::{ScoreScheme, Scoring};
+use crate::setops::SetOperation;
+use crate::shuffle::ShuffleMode;
+use crate::thermo::{Supercoiling, ThermoConditions};
+use crate::utils::{read_quality_masks, safe_extract_records};
 use seq_io::fasta::{OwnedRecord, Record};
+use std::collections::HashMap;
+#[cfg(feature = "wasm")]
+use std::sync::Arc;
+
+/// Input file reported for the sequence of --seq.
+const INLINE_INPUT: &str = "<inline>";
 
 #[derive(Parser, Debug)]
+#[command(
+    after_help = "Every argument can also be set through its IIRS_* environment variable.\n\
+                  Precedence: command line flags > environment variables > defaults."
+)]
 pub struct Cli {
-    /// Input filename (FASTA).
-    #[arg(long, short = 'f', default_value_t = String::from(DEFAULT_INPUT_FILE))]
+    /// Input filename (FASTA, FASTQ, GenBank or EMBL).
+    #[arg(long, short = 'f', default_value_t = String::from(DEFAULT_INPUT_FILE), env = "IIRS_INPUT_FILE")]
     pub input_file: String,
 
+    /// Sequence to search, given inline instead of --input-file (e.g. a short oligo). It is
+    /// named after the first --seq-names
+    #[arg(long, conflicts_with = "input_file", env = "IIRS_SEQ")]
+    pub seq: Option<String>,
+
     /// Input sequence names (ids).
-    #[arg(long, short, default_value = DEFAULT_SEQ_NAME, value_delimiter = ' ')]
+    #[arg(long, short, default_value = DEFAULT_SEQ_NAME, value_delimiter = ' ', env = "IIRS_SEQ_NAMES")]
     pub seq_names: Vec<String>,
 
+    /// Search every sequence of the input file (same as --seq-names ALL_SEQUENCES), with one
+    /// output file per sequence in the --output-file folder
+    #[arg(long, default_value_t = false, conflicts_with_all = ["seq_names", "seq"], env = "IIRS_ALL_SEQUENCES")]
+    pub all_sequences: bool,
+
+    /// Bundle of parameters for a use case. Arguments given explicitly (command line or
+    /// environment) take precedence over the profile
+    #[arg(long, value_enum, env = "IIRS_PROFILE")]
+    pub profile: Option<Profile>,
+
     /// Minimum length.
-    #[arg(long, short, default_value_t = DEFAULT_MIN_LEN)]
+    #[arg(long, short, default_value_t = DEFAULT_MIN_LEN, env = "IIRS_MIN_LEN")]
     pub min_len: usize,
 
     /// Maximum length.
-    #[arg(long, short = 'M', default_value_t = DEFAULT_MAX_LEN)]
+    #[arg(long, short = 'M', default_value_t = DEFAULT_MAX_LEN, env = "IIRS_MAX_LEN")]
     pub max_len: usize,
 
     /// Maximum permissible gap.
-    #[arg(long, short = 'g', default_value_t = DEFAULT_MAX_GAP)]
+    #[arg(long, short = 'g', default_value_t = DEFAULT_MAX_GAP, env = "IIRS_MAX_GAP")]
     pub max_gap: usize,
 
     /// Maximum permissible mismatches.
-    #[arg(long, short = 'x', default_value_t = DEFAULT_MISMATCHES)]
+    #[arg(long, short = 'x', default_value_t = DEFAULT_MISMATCHES, env = "IIRS_MISMATCHES")]
     pub mismatches: usize,
 
+    /// Allow a number of mismatches proportional to the arm length instead (e.g. 0.01),
+    /// for very long IRs such as the IRa/IRb of plastid genomes
+    #[arg(long, conflicts_with = "mismatches", env = "IIRS_MISMATCH_RATE")]
+    pub mismatch_rate: Option<f64>,
+
     // TODO: Document and add default(?)
-    #[arg(long, short = 'S', default_value_t, value_enum)]
+    #[arg(
+        long,
+        short = 'S',
+        default_value_t,
+        value_enum,
+        env = "IIRS_SYMMETRY_MODE"
+    )]
     pub symmetry_mode: SymmetryMode,
 
+    /// Bisulfite mode: C/T and G/A differences are not counted as mismatches.
+    #[arg(long, default_value_t = false, env = "IIRS_BISULFITE")]
+    pub bisulfite: bool,
+
+    /// RNA sequences (e.g. transcripts): A pairs with U in the index, which keeps the search
+    /// of sequences with U as fast as the one of DNA
+    #[arg(long, default_value_t = false, env = "IIRS_RNA")]
+    pub rna: bool,
+
+    /// Do not count G:U wobble pairs as mismatches, for RNA stems
+    #[arg(long, default_value_t = false, requires = "rna", env = "IIRS_WOBBLE")]
+    pub wobble: bool,
+
+    /// Complement of some bases, as pairs of IUPAC symbols where the first one is complemented
+    /// into the second one (e.g. ac,ca for a two-letter alphabet). The other bases keep their
+    /// DNA complement
+    #[arg(long, value_parser = ComplementTable::parse, env = "IIRS_COMPLEMENT")]
+    pub complement: Option<ComplementTable>,
+
+    /// Score used by --min-score and the score column of the custom output format
+    #[arg(long, default_value_t, value_enum, env = "IIRS_SCORE_SCHEME")]
+    pub score_scheme: ScoreScheme,
+
+    /// Score of a matching pair (custom-weights scheme and einverted output)
+    #[arg(long, default_value_t = 3, env = "IIRS_MATCH_SCORE")]
+    pub match_score: i32,
+
+    /// Score of a mismatching pair (custom-weights scheme and einverted output)
+    #[arg(long, default_value_t = -4, allow_hyphen_values = true, env = "IIRS_MISMATCH_SCORE")]
+    pub mismatch_score: i32,
+
+    /// Discard IRs scoring less than this (einverted uses 50)
+    #[arg(long, allow_hyphen_values = true, env = "IIRS_MIN_SCORE")]
+    pub min_score: Option<f64>,
+
+    /// Replace the IRs of any window with more than this many IRs by a summary line
+    #[arg(long, env = "IIRS_MAX_HITS_PER_WINDOW")]
+    pub max_hits_per_window: Option<usize>,
+
+    /// Size of the windows (by IR start) used by --max-hits-per-window
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..), env = "IIRS_HITS_WINDOW")]
+    pub hits_window: u64,
+
+    /// Discard IRs with an arm whose DUST score is above this (a homopolymer arm of length L scores (L - 2) / 2)
+    #[arg(long, env = "IIRS_MAX_DUST")]
+    pub max_dust: Option<f64>,
+
+    /// Discard IRs with an arm whose longest single-base run covers more than this fraction (0 to 1) of the arm
+    #[arg(long, env = "IIRS_MAX_HOMOPOLYMER")]
+    pub max_homopolymer: Option<f64>,
+
+    /// Discard IRs whose whole span (gap included) has more than this fraction (0 to 1) of N or other ambiguity codes
+    #[arg(long, env = "IIRS_MAX_N_FRACTION")]
+    pub max_n_fraction: Option<f64>,
+
+    /// BED file of regions to mask (e.g. tandem repeats from TRF or ULTRA).
+    /// IRs with an arm overlapping them are discarded
+    #[arg(long, env = "IIRS_MASK_BED")]
+    pub mask_bed: Option<String>,
+
+    /// Mask the positions of a FASTQ input with a Phred quality below this (e.g. 20): IRs with
+    /// an arm overlapping them are discarded
+    #[arg(long, conflicts_with = "seq", env = "IIRS_MIN_QUALITY")]
+    pub min_quality: Option<u8>,
+
+    /// Rotate the (circular) sequences so that this 1-based position becomes position 1, as
+    /// when setting the origin of a plasmid map. Regions, features and --anchor are given in the
+    /// original numbering. IRs spanning the new origin are not found, unless --circular
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), env = "IIRS_ORIGIN_OFFSET")]
+    pub origin_offset: Option<u64>,
+
+    /// Only report the IRs centered at (or half a position around) this 1-based position
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), env = "IIRS_ANCHOR")]
+    pub anchor: Option<u64>,
+
+    /// BED file of regions where the left arm must lie (requires --right-arm-bed),
+    /// e.g. to find IRs linking two loci suspected of mediating an inversion
+    #[arg(long, requires = "right_arm_bed", env = "IIRS_LEFT_ARM_BED")]
+    pub left_arm_bed: Option<String>,
+
+    /// BED file of regions where the right arm must lie (requires --left-arm-bed)
+    #[arg(long, requires = "left_arm_bed", env = "IIRS_RIGHT_ARM_BED")]
+    pub right_arm_bed: Option<String>,
+
+    /// BED or GFF file of features (origins, IS elements, genes...): the custom output format
+    /// gets the nearest feature of every IR and the signed distance to it
+    #[arg(long, env = "IIRS_ANNOTATE")]
+    pub annotate: Option<String>,
+
+    /// Named BED (or GFF) file of intervals: the custom output format gets the names of the
+    /// intervals overlapping every IR
+    #[arg(long, env = "IIRS_LABEL_WITH")]
+    pub label_with: Option<String>,
+
+    /// Group the IRs by gap (loop) length, given the largest gap of every group but the last
+    /// (e.g. 0,3,10 gives the groups 0, 1-3, 4-10 and 11+)
+    #[arg(long, value_delimiter = ',', env = "IIRS_GAP_STRATA")]
+    pub gap_strata: Option<Vec<usize>>,
+
+    /// Report the gap groups as a column of the custom output format, or as one output
+    /// file per group (named after the output file and the group, e.g. IUPACpal.out.gap1-3)
+    #[arg(
+        long,
+        default_value_t,
+        value_enum,
+        requires = "gap_strata",
+        env = "IIRS_STRATA_OUTPUT"
+    )]
+    pub strata_output: StrataOutput,
+
+    /// Run several searches over the same index, given as MIN_LEN:MAX_LEN:MAX_GAP:MISMATCHES
+    /// sets (e.g. 10:100:50:0,12:100:50:2) replacing the bounds above. The IRs of every set are
+    /// written to their own file (named after the output file and the set, e.g. IUPACpal.out.m10_M100_g50_x0)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        conflicts_with = "strata_output",
+        env = "IIRS_PARAM_SETS"
+    )]
+    pub param_sets: Option<Vec<ParamSet>>,
+
+    /// Order of the IRs in the output
+    #[arg(long, default_value_t, value_enum, env = "IIRS_SORT_BY")]
+    pub sort_by: SortBy,
+
+    /// Na+ concentration (mM) for the hairpin melting temperature
+    #[arg(long, default_value_t = 50.0, env = "IIRS_NA")]
+    pub na: f64,
+
+    /// Mg2+ concentration (mM) for the hairpin melting temperature
+    #[arg(long, default_value_t = 0.0, env = "IIRS_MG")]
+    pub mg: f64,
+
+    /// Temperature (Celsius) for the hairpin free energy
+    #[arg(
+        long,
+        default_value_t = 37.0,
+        allow_hyphen_values = true,
+        env = "IIRS_TEMPERATURE"
+    )]
+    pub temperature: f64,
+
+    /// Discard IRs whose hairpin melts below this temperature (Celsius)
+    #[arg(long, allow_hyphen_values = true, env = "IIRS_MIN_TM")]
+    pub min_tm: Option<f64>,
+
+    /// Discard IRs whose hairpin melts above this temperature (Celsius)
+    #[arg(long, allow_hyphen_values = true, env = "IIRS_MAX_TM")]
+    pub max_tm: Option<f64>,
+
+    /// Discard IRs with fewer mismatching pairs than this, e.g. 1 to keep the imperfect ones
+    #[arg(long, env = "IIRS_MIN_MISMATCHES_USED")]
+    pub min_mismatches_used: Option<usize>,
+
+    /// Discard IRs with more mismatching pairs than this, e.g. 0 to keep the perfect IRs of a
+    /// search allowing mismatches
+    #[arg(long, env = "IIRS_MAX_MISMATCHES_USED")]
+    pub max_mismatches_used: Option<usize>,
+
+    /// WebAssembly module (.wasm or .wat) exporting iirs_accept and/or iirs_score, which
+    /// decides which of the IRs found are kept (requires the `wasm` feature)
+    #[arg(long, env = "IIRS_PLUGIN")]
+    pub plugin: Option<String>,
+
+    /// Discard IRs to which the iirs_score function of --plugin gives less than this
+    #[arg(
+        long,
+        requires = "plugin",
+        allow_hyphen_values = true,
+        default_value_t = 0.0,
+        env = "IIRS_PLUGIN_MIN_SCORE"
+    )]
+    pub plugin_min_score: f64,
+
+    /// Superhelical density of the sequence, for the extrusion score scheme
+    #[arg(long, default_value_t = -0.06, allow_hyphen_values = true, env = "IIRS_SUPERHELICAL_DENSITY")]
+    pub superhelical_density: f64,
+
+    /// Length (bp) of the topological domain, for the extrusion score scheme
+    /// (the length of the sequence by default)
+    #[arg(long, env = "IIRS_DOMAIN_LEN")]
+    pub domain_len: Option<usize>,
+
     /// Output filename.
     /// For multiple sequences this is treated as a folder.
-    #[arg(long, short, default_value_t = String::from(DEFAULT_OUTPUT_FILE))]
+    #[arg(long, short, default_value_t = String::from(DEFAULT_OUTPUT_FILE), env = "IIRS_OUTPUT_FILE")]
     pub output_file: String,
 
     /// Output format
-    #[arg(long, short = 'F', default_value_t, value_enum)]
+    #[arg(
+        long,
+        short = 'F',
+        default_value_t,
+        value_enum,
+        env = "IIRS_OUTPUT_FORMAT"
+    )]
     pub output_format: OutputFormat,
 
+    /// With the bed and gff3 output formats, write one record per arm (linked by the name of
+    /// the IR) instead of one record per IR
+    #[arg(long, default_value_t = false, env = "IIRS_SPLIT_ARMS")]
+    pub split_arms: bool,
+
+    /// With the custom output format, add a column telling whether every IR is maximal (false
+    /// when its arms were clipped by --max-len)
+    #[arg(long, default_value_t = false, env = "IIRS_REPORT_MAXIMAL")]
+    pub report_maximal: bool,
+
+    /// With the custom output format, add a column with the number of mismatching pairs of
+    /// every IR
+    #[arg(long, default_value_t = false, env = "IIRS_REPORT_MISMATCHES")]
+    pub report_mismatches: bool,
+
+    /// With the features output format, also write the feature vectors as a NumPy float64
+    /// matrix, to the output file followed by .npy
+    #[arg(long, default_value_t = false, env = "IIRS_NPY")]
+    pub npy: bool,
+
+    /// With the custom output format, add UCSC, Ensembl and IGV locus URLs of every IR in this
+    /// assembly (e.g. hg38 or GRCh38), named after the sequences
+    #[arg(long, env = "IIRS_BROWSER_LINKS")]
+    pub browser_links: Option<String>,
+
+    /// With the gff3 output format, add the arms and the loop of every IR as child features
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "split_arms",
+        env = "IIRS_GFF_CHILDREN"
+    )]
+    pub gff_children: bool,
+
+    /// Soft time limit in seconds. When reached, the search stops at the next window of
+    /// centers and the IRs found so far are written, followed by a truncation footer.
+    #[arg(long, env = "IIRS_MAX_TIME")]
+    pub max_time: Option<u64>,
+
+    /// Maximum number of IRs per sequence. Protects against writing huge outputs
+    /// with too permissive parameters.
+    #[arg(long, env = "IIRS_MAX_HITS")]
+    pub max_hits: Option<usize>,
+
+    /// What to do when more than --max-hits IRs are found: stop the search, or
+    /// write the first --max-hits IRs and only count the rest.
+    #[arg(long, default_value_t, value_enum, env = "IIRS_ON_MAX_HITS")]
+    pub on_max_hits: MaxHitsAction,
+
     /// Quiet flag: Suppresses non-essential output when enabled.
-    #[arg(long, short, default_value_t = false)]
+    #[arg(long, short, default_value_t = false, env = "IIRS_QUIET")]
     pub quiet: bool,
+
+    /// Print performance counters (centers, LCE queries, ...) to stderr at the end of the run
+    #[arg(long, default_value_t = false, env = "IIRS_STATS")]
+    pub stats: bool,
+
+    /// Print the throughput of the run (Mbp/s per phase, hits/s, bytes written/s, peak RSS per
+    /// Gbp) to stderr at the end of the run, as a line of JSON
+    #[arg(long, default_value_t = false, env = "IIRS_PERF_REPORT")]
+    pub perf_report: bool,
+
+    /// Report the progress of the search on stderr every second
+    #[arg(long, value_enum, env = "IIRS_PROGRESS")]
+    pub progress: Option<ProgressFormat>,
+
+    /// Plasmid QC preset for AAV/lentiviral constructs: report whether both ITRs are found
+    /// as an IR (arms of at least --min-len, at most --mismatches, cassette of at most --max-gap)
+    /// and list unexpected IRs inside the cassette
+    #[arg(long, default_value_t = false, env = "IIRS_ITR_QC")]
+    pub itr_qc: bool,
+
+    /// Minimum arm length of the unexpected IRs inside the cassette (--itr-qc)
+    #[arg(
+        long,
+        default_value_t = 20,
+        requires = "itr_qc",
+        env = "IIRS_INTERNAL_MIN_LEN"
+    )]
+    pub internal_min_len: usize,
+
+    /// Scan the reads of a SAM/BAM/CRAM file instead of the sequences of --input-file
+    /// (requires the `bam` feature)
+    #[arg(long, env = "IIRS_READS")]
+    pub reads: Option<String>,
+
+    /// Indexed FASTA reference, needed to decode CRAM files
+    #[arg(long, requires = "reads", env = "IIRS_REFERENCE")]
+    pub reference: Option<String>,
+
+    /// Pin one worker thread to every CPU, node by node, so that the workers keep their IRs in
+    /// the memory of their NUMA node (requires the `numa` feature). With --chunk-size, every
+    /// chunk is also indexed on the node of the worker that searches it
+    #[arg(long, default_value_t = false, env = "IIRS_NUMA")]
+    pub numa: bool,
+
+    /// Number of worker threads, 0 for one per CPU (requires the `parallel` feature). Above
+    /// 1, that many sequences of the input file are searched at the same time, and the output
+    /// is still written in the order of the sequences
+    #[arg(
+        long,
+        default_value_t = 0,
+        conflicts_with = "numa",
+        env = "IIRS_THREADS"
+    )]
+    pub threads: usize,
+
+    /// Back the suffix array structures of the index with transparent huge pages (Linux), which
+    /// cuts the TLB misses that dominate the search of sequences of a gigabase or more
+    #[arg(long, default_value_t = false, env = "IIRS_HUGE_PAGES")]
+    pub huge_pages: bool,
+
+    /// Range minimum query structure of the index: `blocks` takes several times less memory
+    /// than the default sparse table on long sequences, for slightly slower queries
+    #[arg(long, default_value_t, value_enum, env = "IIRS_RMQ")]
+    pub rmq: RmqBackend,
+
+    /// Suffix array construction of the index: `sais` (requires the `sais` feature) runs in
+    /// linear time, which pays off on large genomes searched with a small --max-gap
+    #[arg(long, default_value_t, value_enum, env = "IIRS_SA")]
+    pub sa: SaBackend,
+
+    /// Heuristic seed-and-extend search: only extend the arms from pairs of exact K-mers (at
+    /// most 32), much faster on surveys of many genomes, but short or low-identity IRs may be
+    /// missed
+    #[arg(long, env = "IIRS_SEED_LEN")]
+    pub seed_len: Option<usize>,
+
+    /// Rule out most centers with an exact k-mer prefilter before extending the arms, which
+    /// speeds up searches of long arms (--min-len of about 12 or more)
+    #[arg(long, default_value_t = false, env = "IIRS_PREFILTER")]
+    pub prefilter: bool,
+
+    /// Memory budget of every search, in bytes or with a K, M, G or T suffix (e.g. 16G): above
+    /// it, the search falls back to --rmq blocks, then to smaller and smaller chunks, instead
+    /// of being killed for running out of memory
+    #[arg(long, value_parser = parse_memory, env = "IIRS_MAX_MEMORY")]
+    pub max_memory: Option<usize>,
+
+    /// The sequences are circular (plasmids, organellar genomes): also find the IRs spanning
+    /// the origin. Their start is taken modulo the length of the sequence, but their end is
+    /// not: it is reported past the length (e.g. 5010 for position 10 of a 5000 bp plasmid)
+    #[arg(long, default_value_t = false, env = "IIRS_CIRCULAR")]
+    pub circular: bool,
+
+    /// Search every sequence in overlapping chunks of this many positions, on several threads
+    /// (with the `parallel` feature), to use every core on a single long chromosome. Must be at
+    /// least 2 * --max-len + --max-gap
+    #[arg(long, env = "IIRS_CHUNK_SIZE")]
+    pub chunk_size: Option<usize>,
+
+    /// Read and normalize the next sequences of the input file while the current one is
+    /// searched, to hide the latency of slow (e.g. network) filesystems
+    #[arg(long, default_value_t = false, conflicts_with_all = ["itr_qc", "param_sets"], env = "IIRS_PIPELINE")]
+    pub pipeline: bool,
+
+    /// Seed of every random process (e.g. the shuffle subcommand): the same seed gives the
+    /// same output on every machine
+    #[arg(long, global = true, default_value_t = 0, env = "IIRS_SEED")]
+    pub seed: u64,
+
+    /// Write a JSON manifest of the output files (hit counts, parameters and CRC-32 checksums):
+    /// manifest.json in the output folder for multiple sequences, or the output file
+    /// followed by .manifest.json
+    #[arg(long, default_value_t = false, env = "IIRS_MANIFEST")]
+    pub manifest: bool,
+
+    /// Spill the IRs waiting to be written to temporary files when there are more than this
+    /// (e.g. with a huge --max-gap), instead of running out of memory
+    #[arg(long, env = "IIRS_SPILL_THRESHOLD")]
+    pub spill_threshold: Option<usize>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands, run instead of a search: operations on result files (`csv` or `custom`
+/// output format), or masking or shuffling of the input sequences.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// IRs of A matching an IR of B
+    Intersect(SetOperationArgs),
+    /// IRs of A matching no IR of B
+    Subtract(SetOperationArgs),
+    /// IRs of A, and the IRs of B (and of the further files) matching no IR of the previous
+    /// files, e.g. to join the shards of a search
+    Merge(SetOperationArgs),
+    /// Write the input sequences with the IRs found (with the search arguments) masked
+    Mask(MaskArgs),
+    /// Write shuffled copies of the input sequences (background models)
+    Shuffle(ShuffleArgs),
+    /// Compare the IRs found (with the search arguments) in every window of the input
+    /// sequences to shuffled copies, to locate hotspots
+    Enrichment(EnrichmentArgs),
+    /// Chain the IRs of a result file of the input sequence into higher-order structures
+    Chain(ChainArgs),
+    /// Convert a result file of the input sequence between a text output format (`csv` or
+    /// `custom` to binary, --output-format from binary) and the compact binary format
+    Convert(ConvertArgs),
+    /// IRs gained or lost in an alternate assembly of the input sequences, matched by their
+    /// local context
+    Compare(CompareArgs),
+}
+
+impl Command {
+    /// The set operation of the subcommand, if it is one.
+    pub const fn operation(&self) -> Option<(SetOperation, &SetOperationArgs)> {
+        match self {
+            Self::Intersect(args) => Some((SetOperation::Intersect, args)),
+            Self::Subtract(args) => Some((SetOperation::Subtract, args)),
+            Self::Merge(args) => Some((SetOperation::Merge, args)),
+            Self::Mask(_)
+            | Self::Shuffle(_)
+            | Self::Enrichment(_)
+            | Self::Chain(_)
+            | Self::Convert(_)
+            | Self::Compare(_) => None,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct SetOperationArgs {
+    /// First result file
+    pub a: String,
+
+    /// Second result file, with the same output format
+    pub b: String,
+
+    /// Further result files, with the same output format: the operation is applied to the
+    /// result of the previous ones and each of them in turn
+    pub more: Vec<String>,
+
+    /// Two IRs match if the ends of their arms are at most this many positions apart
+    #[arg(long, short, default_value_t = 0, env = "IIRS_TOLERANCE")]
+    pub tolerance: usize,
+
+    /// Output filename (standard output by default)
+    #[arg(long, short, env = "IIRS_OUTPUT_FILE")]
+    pub output_file: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct MaskArgs {
+    /// Soft masking (lowercase) or hard masking (N)
+    #[arg(long, short, default_value_t, value_enum, env = "IIRS_MODE")]
+    pub mode: MaskMode,
+
+    /// Output FASTA filename (standard output by default)
+    #[arg(long, short, env = "IIRS_OUTPUT_FILE")]
+    pub output_file: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ShuffleArgs {
+    /// Preserve the base (mono) or dinucleotide (di) composition
+    #[arg(long, short, default_value_t, value_enum, env = "IIRS_MODE")]
+    pub mode: ShuffleMode,
+
+    /// Number of shuffled copies of every sequence
+    #[arg(long, short, default_value_t = 1, env = "IIRS_COPIES")]
+    pub copies: usize,
+
+    /// Output FASTA filename (standard output by default)
+    #[arg(long, short, env = "IIRS_OUTPUT_FILE")]
+    pub output_file: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct EnrichmentArgs {
+    /// Size of the windows (by IR start)
+    #[arg(long, short, default_value_t = 10000, env = "IIRS_WINDOW")]
+    pub window: usize,
+
+    /// Preserve the base (mono) or dinucleotide (di) composition in the shuffled copies
+    #[arg(long, short, default_value_t, value_enum, env = "IIRS_MODE")]
+    pub mode: ShuffleMode,
+
+    /// Number of shuffled copies of every sequence
+    #[arg(long, short, default_value_t = 20, env = "IIRS_COPIES")]
+    pub copies: usize,
+
+    /// Output filename (standard output by default)
+    #[arg(long, short, env = "IIRS_OUTPUT_FILE")]
+    pub output_file: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ChainArgs {
+    /// Result file (`csv` or `custom` output format) of the input sequence
+    pub results: String,
+
+    /// Largest distance between the end of an IR and the start of the next one in a chain
+    #[arg(long, short = 'd', default_value_t = 100, env = "IIRS_MAX_DISTANCE")]
+    pub max_distance: usize,
+
+    /// Output filename (standard output by default)
+    #[arg(long, short, env = "IIRS_OUTPUT_FILE")]
+    pub output_file: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ConvertArgs {
+    /// Result file: binary, or in the `csv` or `custom` output format
+    pub results: String,
+
+    /// Output filename (standard output by default)
+    #[arg(long, short, env = "IIRS_OUTPUT_FILE")]
+    pub output_file: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// Alternate assembly (FASTA, GenBank or EMBL), with sequences named as the input ones
+    pub alternate: String,
+
+    /// Bases on each side of an IR that must also be identical for it to be the same IR in
+    /// both assemblies
+    #[arg(long, short, default_value_t = 20, env = "IIRS_CONTEXT")]
+    pub context: usize,
+
+    /// Output filename (standard output by default)
+    #[arg(long, short, env = "IIRS_OUTPUT_FILE")]
+    pub output_file: Option<String>,
+}
+
+impl CompareArgs {
+    /// Sequences of `seq_names` (every sequence for `ALL_SEQUENCES`) in the alternate assembly,
+    /// by name.
+    pub fn alternate_records(&self, seq_names: &[String]) -> Result<HashMap<String, OwnedRecord>> {
+        let mut records = HashMap::new();
+        for record in safe_extract_records(&self.alternate, seq_names)? {
+            records.insert(String::from(record.id()?), record);
+        }
+        Ok(records)
+    }
 }
 
 impl Cli {
     pub fn parse_args() -> Self {
-        Self::parse()
+        let matches = Self::command().get_matches();
+        let mut cli = Self::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+        if cli.all_sequences {
+            cli.seq_names = vec![ALL_SEQUENCES.to_string()];
+        }
+        if let Some(profile) = cli.profile {
+            cli.apply_preset(&profile.preset(), |id| {
+                matches.value_source(id) == Some(ValueSource::DefaultValue)
+            });
+        }
+        cli
+    }
+
+    /// Replace the arguments left to their default (as told by `is_default`, given the id of
+    /// the argument) and the unset filters by those of the preset.
+    pub fn apply_preset(&mut self, preset: &ProfilePreset, is_default: impl Fn(&str) -> bool) {
+        if is_default("min_len") {
+            self.min_len = preset.min_len;
+        }
+        if is_default("max_len") {
+            self.max_len = preset.max_len;
+        }
+        if is_default("max_gap") {
+            self.max_gap = preset.max_gap;
+        }
+        if is_default("mismatches") {
+            self.mismatches = preset.mismatches;
+            self.mismatch_rate = self.mismatch_rate.or(preset.mismatch_rate);
+        }
+        self.max_homopolymer = self.max_homopolymer.or(preset.max_homopolymer);
+        self.max_n_fraction = self.max_n_fraction.or(preset.max_n_fraction);
+    }
+
+    /// Search parameters shared by every sequence (without the per-sequence BED regions).
+    pub fn search_params(&self) -> Result<SearchParams> {
+        let mut params = match self.mismatch_rate {
+            Some(rate) => {
+                SearchParams::with_mismatch_rate(self.min_len, self.max_len, self.max_gap, rate)?
+            }
+            None => SearchParams::with_mode(
+                self.min_len,
+                self.max_len,
+                self.max_gap,
+                self.mismatches,
+                self.symmetry_mode,
+            )?,
+        };
+        params.symmetry_mode = self.symmetry_mode;
+        params.anchor = self.anchor.map(|pos| pos as usize - 1);
+        params.bisulfite = self.bisulfite;
+        params.rna = self.rna;
+        params.wobble = self.wobble;
+        params.complement = self.complement.clone();
+        params.scoring = Scoring {
+            match_score: self.match_score,
+            mismatch_score: self.mismatch_score,
+        };
+        params.score_scheme = self.score_scheme;
+        params.min_score = self.min_score;
+        params.max_dust = self.max_dust;
+        params.max_homopolymer = self.max_homopolymer;
+        params.max_n_fraction = self.max_n_fraction;
+        params.thermo = ThermoConditions {
+            na_mm: self.na,
+            mg_mm: self.mg,
+            temperature_c: self.temperature,
+        };
+        params.min_tm = self.min_tm;
+        params.max_tm = self.max_tm;
+        params.min_mismatches_used = self.min_mismatches_used;
+        params.max_mismatches_used = self.max_mismatches_used;
+        params.supercoiling = Supercoiling {
+            density: self.superhelical_density,
+            domain_len: self.domain_len,
+        };
+        params.huge_pages = self.huge_pages;
+        params.rmq = self.rmq;
+        params.sa = self.sa;
+        params.seed_len = self.seed_len;
+        params.prefilter = self.prefilter;
+        params.max_memory = self.max_memory;
+        params.circular = self.circular;
+        params.chunk_len = self.chunk_size;
+        params.threads = self.threads;
+        self.load_plugin(&mut params)?;
+        Ok(params)
+    }
+
+    #[cfg(feature = "wasm")]
+    fn load_plugin(&self, params: &mut SearchParams) -> Result<()> {
+        if let Some(path) = &self.plugin {
+            params.plugin = Some(Arc::new(WasmPlugin::load(path, self.plugin_min_score)?));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    fn load_plugin(&self, _params: &mut SearchParams) -> Result<()> {
+        if self.plugin.is_some() {
+            bail!("--plugin requires iirs to be built with the `wasm` feature.");
+        }
+        Ok(())
     }
 
     /// Return a vector of pairs `(Config, OwnedRecord)` from the CLI arguments.
@@ -68,39 +744,183 @@ impl Cli {
     /// The `Config` is different for every sequence since it contains the sequence name (id)
     /// and the output file. The `SearchParams` do not change.
     pub fn try_from_args(&self, check_bounds: bool) -> Result<Vec<(Config, OwnedRecord)>> {
-        let params = SearchParams::with_mode(self.min_len, self.max_len, self.max_gap, self.mismatches, self.symmetry_mode.clone())?;
-        let records = safe_extract_records(&self.input_file, &self.seq_names)?;
+        let inputs = self.shared_inputs()?;
+        let records = match self.inline_record() {
+            Some(record) => vec![record],
+            None => safe_extract_records(&self.input_file, &self.seq_names)?,
+        };
         let only_one_sequence_found = records.len() == 1;
         let mut config_record_pairs = Vec::new();
 
-        for record in records {
-            // I don't really like this leak hack to preserve the references
-            // but the alternative of making everything a String is even worse.
-
-            // IUPACpal convention is to always use IUPACpal.out no matter the sequence name.
-            // In order to ease the validity checks, we keep that convention if the input consists
-            // of only one sequence. Otherwise we preface the output_file with the sequence name.
-            let seq_name = String::from(record.id()?);
-            let this_output_file: Box<str> = if only_one_sequence_found {
-                self.output_file.clone().into()
-            } else {
-                format!("{}/{}", self.output_file, seq_name).into_boxed_str()
-            };
-
-            let config = Config {
-                input_file: &self.input_file,
-                seq_name: Box::leak(seq_name.into_boxed_str()),
-                params: params.clone(),
-                output_file: Box::leak(this_output_file),
-                output_format: self.output_format.clone(),
-            };
-
-            if check_bounds {
-                config.params.check_bounds(record.seq.len())?;
-            }
+        for mut record in records {
+            let config =
+                self.config_for(&inputs, &mut record, !only_one_sequence_found, check_bounds)?;
             config_record_pairs.push((config, record));
         }
 
         Ok(config_record_pairs)
     }
+
+    /// Record of the inline sequence of --seq (not sanitized), if given.
+    pub(crate) fn inline_record(&self) -> Option<OwnedRecord> {
+        let seq = self.seq.as_ref()?;
+        let name = match self.seq_names.first() {
+            Some(name) if name != ALL_SEQUENCES => name.as_str(),
+            _ => DEFAULT_SEQ_NAME,
+        };
+        Some(OwnedRecord {
+            head: name.as_bytes().to_vec(),
+            seq: seq.as_bytes().to_vec(),
+        })
+    }
+
+    /// Read the files shared by the configs of every sequence.
+    pub(crate) fn shared_inputs(&self) -> Result<SharedInputs> {
+        let mut masks = match &self.mask_bed {
+            Some(path) => read_bed(path)?,
+            None => HashMap::new(),
+        };
+        if let Some(min_quality) = self.min_quality {
+            for (name, low_quality) in read_quality_masks(&self.input_file, min_quality)? {
+                let seq_masks = masks.entry(name).or_default();
+                seq_masks.extend(low_quality);
+                merge(seq_masks);
+            }
+        }
+        let arm_regions = match (&self.left_arm_bed, &self.right_arm_bed) {
+            (Some(left_path), Some(right_path)) => {
+                Some((read_bed(left_path)?, read_bed(right_path)?))
+            }
+            _ => None,
+        };
+        let features = match &self.annotate {
+            Some(path) => Some(read_features(path)?),
+            None => None,
+        };
+        let labels = match &self.label_with {
+            Some(path) => Some(read_features(path)?),
+            None => None,
+        };
+        let gap_strata = match &self.gap_strata {
+            Some(bounds) => Some(GapStrata::new(bounds.clone(), self.strata_output)?),
+            None => None,
+        };
+        Ok(SharedInputs {
+            params: self.search_params()?,
+            masks,
+            arm_regions,
+            features,
+            labels,
+            gap_strata,
+        })
+    }
+
+    /// The `Config` of a (sanitized) record, which is rotated to --origin-offset. With
+    /// `sharded`, the output file is named after the sequence in the output folder.
+    pub(crate) fn config_for(
+        &self,
+        inputs: &SharedInputs,
+        record: &mut OwnedRecord,
+        sharded: bool,
+        check_bounds: bool,
+    ) -> Result<Config<'_>> {
+        // I don't really like this leak hack to preserve the references
+        // but the alternative of making everything a String is even worse.
+
+        // IUPACpal convention is to always use IUPACpal.out no matter the sequence name.
+        // In order to ease the validity checks, we keep that convention if the input consists
+        // of only one sequence. Otherwise we preface the output_file with the sequence name.
+        let seq_name = String::from(record.id()?);
+        let this_output_file: Box<str> = if sharded {
+            format!("{}/{}", self.output_file, seq_name).into_boxed_str()
+        } else {
+            self.output_file.clone().into()
+        };
+
+        let mut params = inputs.params.clone();
+        params.masked = inputs.masks.get(&seq_name).cloned().unwrap_or_default();
+        params.arm_regions = inputs
+            .arm_regions
+            .as_ref()
+            .map(|(left_regions, right_regions)| {
+                (
+                    left_regions.get(&seq_name).cloned().unwrap_or_default(),
+                    right_regions.get(&seq_name).cloned().unwrap_or_default(),
+                )
+            });
+
+        let mut seq_features = inputs
+            .features
+            .as_ref()
+            .map(|features| features.get(&seq_name).cloned().unwrap_or_default());
+        let mut seq_labels = inputs
+            .labels
+            .as_ref()
+            .map(|labels| labels.get(&seq_name).cloned().unwrap_or_default());
+
+        // Rotate the sequence, and everything positioned on it, to the new origin.
+        if let Some(offset) = self.origin_offset {
+            let (origin, len) = (offset as usize - 1, record.seq.len());
+            if origin >= len {
+                bail!(
+                    "--origin-offset {} is past the end of {} ({} bp).",
+                    offset,
+                    seq_name,
+                    len
+                );
+            }
+            record.seq.rotate_left(origin);
+            params.masked = rotate_intervals(&params.masked, origin, len);
+            params.arm_regions = params.arm_regions.map(|(left_regions, right_regions)| {
+                (
+                    rotate_intervals(&left_regions, origin, len),
+                    rotate_intervals(&right_regions, origin, len),
+                )
+            });
+            params.anchor = params.anchor.map(|anchor| (anchor + len - origin) % len);
+            seq_features = seq_features.map(|features| features.rotate(origin, len));
+            seq_labels = seq_labels.map(|labels| labels.rotate(origin, len));
+        }
+
+        let config = Config {
+            input_file: if self.seq.is_some() {
+                INLINE_INPUT
+            } else {
+                &self.input_file
+            },
+            seq_name: Box::leak(seq_name.into_boxed_str()),
+            params,
+            output_file: Box::leak(this_output_file),
+            output_format: self.output_format.clone(),
+            region_cap: self.max_hits_per_window.map(|max_hits| RegionCap {
+                window: self.hits_window as usize,
+                max_hits,
+            }),
+            sort_by: self.sort_by,
+            features: seq_features,
+            labels: seq_labels,
+            gap_strata: inputs.gap_strata.clone(),
+            spill_threshold: self.spill_threshold,
+            split_arms: self.split_arms,
+            gff_children: self.gff_children,
+            report_maximal: self.report_maximal,
+            report_mismatches: self.report_mismatches,
+            assembly: self.browser_links.as_deref(),
+        };
+
+        if check_bounds {
+            config.params.check_bounds(record.seq.len())?;
+        }
+        Ok(config)
+    }
+}
+
+/// Search parameters and files shared by the configs of every sequence.
+pub(crate) struct SharedInputs {
+    params: SearchParams,
+    masks: HashMap<String, Intervals>,
+    arm_regions: Option<(HashMap<String, Intervals>, HashMap<String, Intervals>)>,
+    features: Option<HashMap<String, Features>>,
+    labels: Option<HashMap<String, Features>>,
+    gap_strata: Option<GapStrata>,
 }
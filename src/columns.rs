@@ -0,0 +1,147 @@
+//! Columnar (struct-of-arrays) representation of a set of IRs.
+//!
+//! Whole-genome statistics usually look at one field at a time (e.g. the distribution
+//! of gaps), which is much more cache-friendly with one vector per field.
+use anyhow::Result;
+
+use crate::IrSpan;
+use crate::complement::complement_array;
+use crate::config::SearchParams;
+use crate::matrix::MatchMatrix;
+use crate::score::count_mismatches;
+use crate::utils::sanitize_sequence;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IrColumns {
+    /// 0-based start of the left arm.
+    pub starts: Vec<usize>,
+    /// 0-based end (inclusive) of the right arm.
+    pub ends: Vec<usize>,
+    pub gaps: Vec<usize>,
+    /// Number of mismatching pairs between the two arms.
+    pub mismatches: Vec<usize>,
+}
+
+impl IrColumns {
+    /// Build the columns from the output of [`find_irs`](crate::find_irs) on `seq`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use iirs::{IrColumns, IrMatch, SearchParams, find_irs};
+    ///
+    /// let seq = "acbbgt".as_bytes();
+    /// let params = SearchParams::new(3, 6, 2, 1).unwrap();
+    /// let irs: Vec<_> = find_irs(&params, &seq).unwrap().iter().map(IrMatch::span).collect();
+    /// let columns = IrColumns::new(&params, &irs, &seq).unwrap();
+    /// assert_eq!(columns.len(), irs.len());
+    /// assert!(columns.iter().eq(irs.iter().copied()));
+    /// ```
+    pub fn new(params: &SearchParams, irs: &[IrSpan], seq: &[u8]) -> Result<Self> {
+        let seq = sanitize_sequence(seq)?;
+        let matrix = MatchMatrix::from_params(params);
+        let complement = complement_array(params);
+
+        let mut columns = Self {
+            starts: Vec::with_capacity(irs.len()),
+            ends: Vec::with_capacity(irs.len()),
+            gaps: Vec::with_capacity(irs.len()),
+            mismatches: Vec::with_capacity(irs.len()),
+        };
+        for ir in irs {
+            let &(left, right, gap) = ir;
+            columns.starts.push(left);
+            columns.ends.push(right);
+            columns.gaps.push(gap);
+            columns.mismatches.push(count_mismatches(
+                ir,
+                &seq,
+                &params.symmetry_mode,
+                &matrix,
+                &complement,
+            ));
+        }
+
+        Ok(columns)
+    }
+
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// Iterate over the IRs as (start, end, gap) spans, as returned by `IrMatch::span`.
+    pub fn iter(&self) -> impl Iterator<Item = IrSpan> + '_ {
+        self.starts
+            .iter()
+            .zip(&self.ends)
+            .zip(&self.gaps)
+            .map(|((&start, &end), &gap)| (start, end, gap))
+    }
+
+    /// Length of each arm.
+    pub fn arm_lens(&self) -> impl Iterator<Item = usize> + '_ {
+        self.spans()
+            .zip(&self.gaps)
+            .map(|(span, gap)| (span - gap) / 2)
+    }
+
+    /// 1-based center of each IR (see [`ir_center`](crate::ir_center)).
+    pub fn centers(&self) -> impl Iterator<Item = f64> + '_ {
+        self.iter().map(|ir| crate::ir_center(&ir))
+    }
+
+    /// Total length of each IR (both arms and the gap).
+    pub fn spans(&self) -> impl Iterator<Item = usize> + '_ {
+        self.starts
+            .iter()
+            .zip(&self.ends)
+            .map(|(start, end)| end + 1 - start)
+    }
+
+    /// Convert into a polars `DataFrame` with the columns
+    /// `start`, `end`, `gap`, `arm_len` and `mismatches`.
+    #[cfg(feature = "polars")]
+    pub fn to_dataframe(&self) -> polars::prelude::PolarsResult<polars::prelude::DataFrame> {
+        let to_u64 = |values: &[usize]| values.iter().map(|&v| v as u64).collect::<Vec<_>>();
+        let arm_lens: Vec<u64> = self.arm_lens().map(|v| v as u64).collect();
+
+        polars::df!(
+            "start" => to_u64(&self.starts),
+            "end" => to_u64(&self.ends),
+            "gap" => to_u64(&self.gaps),
+            "arm_len" => arm_lens,
+            "mismatches" => to_u64(&self.mismatches),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns() {
+        // The innermost pair (g, a) does not match
+        let seq = "acgagt".as_bytes();
+        let params = SearchParams::new(3, 6, 0, 1).unwrap();
+        let irs = vec![(0, 5, 0)];
+        let columns = IrColumns::new(&params, &irs, seq).unwrap();
+        assert_eq!(columns.mismatches, vec![1]);
+        assert_eq!(columns.arm_lens().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(columns.spans().collect::<Vec<_>>(), vec![6]);
+    }
+
+    #[cfg(feature = "polars")]
+    #[test]
+    fn test_to_dataframe() {
+        let seq = "acgagt".as_bytes();
+        let params = SearchParams::new(3, 6, 0, 1).unwrap();
+        let columns = IrColumns::new(&params, &[(0, 5, 0)], seq).unwrap();
+        let df = columns.to_dataframe().unwrap();
+        assert_eq!(df.shape(), (1, 5));
+    }
+}
@@ -0,0 +1,162 @@
+//! IRs gained or lost between two versions of a sequence: a reference and an alternate
+//! assembly, or a construct and its mutant.
+//!
+//! The IRs of both versions are matched by their local context (the IR and the bases around
+//! it) instead of their coordinates, so that the insertions and deletions elsewhere in the
+//! sequence, which shift every downstream coordinate, do not change the IRs around them.
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::config::SearchParams;
+use crate::utils::sanitize_sequence;
+use crate::{IrSpan, find_spans};
+
+/// Whether an IR only exists in the alternate or in the reference sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IrChange {
+    Gained,
+    Lost,
+}
+
+impl fmt::Display for IrChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Gained => "gained",
+            Self::Lost => "lost",
+        })
+    }
+}
+
+/// An IR gained (in the coordinates of the alternate sequence) or lost (in the coordinates of
+/// the reference sequence).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrDiff {
+    pub change: IrChange,
+    pub ir: IrSpan,
+}
+
+pub fn fmt_ir_diffs_header() -> String {
+    String::from("change,start_n,end_n,gap")
+}
+
+/// `change,start_n,end_n,gap`, with 1-based coordinates.
+impl fmt::Display for IrDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (left, right, gap) = self.ir;
+        write!(f, "{},{},{},{}", self.change, left + 1, right + 1, gap)
+    }
+}
+
+/// The IR and up to `context` bases on each side.
+fn local_context<'a>(ir: &IrSpan, seq: &'a [u8], context: usize) -> &'a [u8] {
+    let &(left, right, _) = ir;
+    &seq[left.saturating_sub(context)..(right + 1 + context).min(seq.len())]
+}
+
+/// IRs of `alternate` whose local context (the IR and `context` bases on each side) is not
+/// found around an IR of `reference` (gained), and conversely (lost). Sorted by change and
+/// position.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{IrChange, SearchParams, compare_irs};
+///
+/// let params = SearchParams::new(6, 10, 3, 0).unwrap();
+/// let reference = "aaaaaaccgatgcaacatcggaaaaaa".as_bytes();
+/// // An insertion before the IR only shifts it
+/// let alternate = "ttaaaaaaccgatgcaacatcggaaaaaa".as_bytes();
+/// assert!(compare_irs(&params, reference, alternate, 4).unwrap().is_empty());
+/// // A substitution in an arm breaks it
+/// let alternate = "aaaaaaccgatgcaacttcggaaaaaa".as_bytes();
+/// let diffs = compare_irs(&params, reference, alternate, 4).unwrap();
+/// assert_eq!(diffs.len(), 1);
+/// assert_eq!(diffs[0].change, IrChange::Lost);
+/// assert_eq!(diffs[0].ir, (6, 20, 3));
+/// ```
+pub fn compare_irs(
+    params: &SearchParams,
+    reference: &[u8],
+    alternate: &[u8],
+    context: usize,
+) -> Result<Vec<IrDiff>> {
+    let reference = sanitize_sequence(reference)?;
+    let alternate = sanitize_sequence(alternate)?;
+
+    // IRs of the alternate sequence by local context, in reverse order to pop the first one
+    let mut unmatched: HashMap<&[u8], Vec<IrSpan>> = HashMap::new();
+    let mut alternate_irs = find_spans(params, &alternate)?;
+    alternate_irs.sort_unstable();
+    for ir in alternate_irs.iter().rev() {
+        let key = local_context(ir, &alternate, context);
+        unmatched.entry(key).or_default().push(*ir);
+    }
+
+    let mut diffs = Vec::new();
+    let mut reference_irs = find_spans(params, &reference)?;
+    reference_irs.sort_unstable();
+    for ir in reference_irs {
+        let key = local_context(&ir, &reference, context);
+        if unmatched.get_mut(key).and_then(Vec::pop).is_none() {
+            diffs.push(IrDiff {
+                change: IrChange::Lost,
+                ir,
+            });
+        }
+    }
+    diffs.extend(unmatched.into_values().flatten().map(|ir| IrDiff {
+        change: IrChange::Gained,
+        ir,
+    }));
+    diffs.sort_unstable_by_key(|diff| (diff.change, diff.ir));
+    Ok(diffs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libtests::random_seq;
+
+    fn count(diffs: &[IrDiff], change: IrChange) -> usize {
+        diffs.iter().filter(|diff| diff.change == change).count()
+    }
+
+    #[test]
+    fn test_compare_irs() {
+        let seq = random_seq(3, 2000, b"acgt");
+        let params = SearchParams::new(5, 20, 10, 1).unwrap();
+        let irs = find_spans(&params, &seq).unwrap();
+        assert!(irs.len() > 5);
+
+        // Same sequence, and the same after an insertion at the start
+        assert!(compare_irs(&params, &seq, &seq, 10).unwrap().is_empty());
+        let shifted = [b"gattaca".as_slice(), &seq].concat();
+        assert!(compare_irs(&params, &seq, &shifted, 10).unwrap().is_empty());
+
+        // A perfect palindrome planted in the middle
+        let planted = [&seq[..1000], b"tcgatcgaatcgatcga".as_slice(), &seq[1000..]].concat();
+        let diffs = compare_irs(&params, &seq, &planted, 10).unwrap();
+        assert!(
+            diffs.iter().any(|diff| diff.change == IrChange::Gained
+                && diff.ir.0 >= 1000
+                && diff.ir.1 < 1017)
+        );
+        // Only the IRs around the insertion change
+        for diff in &diffs {
+            let (left, right, _) = diff.ir;
+            assert!(left <= 1017 + 10 && right + 10 >= 1000, "{diff}");
+        }
+
+        // And the other way around
+        let reverse = compare_irs(&params, &planted, &seq, 10).unwrap();
+        assert_eq!(
+            count(&reverse, IrChange::Lost),
+            count(&diffs, IrChange::Gained)
+        );
+        assert_eq!(
+            count(&reverse, IrChange::Gained),
+            count(&diffs, IrChange::Lost)
+        );
+    }
+}
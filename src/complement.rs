@@ -0,0 +1,118 @@
+//! Complement of the bases, which decides how the arms of an IR pair. DNA by default, RNA with
+//! `SearchParams::rna`, or a custom table (e.g. for reduced alphabets).
+use anyhow::{Result, anyhow};
+
+use crate::config::SearchParams;
+use crate::constants::{IUPAC_SYMBOLS, build_complement_array, build_rna_complement_array};
+
+/// Complement of every symbol of the sequences, for [`SearchParams::complement`].
+///
+/// The sequences are still made of IUPAC symbols, whose matches follow the IUPAC rules: the
+/// table only changes which symbol the other arm is compared with.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{ComplementTable, SearchParams, find_irs};
+///
+/// // A pairs with C, e.g. in a two-letter alphabet.
+/// let complement = ComplementTable::from_pairs(&[(b'a', b'c'), (b'c', b'a')]).unwrap();
+/// assert_eq!(complement.get(b'a'), b'c');
+/// assert_eq!(complement.get(b'g'), b'c');
+///
+/// let mut params = SearchParams::new(3, 6, 0, 0).unwrap();
+/// assert!(find_irs(&params, "aaaccc".as_bytes()).unwrap().is_empty());
+/// params.complement = Some(complement);
+/// assert_eq!(find_irs(&params, "aaaccc".as_bytes()).unwrap()[0].span(), (0, 5, 0));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComplementTable {
+    table: [u8; 128],
+}
+
+impl Default for ComplementTable {
+    fn default() -> Self {
+        Self::dna()
+    }
+}
+
+impl ComplementTable {
+    /// Complement of DNA and of the IUPAC ambiguity codes (U pairs with A).
+    pub fn dna() -> Self {
+        Self {
+            table: build_complement_array(),
+        }
+    }
+
+    /// Complement of RNA: A pairs with U.
+    pub fn rna() -> Self {
+        Self {
+            table: build_rna_complement_array(),
+        }
+    }
+
+    /// The DNA complement, with the symbol of every pair complemented into the other one.
+    /// Both must be IUPAC symbols (case-insensitive).
+    pub fn from_pairs(pairs: &[(u8, u8)]) -> Result<Self> {
+        let mut complement = Self::dna();
+        for &(symbol, other) in pairs {
+            let (symbol, other) = (symbol.to_ascii_lowercase(), other.to_ascii_lowercase());
+            for b in [symbol, other] {
+                if !IUPAC_SYMBOLS.contains(b as char) {
+                    return Err(anyhow!(
+                        "complement of '{}': only IUPAC symbols can be complemented.",
+                        b as char
+                    ));
+                }
+            }
+            complement.table[symbol as usize] = other;
+        }
+        Ok(complement)
+    }
+
+    /// Pairs given as `XY,...` (X is complemented into Y), e.g. `ac,ca`.
+    pub fn parse(s: &str) -> Result<Self> {
+        let pairs = s
+            .split(',')
+            .map(|pair| match pair.trim().as_bytes() {
+                &[symbol, other] => Ok((symbol, other)),
+                _ => Err(anyhow!(
+                    "invalid complement pair '{pair}': expected two symbols, e.g. 'ac'."
+                )),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_pairs(&pairs)
+    }
+
+    /// Complement of the (lowercase) symbol `b`.
+    pub fn get(&self, b: u8) -> u8 {
+        self.table[b as usize]
+    }
+
+    pub const fn as_array(&self) -> &[u8; 128] {
+        &self.table
+    }
+}
+
+/// Complement of the searches of `params`.
+pub fn complement_array(params: &SearchParams) -> [u8; 128] {
+    match &params.complement {
+        Some(complement) => *complement.as_array(),
+        None if params.rna => build_rna_complement_array(),
+        None => build_complement_array(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_complement() {
+        let complement = ComplementTable::parse("AC, ca").unwrap();
+        assert_eq!((complement.get(b'a'), complement.get(b'c')), (b'c', b'a'));
+        assert_eq!(complement.get(b't'), b'a');
+        assert!(ComplementTable::parse("acg").is_err());
+        assert!(ComplementTable::parse("a$").is_err());
+    }
+}
@@ -0,0 +1,79 @@
+//! Low-complexity measures of the arms of IRs.
+use crate::IrSpan;
+use crate::score::arm_len;
+
+/// The two arms of an IR, as they appear in `seq`.
+pub fn arms<'a>(ir: &IrSpan, seq: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+    let &(left, right, _) = ir;
+    let len = arm_len(ir);
+    (&seq[left..left + len], &seq[right + 1 - len..=right])
+}
+
+/// DUST score of a sequence: sum over its triplets of `c * (c - 1) / 2`, where `c` is the
+/// number of occurrences of the triplet, divided by the number of triplets minus one.
+///
+/// A random sequence scores close to 0, while a homopolymer of length `l` scores `(l - 2) / 2`.
+pub fn dust_score(seq: &[u8]) -> f64 {
+    if seq.len() < 4 {
+        return 0.0;
+    }
+    let mut counts = std::collections::HashMap::new();
+    for triplet in seq.windows(3) {
+        *counts.entry(triplet).or_insert(0usize) += 1;
+    }
+    let sum: usize = counts.values().map(|c| c * (c - 1) / 2).sum();
+    sum as f64 / (seq.len() - 3) as f64
+}
+
+/// Fraction of `seq` covered by its longest run of a single base.
+pub fn homopolymer_fraction(seq: &[u8]) -> f64 {
+    let longest = seq
+        .chunk_by(|a, b| a == b)
+        .map(<[u8]>::len)
+        .max()
+        .unwrap_or(0);
+    longest as f64 / seq.len().max(1) as f64
+}
+
+/// Fraction of `seq` made of N or other ambiguity codes (anything but a, c, g, t and u).
+pub fn ambiguous_fraction(seq: &[u8]) -> f64 {
+    let ambiguous = seq
+        .iter()
+        .filter(|base| !b"acgtu".contains(&base.to_ascii_lowercase()))
+        .count();
+    ambiguous as f64 / seq.len().max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dust_score() {
+        assert_eq!(dust_score(b"aaa"), 0.0);
+        assert_eq!(dust_score(b"aaaaaaaaaa"), 4.0);
+        assert_eq!(dust_score(b"acgtgcatta"), 0.0);
+        // Dinucleotide repeat: "ata" and "tat" appear 5 times each
+        assert_eq!(dust_score(b"atatatatatat"), 20.0 / 9.0);
+    }
+
+    #[test]
+    fn test_homopolymer_fraction() {
+        assert_eq!(homopolymer_fraction(b""), 0.0);
+        assert_eq!(homopolymer_fraction(b"aaaa"), 1.0);
+        assert_eq!(homopolymer_fraction(b"acaaaaag"), 5.0 / 8.0);
+    }
+
+    #[test]
+    fn test_ambiguous_fraction() {
+        assert_eq!(ambiguous_fraction(b""), 0.0);
+        assert_eq!(ambiguous_fraction(b"acgu"), 0.0);
+        assert_eq!(ambiguous_fraction(b"acnnnnrt"), 5.0 / 8.0);
+    }
+
+    #[test]
+    fn test_arms() {
+        let seq = b"acgnnnncgt";
+        assert_eq!(arms(&(0, 9, 4), seq), (&b"acg"[..], &b"cgt"[..]));
+    }
+}
@@ -1,11 +1,24 @@
 use anyhow::{Result, anyhow};
+use std::borrow::Cow;
+
+use crate::IrSpan;
+use crate::annotate::Features;
+use crate::bed::Intervals;
+use crate::complement::ComplementTable;
+use crate::memory::{self, MemoryEstimate};
+#[cfg(feature = "wasm")]
+use crate::plugin::WasmPlugin;
+use crate::score::{ScoreScheme, Scoring};
+use crate::thermo::{Supercoiling, ThermoConditions};
+#[cfg(feature = "wasm")]
+use std::sync::Arc;
 
 use crate::constants::{
     DEFAULT_INPUT_FILE, DEFAULT_MAX_GAP, DEFAULT_MAX_LEN, DEFAULT_MIN_LEN, DEFAULT_MISMATCHES,
     DEFAULT_OUTPUT_FILE, DEFAULT_SEQ_NAME,
 };
 
-#[derive(clap::ValueEnum, Debug, Default, Clone)]
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum SymmetryMode {
     #[default]
     Inverted, // TODO: Pon comentario
@@ -14,6 +27,40 @@ pub enum SymmetryMode {
     DirectComplementary,
 }
 
+impl SymmetryMode {
+    /// Name of the mode, as given on the command line.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Inverted => "inverted",
+            Self::InvertedComplementary => "inverted-complementary",
+            Self::Direct => "direct",
+            Self::DirectComplementary => "direct-complementary",
+        }
+    }
+}
+
+/// Range minimum query structure over the LCP array of the index.
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RmqBackend {
+    /// Sparse table over the whole LCP array: the fastest queries, but `log2(2n)` integers
+    /// per base of the doubled sequence, which dominates the memory on whole chromosomes.
+    #[default]
+    Sparse,
+    /// Sparse table over blocks of 32 positions and in-block bit masks: still constant time,
+    /// a bit slower, for several times less memory.
+    Blocks,
+}
+
+/// Suffix array construction of the index.
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SaBackend {
+    /// The `divsufsort` crate.
+    #[default]
+    Divsufsort,
+    /// Induced sorting (SA-IS), linear time on any sequence (requires the `sais` feature).
+    Sais,
+}
+
 #[derive(Debug, Clone)]
 pub struct SearchParams {
     pub min_len: usize,
@@ -21,6 +68,92 @@ pub struct SearchParams {
     pub max_gap: usize,
     pub mismatches: usize,
     pub symmetry_mode: SymmetryMode,
+    /// Bisulfite-aware matching: C/T (and G/A on the other strand) differences
+    /// are not counted as mismatches.
+    pub bisulfite: bool,
+    /// RNA sequences: A pairs with U in the index, which keeps the search of sequences with
+    /// U (transcripts) as fast as the one of DNA. U matches T either way.
+    pub rna: bool,
+    /// G:U wobble pairs are not counted as mismatches (RNA stems). Unlike the bisulfite mode,
+    /// A:C pairs still are.
+    pub wobble: bool,
+    /// Complement of the bases, instead of the one of DNA (or RNA, see `rna`), e.g. for
+    /// reduced alphabets.
+    pub complement: Option<ComplementTable>,
+    /// How IRs are scored.
+    pub score_scheme: ScoreScheme,
+    /// Weights of the `CustomWeights` score scheme (and of the einverted output format).
+    pub scoring: Scoring,
+    /// IRs scoring less than this are discarded.
+    pub min_score: Option<f64>,
+    /// IRs with an arm whose DUST score is above this are discarded (low-complexity filter).
+    pub max_dust: Option<f64>,
+    /// IRs with an arm whose longest single-base run covers more than this fraction
+    /// of the arm are discarded.
+    pub max_homopolymer: Option<f64>,
+    /// IRs whose whole span (gap included) has more than this fraction of N or other
+    /// ambiguity codes are discarded (e.g. IRs on assembly gaps).
+    pub max_n_fraction: Option<f64>,
+    /// Masked regions (0-based, half-open, sorted and non-overlapping), e.g. tandem repeats.
+    /// IRs with an arm overlapping them are discarded.
+    pub masked: Vec<(usize, usize)>,
+    /// IRs with more mismatches than this fraction of their arm length are discarded.
+    pub max_mismatch_rate: Option<f64>,
+    /// Only examine the IRs centered within half a position of this (0-based) position.
+    pub anchor: Option<usize>,
+    /// Only keep the IRs whose left arm lies inside the first set of regions and whose
+    /// right arm lies inside the second one (0-based, half-open, sorted and non-overlapping).
+    pub arm_regions: Option<(Intervals, Intervals)>,
+    /// Conditions of the hairpin melting temperature estimates.
+    pub thermo: ThermoConditions,
+    /// IRs whose hairpin melts below this temperature (Celsius) are discarded.
+    pub min_tm: Option<f64>,
+    /// IRs whose hairpin melts above this temperature (Celsius) are discarded.
+    pub max_tm: Option<f64>,
+    /// IRs with fewer mismatching pairs than this are discarded.
+    pub min_mismatches_used: Option<usize>,
+    /// IRs with more mismatching pairs than this are discarded, e.g. 0 to keep the perfect
+    /// IRs of a search allowing mismatches (whose arms extend through them).
+    pub max_mismatches_used: Option<usize>,
+    /// Supercoiling of the sequence, for the extrusion score scheme.
+    pub supercoiling: Supercoiling,
+    /// Back the arrays of the index with transparent huge pages (Linux), which cuts the TLB
+    /// misses of the search on long sequences. The sparse table of the `rmq` crate (the
+    /// default [`RmqBackend::Sparse`] without `index32`) and the divsufsort suffix array
+    /// allocate their own, with normal pages.
+    pub huge_pages: bool,
+    /// Range minimum query structure of the index.
+    pub rmq: RmqBackend,
+    /// Suffix array construction of the index.
+    pub sa: SaBackend,
+    /// Heuristic seed-and-extend search: only extend the arms from the centers of pairs of
+    /// exact k-mers of plain bases of this length (at most 32), e.g. for surveys of many
+    /// genomes. IRs whose arms have no such pair, short or with many mismatches or ambiguity
+    /// codes, may be missed.
+    pub seed_len: Option<usize>,
+    /// Exact k-mer prefilter: only extend the arms from the centers that may have an IR,
+    /// found by hashing k-mers. It pays off from a `min_len` of about 12, and is skipped when
+    /// the arms are too short or have too many mismatches to rule out most centers.
+    pub prefilter: bool,
+    /// Memory budget of the search, in bytes: when the estimate of the default structures
+    /// exceeds it (see [`SearchParams::estimate_memory`]), the search falls back to the blocks
+    /// RMQ, then to smaller and smaller chunks, instead of running out of memory.
+    pub max_memory: Option<usize>,
+    /// The sequence is circular (plasmid, organellar genome): the IRs spanning its origin
+    /// are found too. Their start is taken modulo the length `n`, and their end is past the
+    /// end of the sequence (at `end - n` on the circle).
+    pub circular: bool,
+    /// Search the sequence in overlapping chunks of this many positions, whose indexes are
+    /// built and searched on several threads (`parallel` feature), e.g. for a chromosome
+    /// (inverted symmetry modes only).
+    pub chunk_len: Option<usize>,
+    /// Worker threads of the search (`parallel` feature), e.g. to cap the CPU usage of a
+    /// server embedding iirs. 0 uses the current `rayon` pool (by default the global one,
+    /// with a thread per CPU).
+    pub threads: usize,
+    /// WebAssembly plugin deciding which IRs are kept, after the other filters.
+    #[cfg(feature = "wasm")]
+    pub plugin: Option<Arc<WasmPlugin>>,
 }
 
 impl SearchParams {
@@ -57,6 +190,37 @@ impl SearchParams {
             max_gap,
             mismatches,
             symmetry_mode,
+            bisulfite: false,
+            rna: false,
+            wobble: false,
+            complement: None,
+            score_scheme: ScoreScheme::default(),
+            scoring: Scoring::default(),
+            min_score: None,
+            max_dust: None,
+            max_homopolymer: None,
+            max_n_fraction: None,
+            masked: Vec::new(),
+            max_mismatch_rate: None,
+            anchor: None,
+            arm_regions: None,
+            thermo: ThermoConditions::default(),
+            min_tm: None,
+            max_tm: None,
+            min_mismatches_used: None,
+            max_mismatches_used: None,
+            supercoiling: Supercoiling::default(),
+            huge_pages: false,
+            rmq: RmqBackend::default(),
+            sa: SaBackend::default(),
+            seed_len: None,
+            prefilter: false,
+            max_memory: None,
+            circular: false,
+            chunk_len: None,
+            threads: 0,
+            #[cfg(feature = "wasm")]
+            plugin: None,
         })
     }
 
@@ -70,6 +234,30 @@ impl SearchParams {
         )
     }
 
+    /// Constructor for very long IRs (e.g. the IRa/IRb of plastid genomes, tens of kilobases),
+    /// where the number of allowed mismatches grows with the length of the arms.
+    ///
+    /// The search allows up to `mismatch_rate * max_len` mismatches, and then extends the arms
+    /// of every IR again from the gap, stopping at the first mismatch that exceeds
+    /// `mismatch_rate` times the length extended so far.
+    pub fn with_mismatch_rate(
+        min_len: usize,
+        max_len: usize,
+        max_gap: usize,
+        mismatch_rate: f64,
+    ) -> Result<Self> {
+        if !(0.0..1.0).contains(&mismatch_rate) {
+            return Err(anyhow!(
+                "mismatch_rate={} must be in [0, 1).",
+                mismatch_rate
+            ));
+        }
+        let mismatches = (mismatch_rate * max_len as f64).ceil() as usize;
+        let mut params = Self::new(min_len, max_len, max_gap, mismatches)?;
+        params.max_mismatch_rate = Some(mismatch_rate);
+        Ok(params)
+    }
+
     pub fn check_bounds(&self, n: usize) -> Result<()> {
         if self.min_len >= n {
             return Err(anyhow!(
@@ -96,6 +284,122 @@ impl SearchParams {
 
         Ok(())
     }
+
+    /// The parameters with the supercoiling domain of the extrusion scores defaulting to the
+    /// `n` positions of the sequence, for the searches and scores that only see a part of it
+    /// (a chunk) or more (the copies of a circular sequence).
+    pub(crate) fn with_domain_len(&self, n: usize) -> Cow<'_, Self> {
+        if self.score_scheme != ScoreScheme::Extrusion || self.supercoiling.domain_len.is_some() {
+            return Cow::Borrowed(self);
+        }
+        let mut params = self.clone();
+        params.supercoiling.domain_len = Some(n);
+        Cow::Owned(params)
+    }
+
+    /// Warnings about valid but probably unintended parameters for a sequence of length `n`,
+    /// unlike [`SearchParams::check_bounds`] which rejects invalid ones.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use iirs::{SearchParams, Warning};
+    ///
+    /// let params = SearchParams::new(6, 100, 100, 3).unwrap();
+    /// let warnings = params.advise(1_000_000);
+    /// assert!(warnings.contains(&Warning::ManyMismatches { mismatches: 3, min_len: 6 }));
+    /// assert!(SearchParams::new(12, 100, 50, 0).unwrap().advise(1_000_000).is_empty());
+    /// ```
+    pub fn advise(&self, n: usize) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        if 2 * self.mismatches >= self.min_len {
+            warnings.push(Warning::ManyMismatches {
+                mismatches: self.mismatches,
+                min_len: self.min_len,
+            });
+        }
+        if self.max_gap > TYPICAL_MAX_LOOP {
+            warnings.push(Warning::LargeGap {
+                max_gap: self.max_gap,
+            });
+        }
+        if self.max_len > n / 2 {
+            warnings.push(Warning::UnreachableMaxLen {
+                max_len: self.max_len,
+                n,
+            });
+        }
+        // IRs with exact arms expected in a uniform random sequence.
+        let expected = n as f64 * (self.max_gap + 1) as f64 * 0.25f64.powi(self.min_len as i32);
+        if expected > n as f64 / 100.0 {
+            warnings.push(Warning::ManyRandomHits {
+                expected: expected.round() as usize,
+            });
+        }
+        warnings
+    }
+
+    /// Estimate of the peak memory of a search of a sequence of `n` bases with these
+    /// parameters, by structure, e.g. to request the right allocation from a cluster
+    /// scheduler before launching the job.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use iirs::{RmqBackend, SearchParams};
+    ///
+    /// let mut params = SearchParams::new(10, 100, 20, 2).unwrap();
+    /// let sparse = params.estimate_memory(50_000_000);
+    /// params.rmq = RmqBackend::Blocks;
+    /// let blocks = params.estimate_memory(50_000_000);
+    /// assert!(blocks.peak() < sparse.peak());
+    /// println!("{blocks}");
+    /// ```
+    pub fn estimate_memory(&self, n: usize) -> MemoryEstimate {
+        memory::estimate(self, n)
+    }
+}
+
+/// Loops (gaps) of hairpins and cruciforms are rarely longer than this.
+const TYPICAL_MAX_LOOP: usize = 1000;
+
+/// A valid but probably unintended choice of parameters (see [`SearchParams::advise`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// At least half of an arm of minimum length may mismatch, which explodes the number of IRs.
+    ManyMismatches { mismatches: usize, min_len: usize },
+    /// The gap is larger than the loops of typical hairpins and cruciforms.
+    LargeGap { max_gap: usize },
+    /// The arms can not be longer than half the sequence.
+    UnreachableMaxLen { max_len: usize, n: usize },
+    /// A random sequence of the same length would already have this many IRs.
+    ManyRandomHits { expected: usize },
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ManyMismatches {
+                mismatches,
+                min_len,
+            } => write!(
+                f,
+                "mismatches={mismatches} is at least half of min_len={min_len}: expect a huge number of IRs."
+            ),
+            Self::LargeGap { max_gap } => write!(
+                f,
+                "max_gap={max_gap} is larger than typical loop sizes ({TYPICAL_MAX_LOOP}): the search is slower and most IRs are spurious."
+            ),
+            Self::UnreachableMaxLen { max_len, n } => write!(
+                f,
+                "max_len={max_len} is more than half the sequence length={n} and can not be reached."
+            ),
+            Self::ManyRandomHits { expected } => write!(
+                f,
+                "about {expected} IRs are expected by chance in a random sequence of this length: consider a larger min_len."
+            ),
+        }
+    }
 }
 
 impl Default for SearchParams {
@@ -116,6 +420,30 @@ pub enum OutputFormat {
     Classic,
     Csv,
     Custom,
+    /// Same report as EMBOSS `einverted`.
+    Einverted,
+    /// Same report as EMBOSS `palindrome`.
+    Palindrome,
+    /// Sparse self dot-plot: the coordinates of the matching pairs of bases of every IR.
+    Dotplot,
+    /// BED6 intervals (0-based, half-open).
+    Bed,
+    /// GFF3 `inverted_repeat` features.
+    Gff3,
+    /// Tab-separated numeric feature vectors, for machine learning.
+    Features,
+    /// A JSON array of objects, one per IR.
+    Json,
+    /// JSON Lines: one JSON object per IR and per line.
+    Jsonl,
+}
+
+impl OutputFormat {
+    /// Whether an empty line is written after the IRs. Formats reproducing the output of
+    /// another tool, or with one record per line, end exactly after their last IR.
+    pub const fn has_trailing_line(&self) -> bool {
+        !matches!(self, Self::Palindrome | Self::Jsonl)
+    }
 }
 
 impl std::fmt::Display for OutputFormat {
@@ -124,18 +452,199 @@ impl std::fmt::Display for OutputFormat {
             Self::Classic => "classic",
             Self::Csv => "csv",
             Self::Custom => "custom",
+            Self::Einverted => "einverted",
+            Self::Palindrome => "palindrome",
+            Self::Dotplot => "dotplot",
+            Self::Bed => "bed",
+            Self::Gff3 => "gff3",
+            Self::Features => "features",
+            Self::Json => "json",
+            Self::Jsonl => "jsonl",
         };
         write!(f, "{fmted}")
     }
 }
 
-#[derive(Debug)]
+/// Order of the IRs in the output.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortBy {
+    /// By start position, longest first.
+    #[default]
+    Start,
+    /// By center (midpoint of the IR), e.g. to anchor alignments or center motifs.
+    Center,
+}
+
+impl SortBy {
+    /// 0-based position of an IR in this order (rounded down for centers).
+    pub const fn position(self, ir: &IrSpan) -> usize {
+        match self {
+            Self::Start => ir.0,
+            Self::Center => (ir.0 + ir.1) / 2,
+        }
+    }
+
+    /// Sort key of an IR in this order, whose first component is increasing with
+    /// [`SortBy::position`]. Unlike [`crate::sort_irs_by`], ties are broken by gap.
+    pub(crate) const fn key(self, ir: &IrSpan) -> (usize, usize, usize) {
+        match self {
+            Self::Start => (ir.0, usize::MAX - ir.1, ir.2),
+            Self::Center => (ir.0 + ir.1, ir.0, ir.2),
+        }
+    }
+}
+
+/// Limit on the number of IRs reported per window of `window` positions
+/// (by IR start, or by IR center when sorting by center).
+///
+/// Windows with more than `max_hits` IRs are replaced by a single summary line, which keeps
+/// the output of telomeric or satellite regions usable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionCap {
+    pub window: usize,
+    pub max_hits: usize,
+}
+
+/// Where the gap stratum of every IR is reported.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StrataOutput {
+    /// A `stratum` column (custom output format).
+    #[default]
+    Column,
+    /// One output file per stratum.
+    Files,
+}
+
+/// Strata of IRs by gap (loop) length, since gapless palindromes and long-spacer IRs are
+/// analyzed very differently downstream.
+///
+/// They are given by the largest gap of every stratum but the last: `[0, 3, 10]` gives
+/// the strata `0`, `1-3`, `4-10` and `11+`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GapStrata {
+    bounds: Vec<usize>,
+    pub output: StrataOutput,
+}
+
+impl GapStrata {
+    pub fn new(bounds: Vec<usize>, output: StrataOutput) -> Result<Self> {
+        if bounds.is_empty() || !bounds.windows(2).all(|pair| pair[0] < pair[1]) {
+            return Err(anyhow!(
+                "gap strata bounds must be increasing, got {:?}.",
+                bounds
+            ));
+        }
+        Ok(Self { bounds, output })
+    }
+
+    /// Number of strata.
+    pub fn count(&self) -> usize {
+        self.bounds.len() + 1
+    }
+
+    /// Index of the stratum of an IR with this gap.
+    pub fn stratum(&self, gap: usize) -> usize {
+        self.bounds.partition_point(|&bound| bound < gap)
+    }
+
+    /// Label of the `idx`-th stratum, such as `0`, `1-3` or `11+`.
+    pub fn label(&self, idx: usize) -> String {
+        let low = match idx {
+            0 => 0,
+            _ => self.bounds[idx - 1] + 1,
+        };
+        match self.bounds.get(idx) {
+            Some(&high) if high == low => low.to_string(),
+            Some(&high) => format!("{low}-{high}"),
+            None => format!("{low}+"),
+        }
+    }
+}
+
+/// The bounds of one of several searches over the same index (see [`crate::find_irs_multi`]),
+/// written `MIN_LEN:MAX_LEN:MAX_GAP:MISMATCHES`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParamSet {
+    pub min_len: usize,
+    pub max_len: usize,
+    pub max_gap: usize,
+    pub mismatches: usize,
+}
+
+impl ParamSet {
+    /// Copy of `params` with these bounds.
+    pub fn apply(&self, params: &SearchParams) -> Result<SearchParams> {
+        // Validates the bounds
+        SearchParams::new(self.min_len, self.max_len, self.max_gap, self.mismatches)?;
+        Ok(SearchParams {
+            min_len: self.min_len,
+            max_len: self.max_len,
+            max_gap: self.max_gap,
+            mismatches: self.mismatches,
+            ..params.clone()
+        })
+    }
+
+    /// Label of the results of this set, after the CLI flags: `m10_M100_g5_x0`.
+    pub fn label(&self) -> String {
+        format!(
+            "m{}_M{}_g{}_x{}",
+            self.min_len, self.max_len, self.max_gap, self.mismatches
+        )
+    }
+}
+
+impl std::str::FromStr for ParamSet {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let values = s
+            .split(':')
+            .map(|value| value.trim().parse::<usize>())
+            .collect::<Result<Vec<_>, _>>();
+        match values.as_deref() {
+            Ok(&[min_len, max_len, max_gap, mismatches]) => Ok(Self {
+                min_len,
+                max_len,
+                max_gap,
+                mismatches,
+            }),
+            _ => Err(anyhow!(
+                "invalid parameter set '{}', expected MIN_LEN:MAX_LEN:MAX_GAP:MISMATCHES.",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Config<'a> {
     pub input_file: &'a str,
     pub seq_name: &'a str,
     pub params: SearchParams,
     pub output_file: &'a str,
     pub output_format: OutputFormat,
+    pub region_cap: Option<RegionCap>,
+    pub sort_by: SortBy,
+    /// Features used to annotate every IR with the nearest one (custom output format).
+    pub features: Option<Features>,
+    /// Named intervals listed for every IR overlapping them (custom output format).
+    pub labels: Option<Features>,
+    pub gap_strata: Option<GapStrata>,
+    /// Above this number of pending IRs, the streaming writer spills them to temporary files.
+    pub spill_threshold: Option<usize>,
+    /// Report every arm as its own interval instead of the span of the IR (bed and gff3 output
+    /// formats).
+    pub split_arms: bool,
+    /// Add the arms and the loop as children of every IR (gff3 output format).
+    pub gff_children: bool,
+    /// Tell whether every IR is maximal or clipped by `max_len` (custom output format).
+    pub report_maximal: bool,
+    /// Tell how many pairs of every IR do not match (custom output format).
+    pub report_mismatches: bool,
+    /// Assembly of the sequences, to add genome browser URLs to every IR (custom output
+    /// format).
+    pub assembly: Option<&'a str>,
 }
 
 impl Default for Config<'_> {
@@ -146,6 +655,17 @@ impl Default for Config<'_> {
             params: SearchParams::default(),
             output_file: DEFAULT_OUTPUT_FILE,
             output_format: OutputFormat::default(),
+            region_cap: None,
+            sort_by: SortBy::default(),
+            features: None,
+            labels: None,
+            gap_strata: None,
+            spill_threshold: None,
+            split_arms: false,
+            gff_children: false,
+            report_maximal: false,
+            report_mismatches: false,
+            assembly: None,
         }
     }
 }
@@ -168,8 +688,58 @@ impl std::fmt::Display for Config<'_> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_advise() {
+        let params = SearchParams::new(6, 100, 5000, 3).unwrap();
+        let warnings = params.advise(150);
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::ManyMismatches {
+                    mismatches: 3,
+                    min_len: 6
+                },
+                Warning::LargeGap { max_gap: 5000 },
+                Warning::UnreachableMaxLen {
+                    max_len: 100,
+                    n: 150
+                },
+                Warning::ManyRandomHits { expected: 183 },
+            ]
+        );
+        assert!(SearchParams::default().advise(10_000).is_empty());
+    }
+
     #[test]
     fn test_invalid_min_len_less_than_two() {
         assert!(SearchParams::new(0, 100, 0, 0).is_err());
     }
+
+    #[test]
+    fn test_gap_strata() {
+        let strata = GapStrata::new(vec![0, 3, 10], StrataOutput::Column).unwrap();
+        assert_eq!(strata.count(), 4);
+        let labels: Vec<_> = [0, 1, 3, 4, 10, 11, 500]
+            .iter()
+            .map(|&gap| strata.label(strata.stratum(gap)))
+            .collect();
+        assert_eq!(labels, ["0", "1-3", "1-3", "4-10", "4-10", "11+", "11+"]);
+        assert!(GapStrata::new(vec![3, 3], StrataOutput::Column).is_err());
+    }
+
+    #[test]
+    fn test_param_set() {
+        let set: ParamSet = "10:100:5:1".parse().unwrap();
+        assert_eq!(set.label(), "m10_M100_g5_x1");
+        let mut params = SearchParams::new(3, 6, 2, 0).unwrap();
+        params.max_dust = Some(2.0);
+        let applied = set.apply(&params).unwrap();
+        assert_eq!((applied.min_len, applied.mismatches), (10, 1));
+        assert_eq!(applied.max_dust, Some(2.0));
+
+        assert!("10:100:5".parse::<ParamSet>().is_err());
+        assert!("10:100:5:x".parse::<ParamSet>().is_err());
+        let invalid: ParamSet = "5:100:5:5".parse().unwrap();
+        assert!(invalid.apply(&params).is_err());
+    }
 }
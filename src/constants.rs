@@ -5,9 +5,95 @@ pub const DEFAULT_MAX_LEN: usize = 100;
 pub const DEFAULT_MAX_GAP: usize = 100;
 pub const DEFAULT_MISMATCHES: usize = 0;
 
+/// Named bundles of parameters (`--profile`) for common use cases.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Rho-independent terminator hairpins: short stems with a 3-9 nt loop.
+    Terminators,
+    /// Perfect IRs with a short loop, able to extrude as cruciforms.
+    Cruciform,
+    /// Terminal inverted repeats of transposons, a few kb apart.
+    Tir,
+    /// Self-complementarity (hairpins) of primers and probes.
+    PrimerQc,
+    /// IRa/IRb of plastid genomes: tens of kb, with a mismatch rate.
+    PlastidIr,
+}
+
+/// Parameters of a [`Profile`]. The filters are only set when `Some`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProfilePreset {
+    pub min_len: usize,
+    pub max_len: usize,
+    pub max_gap: usize,
+    pub mismatches: usize,
+    pub mismatch_rate: Option<f64>,
+    pub max_homopolymer: Option<f64>,
+    pub max_n_fraction: Option<f64>,
+}
+
+const PRESET: ProfilePreset = ProfilePreset {
+    min_len: DEFAULT_MIN_LEN,
+    max_len: DEFAULT_MAX_LEN,
+    max_gap: DEFAULT_MAX_GAP,
+    mismatches: DEFAULT_MISMATCHES,
+    mismatch_rate: None,
+    max_homopolymer: None,
+    max_n_fraction: None,
+};
+
+impl Profile {
+    pub const fn preset(self) -> ProfilePreset {
+        match self {
+            Self::Terminators => ProfilePreset {
+                min_len: 5,
+                max_len: 20,
+                max_gap: 9,
+                mismatches: 1,
+                max_homopolymer: Some(0.6),
+                max_n_fraction: Some(0.1),
+                ..PRESET
+            },
+            Self::Cruciform => ProfilePreset {
+                min_len: 6,
+                max_len: 100,
+                max_gap: 8,
+                max_n_fraction: Some(0.1),
+                ..PRESET
+            },
+            Self::Tir => ProfilePreset {
+                min_len: 12,
+                max_len: 50,
+                max_gap: 10_000,
+                mismatches: 3,
+                max_homopolymer: Some(0.5),
+                ..PRESET
+            },
+            Self::PrimerQc => ProfilePreset {
+                min_len: 4,
+                max_len: 15,
+                max_gap: 10,
+                ..PRESET
+            },
+            Self::PlastidIr => ProfilePreset {
+                min_len: 5_000,
+                max_len: 80_000,
+                max_gap: 30_000,
+                mismatch_rate: Some(0.001),
+                max_n_fraction: Some(0.05),
+                ..PRESET
+            },
+        }
+    }
+}
+
 pub const DEFAULT_INPUT_FILE: &str = "input.fasta";
 pub const DEFAULT_SEQ_NAME: &str = "seq0";
+/// Sequence name selecting every sequence of the input file.
+pub const ALL_SEQUENCES: &str = "ALL_SEQUENCES";
 pub const DEFAULT_OUTPUT_FILE: &str = "iirs.out";
+/// Width of the lines of FASTA output.
+pub const FASTA_LINE_WIDTH: usize = 60;
 
 pub const IUPAC_SYMBOLS: &str = "acgturyswkmbdhvn*-";
 #[allow(dead_code)] // used in the tests
@@ -66,6 +152,13 @@ pub fn build_complement_array() -> [u8; 128] {
     complement
 }
 
+/// Complement of RNA: A pairs with U (and T, if any, with A).
+pub fn build_rna_complement_array() -> [u8; 128] {
+    let mut complement = build_complement_array();
+    complement[b'a' as usize] = b'u';
+    complement
+}
+
 pub fn build_iupac_rules() -> Vec<(char, BTreeSet<char>)> {
     IUPAC_RULES
         .iter()
@@ -78,4 +171,36 @@ mod tests {
     fn test_constants() {
         assert_eq!(super::ALL_SYMBOLS.len(), super::ALL_SYMBOLS_COUNT);
     }
+
+    #[test]
+    fn test_profiles_are_valid() {
+        use super::Profile;
+        use crate::config::SearchParams;
+
+        let profiles = [
+            Profile::Terminators,
+            Profile::Cruciform,
+            Profile::Tir,
+            Profile::PrimerQc,
+            Profile::PlastidIr,
+        ];
+        for profile in profiles {
+            let preset = profile.preset();
+            let params = match preset.mismatch_rate {
+                Some(rate) => SearchParams::with_mismatch_rate(
+                    preset.min_len,
+                    preset.max_len,
+                    preset.max_gap,
+                    rate,
+                ),
+                None => SearchParams::new(
+                    preset.min_len,
+                    preset.max_len,
+                    preset.max_gap,
+                    preset.mismatches,
+                ),
+            };
+            assert!(params.is_ok(), "{profile:?}");
+        }
+    }
 }
@@ -0,0 +1,140 @@
+//! Early termination of a search.
+use crate::IrMatch;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Cloneable handle used to ask a running search to stop, optionally with a deadline.
+///
+/// The search only checks it between windows of centers, so the results found
+/// up to that point are always complete for the positions that were covered.
+#[derive(Debug, Clone, Default)]
+pub struct StopHandle {
+    flag: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+    max_hits: Option<(usize, MaxHitsAction)>,
+}
+
+/// What to do once a search has found more IRs than allowed.
+#[derive(clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MaxHitsAction {
+    /// Stop the search.
+    #[default]
+    Abort,
+    /// Keep searching, but only count the remaining IRs.
+    Count,
+}
+
+impl StopHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Soft time limit: searches stop at the first window boundary after `limit` has elapsed
+    /// (counting from now).
+    #[must_use]
+    pub fn with_time_limit(mut self, limit: Duration) -> Self {
+        self.deadline = Some(Instant::now() + limit);
+        self
+    }
+
+    /// Guard against exploding outputs: once more than `max_hits` IRs are found,
+    /// either stop or only keep counting (see [`MaxHitsAction`]).
+    #[must_use]
+    pub const fn with_max_hits(mut self, max_hits: usize, action: MaxHitsAction) -> Self {
+        self.max_hits = Some((max_hits, action));
+        self
+    }
+
+    pub const fn max_hits(&self) -> Option<(usize, MaxHitsAction)> {
+        self.max_hits
+    }
+
+    /// Same handle (sharing the flag and the deadline), without the guard on the number of IRs.
+    pub(crate) fn without_max_hits(&self) -> Self {
+        Self {
+            max_hits: None,
+            ..self.clone()
+        }
+    }
+
+    pub fn stop(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+
+    /// Return the reason to stop, if any.
+    pub fn check(&self) -> Option<StopReason> {
+        if self.is_stopped() {
+            return Some(StopReason::Interrupted);
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Some(StopReason::TimeLimit),
+            _ => None,
+        }
+    }
+}
+
+/// Why a search ended before examining every center.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The [`StopHandle`] was triggered (Ctrl-C / SIGTERM in the CLI).
+    Interrupted,
+    /// The time limit of the [`StopHandle`] was reached.
+    TimeLimit,
+    /// More IRs than the maximum allowed by the [`StopHandle`] were found.
+    MaxHits,
+}
+
+impl StopReason {
+    /// Exit code used by the CLI.
+    pub const fn exit_code(self) -> i32 {
+        match self {
+            // 128 + SIGINT
+            Self::Interrupted => 130,
+            // Same as coreutils' timeout
+            Self::TimeLimit => 124,
+            Self::MaxHits => 3,
+        }
+    }
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fmted = match self {
+            Self::Interrupted => "interrupted",
+            Self::TimeLimit => "time limit reached",
+            Self::MaxHits => "too many inverted repeats",
+        };
+        write!(f, "{fmted}")
+    }
+}
+
+/// Statistics of a search that may have been stopped early.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchSummary {
+    /// Number of IRs returned or written, which is smaller than `found` in
+    /// [`MaxHitsAction::Count`] mode.
+    pub kept: usize,
+    /// Number of IRs found.
+    pub found: usize,
+    /// Every IR centered in the first `covered` positions of the sequence has been found.
+    pub covered: usize,
+    /// `None` if the whole sequence was searched.
+    pub stopped: Option<StopReason>,
+}
+
+/// Inverted repeats of a search that may have been stopped early.
+#[derive(Debug)]
+pub struct PartialIrs {
+    pub irs: Vec<IrMatch>,
+    /// Every IR centered in the first `covered` positions of the sequence has been found.
+    pub covered: usize,
+    /// Number of IRs found, which is larger than `irs.len()` in [`MaxHitsAction::Count`] mode.
+    pub found: usize,
+    /// `None` if the whole sequence was searched.
+    pub stopped: Option<StopReason>,
+}
@@ -0,0 +1,163 @@
+//! Deduplication of streams of IRs, with memory bounded by the IRs of a sliding window instead
+//! of every IR.
+//!
+//! This is for callers that split a sequence into overlapping windows themselves, e.g. a
+//! workflow searching the windows of a chromosome in separate cluster jobs, and stitch their
+//! results back. The chunked search of the crate (`SearchParams::chunk_len`) does not need it:
+//! every center is owned by one chunk, so its IRs are never reported twice.
+use anyhow::Result;
+use std::collections::BTreeSet;
+
+use crate::IrSpan;
+use crate::config::Config;
+use crate::control::SearchSummary;
+use crate::sink::ResultSink;
+
+/// Remembers the IRs of the last `window` positions of a stream of IRs sorted by position,
+/// where a duplicate comes at most `window` positions after the furthest IR seen so far
+/// (e.g. the overlap between consecutive chunks).
+#[derive(Debug, Clone, Default)]
+pub struct StreamDedup {
+    window: usize,
+    /// Furthest position seen.
+    front: usize,
+    recent: BTreeSet<(usize, IrSpan)>,
+}
+
+impl StreamDedup {
+    pub const fn new(window: usize) -> Self {
+        Self {
+            window,
+            front: 0,
+            recent: BTreeSet::new(),
+        }
+    }
+
+    /// Whether `ir`, at `position` in the stream, was not among the recent IRs.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use iirs::StreamDedup;
+    ///
+    /// // Two chunks overlapping by 10 positions
+    /// let mut dedup = StreamDedup::new(10);
+    /// let chunks = [vec![(2, 9, 0), (95, 104, 2)], vec![(95, 104, 2), (120, 131, 0)]];
+    /// let irs: Vec<_> = chunks
+    ///     .into_iter()
+    ///     .flatten()
+    ///     .filter(|&ir| dedup.insert(ir.0, ir))
+    ///     .collect();
+    /// assert_eq!(irs, vec![(2, 9, 0), (95, 104, 2), (120, 131, 0)]);
+    /// ```
+    pub fn insert(&mut self, position: usize, ir: IrSpan) -> bool {
+        self.front = self.front.max(position);
+        // IRs too far behind to be seen again
+        while let Some(&(oldest, _)) = self.recent.first() {
+            if oldest + self.window >= self.front {
+                break;
+            }
+            self.recent.pop_first();
+        }
+        self.recent.insert((position, ir))
+    }
+
+    /// Number of IRs remembered.
+    pub fn len(&self) -> usize {
+        self.recent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recent.is_empty()
+    }
+}
+
+/// Hands the IRs to another sink, without the duplicates (see [`StreamDedup`]) of the last
+/// `window` positions (in the order of `config.sort_by`).
+pub struct DedupSink<S: ResultSink> {
+    inner: S,
+    dedup: StreamDedup,
+    duplicates: usize,
+}
+
+impl<S: ResultSink> DedupSink<S> {
+    pub const fn new(inner: S, window: usize) -> Self {
+        Self {
+            inner,
+            dedup: StreamDedup::new(window),
+            duplicates: 0,
+        }
+    }
+
+    /// Number of IRs dropped.
+    pub const fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: ResultSink> ResultSink for DedupSink<S> {
+    fn begin(&mut self, config: &Config, seq: &[u8]) -> Result<()> {
+        self.dedup = StreamDedup::new(self.dedup.window);
+        self.inner.begin(config, seq)
+    }
+
+    fn write_irs(&mut self, config: &Config, irs: &[IrSpan], seq: &[u8]) -> Result<()> {
+        let unique: Vec<IrSpan> = irs
+            .iter()
+            .filter(|&&ir| self.dedup.insert(config.sort_by.position(&ir), ir))
+            .copied()
+            .collect();
+        self.duplicates += irs.len() - unique.len();
+        self.inner.write_irs(config, &unique, seq)
+    }
+
+    fn end(&mut self, config: &Config, summary: &SearchSummary) -> Result<()> {
+        self.inner.end(config, summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SearchParams;
+    use crate::find_spans;
+    use crate::libtests::random_seq;
+
+    #[test]
+    fn test_stitch_overlapping_chunks() {
+        let seq = random_seq(5, 3000, b"acgt");
+        let params = SearchParams::new(4, 10, 3, 0).unwrap();
+        let config = Config {
+            params: params.clone(),
+            ..Default::default()
+        };
+
+        // Chunks of 600 positions overlapping by 100, searched independently
+        let (chunk, overlap) = (600, 100);
+        let mut sink = DedupSink::new(Vec::new(), overlap);
+        let mut all = BTreeSet::new();
+        let mut max_remembered = 0;
+        for start in (0..seq.len()).step_by(chunk - overlap) {
+            let end = (start + chunk).min(seq.len());
+            let mut irs: Vec<IrSpan> = find_spans(&params, &seq[start..end])
+                .unwrap()
+                .into_iter()
+                .map(|(left, right, gap)| (left + start, right + start, gap))
+                .collect();
+            irs.sort_unstable();
+            all.extend(irs.iter().copied());
+            sink.write_irs(&config, &irs, &seq).unwrap();
+            max_remembered = max_remembered.max(sink.dedup.len());
+        }
+        assert!(sink.duplicates() > 0);
+
+        // Same as a global set, remembering only the IRs of about one overlap
+        let stitched = sink.into_inner();
+        assert_eq!(stitched, all.iter().copied().collect::<Vec<_>>());
+        assert!(max_remembered < stitched.len() / 4);
+    }
+}
@@ -0,0 +1,163 @@
+//! Enrichment of IRs along a sequence, against shuffled copies of it (the background model),
+//! to locate palindrome hotspots.
+use anyhow::{Result, bail};
+use std::fmt;
+
+use crate::config::SearchParams;
+use crate::shuffle::{ShuffleMode, shuffle_sequence};
+use crate::{IrSpan, find_spans};
+
+/// IRs starting in a window of the sequence, compared to the shuffled copies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowEnrichment {
+    /// 0-based inclusive span of the window.
+    pub start: usize,
+    pub end: usize,
+    pub observed: usize,
+    /// Mean number of IRs in the window over the shuffled copies.
+    pub expected: f64,
+    /// Standard deviation of the number of IRs in the window over the shuffled copies.
+    pub std_dev: f64,
+}
+
+impl WindowEnrichment {
+    /// Observed over expected IRs, if any IR is expected.
+    pub fn fold(&self) -> Option<f64> {
+        (self.expected > 0.0).then(|| self.observed as f64 / self.expected)
+    }
+
+    /// Distance of the observed IRs to the mean of the shuffled copies, in standard deviations
+    /// (undefined if the copies all have the same number of IRs).
+    pub fn z_score(&self) -> Option<f64> {
+        (self.std_dev > 0.0).then(|| (self.observed as f64 - self.expected) / self.std_dev)
+    }
+}
+
+pub fn fmt_enrichment_header() -> String {
+    String::from("window_start,window_end,observed,expected,std_dev,fold,z_score")
+}
+
+/// `window_start,window_end,observed,expected,std_dev,fold,z_score`, with 1-based
+/// coordinates and `NA` for an undefined fold or z-score.
+impl fmt::Display for WindowEnrichment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let na = |value: Option<f64>| value.map_or(String::from("NA"), |v| format!("{v:.2}"));
+        write!(
+            f,
+            "{},{},{},{:.2},{:.2},{},{}",
+            self.start + 1,
+            self.end + 1,
+            self.observed,
+            self.expected,
+            self.std_dev,
+            na(self.fold()),
+            na(self.z_score())
+        )
+    }
+}
+
+/// Number of IRs starting in every window.
+fn counts_per_window(irs: &[IrSpan], window: usize, n_windows: usize) -> Vec<usize> {
+    let mut counts = vec![0; n_windows];
+    for &(left, _, _) in irs {
+        counts[left / window] += 1;
+    }
+    counts
+}
+
+/// Compare the number of IRs starting in every window of `window` positions of `seq` to
+/// `copies` shuffled copies of `seq` (see `shuffle_sequence`), the `i`-th copy being shuffled
+/// with `seed + i`. The last window may be shorter.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{SearchParams, ShuffleMode, ir_enrichment};
+///
+/// let seq = "acgtagctacgtacgtagctacgttttgcaaattcggatcgactagca".as_bytes();
+/// let params = SearchParams::new(6, 20, 3, 0).unwrap();
+/// let windows = ir_enrichment(&params, seq, 24, ShuffleMode::Di, 10, 0).unwrap();
+/// assert_eq!(windows.len(), 2);
+/// assert_eq!((windows[1].start, windows[1].end), (24, 47));
+/// assert!(windows[0].observed >= 2);
+/// ```
+pub fn ir_enrichment(
+    params: &SearchParams,
+    seq: &[u8],
+    window: usize,
+    mode: ShuffleMode,
+    copies: usize,
+    seed: u64,
+) -> Result<Vec<WindowEnrichment>> {
+    if window == 0 {
+        bail!("window must be greater than 0.");
+    }
+    if copies == 0 {
+        bail!("at least one shuffled copy is needed.");
+    }
+    let n_windows = seq.len().div_ceil(window);
+    let observed = counts_per_window(&find_spans(params, seq)?, window, n_windows);
+
+    let mut sums = vec![0.0; n_windows];
+    let mut squares = vec![0.0; n_windows];
+    for copy in 0..copies {
+        let shuffled = shuffle_sequence(seq, mode, seed.wrapping_add(copy as u64));
+        let counts = counts_per_window(&find_spans(params, &shuffled)?, window, n_windows);
+        for (idx, &count) in counts.iter().enumerate() {
+            sums[idx] += count as f64;
+            squares[idx] += (count * count) as f64;
+        }
+    }
+
+    let copies = copies as f64;
+    Ok((0..n_windows)
+        .map(|idx| {
+            let expected = sums[idx] / copies;
+            // Sample standard deviation
+            let variance = if copies > 1.0 {
+                ((squares[idx] - copies * expected * expected) / (copies - 1.0)).max(0.0)
+            } else {
+                0.0
+            };
+            WindowEnrichment {
+                start: idx * window,
+                end: ((idx + 1) * window).min(seq.len()) - 1,
+                observed: observed[idx],
+                expected,
+                std_dev: variance.sqrt(),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libtests::random_seq;
+
+    #[test]
+    fn test_ir_enrichment_finds_hotspot() {
+        let mut seq = random_seq(3, 2000, b"acgt");
+        // Hotspot of palindromes in the third window
+        for idx in 0..10 {
+            let start = 420 + idx * 15;
+            seq[start..start + 12].copy_from_slice(b"acgtagctacgt");
+        }
+
+        let params = SearchParams::new(6, 20, 3, 0).unwrap();
+        let windows = ir_enrichment(&params, &seq, 200, ShuffleMode::Di, 20, 0).unwrap();
+        assert_eq!(windows.len(), 10);
+        assert_eq!((windows[9].start, windows[9].end), (1800, 1999));
+
+        let hotspot = &windows[2];
+        assert!(hotspot.observed >= 10);
+        assert!(hotspot.fold().unwrap() > 2.0);
+        let z = |window: &WindowEnrichment| window.z_score().unwrap_or(f64::NEG_INFINITY);
+        let best = windows.iter().max_by(|a, b| z(a).total_cmp(&z(b))).unwrap();
+        assert_eq!(best, hotspot);
+        assert!(hotspot.to_string().starts_with("401,600,"));
+
+        assert!(ir_enrichment(&params, &seq, 0, ShuffleMode::Di, 20, 0).is_err());
+        assert!(ir_enrichment(&params, &seq, 200, ShuffleMode::Di, 0, 0).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Group IRs into dispersed repeat families.
+//!
+//! Two IRs belong to the same family when their arms are identical (on either strand),
+//! which separates repeat families spread over the genome from unique palindromes.
+use anyhow::Result;
+use std::collections::HashMap;
+
+use crate::IrSpan;
+use crate::constants::build_complement_array;
+use crate::score::arm_len;
+use crate::utils::sanitize_sequence;
+
+/// Family id of every IR of `irs` (found in `seq`), or `None` for IRs whose arm is not
+/// shared with any other IR.
+///
+/// Ids are given in order of first appearance in `irs`, starting at 0.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::label_families;
+///
+/// // The same IR "acgttcgt" appears twice, then a unique one.
+/// let seq = "acgttcgtaaaaacgttcgtaaaaggccc".as_bytes();
+/// let irs = vec![(0, 7, 0), (12, 19, 0), (24, 28, 1)];
+/// let families = label_families(&irs, &seq).unwrap();
+/// assert_eq!(families, vec![Some(0), Some(0), None]);
+/// ```
+pub fn label_families(irs: &[IrSpan], seq: &[u8]) -> Result<Vec<Option<usize>>> {
+    let seq = sanitize_sequence(seq)?;
+    let complement = build_complement_array();
+
+    // Read on the other strand, the left arm of an IR is the reverse complement of its
+    // right arm (they only differ where there are mismatches).
+    let arm_key = |ir: &IrSpan| -> Vec<u8> {
+        let &(left, right, _) = ir;
+        let len = arm_len(ir);
+        let left_arm = seq[left..left + len].to_vec();
+        let other_strand_arm: Vec<u8> = seq[right + 1 - len..=right]
+            .iter()
+            .rev()
+            .map(|&c| complement[c as usize])
+            .collect();
+        left_arm.min(other_strand_arm)
+    };
+
+    let keys: Vec<_> = irs.iter().map(arm_key).collect();
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for key in &keys {
+        *counts.entry(key).or_default() += 1;
+    }
+
+    let mut ids: HashMap<&[u8], usize> = HashMap::new();
+    let families = keys
+        .iter()
+        .map(|key| {
+            if counts[key.as_slice()] < 2 {
+                return None;
+            }
+            let next_id = ids.len();
+            Some(*ids.entry(key).or_insert(next_id))
+        })
+        .collect();
+
+    Ok(families)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_families_other_strand() {
+        // "atcgtt" is "aacgat" (with one mismatch) read on the other strand.
+        let seq = "aacgatnnnnatcgtt".as_bytes();
+        let irs = vec![(0, 5, 0), (10, 15, 0)];
+        assert_eq!(label_families(&irs, seq).unwrap(), vec![Some(0), Some(0)]);
+
+        let seq = "aacgatnnnnccgcgg".as_bytes();
+        assert_eq!(label_families(&irs, seq).unwrap(), vec![None, None]);
+    }
+}
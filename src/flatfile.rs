@@ -0,0 +1,186 @@
+//! Readers for annotated flat-file formats (GenBank, EMBL), turned into plain records.
+//!
+//! Only the name and the sequence of each record are kept: the features are ignored.
+use anyhow::{Context, Result, anyhow};
+use seq_io::fasta::OwnedRecord;
+use std::io::{BufRead, Read};
+
+use crate::utils::open_input;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlatFileFormat {
+    GenBank,
+    Embl,
+}
+
+/// Detect a flat-file format from the first non-empty line. `None` means FASTA.
+pub fn sniff(path: &str) -> Result<Option<FlatFileFormat>> {
+    let reader = open_input(path)?;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        return Ok(if line.starts_with("LOCUS") {
+            Some(FlatFileFormat::GenBank)
+        } else if line.starts_with("ID   ") {
+            Some(FlatFileFormat::Embl)
+        } else {
+            None
+        });
+    }
+    Ok(None)
+}
+
+/// Read every record of a flat file. Records are named after their accession
+/// (or their locus name when there is none).
+pub fn read_records(path: &str, format: FlatFileFormat) -> Result<Vec<OwnedRecord>> {
+    let mut content = String::new();
+    open_input(path)?
+        .read_to_string(&mut content)
+        .with_context(|| format!("Could not read {path}"))?;
+    match format {
+        FlatFileFormat::GenBank => parse_genbank(&content),
+        FlatFileFormat::Embl => parse_embl(&content),
+    }
+}
+
+fn parse_genbank(content: &str) -> Result<Vec<OwnedRecord>> {
+    let mut records = Vec::new();
+    let mut locus = None;
+    let mut accession = None;
+    let mut seq = Vec::new();
+    let mut in_sequence = false;
+
+    for line in content.lines() {
+        if line.starts_with("//") {
+            let name: Option<String> = accession.take().or(locus.take());
+            let name = name.ok_or_else(|| anyhow!("GenBank record without LOCUS line"))?;
+            records.push(OwnedRecord {
+                head: name.into_bytes(),
+                seq: std::mem::take(&mut seq),
+            });
+            in_sequence = false;
+        } else if in_sequence {
+            // "        1 acgtacgtac gtacgtacgt ..."
+            seq.extend(line.bytes().filter(u8::is_ascii_alphabetic));
+        } else if let Some(rest) = line.strip_prefix("LOCUS") {
+            locus = rest.split_whitespace().next().map(String::from);
+        } else if let Some(rest) = line.strip_prefix("ACCESSION") {
+            accession = rest.split_whitespace().next().map(String::from);
+        } else if line.starts_with("ORIGIN") {
+            in_sequence = true;
+        }
+    }
+
+    if in_sequence {
+        return Err(anyhow!("GenBank record not terminated by '//'"));
+    }
+    Ok(records)
+}
+
+fn parse_embl(content: &str) -> Result<Vec<OwnedRecord>> {
+    let mut records = Vec::new();
+    let mut id = None;
+    let mut accession = None;
+    let mut seq = Vec::new();
+    let mut in_sequence = false;
+
+    // "ID   X56734; SV 1; ..." and "AC   X56734; S46826;"
+    let first_token = |rest: &str| {
+        rest.split_whitespace()
+            .next()
+            .map(|token| token.trim_end_matches(';').to_string())
+    };
+
+    for line in content.lines() {
+        if line.starts_with("//") {
+            let name: Option<String> = accession.take().or(id.take());
+            let name = name.ok_or_else(|| anyhow!("EMBL record without ID line"))?;
+            records.push(OwnedRecord {
+                head: name.into_bytes(),
+                seq: std::mem::take(&mut seq),
+            });
+            in_sequence = false;
+        } else if in_sequence {
+            // "     aaacaaacca aatatggatt ttattgtagc        60"
+            seq.extend(line.bytes().filter(u8::is_ascii_alphabetic));
+        } else if let Some(rest) = line.strip_prefix("ID   ") {
+            id = first_token(rest);
+        } else if let Some(rest) = line.strip_prefix("AC   ") {
+            // Only the primary accession (the first AC line) is kept.
+            if accession.is_none() {
+                accession = first_token(rest);
+            }
+        } else if line.starts_with("SQ   ") {
+            in_sequence = true;
+        }
+    }
+
+    if in_sequence {
+        return Err(anyhow!("EMBL record not terminated by '//'"));
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use seq_io::fasta::Record;
+
+    const GENBANK: &str = "\
+LOCUS       pUC19_frag                30 bp    DNA     circular SYN 01-JAN-2024
+DEFINITION  Fragment.
+ACCESSION   L09137 SYNPUC19CV
+FEATURES             Location/Qualifiers
+     source          1..30
+ORIGIN
+        1 tcgcgcgttt cggtgatgac ggtgaaaacc
+//
+LOCUS       noacc                     12 bp    DNA     linear   SYN 01-JAN-2024
+ORIGIN
+        1 acgtacgtac gt
+//
+";
+
+    #[test]
+    fn test_parse_genbank() {
+        let records = parse_genbank(GENBANK).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].id().unwrap(), "L09137");
+        assert_eq!(records[0].seq, b"tcgcgcgtttcggtgatgacggtgaaaacc");
+        assert_eq!(records[1].id().unwrap(), "noacc");
+        assert_eq!(records[1].seq, b"acgtacgtacgt");
+    }
+
+    #[test]
+    fn test_parse_embl() {
+        let embl = "\
+ID   X56734; SV 1; linear; mRNA; STD; PLN; 25 BP.
+XX
+AC   X56734; S46826;
+AC   X00001;
+XX
+FH   Key             Location/Qualifiers
+FT   source          1..25
+SQ   Sequence 25 BP; 9 A; 3 C; 4 G; 9 T; 0 other;
+     aaacaaacca aatatggatt ttatt                                           25
+//
+ID   Y00002; SV 1; linear; DNA; STD; SYN; 4 BP.
+SQ   Sequence 4 BP;
+     acgt                                                                   4
+//
+";
+        let records = parse_embl(embl).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].id().unwrap(), "X56734");
+        assert_eq!(records[0].seq, b"aaacaaaccaaatatggattttatt");
+        assert_eq!(records[1].id().unwrap(), "Y00002");
+        assert_eq!(records[1].seq, b"acgt");
+    }
+
+    #[test]
+    fn test_parse_genbank_unterminated() {
+        assert!(parse_genbank("LOCUS x\nORIGIN\n   1 acgt\n").is_err());
+    }
+}
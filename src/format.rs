@@ -2,13 +2,160 @@
 #![allow(clippy::needless_range_loop)]
 #![allow(clippy::similar_names)]
 
-use crate::{config::Config, matrix::MatchMatrix};
+use crate::{
+    IrSpan,
+    annotate::Features,
+    circular::{at_origin, rolled_len},
+    config::{Config, GapStrata, OutputFormat, RegionCap, StrataOutput},
+    ir_center,
+    links::BrowserLinks,
+    matrix::MatchMatrix,
+    progress::json_string,
+    score::{ScoreScheme, Scoring, count_mismatches, ir_score, is_clipped},
+    vectors::{FEATURE_NAMES, FeatureVector, feature_vector},
+};
 use std::fmt::Write;
 
 const fn int_size(x: usize) -> usize {
     (x.ilog10() + 1) as usize
 }
 
+/// Header of the configured output format (for a sequence of length `n`).
+pub fn fmt_header(config: &Config, n: usize) -> String {
+    match config.output_format {
+        OutputFormat::Classic => fmt_classic_header(config, n),
+        OutputFormat::Csv => fmt_csv_header(),
+        // Only which columns are reported matters for the header.
+        OutputFormat::Custom => fmt_custom_header(&CustomColumns::new(config, &|_| false, &|_| 0)),
+        OutputFormat::Einverted => String::new(),
+        OutputFormat::Palindrome => fmt_palindrome_header(config, n),
+        OutputFormat::Dotplot => fmt_dotplot_header(),
+        OutputFormat::Bed => fmt_bed_header(config.seq_name),
+        OutputFormat::Gff3 => fmt_gff3_header(config.seq_name, n),
+        OutputFormat::Features => fmt_features_header(),
+        OutputFormat::Json => String::from("["),
+        OutputFormat::Jsonl => String::new(),
+    }
+}
+
+/// Written after the IRs (and their trailing empty line) to close the output, if any.
+pub fn fmt_footer(config: &Config) -> Option<&'static str> {
+    match config.output_format {
+        OutputFormat::Json => Some("]"),
+        _ => None,
+    }
+}
+
+/// IRs in the configured output format. Formatting consecutive slices and
+/// concatenating the results gives the same as formatting everything at once
+/// (with a region cap, as long as the slices do not split a window).
+pub fn fmt_irs(
+    config: &Config,
+    irs: &[(usize, usize, usize)],
+    seq: &[u8],
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+) -> String {
+    let Some(cap) = config.region_cap else {
+        return fmt_irs_uncapped(config, irs, seq, matrix, complement);
+    };
+
+    // IRs are sorted by position, so every window is a contiguous chunk.
+    let mut out = String::new();
+    let window_idx = |ir: &(usize, usize, usize)| config.sort_by.position(ir) / cap.window;
+    for chunk in irs.chunk_by(|a, b| window_idx(a) == window_idx(b)) {
+        if chunk.len() > cap.max_hits {
+            out.push_str(&fmt_dense_region(&cap, window_idx(&chunk[0]), chunk.len()));
+        } else {
+            out.push_str(&fmt_irs_uncapped(config, chunk, seq, matrix, complement));
+        }
+    }
+    out
+}
+
+/// Summary line replacing the IRs of the `idx`-th window when there are too many.
+fn fmt_dense_region(cap: &RegionCap, idx: usize, hits: usize) -> String {
+    format!(
+        "# DENSE REGION: {} inverted repeats at positions {}..{} were not reported\n",
+        hits,
+        idx * cap.window + 1,
+        (idx + 1) * cap.window
+    )
+}
+
+fn fmt_irs_uncapped(
+    config: &Config,
+    irs: &[(usize, usize, usize)],
+    seq: &[u8],
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+) -> String {
+    // The length of a circular sequence, whose IRs spanning the origin are split in two records
+    let circle = config
+        .params
+        .circular
+        .then(|| rolled_len(&config.params, seq.len()));
+    match config.output_format {
+        OutputFormat::Classic => fmt_classic(irs, seq, matrix, complement),
+        OutputFormat::Csv => fmt_csv(irs, seq, matrix, complement),
+        OutputFormat::Custom => {
+            let maximal = |ir: &(usize, usize, usize)| {
+                !is_clipped(ir, seq, &config.params, matrix, complement)
+            };
+            let mismatches = |ir: &IrSpan| {
+                count_mismatches(ir, seq, &config.params.symmetry_mode, matrix, complement)
+            };
+            fmt_custom(
+                irs,
+                seq,
+                &CustomColumns::new(config, &maximal, &mismatches),
+                |ir| ir_score(ir, seq, &config.params, matrix, complement),
+            )
+        }
+        OutputFormat::Einverted => fmt_einverted(
+            config.seq_name,
+            &config.params.scoring,
+            irs,
+            seq,
+            matrix,
+            complement,
+        ),
+        OutputFormat::Palindrome => fmt_palindrome(irs, seq, matrix, complement),
+        OutputFormat::Dotplot => fmt_dotplot(irs, seq, matrix, complement),
+        OutputFormat::Bed => fmt_bed(config.seq_name, irs, config.split_arms, circle, |ir| {
+            let score = ir_score(ir, seq, &config.params, matrix, complement);
+            bed_score(score, config.params.score_scheme)
+        }),
+        OutputFormat::Gff3 => {
+            let layout = if config.gff_children {
+                GffLayout::Children
+            } else if config.split_arms {
+                GffLayout::SplitArms
+            } else {
+                GffLayout::Span
+            };
+            fmt_gff3(
+                config.seq_name,
+                irs,
+                layout,
+                circle,
+                |ir| ir_score(ir, seq, &config.params, matrix, complement),
+                |ir| count_mismatches(ir, seq, &config.params.symmetry_mode, matrix, complement),
+            )
+        }
+        OutputFormat::Features => fmt_features(irs, |ir| {
+            feature_vector(ir, seq, &config.params, matrix, complement)
+        }),
+        OutputFormat::Json | OutputFormat::Jsonl => fmt_json(
+            config.seq_name,
+            config.params.symmetry_mode.as_str(),
+            irs,
+            config.output_format == OutputFormat::Jsonl,
+            |ir| count_mismatches(ir, seq, &config.params.symmetry_mode, matrix, complement),
+        ),
+    }
+}
+
 /// Follows [IUPACpal](https://github.com/steven31415/IUPACpal) convention
 /// of calling Inverted Repeats, palindromes
 pub fn fmt_classic_header(config: &Config, n: usize) -> String {
@@ -133,11 +280,82 @@ pub fn fmt_csv(
     out
 }
 
-pub fn fmt_custom_header() -> String {
-    String::from("ir_start,motif,gap_motif,reverse_complement")
+/// Gap strata reported as a column.
+fn stratum_column<'a>(config: &'a Config) -> Option<&'a GapStrata> {
+    config
+        .gap_strata
+        .as_ref()
+        .filter(|strata| strata.output == StrataOutput::Column)
+}
+
+/// Optional columns of the custom format, in their order after the score. Both the header and
+/// the rows are built from them.
+#[derive(Default)]
+pub struct CustomColumns<'a> {
+    /// Gap stratum.
+    pub strata: Option<&'a GapStrata>,
+    /// Nearest feature and the signed distance to it.
+    pub features: Option<&'a Features>,
+    /// Whether the IR is maximal.
+    pub maximal: Option<&'a dyn Fn(&IrSpan) -> bool>,
+    /// Genome browser URLs.
+    pub links: Option<BrowserLinks<'a>>,
+    /// Names of the overlapping intervals.
+    pub labels: Option<&'a Features>,
+    /// Mismatches between the arms.
+    pub mismatches: Option<&'a dyn Fn(&IrSpan) -> usize>,
 }
 
-pub fn fmt_custom(irs: &[(usize, usize, usize)], seq: &[u8]) -> String {
+impl<'a> CustomColumns<'a> {
+    /// Columns reported with `config`, where `maximal` and `mismatches` compute their columns.
+    pub fn new(
+        config: &'a Config,
+        maximal: &'a dyn Fn(&IrSpan) -> bool,
+        mismatches: &'a dyn Fn(&IrSpan) -> usize,
+    ) -> Self {
+        Self {
+            strata: stratum_column(config),
+            features: config.features.as_ref(),
+            maximal: config.report_maximal.then_some(maximal),
+            links: config
+                .assembly
+                .map(|assembly| BrowserLinks::new(assembly, config.seq_name)),
+            labels: config.labels.as_ref(),
+            mismatches: config.report_mismatches.then_some(mismatches),
+        }
+    }
+}
+
+/// Header of the custom format, followed by the names of the optional `columns`.
+pub fn fmt_custom_header(columns: &CustomColumns) -> String {
+    let mut header = String::from("ir_start,motif,gap_motif,reverse_complement,center,score");
+    if columns.strata.is_some() {
+        header.push_str(",stratum");
+    }
+    if columns.features.is_some() {
+        header.push_str(",nearest_feature,distance");
+    }
+    if columns.maximal.is_some() {
+        header.push_str(",maximal");
+    }
+    if columns.links.is_some() {
+        header.push_str(",ucsc_url,ensembl_url,igv_url");
+    }
+    if columns.labels.is_some() {
+        header.push_str(",labels");
+    }
+    if columns.mismatches.is_some() {
+        header.push_str(",mismatches");
+    }
+    header
+}
+
+pub fn fmt_custom(
+    irs: &[(usize, usize, usize)],
+    seq: &[u8],
+    columns: &CustomColumns,
+    score: impl Fn(&(usize, usize, usize)) -> f64,
+) -> String {
     let mut out = String::new();
 
     for &(left, right, gap) in irs {
@@ -164,6 +382,449 @@ pub fn fmt_custom(irs: &[(usize, usize, usize)], seq: &[u8]) -> String {
         for i in (inner_right..=outer_right).rev() {
             out.push(seq[i - 1] as char);
         }
+
+        // 4. Center and score (rounded to two decimals)
+        let ir = (left, right, gap);
+        let score = (score(&ir) * 100.0).round() / 100.0;
+        write!(&mut out, ",{},{}", ir_center(&ir), score).unwrap();
+
+        // 5. Gap stratum
+        if let Some(strata) = columns.strata {
+            write!(&mut out, ",{}", strata.label(strata.stratum(gap))).unwrap();
+        }
+
+        // 6. Nearest feature (empty if the sequence has none)
+        if let Some(features) = columns.features {
+            match features.nearest(&ir) {
+                Some((feature, distance)) => write!(&mut out, ",{},{}", feature.name, distance),
+                None => write!(&mut out, ",,"),
+            }
+            .unwrap();
+        }
+
+        // 7. Maximal or clipped by max_len
+        if let Some(maximal) = columns.maximal {
+            write!(&mut out, ",{}", maximal(&ir)).unwrap();
+        }
+
+        // 8. Genome browser URLs (empty if unavailable)
+        if let Some(links) = &columns.links {
+            for url in links.urls(outer_left, outer_right) {
+                write!(&mut out, ",{}", url.unwrap_or_default()).unwrap();
+            }
+        }
+
+        // 9. Names of the overlapping intervals
+        if let Some(labels) = columns.labels {
+            let names: Vec<&str> = labels
+                .overlapping(&ir)
+                .map(|label| label.name.as_str())
+                .collect();
+            write!(&mut out, ",{}", names.join(";")).unwrap();
+        }
+
+        // 10. Mismatches between the arms
+        if let Some(mismatches) = columns.mismatches {
+            write!(&mut out, ",{}", mismatches(&ir)).unwrap();
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+pub fn fmt_dotplot_header() -> String {
+    String::from("x,y")
+}
+
+/// Points of the self dot-plot (the sequence against its reverse complement) restricted to
+/// the IRs: one `x,y` line (1-based) per matching pair of bases, so that large inverted
+/// duplications show up as anti-diagonals when plotted.
+pub fn fmt_dotplot(
+    irs: &[(usize, usize, usize)],
+    seq: &[u8],
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+) -> String {
+    let mut out = String::new();
+
+    for &(left, right, gap) in irs {
+        let arm_len = (right + 1 - left - gap) / 2;
+        for i in 0..arm_len {
+            if matrix.match_u8(seq[left + i], complement[seq[right - i] as usize]) {
+                writeln!(&mut out, "{},{}", left + i + 1, right - i + 1).unwrap();
+            }
+        }
+    }
+
+    out
+}
+
+pub fn fmt_bed_header(seq_name: &str) -> String {
+    format!("track name=iirs description=\"Inverted repeats of {seq_name}\"")
+}
+
+/// Score of the BED records, an integer from 0 to 1000: the extrusion probabilities and the
+/// identity percentages are scaled to that range, and the other scores are rounded and clamped
+/// to it (e.g. negative energies give 0).
+fn bed_score(score: f64, scheme: ScoreScheme) -> u16 {
+    let scaled = match scheme {
+        ScoreScheme::Extrusion => score * 1000.0,
+        ScoreScheme::Identity => score * 10.0,
+        _ => score,
+    };
+    // NaN gives 0
+    scaled.round().clamp(0.0, 1000.0) as u16
+}
+
+/// Name of an IR shared by its records, unique within a sequence.
+fn ir_name(&(left, right, gap): &(usize, usize, usize)) -> String {
+    format!("ir_{}_{}_{}", left + 1, right + 1, gap)
+}
+
+/// BED6 records: one per IR spanning it, or with `split_arms`, one per arm (the left arm on
+/// the `+` strand and the right arm on the `-` strand) with the same name. On a `circle` of
+/// that length, the records spanning the origin are split in two at it.
+pub fn fmt_bed(
+    seq_name: &str,
+    irs: &[(usize, usize, usize)],
+    split_arms: bool,
+    circle: Option<usize>,
+    score: impl Fn(&(usize, usize, usize)) -> u16,
+) -> String {
+    let mut out = String::new();
+    let mut record = |start: usize, end: usize, name: &str, score: u16, strand: char| {
+        for (start, end) in at_origin(start, end, circle) {
+            writeln!(
+                &mut out,
+                "{seq_name}\t{start}\t{end}\t{name}\t{score}\t{strand}"
+            )
+            .unwrap();
+        }
+    };
+
+    for ir in irs {
+        let &(left, right, gap) = ir;
+        let name = ir_name(ir);
+        let score = score(ir);
+        if split_arms {
+            let arm_len = (right + 1 - left - gap) / 2;
+            record(left, left + arm_len, &name, score, '+');
+            record(right + 1 - arm_len, right + 1, &name, score, '-');
+        } else {
+            record(left, right + 1, &name, score, '.');
+        }
+    }
+
+    out
+}
+
+pub fn fmt_gff3_header(seq_name: &str, n: usize) -> String {
+    format!("##gff-version 3\n##sequence-region {seq_name} 1 {n}")
+}
+
+/// How the IRs are laid out as GFF3 features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GffLayout {
+    /// One feature spanning the IR.
+    Span,
+    /// One feature per arm, sharing the `ID` of the IR (a discontinuous feature).
+    SplitArms,
+    /// A parent feature spanning the IR, with a `repeat_unit` child for every arm and the loop
+    /// (if any), so that browsers render its structure.
+    Children,
+}
+
+/// GFF3 features of type `inverted_repeat` (SO:0000294), with the score of the IR and its
+/// `arm_len`, `gap` and `mismatches` as attributes. The children are of type `repeat_unit`
+/// (SO:0000726). On a `circle` of that length, the features spanning the origin are split in
+/// two at it, sharing their `ID`.
+pub fn fmt_gff3(
+    seq_name: &str,
+    irs: &[(usize, usize, usize)],
+    layout: GffLayout,
+    circle: Option<usize>,
+    score: impl Fn(&(usize, usize, usize)) -> f64,
+    mismatches: impl Fn(&(usize, usize, usize)) -> usize,
+) -> String {
+    let mut out = String::new();
+    let mut record =
+        |feature: &str, start: usize, end: usize, score: &str, strand: char, attributes: &str| {
+            for (start, end) in at_origin(start - 1, end, circle) {
+                writeln!(
+                    &mut out,
+                    "{seq_name}\tiirs\t{feature}\t{}\t{end}\t{score}\t{strand}\t.\t{attributes}",
+                    start + 1
+                )
+                .unwrap();
+            }
+        };
+
+    for ir in irs {
+        let &(left, right, gap) = ir;
+        let name = ir_name(ir);
+        let score = ((score(ir) * 100.0).round() / 100.0).to_string();
+        let arm_len = (right + 1 - left - gap) / 2;
+        let attributes = format!(
+            "ID={name};arm_len={arm_len};gap={gap};mismatches={}",
+            mismatches(ir)
+        );
+        // 1-based inclusive
+        let left_arm = (left + 1, left + arm_len);
+        let right_arm = (right + 2 - arm_len, right + 1);
+
+        match layout {
+            GffLayout::Span => {
+                record(
+                    "inverted_repeat",
+                    left + 1,
+                    right + 1,
+                    &score,
+                    '.',
+                    &attributes,
+                );
+            }
+            GffLayout::SplitArms => {
+                record(
+                    "inverted_repeat",
+                    left_arm.0,
+                    left_arm.1,
+                    &score,
+                    '+',
+                    &attributes,
+                );
+                record(
+                    "inverted_repeat",
+                    right_arm.0,
+                    right_arm.1,
+                    &score,
+                    '-',
+                    &attributes,
+                );
+            }
+            GffLayout::Children => {
+                record(
+                    "inverted_repeat",
+                    left + 1,
+                    right + 1,
+                    &score,
+                    '.',
+                    &attributes,
+                );
+                let child = |part: &str| format!("ID={name}_{part};Name={part};Parent={name}");
+                record(
+                    "repeat_unit",
+                    left_arm.0,
+                    left_arm.1,
+                    ".",
+                    '+',
+                    &child("left_arm"),
+                );
+                if gap > 0 {
+                    record(
+                        "repeat_unit",
+                        left_arm.1 + 1,
+                        right_arm.0 - 1,
+                        ".",
+                        '.',
+                        &child("loop"),
+                    );
+                }
+                record(
+                    "repeat_unit",
+                    right_arm.0,
+                    right_arm.1,
+                    ".",
+                    '-',
+                    &child("right_arm"),
+                );
+            }
+        }
+    }
+
+    out
+}
+
+/// Follows the report of EMBOSS [einverted](https://emboss.sourceforge.net/apps/cvs/emboss/apps/einverted.html).
+/// The arms never contain indels, so the number of gaps is always 0.
+pub fn fmt_einverted(
+    seq_name: &str,
+    scoring: &Scoring,
+    irs: &[(usize, usize, usize)],
+    seq: &[u8],
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+) -> String {
+    let mut out = String::new();
+
+    for &(left, right, gap) in irs {
+        let outer_left = left + 1;
+        let outer_right = right + 1;
+        let inner_left = (outer_left + outer_right - 1 - gap) / 2;
+        let inner_right = (outer_right + outer_left + 1 + gap) / 2;
+        let arm_len = inner_left - left;
+
+        let bars: String = (0..arm_len)
+            .map(|i| {
+                let matching = matrix.match_u8(seq[left + i], complement[seq[right - i] as usize]);
+                if matching { '|' } else { ' ' }
+            })
+            .collect();
+        let matches = bars.chars().filter(|&c| c == '|').count();
+        let score = scoring.score(matches, arm_len - matches);
+        let percent = 100 * matches / arm_len;
+
+        writeln!(
+            &mut out,
+            "{seq_name}: Score {score}: {matches}/{arm_len} ({percent:>3}%) matches, 0 gaps"
+        )
+        .unwrap();
+
+        // 1. Nucleotide strand
+        write!(&mut out, "{outer_left:>8} ").unwrap();
+        for i in left..inner_left {
+            out.push(seq[i] as char);
+        }
+        writeln!(&mut out, " {inner_left:<8}").unwrap();
+
+        // 2. Matching bars
+        writeln!(&mut out, "{:9}{bars}", "").unwrap();
+
+        // 3. Reverse complement
+        write!(&mut out, "{outer_right:>8} ").unwrap();
+        for i in (inner_right..=outer_right).rev() {
+            out.push(seq[i - 1] as char);
+        }
+        write!(&mut out, " {inner_right:<8}\n\n").unwrap();
+    }
+
+    out
+}
+
+/// Header of EMBOSS [palindrome](https://emboss.sourceforge.net/apps/cvs/emboss/apps/palindrome.html),
+/// trailing spaces included.
+pub fn fmt_palindrome_header(config: &Config, n: usize) -> String {
+    format!(
+        "Palindromes of:  {} \n\
+        Sequence length is: {} \n\
+        Start at position: {}\n\
+        End at position: {}\n\
+        Minimum length of Palindromes is: {} \n\
+        Maximum length of Palindromes is: {} \n\
+        Maximum gap between elements is: {} \n\
+        Number of mismatches allowed in Palindrome: {}\n\n\n\n\
+        Palindromes:",
+        config.seq_name,
+        n,
+        1,
+        n,
+        config.params.min_len,
+        config.params.max_len,
+        config.params.max_gap,
+        config.params.mismatches,
+    )
+}
+
+/// Follows the report of EMBOSS `palindrome`: unlike the classic format, the positions are
+/// padded to 5 characters and every IR is followed by an empty line, the last one included.
+pub fn fmt_palindrome(
+    irs: &[(usize, usize, usize)],
+    seq: &[u8],
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+) -> String {
+    let mut out = String::new();
+
+    for &(left, right, gap) in irs {
+        let arm_len = (right + 1 - left - gap) / 2;
+        let left_end = left + arm_len;
+        let right_start = right + 2 - arm_len;
+
+        // 1. Nucleotide strand
+        write!(&mut out, "{:<5} ", left + 1).unwrap();
+        for i in left..left_end {
+            out.push(seq[i] as char);
+        }
+        writeln!(&mut out, " {left_end:>5}").unwrap();
+
+        // 2. Matching bars
+        out.push_str("      ");
+        for i in 0..arm_len {
+            let matching = matrix.match_u8(seq[left + i], complement[seq[right - i] as usize]);
+            out.push(if matching { '|' } else { ' ' });
+        }
+        out.push('\n');
+
+        // 3. Reverse complement strand
+        write!(&mut out, "{:<5} ", right + 1).unwrap();
+        for i in (right_start - 1..=right).rev() {
+            out.push(seq[i] as char);
+        }
+        write!(&mut out, " {right_start:>5}\n\n").unwrap();
+    }
+
+    out
+}
+
+/// One JSON object per IR, with 1-based inclusive positions. With `lines`, every object is on
+/// its own line (JSON Lines). Otherwise the objects are separated by commas, without the
+/// brackets of the array (see `fmt_header` and `fmt_footer`) nor a trailing newline.
+pub fn fmt_json(
+    seq_name: &str,
+    symmetry_mode: &str,
+    irs: &[(usize, usize, usize)],
+    lines: bool,
+    mismatches: impl Fn(&(usize, usize, usize)) -> usize,
+) -> String {
+    let seq_name = json_string(seq_name);
+    let objects = irs.iter().map(|ir| {
+        let &(left, right, gap) = ir;
+        let arm_len = (right + 1 - left - gap) / 2;
+        format!(
+            "{{\"seq_name\":{},\"start\":{},\"end\":{},\"left_arm_end\":{},\"right_arm_start\":{},\
+            \"arm_len\":{},\"gap\":{},\"mismatches\":{},\"symmetry_mode\":\"{}\"}}",
+            seq_name,
+            left + 1,
+            right + 1,
+            left + arm_len,
+            right + 2 - arm_len,
+            arm_len,
+            gap,
+            mismatches(ir),
+            symmetry_mode
+        )
+    });
+    if lines {
+        objects.map(|object| object + "\n").collect()
+    } else {
+        objects.collect::<Vec<_>>().join(",\n")
+    }
+}
+
+pub fn fmt_features_header() -> String {
+    format!("start\tend\t{}", FEATURE_NAMES.join("\t"))
+}
+
+/// One line per IR: its 1-based coordinates and its feature vector (see `FEATURE_NAMES`).
+pub fn fmt_features(irs: &[IrSpan], features: impl Fn(&IrSpan) -> FeatureVector) -> String {
+    let mut out = String::new();
+
+    for ir in irs {
+        let &(left, right, _) = ir;
+        let vector = features(ir);
+        write!(
+            &mut out,
+            "{}\t{}\t{}\t{}\t{}",
+            left + 1,
+            right + 1,
+            vector.arm_len,
+            vector.gap,
+            vector.mismatches
+        )
+        .unwrap();
+        for value in &vector.to_array()[3..] {
+            write!(&mut out, "\t{value:.4}").unwrap();
+        }
         out.push('\n');
     }
 
@@ -173,8 +834,9 @@ pub fn fmt_custom(irs: &[(usize, usize, usize)], seq: &[u8]) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::annotate::Feature;
     use crate::config::SearchParams;
-    use crate::{constants::build_complement_array, find_irs, matrix};
+    use crate::{constants::build_complement_array, find_spans, matrix};
 
     #[test]
     fn test_format_classic() {
@@ -182,8 +844,8 @@ mod tests {
         let seq = string.to_ascii_lowercase().as_bytes().to_vec();
         let params = SearchParams::new(10, 100, 10, 1).unwrap();
         params.check_bounds(seq.len()).unwrap();
-        let irs = find_irs(&params, &seq).unwrap();
-        let matrix = matrix::MatchMatrix::new();
+        let irs = find_spans(&params, &seq).unwrap();
+        let matrix = matrix::MatchMatrix::default();
         let complement = build_complement_array();
         let received = fmt_classic(&irs, &seq, &matrix, &complement);
         let expected = r"2        gucsggtgtwkmmm       15
@@ -250,8 +912,8 @@ mod tests {
         let seq = string.to_ascii_lowercase().as_bytes().to_vec();
         let params = SearchParams::new(10, 100, 10, 1).unwrap();
         params.check_bounds(seq.len()).unwrap();
-        let irs = find_irs(&params, &seq).unwrap();
-        let matrix = matrix::MatchMatrix::new();
+        let irs = find_spans(&params, &seq).unwrap();
+        let matrix = matrix::MatchMatrix::default();
         let complement = build_complement_array();
         let received = format!(
             "{}\n{}",
@@ -286,22 +948,29 @@ mod tests {
         let seq = string.to_ascii_lowercase().as_bytes().to_vec();
         let params = SearchParams::new(10, 100, 10, 1).unwrap();
         params.check_bounds(seq.len()).unwrap();
-        let irs = find_irs(&params, &seq).unwrap();
-        let received = format!("{}\n{}", fmt_custom_header(), fmt_custom(&irs, &seq));
-        let expected = r"ir_start,motif,gap_motif,reverse_complement
-2,gucsggtgtwkmmm,k,nngah*nn-nddbk
-3,ucsggtgtwkmmm,kk,nngah*nn-nddb
-3,ucsggtgtwkmm,m,ah*nn-nddbkk
-5,sggtgtwkmmmkk,,nngah*nn-nddb
-5,sggtgtwkmmm,,h*nn-nddbkk
-7,gtgtwkmmmkkb,,nngah*nn-ndd
-8,tgtwkmmmkkbd,,anngah*nn-nd
-8,tgtwkmmmkkb,d,nngah*nn-nd
-10,twkmmmkkbdd,,anngah*nn-n
-11,wkmmmkkbdd,n,anngah*nn-
-12,kmmmkkbddn,,anngah*nn-
-13,mmmkkbddn-n,,guganngah*n
-13,mmmkkbddn-,n,uganngah*n
+        let irs = find_spans(&params, &seq).unwrap();
+        let matrix = matrix::MatchMatrix::default();
+        let complement = build_complement_array();
+        let score = |ir: &_| ir_score(ir, &seq, &params, &matrix, &complement);
+        let received = format!(
+            "{}\n{}",
+            fmt_custom_header(&CustomColumns::default()),
+            fmt_custom(&irs, &seq, &CustomColumns::default(), score)
+        );
+        let expected = r"ir_start,motif,gap_motif,reverse_complement,center,score
+2,gucsggtgtwkmmm,k,nngah*nn-nddbk,16,35
+3,ucsggtgtwkmmm,kk,nngah*nn-nddb,16.5,32
+3,ucsggtgtwkmm,m,ah*nn-nddbkk,15,29
+5,sggtgtwkmmmkk,,nngah*nn-nddb,17.5,32
+5,sggtgtwkmmm,,h*nn-nddbkk,15.5,33
+7,gtgtwkmmmkkb,,nngah*nn-ndd,18.5,29
+8,tgtwkmmmkkbd,,anngah*nn-nd,19.5,29
+8,tgtwkmmmkkb,d,nngah*nn-nd,19,26
+10,twkmmmkkbdd,,anngah*nn-n,20.5,26
+11,wkmmmkkbdd,n,anngah*nn-,21,23
+12,kmmmkkbddn,,anngah*nn-,21.5,30
+13,mmmkkbddn-n,,guganngah*n,23.5,26
+13,mmmkkbddn-,n,uganngah*n,23,23
 ";
         let expected_lines = expected.split('\n');
         let received_lines = received.split('\n');
@@ -309,4 +978,279 @@ mod tests {
             assert_eq!(e, r, "Difference at line {idx}");
         }
     }
+
+    #[test]
+    fn test_format_einverted() {
+        let seq = "acgagt".as_bytes();
+        let matrix = matrix::MatchMatrix::default();
+        let complement = build_complement_array();
+        let received = fmt_einverted(
+            "seq0",
+            &Scoring::default(),
+            &[(0, 5, 0)],
+            seq,
+            &matrix,
+            &complement,
+        );
+        let expected = "seq0: Score 2: 2/3 ( 66%) matches, 0 gaps
+       1 acg 3       
+         || 
+       6 tga 4       
+
+";
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn test_format_palindrome() {
+        let seq = "ttacgagtaa".as_bytes();
+        let matrix = matrix::MatchMatrix::default();
+        let complement = build_complement_array();
+        let received = fmt_palindrome(&[(2, 7, 0), (0, 9, 2)], seq, &matrix, &complement);
+        let expected = "\
+3     acg     5
+      || 
+8     tga     6
+
+1     ttac     4
+      ||||
+10    aatg     7
+
+";
+        assert_eq!(received, expected);
+
+        let config = Config {
+            params: SearchParams::new(10, 100, 100, 0).unwrap(),
+            seq_name: "ECLAC",
+            ..Default::default()
+        };
+        let header = fmt_palindrome_header(&config, 7477);
+        assert!(header.starts_with("Palindromes of:  ECLAC \nSequence length is: 7477 \n"));
+        assert!(header.ends_with("Palindrome: 0\n\n\n\nPalindromes:"));
+    }
+
+    #[test]
+    fn test_format_custom_annotated() {
+        let seq = "acbbgt".as_bytes();
+        let features = Features::new(vec![Feature {
+            name: String::from("oriC"),
+            start: 10,
+            end: 20,
+        }]);
+        let columns = CustomColumns {
+            features: Some(&features),
+            ..Default::default()
+        };
+        let received = format!(
+            "{}\n{}",
+            fmt_custom_header(&columns),
+            fmt_custom(&[(0, 5, 0)], seq, &columns, |_| 0.0)
+        );
+        let expected = "\
+ir_start,motif,gap_motif,reverse_complement,center,score,nearest_feature,distance
+1,acb,,tgb,3.5,0,oriC,5
+";
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn test_format_custom_labeled() {
+        let seq = b"acbbgtacbbgt";
+        let feature = |name: &str, start, end| Feature {
+            name: name.to_string(),
+            start,
+            end,
+        };
+        let labels = Features::new(vec![feature("oriC", 0, 2), feature("IS1", 1, 8)]);
+        let irs = [(0, 5, 0), (6, 11, 0)];
+        let columns = CustomColumns {
+            labels: Some(&labels),
+            ..Default::default()
+        };
+        let received = fmt_custom(&irs, seq, &columns, |_| 0.0);
+        assert_eq!(
+            received,
+            "1,acb,,tgb,3.5,0,oriC;IS1\n7,acb,,tgb,9.5,0,IS1\n"
+        );
+        assert!(fmt_custom_header(&columns).ends_with(",score,labels"));
+    }
+
+    #[test]
+    fn test_format_custom_mismatches() {
+        let config = Config {
+            output_format: OutputFormat::Custom,
+            report_mismatches: true,
+            ..Default::default()
+        };
+        // acg / cgt with a mismatching outer pair
+        let seq = b"gcgaacgt";
+        let matrix = matrix::MatchMatrix::default();
+        let complement = build_complement_array();
+        let received = fmt_irs(&config, &[(0, 7, 2)], seq, &matrix, &complement);
+        assert_eq!(received, "1,gcg,aa,tgc,4.5,2,1\n");
+        assert!(fmt_header(&config, seq.len()).ends_with(",score,mismatches"));
+    }
+
+    #[test]
+    fn test_format_custom_linked() {
+        let seq = b"acbbgt";
+        let columns = CustomColumns {
+            links: Some(BrowserLinks::new("sacCer3", "chrIV")),
+            ..Default::default()
+        };
+        let received = format!(
+            "{}\n{}",
+            fmt_custom_header(&columns),
+            fmt_custom(&[(0, 5, 0)], seq, &columns, |_| 0.0)
+        );
+        let expected = "\
+ir_start,motif,gap_motif,reverse_complement,center,score,ucsc_url,ensembl_url,igv_url
+1,acb,,tgb,3.5,0,https://genome.ucsc.edu/cgi-bin/hgTracks?db=sacCer3&position=chrIV:1-6,,https://igv.org/app/?genome=sacCer3&locus=chrIV:1-6
+";
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn test_format_dotplot() {
+        // The innermost pair (g, a) does not match
+        let seq = "acgagt".as_bytes();
+        let matrix = matrix::MatchMatrix::default();
+        let complement = build_complement_array();
+        let received = fmt_dotplot(&[(0, 5, 0)], seq, &matrix, &complement);
+        assert_eq!(received, "1,6\n2,5\n");
+    }
+
+    #[test]
+    fn test_format_bed() {
+        let irs = [(0, 9, 2)];
+        assert_eq!(
+            fmt_bed("chr1", &irs, false, None, |_| 4),
+            "chr1\t0\t10\tir_1_10_2\t4\t.\n"
+        );
+        let expected = "chr1\t0\t4\tir_1_10_2\t4\t+\nchr1\t6\t10\tir_1_10_2\t4\t-\n";
+        assert_eq!(fmt_bed("chr1", &irs, true, None, |_| 4), expected);
+
+        // Spanning the origin of a circle of 8
+        let expected = "\
+chr1\t0\t4\tir_1_10_2\t4\t+
+chr1\t6\t8\tir_1_10_2\t4\t-
+chr1\t0\t2\tir_1_10_2\t4\t-
+";
+        assert_eq!(fmt_bed("chr1", &irs, true, Some(8), |_| 4), expected);
+    }
+
+    #[test]
+    fn test_bed_score() {
+        assert_eq!(bed_score(24.0, ScoreScheme::Einverted), 24);
+        assert_eq!(bed_score(1500.0, ScoreScheme::Einverted), 1000);
+        assert_eq!(bed_score(-12.3, ScoreScheme::Energy), 0);
+        assert_eq!(bed_score(0.4567, ScoreScheme::Extrusion), 457);
+        assert_eq!(bed_score(87.5, ScoreScheme::Identity), 875);
+        assert_eq!(bed_score(f64::NAN, ScoreScheme::Length), 0);
+    }
+
+    #[test]
+    fn test_format_gff3() {
+        let irs = [(0, 9, 2)];
+        let received = fmt_gff3("chr1", &irs, GffLayout::Span, None, |_| 4.0, |_| 1);
+        assert_eq!(
+            received,
+            "chr1\tiirs\tinverted_repeat\t1\t10\t4\t.\t.\tID=ir_1_10_2;arm_len=4;gap=2;mismatches=1\n"
+        );
+
+        let received = fmt_gff3("chr1", &irs, GffLayout::Children, None, |_| 4.0, |_| 1);
+        let expected = "\
+chr1\tiirs\tinverted_repeat\t1\t10\t4\t.\t.\tID=ir_1_10_2;arm_len=4;gap=2;mismatches=1
+chr1\tiirs\trepeat_unit\t1\t4\t.\t+\t.\tID=ir_1_10_2_left_arm;Name=left_arm;Parent=ir_1_10_2
+chr1\tiirs\trepeat_unit\t5\t6\t.\t.\t.\tID=ir_1_10_2_loop;Name=loop;Parent=ir_1_10_2
+chr1\tiirs\trepeat_unit\t7\t10\t.\t-\t.\tID=ir_1_10_2_right_arm;Name=right_arm;Parent=ir_1_10_2
+";
+        assert_eq!(received, expected);
+
+        // Without a loop
+        let received = fmt_gff3(
+            "chr1",
+            &[(0, 5, 0)],
+            GffLayout::Children,
+            None,
+            |_| 0.0,
+            |_| 0,
+        );
+        assert_eq!(received.lines().count(), 3);
+
+        // Spanning the origin of a circle of 8
+        let received = fmt_gff3("chr1", &irs, GffLayout::Span, Some(8), |_| 4.0, |_| 1);
+        let expected = "\
+chr1\tiirs\tinverted_repeat\t1\t8\t4\t.\t.\tID=ir_1_10_2;arm_len=4;gap=2;mismatches=1
+chr1\tiirs\tinverted_repeat\t1\t2\t4\t.\t.\tID=ir_1_10_2;arm_len=4;gap=2;mismatches=1
+";
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn test_format_json() {
+        let irs = [(0, 9, 2), (20, 25, 0)];
+        let received = fmt_json("chr\"1", "inverted", &irs, true, |_| 1);
+        let expected = "\
+{\"seq_name\":\"chr\\\"1\",\"start\":1,\"end\":10,\"left_arm_end\":4,\"right_arm_start\":7,\"arm_len\":4,\"gap\":2,\"mismatches\":1,\"symmetry_mode\":\"inverted\"}
+{\"seq_name\":\"chr\\\"1\",\"start\":21,\"end\":26,\"left_arm_end\":23,\"right_arm_start\":24,\"arm_len\":3,\"gap\":0,\"mismatches\":1,\"symmetry_mode\":\"inverted\"}
+";
+        assert_eq!(received, expected);
+
+        // The array only separates the objects
+        let received = fmt_json("chr\"1", "inverted", &irs, false, |_| 1);
+        assert_eq!(received, expected.trim_end().replace('\n', ",\n"));
+        assert_eq!(fmt_json("chr1", "inverted", &[], false, |_| 0), "");
+    }
+
+    #[test]
+    fn test_format_features() {
+        let vector = FeatureVector {
+            arm_len: 4,
+            gap: 2,
+            mismatches: 1,
+            gc: 0.5,
+            dg: -1.25,
+            entropy: 2.0,
+            degeneracy: 1.0,
+            flank: [0.25; 4],
+        };
+        let received = fmt_features(&[(0, 9, 2)], |_| vector);
+        let expected =
+            "1\t10\t4\t2\t1\t0.5000\t-1.2500\t2.0000\t1.0000\t0.2500\t0.2500\t0.2500\t0.2500\n";
+        assert_eq!(received, expected);
+        assert_eq!(
+            fmt_features_header().split('\t').count(),
+            received.split('\t').count()
+        );
+
+        let path = std::env::temp_dir().join(format!("iirs-features-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, format!("{}\n{received}", fmt_features_header())).unwrap();
+        let vectors = crate::vectors::read_feature_tsv(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(vectors, vec![vector.to_array()]);
+    }
+
+    #[test]
+    fn test_format_region_cap() {
+        let seq = "n".repeat(20).into_bytes();
+        let matrix = matrix::MatchMatrix::default();
+        let complement = build_complement_array();
+        let config = Config {
+            output_format: OutputFormat::Custom,
+            region_cap: Some(RegionCap {
+                window: 10,
+                max_hits: 1,
+            }),
+            ..Default::default()
+        };
+        let irs = [(0, 5, 0), (1, 6, 0), (12, 17, 0)];
+        let received = fmt_irs(&config, &irs, &seq, &matrix, &complement);
+        let expected = "\
+# DENSE REGION: 2 inverted repeats at positions 1..10 were not reported
+13,nnn,,nnn,15.5,9
+";
+        assert_eq!(received, expected);
+    }
 }
@@ -0,0 +1,90 @@
+//! Transparent huge pages for the index of long sequences (Linux).
+//!
+//! The extensions jump between random positions of the inverse suffix array and the LCP array,
+//! so on long sequences most of their reads miss the TLB. Backed by 2 MiB pages instead of
+//! 4 KiB pages, the same TLB covers 512 times more of these arrays.
+use std::fs;
+
+const HUGE_PAGE_BYTES: usize = 2 << 20;
+
+/// Buffers smaller than this are left to the allocator.
+const MIN_HUGE_BYTES: usize = 4 * HUGE_PAGE_BYTES;
+
+/// Clear `buf` and make room for `len` elements, backed by transparent huge pages where the
+/// kernel allows it. The pages are advised before being touched, so `buf` must be filled
+/// afterwards (e.g. with `resize`). Whether huge pages were advised.
+pub(crate) fn reserve_huge<T>(buf: &mut Vec<T>, len: usize) -> bool {
+    buf.clear();
+    buf.reserve(len);
+    let bytes = len * size_of::<T>();
+    bytes >= MIN_HUGE_BYTES && advise_huge_pages(buf.as_mut_ptr().cast(), bytes)
+}
+
+/// Empty vector with room for `len` elements, backed by transparent huge pages if
+/// `huge_pages` (see [`reserve_huge`]), for the arrays of the index allocated outside the arena.
+pub(crate) fn with_capacity<T>(len: usize, huge_pages: bool) -> Vec<T> {
+    let mut buf = Vec::new();
+    if huge_pages {
+        reserve_huge(&mut buf, len);
+    } else {
+        buf.reserve(len);
+    }
+    buf
+}
+
+/// Advise the huge pages lying entirely inside the `bytes` bytes from `start`.
+#[cfg(target_os = "linux")]
+fn advise_huge_pages(start: *mut u8, bytes: usize) -> bool {
+    use std::ffi::{c_int, c_void};
+
+    const MADV_HUGEPAGE: c_int = 14;
+    unsafe extern "C" {
+        fn madvise(addr: *mut c_void, length: usize, advice: c_int) -> c_int;
+    }
+
+    let first = start.addr().next_multiple_of(HUGE_PAGE_BYTES);
+    let end = (start.addr() + bytes) / HUGE_PAGE_BYTES * HUGE_PAGE_BYTES;
+    if end <= first {
+        return false;
+    }
+    let addr = start.wrapping_add(first - start.addr()).cast();
+    // SAFETY: the range is page-aligned and inside the allocation, and the advice does not
+    // change its content.
+    unsafe { madvise(addr, end - first, MADV_HUGEPAGE) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn advise_huge_pages(_start: *mut u8, _bytes: usize) -> bool {
+    false
+}
+
+/// Mode of transparent huge pages of the kernel (`always`, `madvise` or `never`), if it has
+/// them.
+pub fn transparent_huge_pages() -> Option<String> {
+    let enabled = fs::read_to_string("/sys/kernel/mm/transparent_hugepage/enabled").ok()?;
+    // The current mode is in brackets: `always [madvise] never`
+    let (_, current) = enabled.split_once('[')?;
+    let (mode, _) = current.split_once(']')?;
+    Some(mode.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_huge() {
+        let mut small: Vec<usize> = vec![1, 2, 3];
+        assert!(!reserve_huge(&mut small, 10));
+        assert!(small.is_empty() && small.capacity() >= 10);
+
+        let len = 2 * MIN_HUGE_BYTES / size_of::<usize>();
+        let mut large: Vec<usize> = Vec::new();
+        let advised = reserve_huge(&mut large, len);
+        large.resize(len, 7);
+        assert_eq!(large[len - 1], 7);
+        if cfg!(target_os = "linux") && transparent_huge_pages().is_some() {
+            assert!(advised);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Two-pass search: enumeration of the candidate centers of a sequence, then verification of
+//! every candidate, so that advanced users can parallelize or distribute the verification
+//! themselves.
+use anyhow::{Result, anyhow};
+use std::ops::Range;
+
+use crate::algo;
+use crate::arena::SearchArena;
+use crate::complement::{ComplementTable, complement_array};
+use crate::config::{RmqBackend, SaBackend, SearchParams, SymmetryMode};
+use crate::constants::build_complement_array;
+use crate::matrix::MatchMatrix;
+use crate::utils::sanitize_sequence;
+use crate::{IndexInt, IrMatch, IrSpan, LcpRmq, build_index, sort_irs};
+
+/// Index of a sequence (suffix array, LCP array and RMQ of the doubled sequence) for one
+/// symmetry mode, shared by any number of searches. It is `Sync`, so candidate centers can be
+/// verified from several threads.
+///
+/// Centers are given in the coordinates of the algorithm: the IRs of the center `c` satisfy
+/// `left + right = c` (see [`crate::Observer`]).
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{IrIndex, IrMatch, SearchParams, SymmetryMode, find_irs};
+///
+/// let seq = "acbbgtttaaacgt".as_bytes();
+/// let params = SearchParams::new(3, 6, 2, 0).unwrap();
+/// let index = IrIndex::new(seq, SymmetryMode::Inverted).unwrap();
+/// let centers = index.enumerate_centers(&params).unwrap();
+///
+/// let mut irs: Vec<_> = centers
+///     .iter()
+///     .flat_map(|&center| index.verify_center(&params, center).unwrap())
+///     .collect();
+/// irs.sort();
+/// let mut expected: Vec<_> = find_irs(&params, seq).unwrap().iter().map(IrMatch::span).collect();
+/// expected.sort();
+/// assert_eq!(irs, expected);
+/// ```
+pub struct IrIndex {
+    symmetry_mode: SymmetryMode,
+    complement: [u8; 128],
+    s: Vec<u8>,
+    inv_sa: Vec<IndexInt>,
+    rmq: LcpRmq,
+}
+
+impl IrIndex {
+    pub fn new(seq: &[u8], symmetry_mode: SymmetryMode) -> Result<Self> {
+        Self::with_rmq(seq, symmetry_mode, RmqBackend::default())
+    }
+
+    /// Same as [`IrIndex::new`] with the given range minimum query structure, e.g.
+    /// [`RmqBackend::Blocks`] to keep the index of a whole chromosome in memory.
+    pub fn with_rmq(seq: &[u8], symmetry_mode: SymmetryMode, backend: RmqBackend) -> Result<Self> {
+        let arena = SearchArena::default();
+        let complement = build_complement_array();
+        Self::build(
+            arena,
+            seq,
+            symmetry_mode,
+            SaBackend::default(),
+            backend,
+            complement,
+        )
+    }
+
+    /// Same as [`IrIndex::with_rmq`] for the parameters with the given
+    /// [`SearchParams::complement`].
+    pub fn with_complement(
+        seq: &[u8],
+        symmetry_mode: SymmetryMode,
+        backend: RmqBackend,
+        complement: &ComplementTable,
+    ) -> Result<Self> {
+        let arena = SearchArena::default();
+        let complement = *complement.as_array();
+        Self::build(
+            arena,
+            seq,
+            symmetry_mode,
+            SaBackend::default(),
+            backend,
+            complement,
+        )
+    }
+
+    /// Index of `seq` for `params`, with its suffix array and RMQ backends and huge pages.
+    pub(crate) fn for_params(seq: &[u8], params: &SearchParams) -> Result<Self> {
+        let arena = SearchArena {
+            huge_pages: params.huge_pages,
+            ..SearchArena::default()
+        };
+        let complement = complement_array(params);
+        Self::build(
+            arena,
+            seq,
+            params.symmetry_mode,
+            params.sa,
+            params.rmq,
+            complement,
+        )
+    }
+
+    fn build(
+        mut arena: SearchArena,
+        seq: &[u8],
+        symmetry_mode: SymmetryMode,
+        sa_backend: SaBackend,
+        rmq_backend: RmqBackend,
+        complement: [u8; 128],
+    ) -> Result<Self> {
+        let sanitized_seq = sanitize_sequence(seq)?;
+        let rmq = build_index(
+            &mut arena,
+            &sanitized_seq,
+            &symmetry_mode,
+            &complement,
+            sa_backend,
+            rmq_backend,
+        )?;
+        Ok(Self {
+            symmetry_mode,
+            complement,
+            s: arena.s,
+            inv_sa: arena.inv_sa,
+            rmq,
+        })
+    }
+
+    fn lce(&self) -> algo::IndexLce<'_, LcpRmq> {
+        algo::IndexLce {
+            inv_sa: &self.inv_sa,
+            rmq: &self.rmq,
+        }
+    }
+
+    fn check_params(&self, params: &SearchParams) -> Result<()> {
+        if params.symmetry_mode != self.symmetry_mode {
+            return Err(anyhow!(
+                "the index was built for the {:?} symmetry mode, not {:?}.",
+                self.symmetry_mode,
+                params.symmetry_mode
+            ));
+        }
+        if complement_array(params) != self.complement {
+            return Err(anyhow!(
+                "the index was built for another complement of the bases: see IrIndex::with_complement."
+            ));
+        }
+        params.check_bounds(self.s.len() / 2 - 1)
+    }
+
+    /// Centers with at least one IR before the post-filters (score, complexity, mismatch
+    /// rate...), by position. This is the expensive part of the search.
+    ///
+    /// The candidates stay valid for parameters with the same `max_gap` and `mismatches`,
+    /// an equal or larger `min_len`, and any `max_len` and post-filters.
+    pub fn enumerate_centers(&self, params: &SearchParams) -> Result<Vec<usize>> {
+        self.check_params(params)?;
+        let matrix = MatchMatrix::from_params(params);
+        let range = algo::center_range(self.s.len(), params);
+        Ok(algo::candidate_centers(
+            &self.s,
+            &self.lce(),
+            params,
+            &matrix,
+            range,
+        ))
+    }
+
+    /// IRs of the center `center`, post-filtered and sorted as by [`crate::find_irs`].
+    pub fn verify_center(&self, params: &SearchParams, center: usize) -> Result<Vec<IrSpan>> {
+        self.check_params(params)?;
+        if !algo::center_range(self.s.len(), params).contains(&center) {
+            return Ok(Vec::new());
+        }
+        let matrix = MatchMatrix::from_params(params);
+        let mut irs = algo::irs_at_center(&self.s, &self.lce(), params, &matrix, center);
+        sort_irs(&mut irs);
+        Ok(irs)
+    }
+}
+/// Lazy search over an index: every center is verified when the IRs of the previous ones were
+/// consumed (see [`crate::iter_irs`]).
+pub(crate) struct IrIter {
+    index: IrIndex,
+    params: SearchParams,
+    matrix: MatchMatrix,
+    complement: [u8; 128],
+    centers: Range<usize>,
+    pending: std::vec::IntoIter<IrSpan>,
+}
+
+impl IrIter {
+    pub(crate) fn new(index: IrIndex, params: &SearchParams) -> Self {
+        let centers = algo::center_range(index.s.len(), params);
+        Self {
+            index,
+            params: params.clone(),
+            matrix: MatchMatrix::from_params(params),
+            complement: index.complement,
+            centers,
+            pending: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl Iterator for IrIter {
+    type Item = IrMatch;
+
+    fn next(&mut self) -> Option<IrMatch> {
+        loop {
+            if let Some(ir) = self.pending.next() {
+                let IrIndex { s, .. } = &self.index;
+                let seq = &s[..s.len() / 2 - 1];
+                let mode = self.params.symmetry_mode;
+                return Some(IrMatch::from_span(
+                    ir,
+                    seq,
+                    mode,
+                    &self.matrix,
+                    &self.complement,
+                ));
+            }
+            let center = self.centers.next()?;
+            let index = &self.index;
+            let lce = index.lce();
+            let mut irs = algo::irs_at_center(&index.s, &lce, &self.params, &self.matrix, center);
+            sort_irs(&mut irs);
+            self.pending = irs.into_iter();
+        }
+    }
+}
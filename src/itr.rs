@@ -0,0 +1,127 @@
+//! Quality control of the inverted terminal repeats (ITRs) of AAV or lentiviral constructs.
+use anyhow::Result;
+use std::fmt;
+
+use crate::complement::complement_array;
+use crate::config::SearchParams;
+use crate::matrix::MatchMatrix;
+use crate::score::{arm_len, count_mismatches};
+use crate::utils::sanitize_sequence;
+use crate::{IrSpan, find_spans};
+
+/// Result of [`itr_qc`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItrQc {
+    /// The outermost IR with arms of at least `min_len` (the two copies of the ITR),
+    /// and its number of mismatches.
+    pub itr: Option<(IrSpan, usize)>,
+    /// IRs with arms of at least the internal threshold lying between the two ITRs
+    /// (or anywhere if they were not found).
+    pub internal: Vec<IrSpan>,
+}
+
+impl ItrQc {
+    /// Both ITR copies are present and intact (within the mismatch tolerance), and there is no
+    /// unexpected internal IR.
+    pub fn passed(&self) -> bool {
+        self.itr.is_some() && self.internal.is_empty()
+    }
+}
+
+/// Check that a construct has its two ITRs, as an IR whose arms are the ITR copies:
+/// arms of at least `params.min_len` with at most `params.mismatches` mismatches, separated by
+/// the cassette (at most `params.max_gap`). Then look for unexpected IRs with arms of at least
+/// `internal_min_len` inside the cassette.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{SearchParams, itr_qc};
+///
+/// // Two copies of an 8 bp ITR around a cassette without IRs
+/// let seq = "ccatgcaagatcctgaagtcaaggactattgcatgg".as_bytes();
+/// let params = SearchParams::new(8, 100, 30, 0).unwrap();
+/// let qc = itr_qc(&params, 6, seq).unwrap();
+/// assert_eq!(qc.itr, Some(((0, 35, 20), 0)));
+/// assert!(qc.passed());
+/// ```
+pub fn itr_qc(params: &SearchParams, internal_min_len: usize, seq: &[u8]) -> Result<ItrQc> {
+    let seq = sanitize_sequence(seq)?;
+    let matrix = MatchMatrix::from_params(params);
+    let complement = complement_array(params);
+
+    // The outermost IR, and the one with the fewest mismatches among them.
+    let itr = find_spans(params, &seq)?
+        .into_iter()
+        .map(|ir| {
+            (
+                ir,
+                count_mismatches(&ir, &seq, &params.symmetry_mode, &matrix, &complement),
+            )
+        })
+        .min_by_key(|&((left, right, _), mismatches)| (left, std::cmp::Reverse(right), mismatches));
+
+    let mut internal_params = params.clone();
+    internal_params.min_len = internal_min_len;
+    let internal = find_spans(&internal_params, &seq)?
+        .into_iter()
+        .filter(|ir| match itr {
+            Some((itr, _)) => {
+                let len = arm_len(&itr);
+                ir.0 >= itr.0 + len && ir.1 + len <= itr.1
+            }
+            None => true,
+        })
+        .collect();
+
+    Ok(ItrQc { itr, internal })
+}
+
+impl fmt::Display for ItrQc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.itr {
+            Some((itr, mismatches)) => {
+                let len = arm_len(&itr);
+                writeln!(
+                    f,
+                    "ITRs: {}..{} and {}..{} (length {}, {} mismatches)",
+                    itr.0 + 1,
+                    itr.0 + len,
+                    itr.1 + 2 - len,
+                    itr.1 + 1,
+                    len,
+                    mismatches
+                )?;
+            }
+            None => writeln!(f, "ITRs: not found")?,
+        }
+        writeln!(f, "Internal IRs: {}", self.internal.len())?;
+        for &(left, right, gap) in &self.internal {
+            writeln!(f, "{}..{} (gap {})", left + 1, right + 1, gap)?;
+        }
+        write!(f, "{}", if self.passed() { "PASS" } else { "FAIL" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_itr_qc() {
+        let params = SearchParams::new(8, 100, 30, 0).unwrap();
+
+        // A hairpin inside the cassette
+        let seq = b"ccatgcaagatcgtacgtacaaaagtacgtactattgcatgg";
+        let qc = itr_qc(&params, 6, seq).unwrap();
+        assert_eq!(qc.itr, Some(((0, 41, 26), 0)));
+        assert_eq!(qc.internal, vec![(12, 31, 4)]);
+        assert!(!qc.passed());
+
+        // The right copy is damaged
+        let seq = b"ccatgcaagatcctgaagtcaaggactattgcttgg";
+        let qc = itr_qc(&params, 6, seq).unwrap();
+        assert_eq!(qc.itr, None);
+        assert!(!qc.passed());
+    }
+}
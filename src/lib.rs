@@ -1,34 +1,264 @@
 mod cli;
-pub use cli::Cli;
+pub use cli::{
+    ChainArgs, Cli, Command, CompareArgs, ConvertArgs, EnrichmentArgs, MaskArgs, SetOperationArgs,
+    ShuffleArgs,
+};
 
 mod config;
-pub use config::{Config, OutputFormat, SearchParams, SymmetryMode};
+pub use config::{
+    Config, GapStrata, OutputFormat, ParamSet, RegionCap, RmqBackend, SaBackend, SearchParams,
+    SortBy, StrataOutput, SymmetryMode, Warning,
+};
 
 mod constants;
+pub use constants::{ALL_SEQUENCES, FASTA_LINE_WIDTH, Profile, ProfilePreset};
+
+mod columns;
+mod compare;
+pub use compare::{IrChange, IrDiff, compare_irs, fmt_ir_diffs_header};
+mod complement;
+pub use complement::ComplementTable;
+mod complexity;
+pub use columns::IrColumns;
+
+mod control;
+pub use control::{MaxHitsAction, PartialIrs, SearchSummary, StopHandle, StopReason};
+mod dedup;
+pub use dedup::{DedupSink, StreamDedup};
 
 mod algo;
+mod arena;
+#[cfg(feature = "arena")]
+pub use arena::SearchArena;
+mod chain;
+pub use chain::{IrChain, chain_irs, fmt_chains_header};
+mod annotate;
+mod chunked;
+mod circular;
+pub use annotate::{Feature, Features, read_features};
+mod bed;
+mod binary;
+pub use binary::{BINARY_VERSION, BinaryResults, is_binary_results};
+mod enrichment;
+pub use enrichment::{WindowEnrichment, fmt_enrichment_header, ir_enrichment};
+mod families;
+mod flatfile;
+pub use families::label_families;
 mod format;
+mod hugepages;
+pub use hugepages::transparent_huge_pages;
+mod index;
+pub use index::IrIndex;
+mod itr;
+pub use itr::{ItrQc, itr_qc};
+mod links;
+pub use links::BrowserLinks;
+mod mask;
+pub use mask::{MaskMode, mask_irs};
+mod manifest;
+pub use manifest::{Manifest, ManifestEntry, crc32_file};
 mod matrix;
+mod memory;
+pub use memory::MemoryEstimate;
+mod normalize;
+pub use normalize::{CanonicalIr, normalize_irs};
+#[cfg(feature = "numa")]
+mod numa;
+#[cfg(feature = "numa")]
+pub use numa::{NumaTopology, parse_cpulist};
+mod observer;
+use observer::NoObserver;
+#[cfg(feature = "observer")]
+pub use observer::{AlgoEvent, Observer};
+mod pipeline;
+#[cfg(feature = "remote")]
+mod remote;
+pub use pipeline::pipeline_records;
+#[cfg(feature = "wasm")]
+mod plugin;
+#[cfg(feature = "wasm")]
+pub use plugin::WasmPlugin;
+mod progress;
+pub use progress::{ProgressFormat, ProgressReporter};
+#[cfg(feature = "bam")]
+mod reads;
+#[cfg(feature = "index32")]
+mod rmq32;
+mod rmq_blocks;
+#[cfg(feature = "bam")]
+pub use reads::scan_reads;
+mod result_index;
+pub use result_index::ResultIndex;
+#[cfg(feature = "sais")]
+mod sais;
+mod score;
+mod seeds;
+mod setops;
+mod simd;
+mod sink;
+pub use sink::{EinvertedSeqSink, FormatSink, ResultSink};
+mod shuffle;
+mod spill;
+pub use score::{ScoreScheme, Scoring};
+pub use setops::{ResultFile, SetOperation, irs_match, set_operation};
+pub use shuffle::{ShuffleMode, shuffle_sequence};
+mod stats;
+pub use stats::{PerfCounters, PerfReport, peak_rss};
+mod thermo;
+pub use thermo::{
+    HairpinThermo, Supercoiling, ThermoConditions, extrusion_probability, hairpin_thermo,
+};
 mod utils;
+mod validate;
+pub use validate::{IrCheck, IrViolation, is_ir};
+mod vectors;
+pub use vectors::{FEATURE_NAMES, FLANK_LEN, FeatureVector, read_feature_tsv, write_npy};
+
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::io::Write;
+use std::time::Instant;
+
+/// An inverted repeat as (start position, end position, gap size), 0-based and inclusive.
+/// The compact form of the IRs taken by the rest of the library (sinks, output formats, set
+/// operations...).
+pub type IrSpan = (usize, usize, usize);
+
+/// An inverted repeat found by [`find_irs`]. Positions are 0-based and inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrMatch {
+    /// Start of the left arm.
+    pub start: usize,
+    /// End of the right arm. Past the end of a circular sequence when the IR spans its
+    /// origin (see [`SearchParams::circular`]).
+    pub end: usize,
+    pub arm_len: usize,
+    pub gap: usize,
+    /// Number of pairs of the arms that do not match.
+    pub mismatches: usize,
+    /// How the arms were paired.
+    pub mode: SymmetryMode,
+}
+
+impl IrMatch {
+    /// The IR as (start, end, gap), for the functions taking an [`IrSpan`].
+    pub fn span(&self) -> IrSpan {
+        (self.start, self.end, self.gap)
+    }
+
+    /// The IR `ir` of the sanitized `seq`, with its mismatches under `mode`.
+    fn from_span(
+        ir: IrSpan,
+        seq: &[u8],
+        mode: SymmetryMode,
+        matrix: &matrix::MatchMatrix,
+        complement: &[u8; 128],
+    ) -> Self {
+        Self {
+            start: ir.0,
+            end: ir.1,
+            arm_len: score::arm_len(&ir),
+            gap: ir.2,
+            mismatches: score::mismatched_pairs(&ir, seq, &mode, matrix, complement).len(),
+            mode,
+        }
+    }
+}
+
+/// Id of a record (sequence) of the input file.
+pub type RecordId = String;
+
+/// 1-based center (midpoint) of an IR. It ends in .5 when the IR has an even length.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::ir_center;
+///
+/// // "acbbgt" spans positions 1 to 6
+/// assert_eq!(ir_center(&(0, 5, 0)), 3.5);
+/// assert_eq!(ir_center(&(0, 6, 1)), 4.0);
+/// ```
+pub fn ir_center(ir: &IrSpan) -> f64 {
+    (ir.0 + ir.1 + 2) as f64 / 2.0
+}
+
+/// Whether an IR found in `seq` is maximal, i.e. its arms cannot be extended under the
+/// parameters, as opposed to an IR whose arms were clipped by `params.max_len`.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{SearchParams, find_irs, is_maximal};
+///
+/// let seq = "aacgtt".as_bytes();
+/// let params = SearchParams::new(2, 2, 0, 0).unwrap();
+/// let irs = find_irs(&params, &seq).unwrap();
+/// assert!(irs.iter().all(|ir| !is_maximal(&params, &ir.span(), &seq).unwrap()));
+/// let params = SearchParams::new(2, 3, 0, 0).unwrap();
+/// assert!(is_maximal(&params, &(0, 5, 0), &seq).unwrap());
+/// ```
+pub fn is_maximal(params: &SearchParams, ir: &IrSpan, seq: &[u8]) -> Result<bool> {
+    let seq = utils::sanitize_sequence(seq)?;
+    let matrix = matrix::MatchMatrix::from_params(params);
+    let complement = complement::complement_array(params);
+    Ok(!score::is_clipped(ir, &seq, params, &matrix, &complement))
+}
 
-use anyhow::Result;
+/// Feature vectors of IRs found in `seq`, e.g. to train a classifier on IR calls.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{FEATURE_NAMES, IrMatch, SearchParams, feature_vectors, find_irs};
+///
+/// let seq = "aaaaccgcgntttttcgcggttttt".as_bytes();
+/// let params = SearchParams::new(5, 10, 6, 0).unwrap();
+/// let irs: Vec<_> = find_irs(&params, &seq).unwrap().iter().map(IrMatch::span).collect();
+/// let vectors = feature_vectors(&params, &irs, &seq).unwrap();
+/// assert_eq!(vectors.len(), irs.len());
+/// assert_eq!(vectors[0].to_array().len(), FEATURE_NAMES.len());
+/// ```
+pub fn feature_vectors(
+    params: &SearchParams,
+    irs: &[IrSpan],
+    seq: &[u8],
+) -> Result<Vec<FeatureVector>> {
+    let seq = utils::sanitize_sequence(seq)?;
+    let matrix = matrix::MatchMatrix::from_params(params);
+    let complement = complement::complement_array(params);
+    Ok(irs
+        .iter()
+        .map(|ir| vectors::feature_vector(ir, &seq, params, &matrix, &complement))
+        .collect())
+}
 
 /// Find all the [Inverted Repeats](https://en.wikipedia.org/wiki/Inverted_repeat) (IRs) in a sequence
 /// based on the provided parameters.
 ///
-/// Each IR is returned a tuple of three integers (usize): start position, end position, and gap size.
+/// The IRs are sorted by start position. The other `find_irs_*` functions return them as
+/// [`IrSpan`] tuples, as [`IrMatch::span`] does.
 ///
 /// # Examples
 ///
 /// ```rust
-/// use iirs::{SearchParams, find_irs};
+/// use iirs::{IrMatch, SearchParams, SymmetryMode, find_irs};
 ///
 /// let seq = "acbbgt".as_bytes();
 /// let params = SearchParams::new(3, 6, 2, 0).unwrap();
 /// assert!(params.check_bounds(seq.len()).is_ok());
-/// let irs = find_irs(&params, &seq);
-/// // The only IR in the sequence is "acbbg"
-/// assert_eq!(irs.unwrap(), vec![(0, 5, 0)]);
+/// let irs = find_irs(&params, &seq).unwrap();
+/// // The only IR in the sequence is "acbbgt"
+/// let ir = IrMatch {
+///     start: 0,
+///     end: 5,
+///     arm_len: 3,
+///     gap: 0,
+///     mismatches: 0,
+///     mode: SymmetryMode::Inverted,
+/// };
+/// assert_eq!(irs, vec![ir]);
+/// assert_eq!(ir.span(), (0, 5, 0));
 ///
 /// // Returns an error if the given sequence contains invalid characters
 /// let seq = "jj".as_bytes();
@@ -37,67 +267,983 @@ use anyhow::Result;
 ///
 /// // It is not case-sensitive and ignores newlines.
 /// let seq = "ACB\n\rBGT".as_bytes();
-/// let irs = find_irs(&params, &seq);
-/// assert_eq!(irs.unwrap(), vec![(0, 5, 0)]);
+/// let irs = find_irs(&params, &seq).unwrap();
+/// assert_eq!(irs, vec![ir]);
 /// ```
-#[elapsed_time::elapsed]
-pub fn find_irs(params: &SearchParams, seq: &[u8]) -> Result<Vec<(usize, usize, usize)>> {
+pub fn find_irs(params: &SearchParams, seq: &[u8]) -> Result<Vec<IrMatch>> {
+    Ok(find_irs_until(params, seq, &StopHandle::new())?.irs)
+}
+
+/// The IRs `irs` of the sanitized `seq` as [`IrMatch`]es, with their mismatches counted.
+fn ir_matches(params: &SearchParams, sanitized_seq: &[u8], irs: Vec<IrSpan>) -> Vec<IrMatch> {
+    let matrix = matrix::MatchMatrix::from_params(params);
+    let complement = complement::complement_array(params);
+    let seq = circular::unrolled(params, sanitized_seq);
+    irs.into_iter()
+        .map(|ir| IrMatch::from_span(ir, &seq, params.symmetry_mode, &matrix, &complement))
+        .collect()
+}
+
+/// Same as [`find_irs`], but `sink` is called on every IR as soon as the search finds it, e.g.
+/// to write the IRs straight into a database, instead of collecting them first.
+///
+/// The IRs are handed by windows of centers, and are not sorted within a window.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{SearchParams, find_irs_with};
+///
+/// let seq = "acbbgtttaaacgt".as_bytes();
+/// let params = SearchParams::new(3, 6, 2, 0).unwrap();
+/// let mut arms = Vec::new();
+/// find_irs_with(&params, seq, |ir| arms.push(ir.arm_len)).unwrap();
+/// arms.sort();
+/// assert_eq!(arms, vec![3, 5]);
+/// ```
+pub fn find_irs_with<F: FnMut(IrMatch)>(
+    params: &SearchParams,
+    seq: &[u8],
+    mut sink: F,
+) -> Result<()> {
+    let seq = utils::sanitize_sequence(seq)?;
+    let matrix = matrix::MatchMatrix::from_params(params);
+    let complement = complement::complement_array(params);
+    let mode = params.symmetry_mode;
+    let unrolled_seq = circular::unrolled(params, &seq);
+    search(
+        params,
+        &seq,
+        &StopHandle::new(),
+        &NoObserver,
+        |window_irs, _| {
+            for ir in window_irs {
+                sink(IrMatch::from_span(
+                    ir,
+                    &unrolled_seq,
+                    mode,
+                    &matrix,
+                    &complement,
+                ));
+            }
+            Ok(())
+        },
+    )?;
+    Ok(())
+}
+
+/// Same as [`find_irs`], but the IRs are yielded as the centers are scanned instead of being
+/// collected first, so that the memory stays flat however many IRs the parameters find. Only
+/// the index of the sequence is built upfront.
+///
+/// The IRs come by center (`start + end`), and by start within a center, instead of by start.
+///
+/// The index is built with the [`SearchParams::sa`] and [`SearchParams::rmq`] backends (and
+/// [`SearchParams::huge_pages`]). It is built whole: [`SearchParams::max_memory`] and
+/// [`SearchParams::chunk_len`], which split the search in chunks, are rejected.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{SearchParams, find_irs, iter_irs};
+///
+/// let seq = "acbbgtttaaacgt".as_bytes();
+/// let params = SearchParams::new(3, 6, 2, 0).unwrap();
+/// // Stop at the first IR with arms of 5 bases
+/// let ir = iter_irs(&params, seq).unwrap().find(|ir| ir.arm_len == 5);
+/// assert_eq!(ir.unwrap().span(), (3, 12, 0));
+///
+/// let mut irs: Vec<_> = iter_irs(&params, seq).unwrap().collect();
+/// irs.sort_by_key(|ir| ir.span());
+/// let mut expected = find_irs(&params, seq).unwrap();
+/// expected.sort_by_key(|ir| ir.span());
+/// assert_eq!(irs, expected);
+/// ```
+pub fn iter_irs(params: &SearchParams, seq: &[u8]) -> Result<impl Iterator<Item = IrMatch>> {
+    if params.circular {
+        return Err(anyhow::anyhow!(
+            "iter_irs does not support circular sequences: use find_irs_with."
+        ));
+    }
+    if params.max_memory.is_some() || params.chunk_len.is_some() {
+        return Err(anyhow::anyhow!(
+            "iter_irs builds the index of the whole sequence: unset max_memory and chunk_len, or use find_irs_with."
+        ));
+    }
+    let index = IrIndex::for_params(seq, params)?;
+    Ok(index::IrIter::new(index, params))
+}
+
+/// [`find_irs`] before it returned [`IrMatch`]: the IRs as (start, end, gap) tuples.
+#[deprecated(note = "use `find_irs`, and `IrMatch::span` where a tuple is needed")]
+pub fn find_irs_tuples(params: &SearchParams, seq: &[u8]) -> Result<Vec<IrSpan>> {
+    find_spans(params, seq)
+}
+
+/// The IRs of [`find_irs`] as spans, without counting their mismatches.
+pub(crate) fn find_spans(params: &SearchParams, seq: &[u8]) -> Result<Vec<IrSpan>> {
+    let sanitized_seq = utils::sanitize_sequence(seq)?;
+    Ok(find_spans_until(params, &sanitized_seq, &StopHandle::new())?.0)
+}
+
+/// Same as [`find_irs`], but only for the IRs centered at (or half a position around)
+/// the 0-based position `center`, which is much faster than a full search.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{SearchParams, find_irs_at, ir_center};
+///
+/// let seq = "acbbgtttaaacgt".as_bytes();
+/// let params = SearchParams::new(3, 6, 2, 0).unwrap();
+/// let irs = find_irs_at(&params, &seq, 2).unwrap();
+/// assert_eq!(irs.len(), 1);
+/// assert_eq!(irs[0].span(), (0, 5, 0));
+/// assert_eq!(ir_center(&irs[0].span()), 3.5);
+/// ```
+pub fn find_irs_at(params: &SearchParams, seq: &[u8], center: usize) -> Result<Vec<IrMatch>> {
+    let mut params = params.clone();
+    params.anchor = Some(center);
+    find_irs(&params, seq)
+}
+
+/// Same as [`find_irs`], but the IRs are stored in a caller-provided vector, so that its
+/// allocation can be reused when searching many (short) sequences.
+///
+/// If `clear` is true, the previous content of `irs` is discarded, otherwise the new IRs are
+/// appended (only the appended part is sorted).
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{IrMatch, SearchParams, find_irs_into};
+///
+/// let params = SearchParams::new(3, 6, 2, 0).unwrap();
+/// let mut irs = Vec::new();
+/// for seq in ["acbbgt", "ttacbbgt"] {
+///     find_irs_into(&params, seq.as_bytes(), &mut irs, false).unwrap();
+/// }
+/// let spans: Vec<_> = irs.iter().map(IrMatch::span).collect();
+/// assert_eq!(spans, vec![(0, 5, 0), (2, 7, 0)]);
+///
+/// find_irs_into(&params, "acbbgt".as_bytes(), &mut irs, true).unwrap();
+/// assert_eq!(irs.len(), 1);
+/// assert_eq!(irs[0].span(), (0, 5, 0));
+/// ```
+pub fn find_irs_into(
+    params: &SearchParams,
+    seq: &[u8],
+    irs: &mut Vec<IrMatch>,
+    clear: bool,
+) -> Result<()> {
+    let sanitized_seq = utils::sanitize_sequence(seq)?;
+    let (spans, _) = find_spans_until(params, &sanitized_seq, &StopHandle::new())?;
+
+    if clear {
+        irs.clear();
+    }
+    irs.extend(ir_matches(params, &sanitized_seq, spans));
+
+    Ok(())
+}
+
+/// Same as [`find_irs`], but the temporary structures of the search are built in the buffers
+/// of `arena`, which are reused across calls. This cuts the allocator overhead when searching
+/// millions of short sequences.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{SearchArena, SearchParams, find_irs, find_irs_in};
+///
+/// let params = SearchParams::new(3, 6, 2, 0).unwrap();
+/// let mut arena = SearchArena::new();
+/// for seq in ["acbbgt", "ttacbbgt"] {
+///     let irs = find_irs_in(&mut arena, &params, seq.as_bytes()).unwrap();
+///     assert_eq!(irs, find_irs(&params, seq.as_bytes()).unwrap());
+/// }
+/// assert!(arena.capacity() > 0);
+/// ```
+#[cfg(feature = "arena")]
+pub fn find_irs_in(
+    arena: &mut SearchArena,
+    params: &SearchParams,
+    seq: &[u8],
+) -> Result<Vec<IrMatch>> {
+    // Taken out of the arena while the other buffers are in use.
+    let mut sanitized_seq = std::mem::take(&mut arena.seq);
+    let result = utils::sanitize_sequence_into(seq, &mut sanitized_seq).and_then(|()| {
+        let mut irs = Vec::new();
+        search_all_in(
+            arena,
+            std::slice::from_ref(params),
+            &sanitized_seq,
+            &StopHandle::new(),
+            &NoObserver,
+            |_, window_irs, _| {
+                irs.extend(window_irs);
+                Ok(())
+            },
+        )?;
+        sort_irs(&mut irs);
+        Ok(ir_matches(params, &sanitized_seq, irs))
+    });
+    arena.seq = sanitized_seq;
+    result
+}
+
+/// Same as [`find_irs`] for several sets of parameters at once, which is much cheaper than
+/// independent searches: the index of the sequence is only built once (per symmetry mode).
+///
+/// The IRs of every set are returned in the same order as `params_list`.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{SearchParams, find_irs, find_irs_multi};
+///
+/// let seq = "acbbgtttaaacgt".as_bytes();
+/// let params_list = [
+///     SearchParams::new(3, 6, 2, 0).unwrap(),
+///     SearchParams::new(4, 8, 4, 1).unwrap(),
+/// ];
+/// let results = find_irs_multi(&params_list, &seq).unwrap();
+/// for (params, irs) in params_list.iter().zip(&results) {
+///     assert_eq!(irs, &find_irs(params, &seq).unwrap());
+/// }
+/// ```
+pub fn find_irs_multi(params_list: &[SearchParams], seq: &[u8]) -> Result<Vec<Vec<IrMatch>>> {
+    let sanitized_seq = utils::sanitize_sequence(seq)?;
+    Ok(params_list
+        .iter()
+        .zip(find_spans_multi(params_list, &sanitized_seq)?)
+        .map(|(params, irs)| ir_matches(params, &sanitized_seq, irs))
+        .collect())
+}
+
+/// The IRs of [`find_irs_multi`] as spans, for the sanitized `seq`.
+fn find_spans_multi(
+    params_list: &[SearchParams],
+    sanitized_seq: &[u8],
+) -> Result<Vec<Vec<IrSpan>>> {
+    let mut results = vec![Vec::new(); params_list.len()];
+    search_all(
+        params_list,
+        sanitized_seq,
+        &StopHandle::new(),
+        &NoObserver,
+        |idx, window_irs, _| {
+            results[idx].extend(window_irs);
+            Ok(())
+        },
+    )?;
+    for irs in &mut results {
+        sort_irs(irs);
+    }
+
+    Ok(results)
+}
+
+/// Search the records of `config.input_file` with `config.params`: every record if
+/// `config.seq_name` is `ALL_SEQUENCES` (e.g. a bacterial chromosome and its plasmids), else
+/// only that one. The IRs are grouped by record, in the order of the file, and every group is
+/// sorted as in [`find_irs`].
+///
+/// The records are read one at a time, so only the largest one has to fit in memory. Errors
+/// if the parameters are out of bounds for one of the records.
+pub fn find_irs_all(config: &Config) -> Result<Vec<(RecordId, Vec<IrMatch>)>> {
+    use seq_io::fasta::Record;
+
+    let mut results = Vec::new();
+    let seq_ids = [config.seq_name.to_string()];
+    utils::for_each_record(config.input_file, &seq_ids, |record| {
+        let id = record.id()?.to_string();
+        config
+            .params
+            .check_bounds(record.seq.len())
+            .with_context(|| format!("Cannot search {id}"))?;
+        results.push((id, find_irs(&config.params, &record.seq)?));
+        Ok(())
+    })?;
+    Ok(results)
+}
+
+/// Same as [`find_irs`], but every [`AlgoEvent`] of the search is reported to `observer`.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{AlgoEvent, Observer, SearchParams, find_irs_observed};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// #[derive(Default)]
+/// struct HitCounter(AtomicUsize);
+///
+/// impl Observer for HitCounter {
+///     fn on_event(&self, event: AlgoEvent) {
+///         if let AlgoEvent::Hit { .. } = event {
+///             self.0.fetch_add(1, Ordering::Relaxed);
+///         }
+///     }
+/// }
+///
+/// let params = SearchParams::new(3, 6, 2, 0).unwrap();
+/// let counter = HitCounter::default();
+/// let irs = find_irs_observed(&params, "acbbgt".as_bytes(), &counter).unwrap();
+/// assert_eq!(counter.0.load(Ordering::Relaxed), irs.len());
+/// ```
+#[cfg(feature = "observer")]
+pub fn find_irs_observed<O: Observer>(
+    params: &SearchParams,
+    seq: &[u8],
+    observer: &O,
+) -> Result<Vec<IrMatch>> {
+    let sanitized_seq = utils::sanitize_sequence(seq)?;
+
+    let mut irs = Vec::new();
+    search(
+        params,
+        &sanitized_seq,
+        &StopHandle::new(),
+        observer,
+        |window_irs, _| {
+            irs.extend(window_irs);
+            Ok(())
+        },
+    )?;
+    sort_irs(&mut irs);
+
+    Ok(ir_matches(params, &sanitized_seq, irs))
+}
+
+/// Same as [`find_irs`], but the search can be stopped early through a [`StopHandle`]
+/// (on demand, after a time limit or after finding too many IRs).
+///
+/// When stopped, the IRs found so far are returned, together with the number of positions
+/// whose IRs are guaranteed to have been found.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{SearchParams, StopHandle, StopReason, find_irs_until};
+///
+/// let seq = "acbbgt".as_bytes();
+/// let params = SearchParams::new(3, 6, 2, 0).unwrap();
+/// let stop = StopHandle::new();
+/// let partial = find_irs_until(&params, &seq, &stop).unwrap();
+/// assert_eq!(partial.irs.len(), 1);
+/// assert_eq!(partial.irs[0].span(), (0, 5, 0));
+/// assert_eq!(partial.stopped, None);
+///
+/// // A search that is already stopped does not examine anything.
+/// stop.stop();
+/// let partial = find_irs_until(&params, &seq, &stop).unwrap();
+/// assert!(partial.irs.is_empty());
+/// assert_eq!(partial.stopped, Some(StopReason::Interrupted));
+/// ```
+pub fn find_irs_until(params: &SearchParams, seq: &[u8], stop: &StopHandle) -> Result<PartialIrs> {
     // Removes newlines, cast to lowercase and checks that all the character are in IUPAC.
     // This was already done through the CLI, but we need to do it again for the standalone version.
     let sanitized_seq = utils::sanitize_sequence(seq)?;
+    let (irs, summary) = find_spans_until(params, &sanitized_seq, stop)?;
+
+    Ok(PartialIrs {
+        irs: ir_matches(params, &sanitized_seq, irs),
+        covered: summary.covered,
+        found: summary.found,
+        stopped: summary.stopped,
+    })
+}
+
+/// The IRs of [`find_irs_until`] as spans, for the sanitized `seq`.
+#[elapsed_time::elapsed]
+fn find_spans_until(
+    params: &SearchParams,
+    sanitized_seq: &[u8],
+    stop: &StopHandle,
+) -> Result<(Vec<IrSpan>, SearchSummary)> {
+    let mut irs = Vec::new();
+    let summary = search(params, sanitized_seq, stop, &NoObserver, |window_irs, _| {
+        irs.extend(window_irs);
+        Ok(())
+    })?;
+    sort_irs(&mut irs);
+
+    Ok((irs, summary))
+}
+
+/// Find the IRs of a sequence and write them to `out` in the configured output format
+/// as soon as they are final, instead of collecting them first.
+///
+/// The output is the same as the one of [`find_irs`] followed by [`stringify_irs`]
+/// (header, newline, IRs, newline), while the memory used for the IRs stays bounded.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{Config, OutputFormat, SearchParams, StopHandle, write_irs};
+///
+/// let seq = "acbbgt".as_bytes();
+/// let config = Config {
+///     params: SearchParams::new(3, 6, 2, 0).unwrap(),
+///     output_format: OutputFormat::Csv,
+///     ..Default::default()
+/// };
+/// let mut out = Vec::new();
+/// let summary = write_irs(&config, &seq, &StopHandle::new(), &mut out).unwrap();
+/// assert_eq!(summary.found, 1);
+/// let expected = "\
+///     start_n,end_n,nucleotide,start_ir,end_ir,reverse_complement,matching\n\
+///     1,3,acb,6,4,tgb,111\n\n";
+/// assert_eq!(String::from_utf8(out).unwrap(), expected);
+/// ```
+pub fn write_irs<W: Write>(
+    config: &Config,
+    seq: &[u8],
+    stop: &StopHandle,
+    out: &mut W,
+) -> Result<SearchSummary> {
+    write_irs_observed(config, seq, stop, &NoObserver, &mut [FormatSink::new(out)])
+}
+
+/// Same as [`write_irs`], while accumulating the work done by the search into `counters`.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{Config, PerfCounters, SearchParams, StopHandle, write_irs_counted};
+///
+/// let config = Config {
+///     params: SearchParams::new(3, 6, 2, 0).unwrap(),
+///     ..Default::default()
+/// };
+/// let counters = PerfCounters::new();
+/// let mut out = Vec::new();
+/// write_irs_counted(&config, "acbbgt".as_bytes(), &StopHandle::new(), &counters, &mut out).unwrap();
+/// assert_eq!(counters.hits(), 1);
+/// assert!(counters.centers() > 0);
+/// ```
+pub fn write_irs_counted<W: Write>(
+    config: &Config,
+    seq: &[u8],
+    stop: &StopHandle,
+    counters: &PerfCounters,
+    out: &mut W,
+) -> Result<SearchSummary> {
+    write_irs_observed(config, seq, stop, counters, &mut [FormatSink::new(out)])
+}
+
+/// Same as [`write_irs_counted`], but the IRs of every gap stratum of `config.gap_strata`
+/// go to their own output, each one with its header.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{Config, GapStrata, OutputFormat, PerfCounters, SearchParams, StopHandle};
+/// use iirs::{StrataOutput, write_irs_stratified};
+///
+/// // Gapless IRs, and IRs with a gap
+/// let config = Config {
+///     params: SearchParams::new(3, 8, 2, 0).unwrap(),
+///     output_format: OutputFormat::Csv,
+///     gap_strata: Some(GapStrata::new(vec![0], StrataOutput::Files).unwrap()),
+///     ..Default::default()
+/// };
+/// let counters = PerfCounters::new();
+/// let mut outs = vec![Vec::new(), Vec::new()];
+/// let seq = "acbbgtacgaat".as_bytes();
+/// write_irs_stratified(&config, seq, &StopHandle::new(), &counters, &mut outs).unwrap();
+/// let expected = "\
+///     start_n,end_n,nucleotide,start_ir,end_ir,reverse_complement,matching\n\
+///     2,4,cbb,9,7,gca,111\n\n";
+/// assert_eq!(String::from_utf8(outs[1].clone()).unwrap(), expected);
+/// ```
+pub fn write_irs_stratified<W: Write>(
+    config: &Config,
+    seq: &[u8],
+    stop: &StopHandle,
+    counters: &PerfCounters,
+    outs: &mut [W],
+) -> Result<SearchSummary> {
+    let mut sinks: Vec<_> = outs.iter_mut().map(FormatSink::new).collect();
+    write_irs_to_sinks(config, seq, stop, counters, &mut sinks)
+}
+
+/// Same as [`write_irs_stratified`], with a custom [`ResultSink`] per gap stratum (e.g. a
+/// `FormatSink` paired with an `EinvertedSeqSink`).
+pub fn write_irs_to_sinks<S: ResultSink>(
+    config: &Config,
+    seq: &[u8],
+    stop: &StopHandle,
+    counters: &PerfCounters,
+    sinks: &mut [S],
+) -> Result<SearchSummary> {
+    let strata = match &config.gap_strata {
+        Some(strata) if strata.output == StrataOutput::Files => strata.count(),
+        _ => 1,
+    };
+    if sinks.len() != strata {
+        return Err(anyhow::anyhow!(
+            "expected {} outputs (one per gap stratum), got {}.",
+            strata,
+            sinks.len()
+        ));
+    }
+    write_irs_observed(config, seq, stop, counters, sinks)
+}
+
+/// Same as [`write_irs`], but the IRs go through a custom [`ResultSink`] (e.g. a database
+/// writer) instead of being formatted.
+///
+/// # Examples
+///
+/// ```rust
+/// use anyhow::Result;
+/// use iirs::{Config, IrSpan, ResultSink, SearchParams, StopHandle, write_irs_to_sink};
+///
+/// /// Sends the arm length of every IR.
+/// struct ArmLengths(Vec<usize>);
+///
+/// impl ResultSink for ArmLengths {
+///     fn write_irs(&mut self, _config: &Config, irs: &[IrSpan], _seq: &[u8]) -> Result<()> {
+///         self.0.extend(irs.iter().map(|&(left, right, gap)| (right + 1 - left - gap) / 2));
+///         Ok(())
+///     }
+/// }
+///
+/// let config = Config {
+///     params: SearchParams::new(3, 6, 2, 0).unwrap(),
+///     ..Default::default()
+/// };
+/// let mut sink = ArmLengths(Vec::new());
+/// write_irs_to_sink(&config, "acbbgt".as_bytes(), &StopHandle::new(), &mut sink).unwrap();
+/// assert_eq!(sink.0, vec![3]);
+/// ```
+pub fn write_irs_to_sink<S: ResultSink>(
+    config: &Config,
+    seq: &[u8],
+    stop: &StopHandle,
+    sink: &mut S,
+) -> Result<SearchSummary> {
+    write_irs_observed(config, seq, stop, &NoObserver, std::slice::from_mut(sink))
+}
+
+#[elapsed_time::elapsed]
+fn write_irs_observed<S: ResultSink, O: observer::Observer>(
+    config: &Config,
+    seq: &[u8],
+    stop: &StopHandle,
+    observer: &O,
+    sinks: &mut [S],
+) -> Result<SearchSummary> {
+    let sanitized_seq = utils::sanitize_sequence(seq)?;
+    // The IRs are scored in the unrolled circular sequence, longer than the sequence.
+    let scored;
+    let config = match config.params.with_domain_len(sanitized_seq.len()) {
+        Cow::Owned(params) => {
+            scored = Config {
+                params,
+                ..config.clone()
+            };
+            &scored
+        }
+        Cow::Borrowed(_) => config,
+    };
+    let params = &config.params;
+
+    for sink in sinks.iter_mut() {
+        sink.begin(config, &sanitized_seq)?;
+    }
+    // The IRs spanning the origin of a circular sequence are read past its end.
+    let unrolled_seq = circular::unrolled(params, &sanitized_seq);
+    // Index of the output of an IR.
+    let stratified = sinks.len() > 1;
+    let output_of = |ir: &IrSpan| match &config.gap_strata {
+        Some(strata) if stratified => strata.stratum(ir.2),
+        _ => 0,
+    };
+
+    // Every IR found after the center c will start after c / 2 - reach.
+    let reach = params.max_len + params.max_gap + 2;
+    let sort_by = config.sort_by;
+    let mut pending = spill::SpillBuffer::new(
+        sort_by,
+        config.spill_threshold,
+        config.region_cap.map(|cap| cap.window),
+    );
+    let flush = |pending: &mut spill::SpillBuffer, watermark: usize, sinks: &mut [S]| {
+        pending.drain(watermark, |ready| {
+            for (idx, sink) in sinks.iter_mut().enumerate() {
+                let irs: Vec<IrSpan> = ready
+                    .iter()
+                    .filter(|ir| output_of(ir) == idx)
+                    .copied()
+                    .collect();
+                sink.write_irs(config, &irs, &unrolled_seq)?;
+            }
+            Ok(())
+        })
+    };
+
+    let summary = search(
+        params,
+        &sanitized_seq,
+        stop,
+        observer,
+        |window_irs, centers_done| {
+            pending.extend(window_irs)?;
+            // The IRs of the center c satisfy left + right = c.
+            let mut watermark = match sort_by {
+                SortBy::Start => (centers_done / 2).saturating_sub(reach),
+                SortBy::Center => centers_done / 2,
+            };
+            // Capped regions must be flushed at once.
+            if let Some(cap) = config.region_cap {
+                watermark -= watermark % cap.window;
+            }
+            flush(&mut pending, watermark, &mut *sinks)
+        },
+    )?;
+    flush(&mut pending, usize::MAX, sinks)?;
+    for sink in sinks.iter_mut() {
+        sink.end(config, &summary)?;
+    }
+
+    Ok(summary)
+}
+
+/// Build the index of an already sanitized sequence and hand the IRs to `emit`, window by window.
+fn search<O, F>(
+    params: &SearchParams,
+    sanitized_seq: &[u8],
+    stop: &StopHandle,
+    observer: &O,
+    mut emit: F,
+) -> Result<SearchSummary>
+where
+    O: observer::Observer,
+    F: FnMut(Vec<IrSpan>, usize) -> Result<()>,
+{
+    // The chunks and the copies of a circular sequence are scored as parts of the sequence.
+    let whole = params.with_domain_len(sanitized_seq.len());
+    let fitted;
+    let params = match whole.max_memory {
+        Some(budget) => {
+            fitted = memory::fit_memory(&whole, sanitized_seq.len(), budget)?;
+            &fitted
+        }
+        None => &*whole,
+    };
+    if params.circular && !sanitized_seq.is_empty() {
+        let n = sanitized_seq.len();
+        let wrapped_params = circular::wrapped_params(params, n);
+        let wrapped_seq = circular::wrapped(sanitized_seq);
+        let mut summary = search(
+            &wrapped_params,
+            &wrapped_seq,
+            stop,
+            observer,
+            |window_irs, window_end| {
+                let centers_done = window_end.saturating_sub(2 * n).min(2 * n);
+                emit(circular::unwrap_irs(window_irs, n), centers_done)
+            },
+        )?;
+        summary.covered = summary.covered.saturating_sub(n).min(n);
+        return Ok(summary);
+    }
+    // Anchored searches only examine a few centers.
+    if let Some(chunk_len) = params.chunk_len
+        && params.anchor.is_none()
+    {
+        return chunked::search_chunked(params, chunk_len, sanitized_seq, stop, observer, emit);
+    }
+    let mut summaries = search_all(
+        std::slice::from_ref(params),
+        sanitized_seq,
+        stop,
+        observer,
+        |_, window_irs, window_end| emit(window_irs, window_end),
+    )?;
+    Ok(summaries.remove(0))
+}
+
+/// Range minimum query structure over the LCP array.
+// By default use the Sparse Table implementation for the Rmq
+#[cfg(not(any(feature = "tabulation", feature = "index32")))]
+type IndexRmq = rmq::Sparse;
+#[cfg(all(feature = "tabulation", not(feature = "index32")))]
+type IndexRmq = rmq::Tabulation;
+// The rmq crate only takes usize values
+#[cfg(feature = "index32")]
+type IndexRmq = rmq32::Sparse;
+
+/// Sparse table over `lcp`. The tables of the `rmq` crate allocate their own levels, so only the
+/// 32-bit one (`index32` feature) can be backed by huge pages.
+#[cfg(not(feature = "index32"))]
+fn sparse_rmq(lcp: &[IndexInt], _huge_pages: bool) -> IndexRmq {
+    IndexRmq::new(lcp)
+}
+#[cfg(feature = "index32")]
+fn sparse_rmq(lcp: &[IndexInt], huge_pages: bool) -> IndexRmq {
+    IndexRmq::new(lcp, huge_pages)
+}
 
-    // Build matchmatrix
-    let matrix = matrix::MatchMatrix::new();
-    let complement = constants::build_complement_array();
-    println!("{params:?}");
+/// Range minimum query structure over the LCP array, of the backend chosen by
+/// `SearchParams::rmq`.
+enum LcpRmq {
+    Sparse(IndexRmq),
+    Blocks(rmq_blocks::Blocks),
+}
+
+impl rmq::Rmq for LcpRmq {
+    fn rmq(&self, i: usize, j: usize) -> Option<usize> {
+        match self {
+            Self::Sparse(rmq) => rmq.rmq(i, j),
+            Self::Blocks(rmq) => rmq.rmq(i, j),
+        }
+    }
+}
 
+/// Integer type of the inverse suffix array and of the LCP array. The suffix array of
+/// divsufsort is 32-bit anyway, so `u32` fits any sequence it can index.
+#[cfg(not(feature = "index32"))]
+type IndexInt = usize;
+#[cfg(feature = "index32")]
+type IndexInt = u32;
+
+/// Build the doubled sequence in the buffers of `arena`, complemented with `complement`.
+fn build_doubled(
+    arena: &mut arena::SearchArena,
+    sanitized_seq: &[u8],
+    symmetry_mode: &SymmetryMode,
+    complement: &[u8; 128],
+) {
     // Construct s = seq + '$' + complement(reverse(seq)) + '#'
     let n = sanitized_seq.len();
     let s_n = 2 * n + 2;
-    let mut s: Vec<u8> = vec![0u8; s_n];
+    let s = &mut arena.s;
+    if arena.huge_pages {
+        hugepages::reserve_huge(s, s_n);
+    }
+    s.clear();
+    s.resize(s_n, 0);
     for i in 0..n {
         s[i] = sanitized_seq[i];
-        s[n + 1 + i] = match params.symmetry_mode {
-            SymmetryMode::Inverted => complement[sanitized_seq[n - 1 - i] as usize] as u8,
-            SymmetryMode::InvertedComplementary => sanitized_seq[n - 1 - i] as u8,
-            SymmetryMode::Direct => sanitized_seq[i] as u8,
-            SymmetryMode::DirectComplementary => complement[sanitized_seq[i] as usize] as u8,
+        s[n + 1 + i] = match symmetry_mode {
+            SymmetryMode::Inverted => complement[sanitized_seq[n - 1 - i] as usize],
+            SymmetryMode::InvertedComplementary => sanitized_seq[n - 1 - i],
+            SymmetryMode::Direct => sanitized_seq[i],
+            SymmetryMode::DirectComplementary => complement[sanitized_seq[i] as usize],
         };
     }
     s[n] = b'$';
     s[2 * n + 1] = b'#';
+}
+
+/// Longest sequence that fits in one index: divsufsort builds a 32-bit suffix array of the
+/// doubled sequence. Longer sequences are searched in chunks (see `SearchParams::chunk_len`).
+const MAX_INDEX_LEN: usize = (i32::MAX as usize - 2) / 2;
+
+/// Reject sequences whose doubled sequence overflows the suffix array.
+fn check_index_len(n: usize) -> Result<()> {
+    if n > MAX_INDEX_LEN {
+        return Err(anyhow::anyhow!(
+            "sequence length={n} exceeds the {MAX_INDEX_LEN} bp that fit in one index: search it in smaller chunks (--chunk-size)."
+        ));
+    }
+    Ok(())
+}
+
+/// Suffix array of `s`, built by `backend`. Only the one of SA-IS can be backed by huge pages:
+/// divsufsort allocates its own.
+#[cfg_attr(not(feature = "sais"), allow(unused_variables))]
+fn suffix_array(s: &[u8], backend: SaBackend, huge_pages: bool) -> Result<Vec<i32>> {
+    match backend {
+        SaBackend::Divsufsort => Ok(divsufsort::sort(s).into_parts().1),
+        #[cfg(feature = "sais")]
+        SaBackend::Sais => Ok(sais::suffix_array(s, huge_pages)),
+        #[cfg(not(feature = "sais"))]
+        SaBackend::Sais => Err(anyhow::anyhow!(
+            "the sais suffix array requires iirs to be built with the `sais` feature."
+        )),
+    }
+}
 
-    println!("{s:?}");
+/// Build the doubled sequence, its inverse suffix array and its LCP array in the buffers of
+/// `arena`, and return the RMQ over the LCP array.
+fn build_index(
+    arena: &mut arena::SearchArena,
+    sanitized_seq: &[u8],
+    symmetry_mode: &SymmetryMode,
+    complement: &[u8; 128],
+    sa_backend: SaBackend,
+    rmq_backend: RmqBackend,
+) -> Result<LcpRmq> {
+    check_index_len(sanitized_seq.len())?;
+    build_doubled(arena, sanitized_seq, symmetry_mode, complement);
+    let s = &arena.s;
+    let s_n = s.len();
 
     // Construct Suffix Array (sa) & Inverse Suffix Array
-    let sa: Vec<i32> = divsufsort::sort(&s).into_parts().1;
-    let mut inv_sa = vec![0; s_n];
+    let sa = suffix_array(s, sa_backend, arena.huge_pages)?;
+    let inv_sa = &mut arena.inv_sa;
+    if arena.huge_pages {
+        hugepages::reserve_huge(inv_sa, s_n);
+    }
+    inv_sa.clear();
+    inv_sa.resize(s_n, 0);
     for (i, value) in sa.iter().enumerate() {
-        inv_sa[*value as usize] = i;
+        inv_sa[*value as usize] = i as IndexInt;
     }
 
     // Calculate LCP & RMQ
-    let lcp = algo::lcp_array(&s, s_n, &sa, &inv_sa);
-    // By default use the Sparse Table implementation for the Rmq
-    #[cfg(not(feature = "tabulation"))]
-    let rmq = rmq::Sparse::new(&lcp);
-    #[cfg(feature = "tabulation")]
-    let rmq = rmq::Tabulation::new(&lcp);
-
-    // Calculate inverted repeats
-    let mut irs = algo::add_irs(&s, &inv_sa, &rmq, params, &matrix);
-
-    // Deal with the sorting strategy.
-    // Alternatives, or even skipping sorting altogether, can improve the performance.
-    // The original IUPACpal sorts by (left, gap_size, -right)
+    let lcp = &mut arena.lcp;
+    if arena.huge_pages {
+        hugepages::reserve_huge(lcp, s_n);
+    }
+    algo::lcp_array(s, s_n, &sa, inv_sa, lcp);
+    Ok(match rmq_backend {
+        RmqBackend::Sparse => LcpRmq::Sparse(sparse_rmq(lcp, arena.huge_pages)),
+        RmqBackend::Blocks => LcpRmq::Blocks(rmq_blocks::Blocks::new(lcp, arena.huge_pages)),
+    })
+}
+
+/// Same as [`search`] for several sets of parameters, whose IRs are handed to `emit` with the
+/// index of their set. The index is only built once per symmetry mode.
+fn search_all<O, F>(
+    params_list: &[SearchParams],
+    sanitized_seq: &[u8],
+    stop: &StopHandle,
+    observer: &O,
+    emit: F,
+) -> Result<Vec<SearchSummary>>
+where
+    O: observer::Observer,
+    F: FnMut(usize, Vec<IrSpan>, usize) -> Result<()>,
+{
+    let mut arena = arena::SearchArena::default();
+    search_all_in(&mut arena, params_list, sanitized_seq, stop, observer, emit)
+}
+
+/// Same as [`search_all`], building the index in the buffers of `arena`.
+fn search_all_in<O, F>(
+    arena: &mut arena::SearchArena,
+    params_list: &[SearchParams],
+    sanitized_seq: &[u8],
+    stop: &StopHandle,
+    observer: &O,
+    mut emit: F,
+) -> Result<Vec<SearchSummary>>
+where
+    O: observer::Observer,
+    F: FnMut(usize, Vec<IrSpan>, usize) -> Result<()>,
+{
+    if params_list.iter().any(|params| params.circular) {
+        return Err(anyhow::anyhow!(
+            "circular sequences are searched with a single set of parameters."
+        ));
+    }
+    let mut summaries: Vec<Option<SearchSummary>> = vec![None; params_list.len()];
+    // Started once for the whole search.
+    let pools = params_list
+        .iter()
+        .map(|params| algo::thread_pool(params.threads))
+        .collect::<Result<Vec<_>>>()?;
+
+    // An index per symmetry mode and complement of the bases.
+    let keys: Vec<(SymmetryMode, [u8; 128])> = params_list
+        .iter()
+        .map(|params| (params.symmetry_mode, complement::complement_array(params)))
+        .collect();
+    let mut indexes: Vec<&(SymmetryMode, [u8; 128])> = Vec::new();
+    for key in &keys {
+        if !indexes.contains(&key) {
+            indexes.push(key);
+        }
+    }
+
+    arena.huge_pages = params_list.iter().any(|params| params.huge_pages);
+    for index_key in indexes {
+        let (symmetry_mode, complement) = index_key;
+        let start = Instant::now();
+        let of_index = || {
+            params_list
+                .iter()
+                .zip(&keys)
+                .filter(move |(_, key)| *key == index_key)
+        };
+        // Without mismatches, the extensions are scanned directly: no suffix array needed.
+        let exact = of_index().all(|(params, _)| params.mismatches == 0);
+        // The index is shared: the other backends if any set asks for them.
+        let asks = |pred: fn(&SearchParams) -> bool| of_index().any(|(params, _)| pred(params));
+        let sa_backend = if asks(|params| params.sa == SaBackend::Sais) {
+            SaBackend::Sais
+        } else {
+            SaBackend::Divsufsort
+        };
+        let rmq_backend = if asks(|params| params.rmq == RmqBackend::Blocks) {
+            RmqBackend::Blocks
+        } else {
+            RmqBackend::Sparse
+        };
+        let rmq = if exact {
+            build_doubled(arena, sanitized_seq, symmetry_mode, complement);
+            None
+        } else {
+            Some(build_index(
+                arena,
+                sanitized_seq,
+                symmetry_mode,
+                complement,
+                sa_backend,
+                rmq_backend,
+            )?)
+        };
+        observer.on_index_built(sanitized_seq.len(), start.elapsed());
+        let (s, inv_sa) = (&arena.s, &arena.inv_sa);
+
+        // Calculate inverted repeats
+        for (idx, params) in params_list.iter().enumerate() {
+            if &keys[idx] != index_key {
+                continue;
+            }
+            let pool = pools[idx].as_deref();
+            let emit = |window_irs, window_end| emit(idx, window_irs, window_end);
+            let summary = match &rmq {
+                Some(rmq) => {
+                    let lce = algo::IndexLce { inv_sa, rmq };
+                    algo::add_irs(s, &lce, params, pool, stop, observer, emit)?
+                }
+                None => {
+                    let lce = algo::ScanLce::new(s, params);
+                    algo::add_irs(s, &lce, params, pool, stop, observer, emit)?
+                }
+            };
+            summaries[idx] = Some(summary);
+        }
+    }
+
+    Ok(summaries.into_iter().flatten().collect())
+}
+
+// Deal with the sorting strategy.
+// Alternatives, or even skipping sorting altogether, can improve the performance.
+// The original IUPACpal sorts by (left, gap_size, -right)
+fn sort_irs(irs: &mut [IrSpan]) {
     irs.sort_by(|a, b| {
         let cmp_left = a.0.cmp(&b.0);
         let cmp_gap = a.2.cmp(&a.2);
         let cmp_right = b.1.cmp(&a.1);
         cmp_left.then(cmp_gap).then(cmp_right)
     });
+}
 
-    Ok(irs)
+/// Sort IRs in the given order. [`find_irs`] already returns them sorted by start.
+pub fn sort_irs_by(irs: &mut [IrSpan], sort_by: SortBy) {
+    match sort_by {
+        SortBy::Start => sort_irs(irs),
+        SortBy::Center => irs.sort_by_key(|&(left, right, _)| (left + right, left)),
+    }
 }
 
 /// Stringify the given [Inverted Repeats](https://en.wikipedia.org/wiki/Inverted_repeat) (IRs)
@@ -112,7 +1258,7 @@ pub fn find_irs(params: &SearchParams, seq: &[u8]) -> Result<Vec<(usize, usize,
 ///
 /// ```rust
 /// use iirs::{SearchParams, Config};
-/// use iirs::{find_irs, stringify_irs};
+/// use iirs::{IrMatch, find_irs, stringify_irs};
 /// use iirs::OutputFormat;
 ///
 /// // Simple example for the csv output format.
@@ -124,7 +1270,7 @@ pub fn find_irs(params: &SearchParams, seq: &[u8]) -> Result<Vec<(usize, usize,
 ///     // The remaining fields are not relevant here.
 ///     ..Default::default()
 /// };
-/// let irs = find_irs(&config.params, &seq).unwrap();
+/// let irs: Vec<_> = find_irs(&config.params, &seq).unwrap().iter().map(IrMatch::span).collect();
 /// let (header, irs_str) = stringify_irs(&config, &irs, &seq);
 /// let expected = "\
 ///     start_n,end_n,nucleotide,start_ir,end_ir,reverse_complement,matching\n\
@@ -139,6 +1285,7 @@ pub fn find_irs(params: &SearchParams, seq: &[u8]) -> Result<Vec<(usize, usize,
 ///     params,
 ///     output_file: "out.txt",
 ///     output_format: OutputFormat::Classic,
+///     ..Default::default()
 /// };
 /// let (header, irs_str) = stringify_irs(&config, &irs, &seq);
 /// let expected = "\
@@ -154,25 +1301,17 @@ pub fn find_irs(params: &SearchParams, seq: &[u8]) -> Result<Vec<(usize, usize,
 ///     Palindromes:";
 /// assert_eq!(header, expected);
 /// ```
-pub fn stringify_irs(
-    config: &Config,
-    irs: &[(usize, usize, usize)],
-    seq: &[u8],
-) -> (String, String) {
-    let matrix = matrix::MatchMatrix::new();
-    let complement = constants::build_complement_array();
-
-    match config.output_format {
-        OutputFormat::Classic => (
-            format::fmt_classic_header(config, seq.len()),
-            format::fmt_classic(irs, seq, &matrix, &complement),
-        ),
-        OutputFormat::Csv => (
-            format::fmt_csv_header(),
-            format::fmt_csv(irs, seq, &matrix, &complement),
-        ),
-        OutputFormat::Custom => (format::fmt_custom_header(), format::fmt_custom(irs, seq)),
+pub fn stringify_irs(config: &Config, irs: &[IrSpan], seq: &[u8]) -> (String, String) {
+    let matrix = matrix::MatchMatrix::from_params(&config.params);
+    let complement = complement::complement_array(&config.params);
+
+    // The IRs spanning the origin of a circular sequence are read across it
+    let unrolled = circular::unrolled(&config.params, seq);
+    let mut irs_str = format::fmt_irs(config, irs, &unrolled, &matrix, &complement);
+    if let Some(footer) = format::fmt_footer(config) {
+        irs_str = format!("{irs_str}\n{footer}");
     }
+    (format::fmt_header(config, seq.len()), irs_str)
 }
 
 #[cfg(test)]
@@ -1,14 +1,28 @@
 use anyhow::Result;
 use seq_io::fasta::{Reader, Record};
+use std::time::Duration;
 
-use crate::SymmetryMode;
+use crate::{
+    ComplementTable, IrIndex, IrMatch, IrSpan, MaxHitsAction, OutputFormat, RmqBackend, SortBy,
+    StopHandle, StopReason, SymmetryMode, find_irs, find_irs_all, find_irs_at, find_irs_multi,
+    find_irs_until, find_irs_with, ir_center, is_ir, iter_irs, normalize_irs, sort_irs,
+    sort_irs_by, stringify_irs, write_irs, write_irs_to_sink,
+};
 
 use super::config::{Config, SearchParams};
 use super::constants;
-use super::find_irs;
+use super::find_spans;
 use super::matrix;
 use super::utils;
 
+/// Random sequence of `len` symbols of `alphabet`, the same for every `seed`.
+pub(crate) fn random_seq(seed: u64, len: usize, alphabet: &[u8]) -> Vec<u8> {
+    let mut rng = crate::shuffle::Rng::new(seed);
+    (0..len)
+        .map(|_| alphabet[rng.below(alphabet.len())])
+        .collect()
+}
+
 /// Attemps to extract the first sequence (string) from the fasta file. Returns a trimmed lowercase String.
 ///
 /// Returns an error if there are no sequences.
@@ -32,7 +46,7 @@ fn correct_truncation_helper(config: &Config) {
     let seq = string.to_ascii_lowercase().as_bytes().to_vec();
     let n = seq.len();
     config.params.check_bounds(n).unwrap();
-    let irs = find_irs(&config.params, &seq).unwrap();
+    let irs = find_spans(&config.params, &seq).unwrap();
 
     let complement = constants::build_complement_array();
     let s_n = 2 * n + 2;
@@ -43,7 +57,7 @@ fn correct_truncation_helper(config: &Config) {
     }
     s[n] = b'$';
     s[2 * n + 1] = b'#';
-    let matrix = matrix::MatchMatrix::new();
+    let matrix = matrix::MatchMatrix::default();
 
     for (left, right, _) in irs {
         assert!(matrix.match_u8(s[left], complement[s[right] as usize]),);
@@ -87,7 +101,7 @@ fn find_irs_from_first_sequence(config: &Config) -> Vec<(usize, usize, usize)> {
     let string = extract_first_sequence(config).unwrap();
     let seq = string.to_ascii_lowercase().as_bytes().to_vec();
     config.params.check_bounds(seq.len()).unwrap(); // BUT THE OUTPUT FORMAT MIGHT BE WRONG?
-    find_irs(&config.params, &seq).unwrap()
+    find_spans(&config.params, &seq).unwrap()
 }
 
 #[test]
@@ -206,7 +220,7 @@ fn test_test_1() {
 fn mk_test_symmetry(seq_str: &str, symmetry_mode: SymmetryMode, expected: usize) {
     let seq = seq_str.as_bytes();
     let params1 = SearchParams::with_mode(3, 7, 2, 0, symmetry_mode).unwrap();
-    let irs = find_irs(&params1, &seq).unwrap();    // Print the results for debugging
+    let irs = find_spans(&params1, &seq).unwrap(); // Print the results for debugging
     println!("IRs found: {:?}", irs);
     assert_eq!(irs.len(), expected);
 }
@@ -215,7 +229,7 @@ fn mk_test_symmetry(seq_str: &str, symmetry_mode: SymmetryMode, expected: usize)
 fn test_simple_direct_mode() {
     mk_test_symmetry("aataat", SymmetryMode::Direct, 1);
     mk_test_symmetry("aataat", SymmetryMode::default(), 0);
-} 
+}
 #[test]
 fn test_simple_direct_complementary_mode() {
     mk_test_symmetry("aaattt", SymmetryMode::DirectComplementary, 1);
@@ -223,16 +237,838 @@ fn test_simple_direct_complementary_mode() {
 }
 
 #[test]
-fn test_simple_inverted_complementary_mode() { 
+fn test_simple_inverted_complementary_mode() {
     mk_test_symmetry("aattaa", SymmetryMode::InvertedComplementary, 1);
     mk_test_symmetry("aattaa", SymmetryMode::default(), 0);
 }
- 
+
 #[test]
-fn test_simple_inverted_complementary_mode__() {  
+fn test_simple_inverted_complementary_mode__() {
     println!("I");
     mk_test_symmetry("atatat", SymmetryMode::default(), 1);
     println!("II");
     mk_test_symmetry("atatat", SymmetryMode::Direct, 1);
 }
- 
\ No newline at end of file
+
+#[test]
+fn test_bisulfite_mode() {
+    // "aaacccgggttt" after converting every C into T.
+    let seq = "aaatttgggttt".as_bytes();
+    let mut params = SearchParams::new(6, 12, 0, 0).unwrap();
+    assert!(find_spans(&params, seq).unwrap().is_empty());
+    params.bisulfite = true;
+    assert_eq!(find_spans(&params, seq).unwrap(), vec![(0, 11, 0)]);
+}
+
+#[test]
+fn test_rna_mode() {
+    // A stem of 5 pairs closing a loop of 4.
+    let seq = "gcgauaaaaaucgc".as_bytes();
+    let mut params = SearchParams::new(5, 5, 4, 0).unwrap();
+    params.rna = true;
+    assert!(find_spans(&params, seq).unwrap().contains(&(0, 13, 4)));
+
+    // The outer pair is G:U.
+    let seq = "gcgauaaaaaucgu".as_bytes();
+    assert!(!find_spans(&params, seq).unwrap().contains(&(0, 13, 4)));
+    params.wobble = true;
+    assert!(find_spans(&params, seq).unwrap().contains(&(0, 13, 4)));
+    let irs = find_irs(&params, seq).unwrap();
+    assert!(
+        irs.iter()
+            .any(|ir| ir.span() == (0, 13, 4) && ir.mismatches == 0)
+    );
+
+    // T is read as U.
+    params.wobble = false;
+    let seq = "aaaaaggggttttt".as_bytes();
+    assert!(find_spans(&params, seq).unwrap().contains(&(0, 13, 4)));
+    let seq = random_seq(41, 2000, b"acgt");
+    let params = SearchParams::new(6, 10, 4, 1).unwrap();
+    let mut rna_params = params.clone();
+    rna_params.rna = true;
+    assert_eq!(
+        find_spans(&rna_params, &seq).unwrap(),
+        find_spans(&params, &seq).unwrap()
+    );
+}
+
+#[test]
+fn test_custom_complement() {
+    // A two-letter alphabet where A pairs with C.
+    let seq = random_seq(37, 2000, b"ac");
+    let mut params = SearchParams::new(6, 10, 4, 1).unwrap();
+    assert!(find_irs(&params, &seq).unwrap().is_empty());
+
+    let complement = ComplementTable::from_pairs(&[(b'a', b'c'), (b'c', b'a')]).unwrap();
+    params.complement = Some(complement.clone());
+    let irs = find_irs(&params, &seq).unwrap();
+    assert!(!irs.is_empty());
+    for ir in &irs {
+        let mismatches = (0..ir.arm_len)
+            .filter(|&i| seq[ir.start + i] == seq[ir.end - i])
+            .count();
+        assert_eq!(mismatches, ir.mismatches);
+        assert!(mismatches <= 1);
+    }
+
+    let mut lazy: Vec<IrMatch> = iter_irs(&params, &seq).unwrap().collect();
+    lazy.sort_by_key(IrMatch::span);
+    let mut expected = irs.clone();
+    expected.sort_by_key(IrMatch::span);
+    assert_eq!(lazy, expected);
+
+    let index = IrIndex::new(&seq, SymmetryMode::Inverted).unwrap();
+    assert!(index.enumerate_centers(&params).is_err());
+    let rmq = RmqBackend::default();
+    let index = IrIndex::with_complement(&seq, SymmetryMode::Inverted, rmq, &complement).unwrap();
+    assert!(!index.enumerate_centers(&params).unwrap().is_empty());
+
+    // A is complemented into R, which matches A.
+    let seq = "aaaaaggggaaaaa".as_bytes();
+    let mut params = SearchParams::new(5, 5, 4, 0).unwrap();
+    assert!(!find_spans(&params, seq).unwrap().contains(&(0, 13, 4)));
+    params.complement = Some(ComplementTable::from_pairs(&[(b'a', b'r')]).unwrap());
+    assert!(find_spans(&params, seq).unwrap().contains(&(0, 13, 4)));
+}
+
+#[test]
+fn test_min_score() {
+    // The pair (t, g) does not match
+    let seq = "acttaggt".as_bytes();
+    let mut params = SearchParams::new(3, 8, 0, 1).unwrap();
+    assert_eq!(find_spans(&params, seq).unwrap(), vec![(0, 7, 0)]);
+    // 3 matches and 1 mismatch score 3 * 3 - 4 = 5
+    params.min_score = Some(6.0);
+    assert!(find_spans(&params, seq).unwrap().is_empty());
+    params.min_score = Some(5.0);
+    assert_eq!(find_spans(&params, seq).unwrap(), vec![(0, 7, 0)]);
+}
+
+#[test]
+fn test_normalize_merged_searches() {
+    // A single search never reports an IR inside another one with the same center,
+    // but merging searches with different parameters does.
+    let seq = "acgtacgatcgatgcatgcagtcagtacgtagctagctagcgcgatatcg".as_bytes();
+    let mut irs = find_spans(&SearchParams::new(4, 8, 6, 1).unwrap(), seq).unwrap();
+    assert!(normalize_irs(&irs).iter().all(|c| c.sub_forms == 0));
+    irs.extend(find_spans(&SearchParams::new(4, 20, 6, 1).unwrap(), seq).unwrap());
+    let canonical = normalize_irs(&irs);
+    assert!(canonical.len() < irs.len());
+    let total: usize = canonical.iter().map(|c| 1 + c.sub_forms).sum();
+    assert_eq!(total, irs.len());
+}
+
+#[test]
+fn test_find_irs_multi() {
+    let seq = "acbbgtttaaacgtagctagcgatcgatcgacgcgcgtatagcatcgacgactacgc".as_bytes();
+    let mut direct = SearchParams::new(3, 10, 5, 0).unwrap();
+    direct.symmetry_mode = SymmetryMode::Direct;
+    let params_list = [
+        SearchParams::new(3, 6, 2, 0).unwrap(),
+        direct,
+        SearchParams::new(5, 20, 10, 2).unwrap(),
+    ];
+    let results = find_irs_multi(&params_list, seq).unwrap();
+    assert_eq!(results.len(), params_list.len());
+    for (params, irs) in params_list.iter().zip(&results) {
+        assert_eq!(irs, &find_irs(params, seq).unwrap());
+    }
+    assert!(find_irs_multi(&[], seq).unwrap().is_empty());
+}
+
+#[test]
+fn test_ir_index_two_passes() {
+    let seq = "acbbgtttaaacgtagctagcgatcgatcgacgcgcgtatagcatcgacgactacgc".as_bytes();
+    let index = IrIndex::new(seq, SymmetryMode::Inverted).unwrap();
+    let loose = SearchParams::new(5, 20, 10, 2).unwrap();
+    let centers = index.enumerate_centers(&loose).unwrap();
+    assert!(centers.windows(2).all(|pair| pair[0] < pair[1]));
+
+    // The candidates of looser parameters are reused for stricter ones
+    let mut strict = SearchParams::new(6, 8, 10, 2).unwrap();
+    strict.max_homopolymer = Some(0.5);
+    for params in [&loose, &strict] {
+        let mut irs: Vec<IrSpan> = centers
+            .iter()
+            .flat_map(|&center| index.verify_center(params, center).unwrap())
+            .collect();
+        sort_irs(&mut irs);
+        assert_eq!(irs, find_spans(params, seq).unwrap());
+    }
+
+    let mut direct = loose.clone();
+    direct.symmetry_mode = SymmetryMode::Direct;
+    assert!(index.enumerate_centers(&direct).is_err());
+}
+
+#[test]
+fn test_max_dust() {
+    let seq = "aaaaaaaaaaacgtacgtacggtcattttttttttt".as_bytes();
+    let mut params = SearchParams::new(10, 20, 20, 0).unwrap();
+    assert!(!find_spans(&params, seq).unwrap().is_empty());
+    params.max_dust = Some(2.0);
+    assert!(find_spans(&params, seq).unwrap().is_empty());
+}
+
+#[test]
+fn test_max_homopolymer() {
+    let seq = "aaaaaaaaaaacgtacgtacggtcattttttttttt".as_bytes();
+    let mut params = SearchParams::new(10, 20, 20, 0).unwrap();
+    let irs = find_spans(&params, seq).unwrap();
+    assert!(!irs.is_empty());
+    params.max_homopolymer = Some(0.5);
+    let filtered = find_spans(&params, seq).unwrap();
+    assert!(filtered.len() < irs.len());
+    params.max_homopolymer = Some(1.0);
+    assert_eq!(find_spans(&params, seq).unwrap(), irs);
+}
+
+#[test]
+fn test_max_n_fraction() {
+    let seq = "acgtacgtnnnnnnnnnnnnnnnnacgtacgt".as_bytes();
+    let mut params = SearchParams::new(8, 20, 20, 0).unwrap();
+    // "n" matches everything, so most IRs sit on the assembly gap
+    assert_eq!(find_spans(&params, seq).unwrap().len(), 33);
+    params.max_n_fraction = Some(0.5);
+    assert_eq!(
+        find_spans(&params, seq).unwrap(),
+        vec![(0, 31, 0), (0, 15, 0), (16, 31, 0)]
+    );
+}
+
+#[test]
+fn test_masked() {
+    let seq = "acbbgt".as_bytes();
+    let mut params = SearchParams::new(3, 6, 2, 0).unwrap();
+    params.masked = vec![(6, 10)];
+    assert_eq!(find_spans(&params, seq).unwrap(), vec![(0, 5, 0)]);
+    params.masked = vec![(5, 6)];
+    assert!(find_spans(&params, seq).unwrap().is_empty());
+}
+
+#[test]
+fn test_mismatch_rate() {
+    // Two copies of a 200bp arm with 2 mismatches, around a 300bp single-copy region.
+    let arm: String = (0..200)
+        .map(|i| ["a", "c", "g", "t"][(i * 7 + i / 3) % 4])
+        .collect();
+    let mut rev_comp: Vec<u8> = arm
+        .bytes()
+        .rev()
+        .map(|c| match c {
+            b'a' => b't',
+            b'c' => b'g',
+            b'g' => b'c',
+            _ => b'a',
+        })
+        .collect();
+    rev_comp[50] = if rev_comp[50] == b'a' { b'c' } else { b'a' };
+    rev_comp[150] = if rev_comp[150] == b'a' { b'c' } else { b'a' };
+    let single_copy: String = (0..300)
+        .map(|i| ["c", "a", "t", "g"][(i * 5 + i / 7) % 4])
+        .collect();
+    let seq = format!("{arm}{single_copy}{}", String::from_utf8(rev_comp).unwrap());
+
+    let params = SearchParams::with_mismatch_rate(150, 250, 320, 0.02).unwrap();
+    assert_eq!(params.mismatches, 5);
+    let irs = find_spans(&params, seq.as_bytes()).unwrap();
+    assert!(irs.contains(&(0, 699, 300)));
+    assert!(SearchParams::with_mismatch_rate(150, 250, 320, 1.5).is_err());
+}
+
+#[test]
+fn test_mismatch_rate_trims_arms() {
+    // A 100bp arm whose 3 outermost mismatches exceed the allowance (2% of the length
+    // extended from the loop) from the 97th pair on.
+    let arm: String = (0..100)
+        .map(|i| ["a", "c", "g", "t"][(i * 7 + i / 3) % 4])
+        .collect();
+    let mut rev_comp: Vec<u8> = arm
+        .bytes()
+        .rev()
+        .map(|c| match c {
+            b'a' => b't',
+            b'c' => b'g',
+            b'g' => b'c',
+            _ => b'a',
+        })
+        .collect();
+    for i in [94, 96, 98] {
+        rev_comp[i] = if rev_comp[i] == b'a' { b'c' } else { b'a' };
+    }
+    let seq = format!("{arm}aaaaaaaaaa{}", String::from_utf8(rev_comp).unwrap());
+
+    let params = SearchParams::with_mismatch_rate(50, 120, 10, 0.02).unwrap();
+    assert_eq!(params.mismatches, 3);
+    let irs = find_spans(&params, seq.as_bytes()).unwrap();
+    assert!(irs.contains(&(4, 205, 10)));
+    assert!(!irs.contains(&(0, 209, 10)));
+}
+
+#[test]
+fn test_mismatch_rate_early_mismatch() {
+    // A 100bp arm whose only mismatch is on the second pair from the loop: the allowance is
+    // exceeded there, but not on the whole arm.
+    let arm: String = (0..100)
+        .map(|i| ["a", "c", "g", "t"][(i * 7 + i / 3) % 4])
+        .collect();
+    let mut rev_comp: Vec<u8> = arm
+        .bytes()
+        .rev()
+        .map(|c| match c {
+            b'a' => b't',
+            b'c' => b'g',
+            b'g' => b'c',
+            _ => b'a',
+        })
+        .collect();
+    rev_comp[1] = if rev_comp[1] == b'a' { b'c' } else { b'a' };
+    let seq = format!("{arm}aaaaaaaaaa{}", String::from_utf8(rev_comp).unwrap());
+
+    let params = SearchParams::with_mismatch_rate(50, 120, 10, 0.02).unwrap();
+    let irs = find_spans(&params, seq.as_bytes()).unwrap();
+    assert!(irs.contains(&(0, 209, 10)));
+}
+
+#[test]
+fn test_write_irs_sorted_by_center() {
+    let seq = "n".repeat(200).into_bytes();
+    let config = Config {
+        params: SearchParams::new(3, 10, 4, 0).unwrap(),
+        output_format: OutputFormat::Custom,
+        sort_by: SortBy::Center,
+        ..Default::default()
+    };
+    let mut irs = find_spans(&config.params, &seq).unwrap();
+    sort_irs_by(&mut irs, SortBy::Center);
+    assert!(irs.is_sorted_by_key(ir_center));
+    let (header, irs_str) = stringify_irs(&config, &irs, &seq);
+
+    let mut out = Vec::new();
+    write_irs(&config, &seq, &StopHandle::new(), &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        format!("{header}\n{irs_str}\n")
+    );
+}
+
+#[test]
+fn test_find_irs_at() {
+    let seq = "N".repeat(100).into_bytes();
+    let params = SearchParams::new(3, 10, 4, 0).unwrap();
+    let irs = find_spans(&params, &seq).unwrap();
+    for center in [0, 1, 30, 98, 99] {
+        let expected: Vec<_> = irs
+            .iter()
+            .copied()
+            .filter(|ir| (ir_center(ir) - 1.0 - center as f64).abs() <= 0.5)
+            .collect();
+        let found = find_irs_at(&params, &seq, center).unwrap();
+        assert!(found.iter().map(IrMatch::span).eq(expected));
+    }
+}
+
+#[test]
+fn test_arm_regions() {
+    // "ggcgcccg" at 12..20, its reverse complement at 40..48 and the same arm again at 60..68.
+    let seq = format!(
+        "{}ggcgcccg{}cgggcgcc{}ggcgcccg{}",
+        "a".repeat(12),
+        "a".repeat(20),
+        "a".repeat(12),
+        "a".repeat(12)
+    );
+    let mut params = SearchParams::new(6, 10, 50, 0).unwrap();
+    let irs = find_spans(&params, seq.as_bytes()).unwrap();
+    params.arm_regions = Some((vec![(10, 20)], vec![(40, 50)]));
+    let constrained = find_spans(&params, seq.as_bytes()).unwrap();
+    assert!(!constrained.is_empty());
+    assert!(constrained.len() < irs.len());
+    for &(left, right, gap) in &constrained {
+        let arm_len = (right + 1 - left - gap) / 2;
+        assert!(10 <= left && left + arm_len <= 20);
+        assert!(40 <= right + 1 - arm_len && right < 50);
+    }
+}
+
+#[test]
+fn test_tm_filters() {
+    let seq = "aaaagcgcggccttttggccgcgcaaaaatattaatttttattaatataaaa".as_bytes();
+    let mut params = SearchParams::new(8, 8, 4, 0).unwrap();
+    let irs = find_spans(&params, seq).unwrap();
+    assert_eq!(irs, vec![(4, 23, 4), (28, 47, 4)]);
+    params.min_tm = Some(60.0);
+    assert_eq!(find_spans(&params, seq).unwrap(), vec![(4, 23, 4)]);
+    params.min_tm = None;
+    params.max_tm = Some(60.0);
+    assert_eq!(find_spans(&params, seq).unwrap(), vec![(28, 47, 4)]);
+}
+
+#[test]
+fn test_mismatches_used_filters() {
+    let random = random_seq(43, 2000, b"acgt");
+    let cases = [
+        (
+            "ttacgcatgcgtaaccgatggtacgcaaccatcggttagcatgcttacg".as_bytes(),
+            SymmetryMode::Inverted,
+        ),
+        // The arms of direct repeats are paired in the same direction.
+        (&random[..], SymmetryMode::Direct),
+    ];
+    for (seq, mode) in cases {
+        let mut params = SearchParams::new(5, 12, 6, 2).unwrap();
+        params.symmetry_mode = mode;
+        let matrix = matrix::MatchMatrix::from_params(&params);
+        let complement = constants::build_complement_array();
+        let used =
+            |ir: &IrSpan| crate::score::count_mismatches(ir, seq, &mode, &matrix, &complement);
+        let irs = find_spans(&params, seq).unwrap();
+
+        params.max_mismatches_used = Some(0);
+        let perfect = find_spans(&params, seq).unwrap();
+        params.max_mismatches_used = None;
+        params.min_mismatches_used = Some(1);
+        let imperfect = find_spans(&params, seq).unwrap();
+
+        assert!(!perfect.is_empty() && !imperfect.is_empty(), "{mode:?}");
+        assert!(perfect.iter().all(|ir| used(ir) == 0), "{mode:?}");
+        assert!(imperfect.iter().all(|ir| used(ir) > 0), "{mode:?}");
+        assert_eq!(perfect.len() + imperfect.len(), irs.len(), "{mode:?}");
+    }
+}
+
+#[test]
+fn test_time_limit() {
+    let seq = "N".repeat(500).into_bytes();
+    let params = SearchParams::default();
+    let stop = StopHandle::new().with_time_limit(Duration::ZERO);
+    let partial = find_irs_until(&params, &seq, &stop).unwrap();
+    assert!(partial.irs.is_empty());
+    assert_eq!(partial.covered, params.min_len / 2);
+    assert_eq!(partial.stopped, Some(StopReason::TimeLimit));
+}
+
+#[test]
+fn test_max_hits() {
+    let seq = "N".repeat(500).into_bytes();
+    let params = SearchParams::default();
+    // 961 IRs in total (see tests.rs)
+    let stop = StopHandle::new().with_max_hits(100, MaxHitsAction::Count);
+    let partial = find_irs_until(&params, &seq, &stop).unwrap();
+    assert_eq!(partial.irs.len(), 100);
+    assert_eq!(partial.found, 961);
+    assert_eq!(partial.stopped, None);
+
+    let stop = StopHandle::new().with_max_hits(100, MaxHitsAction::Abort);
+    let partial = find_irs_until(&params, &seq, &stop).unwrap();
+    assert_eq!(partial.stopped, Some(StopReason::MaxHits));
+
+    let stop = StopHandle::new().with_max_hits(961, MaxHitsAction::Abort);
+    let partial = find_irs_until(&params, &seq, &stop).unwrap();
+    assert_eq!(partial.irs.len(), 961);
+    assert_eq!(partial.stopped, None);
+}
+
+#[test]
+fn test_write_irs_same_as_stringify() {
+    let config = Config {
+        params: SearchParams::new(3, 100, 20, 1).unwrap(),
+        input_file: "tests/test_data/d00596.fasta",
+        ..Default::default()
+    };
+    let seq = extract_first_sequence(&config).unwrap().into_bytes();
+    let irs = find_spans(&config.params, &seq).unwrap();
+    let (header, irs_str) = stringify_irs(&config, &irs, &seq);
+
+    let mut out = Vec::new();
+    let summary = write_irs(&config, &seq, &StopHandle::new(), &mut out).unwrap();
+    assert_eq!(summary.found, irs.len());
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        format!("{}\n{}\n", header, irs_str)
+    );
+}
+
+#[test]
+fn test_write_irs_spilled() {
+    let config = Config {
+        params: SearchParams::new(3, 100, 20, 1).unwrap(),
+        input_file: "tests/test_data/d00596.fasta",
+        output_format: OutputFormat::Csv,
+        ..Default::default()
+    };
+    let seq = extract_first_sequence(&config).unwrap().into_bytes();
+    let mut expected = Vec::new();
+    write_irs(&config, &seq, &StopHandle::new(), &mut expected).unwrap();
+
+    let spilled_config = Config {
+        spill_threshold: Some(100),
+        ..config
+    };
+    let mut out = Vec::new();
+    write_irs(&spilled_config, &seq, &StopHandle::new(), &mut out).unwrap();
+    assert_eq!(out, expected);
+}
+
+#[test]
+fn test_write_irs_json() {
+    let config = Config {
+        params: SearchParams::new(3, 100, 20, 1).unwrap(),
+        input_file: "tests/test_data/d00596.fasta",
+        output_format: OutputFormat::Json,
+        ..Default::default()
+    };
+    let seq = extract_first_sequence(&config).unwrap().into_bytes();
+    let irs = find_spans(&config.params, &seq).unwrap();
+    let (header, irs_str) = stringify_irs(&config, &irs, &seq);
+    let expected = format!("{}\n{}\n", header, irs_str);
+    assert!(expected.starts_with("[\n{") && expected.ends_with("}\n]\n"));
+    assert_eq!(expected.matches("},\n{").count() + 1, irs.len());
+
+    // Also when the IRs are written in several batches
+    let spilled_config = Config {
+        spill_threshold: Some(100),
+        ..config.clone()
+    };
+    for config in [config, spilled_config] {
+        let mut out = Vec::new();
+        write_irs(&config, &seq, &StopHandle::new(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    // JSON Lines: no header, one line per IR
+    let config = Config {
+        params: SearchParams::new(3, 100, 20, 1).unwrap(),
+        input_file: "tests/test_data/d00596.fasta",
+        output_format: OutputFormat::Jsonl,
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    write_irs(&config, &seq, &StopHandle::new(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.lines().count(), irs.len());
+    assert!(
+        out.lines()
+            .all(|line| line.starts_with('{') && line.ends_with('}'))
+    );
+}
+
+#[test]
+fn test_write_irs_to_vec_sink() {
+    let config = Config {
+        params: SearchParams::new(3, 100, 20, 1).unwrap(),
+        input_file: "tests/test_data/d00596.fasta",
+        ..Default::default()
+    };
+    let seq = extract_first_sequence(&config).unwrap().into_bytes();
+    let mut irs: Vec<IrSpan> = Vec::new();
+    let summary = write_irs_to_sink(&config, &seq, &StopHandle::new(), &mut irs).unwrap();
+    assert_eq!(summary.found, irs.len());
+    assert_eq!(irs, find_spans(&config.params, &seq).unwrap());
+}
+
+#[test]
+fn test_find_irs_all() {
+    let path = std::env::temp_dir().join(format!("iirs-all-{}.fasta", std::process::id()));
+    let path = path.to_str().unwrap();
+    let chromosome = "ttacgcatgcgtaaccgatggtacgcaaccatcggtt";
+    let plasmid = "aaaaaaccgatgcaacatcggaaaaaa";
+    std::fs::write(
+        path,
+        format!(
+            ">chromosome\n{chromosome}\n>plasmid\n{}\n",
+            plasmid.to_uppercase()
+        ),
+    )
+    .unwrap();
+
+    let params = SearchParams::new(4, 12, 6, 1).unwrap();
+    let mut config = Config {
+        params: params.clone(),
+        input_file: path,
+        seq_name: constants::ALL_SEQUENCES,
+        ..Default::default()
+    };
+    let results = find_irs_all(&config).unwrap();
+    let ids: Vec<_> = results.iter().map(|(id, _)| id.as_str()).collect();
+    assert_eq!(ids, ["chromosome", "plasmid"]);
+    assert_eq!(
+        results[0].1,
+        find_irs(&params, chromosome.as_bytes()).unwrap()
+    );
+    assert_eq!(results[1].1, find_irs(&params, plasmid.as_bytes()).unwrap());
+
+    config.seq_name = "plasmid";
+    let results = find_irs_all(&config).unwrap();
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].0, "plasmid");
+}
+
+#[test]
+fn test_find_irs_struct() {
+    let seq = "ttacgcatgcgtaaccgatggtacgcaaccatcggtt".as_bytes();
+    let mut params = SearchParams::new(4, 12, 6, 1).unwrap();
+    let irs = find_irs(&params, seq).unwrap();
+    let spans = find_spans(&params, seq).unwrap();
+    assert!(!irs.is_empty());
+    assert!(irs.iter().map(IrMatch::span).eq(spans.iter().copied()));
+    #[allow(deprecated)]
+    let tuples = crate::find_irs_tuples(&params, seq).unwrap();
+    assert_eq!(tuples, spans);
+    for ir in &irs {
+        let check = is_ir(
+            &params,
+            seq,
+            ir.start,
+            ir.start + ir.arm_len - 1,
+            ir.end + 1 - ir.arm_len,
+            ir.end,
+        )
+        .unwrap();
+        assert_eq!(ir.mismatches, check.mismatches.len());
+        assert_eq!(ir.mode, SymmetryMode::Inverted);
+    }
+
+    // The lazy search finds the same IRs, by center
+    let mut lazy: Vec<_> = iter_irs(&params, seq).unwrap().collect();
+    assert!(
+        lazy.windows(2)
+            .all(|w| w[0].start + w[0].end <= w[1].start + w[1].end)
+    );
+    lazy.sort_by_key(|ir| (ir.start, std::cmp::Reverse(ir.end)));
+    assert_eq!(lazy, irs);
+    // With the backends of the parameters, but not in chunks
+    let mut blocks_params = params.clone();
+    blocks_params.rmq = RmqBackend::Blocks;
+    let mut blocks: Vec<_> = iter_irs(&blocks_params, seq).unwrap().collect();
+    blocks.sort_by_key(|ir| (ir.start, std::cmp::Reverse(ir.end)));
+    assert_eq!(blocks, irs);
+    blocks_params.chunk_len = Some(1000);
+    assert!(iter_irs(&blocks_params, seq).is_err());
+    let mut streamed = Vec::new();
+    find_irs_with(&params, seq, |ir| streamed.push(ir)).unwrap();
+    streamed.sort_by_key(|ir| (ir.start, std::cmp::Reverse(ir.end)));
+    assert_eq!(streamed, irs);
+
+    // The arms of the direct modes are compared in the same direction
+    params.symmetry_mode = SymmetryMode::Direct;
+    let irs = find_irs(&params, "acgtaacgga".as_bytes()).unwrap();
+    assert!(irs.contains(&IrMatch {
+        start: 0,
+        end: 9,
+        arm_len: 5,
+        gap: 0,
+        mismatches: 1,
+        mode: SymmetryMode::Direct
+    }));
+}
+
+#[test]
+fn test_search_threads() {
+    let seq = random_seq(7, 40000, b"acgt");
+    let mut params = SearchParams::new(5, 12, 4, 1).unwrap();
+    let expected = find_spans(&params, &seq).unwrap();
+
+    // Capped thread pools, also shared by the chunks
+    for threads in [1, 3] {
+        params.threads = threads;
+        params.chunk_len = None;
+        assert_eq!(find_spans(&params, &seq).unwrap(), expected);
+        params.chunk_len = Some(5000);
+        assert_eq!(find_spans(&params, &seq).unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_exact_search_without_index() {
+    // Searches without mismatches scan the extensions instead of querying the index.
+    let alphabet = b"acgtacgtacgtacgtnrym";
+    let seq = random_seq(11, 5000, alphabet);
+    // The scans stop at the longest IR in runs.
+    let mut runs = seq.clone();
+    runs.splice(1000..1000, b"n".repeat(300));
+    runs.splice(3000..3000, b"at".repeat(200));
+    for seq in [seq, runs] {
+        for mode in [SymmetryMode::Inverted, SymmetryMode::InvertedComplementary] {
+            let index = IrIndex::new(&seq, mode).unwrap();
+            for (min_len, max_len, max_gap) in [(3, 20, 0), (4, 30, 7), (6, 10, 40)] {
+                let mut params = SearchParams::new(min_len, max_len, max_gap, 0).unwrap();
+                params.symmetry_mode = mode;
+                let mut expected: Vec<_> = index
+                    .enumerate_centers(&params)
+                    .unwrap()
+                    .into_iter()
+                    .flat_map(|center| index.verify_center(&params, center).unwrap())
+                    .collect();
+                expected.sort();
+                let mut irs = find_spans(&params, &seq).unwrap();
+                irs.sort();
+                assert_eq!(irs, expected, "{mode:?} {params:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_scan_bounded_in_runs() {
+    use crate::observer::{AlgoEvent, Observer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct MaxSteps(AtomicUsize);
+
+    impl Observer for MaxSteps {
+        fn on_event(&self, event: AlgoEvent) {
+            if let AlgoEvent::Seed { steps, .. } = event {
+                self.0.fetch_max(steps, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Every extension inside the run would be scanned to its end, 20000 / 21 times.
+    let seq = "n".repeat(20000).into_bytes();
+    let params = SearchParams::new(3, 20, 0, 0).unwrap();
+    let steps = MaxSteps::default();
+    super::search(&params, &seq, &StopHandle::new(), &steps, |_, _| Ok(())).unwrap();
+    assert!(steps.0.load(Ordering::Relaxed) <= 2);
+}
+
+#[test]
+fn test_blocks_rmq_backend() {
+    let seq = random_seq(13, 20000, b"acgtn");
+    let mut params = SearchParams::new(5, 30, 10, 2).unwrap();
+    let expected = find_spans(&params, &seq).unwrap();
+    params.rmq = RmqBackend::Blocks;
+    assert_eq!(find_spans(&params, &seq).unwrap(), expected);
+}
+
+#[test]
+fn test_seed_and_extend() {
+    let seq = random_seq(19, 20000, b"acgt");
+
+    // Perfect arms of 8 bases or more all hold a seed of 8.
+    let mut params = SearchParams::new(8, 30, 12, 0).unwrap();
+    let expected = find_spans(&params, &seq).unwrap();
+    params.seed_len = Some(8);
+    assert_eq!(find_spans(&params, &seq).unwrap(), expected);
+
+    // With mismatches, the heuristic only misses IRs.
+    let mut params = SearchParams::new(8, 30, 12, 2).unwrap();
+    let expected = find_spans(&params, &seq).unwrap();
+    params.seed_len = Some(5);
+    let irs = find_spans(&params, &seq).unwrap();
+    assert!(!irs.is_empty() && irs.len() <= expected.len());
+    assert!(irs.iter().all(|ir| expected.contains(ir)));
+
+    params.seed_len = Some(40);
+    assert!(find_spans(&params, &seq).is_err());
+}
+
+#[test]
+fn test_kmer_prefilter() {
+    let alphabet = b"acgtacgtacgtacgtnr";
+    let mut seq = random_seq(23, 30000, alphabet);
+    // IRs of 20 bases with a mismatch and an N in the arms.
+    for start in (0..29000).step_by(1000) {
+        let gap = start / 1000 % 6;
+        for k in 0..20 {
+            seq[start + 39 + gap - k] = match seq[start + k] {
+                b'a' => b't',
+                b'c' => b'g',
+                b'g' => b'c',
+                b't' => b'a',
+                other => other,
+            };
+        }
+        seq[start + 3] = b'n';
+        seq[start + 12] = if seq[start + 12] == b'a' { b'c' } else { b'a' };
+    }
+
+    for (min_len, mismatches) in [(12, 0), (12, 1), (20, 1), (30, 2)] {
+        let mut params = SearchParams::new(min_len, 40, 8, mismatches).unwrap();
+        let expected = find_spans(&params, &seq).unwrap();
+        params.prefilter = true;
+        assert_eq!(find_spans(&params, &seq).unwrap(), expected, "{params:?}");
+    }
+}
+
+#[test]
+fn test_max_memory_fallback() {
+    let seq = random_seq(29, 40000, b"acgt");
+    let mut params = SearchParams::new(5, 12, 4, 1).unwrap();
+    let expected = find_spans(&params, &seq).unwrap();
+
+    // Too little memory for a single index: blocks RMQ and chunks.
+    let mut blocks = params.clone();
+    blocks.rmq = RmqBackend::Blocks;
+    params.max_memory = Some(blocks.estimate_memory(seq.len()).peak() - 1);
+    assert_eq!(find_spans(&params, &seq).unwrap(), expected);
+
+    params.max_memory = Some(1000);
+    assert!(find_spans(&params, &seq).is_err());
+}
+
+#[test]
+fn test_circular_sequence() {
+    let seq = random_seq(31, 300, b"acgt");
+    let n = seq.len();
+    for mismatches in [0, 1] {
+        let linear = SearchParams::new(4, 8, 3, mismatches).unwrap();
+        let mut params = linear.clone();
+        params.circular = true;
+        let irs = find_spans(&params, &seq).unwrap();
+        assert!(irs.iter().any(|&(_, end, _)| end >= n));
+        assert!(
+            irs.iter()
+                .all(|&(start, end, _)| start < n && end < start + n)
+        );
+
+        // Away from the origin, the IRs are the ones of the linear sequence.
+        let inner = |irs: &[IrSpan]| -> Vec<IrSpan> {
+            irs.iter()
+                .copied()
+                .filter(|&(start, end, _)| start >= 20 && end + 20 < n)
+                .collect()
+        };
+        assert_eq!(inner(&irs), inner(&find_spans(&linear, &seq).unwrap()));
+
+        // The same IRs on every rotation of the circle.
+        for origin in [1, 150, 299] {
+            let mut rotated = seq.clone();
+            rotated.rotate_left(origin);
+            let mut expected: Vec<IrSpan> = irs
+                .iter()
+                .map(|&(start, end, gap)| {
+                    let rotated_start = (start + n - origin) % n;
+                    (rotated_start, rotated_start + end - start, gap)
+                })
+                .collect();
+            expected.sort();
+            let mut found = find_spans(&params, &rotated).unwrap();
+            found.sort();
+            assert_eq!(found, expected, "origin {origin}");
+        }
+    }
+}
+
+#[test]
+fn test_index_len_limit() {
+    assert!(super::check_index_len(super::MAX_INDEX_LEN).is_ok());
+    let err = super::check_index_len(super::MAX_INDEX_LEN + 1).unwrap_err();
+    assert!(err.to_string().contains("--chunk-size"));
+}
+
+#[test]
+#[ignore = "searches 4.3 Gbp, needs about 10 GB of memory"]
+fn test_search_beyond_u32_positions() {
+    // A perfect IR past u32::MAX, in a filler of a and c which has no IRs.
+    let start = 4_300_000_000;
+    let mut seq: Vec<u8> = b"aac".iter().copied().cycle().take(start + 100).collect();
+    seq[start..start + 12].copy_from_slice(b"gggtttaaaccc");
+    let mut params = SearchParams::new(6, 6, 0, 1).unwrap();
+    params.chunk_len = Some(100_000_000);
+    let irs = find_spans(&params, &seq).unwrap();
+    assert_eq!(irs, vec![(start, start + 11, 0)]);
+}
@@ -0,0 +1,98 @@
+//! Ready-made genome browser URLs of IRs.
+
+/// Ensembl site and species of the assemblies it hosts, by UCSC and Genome Reference
+/// Consortium name.
+const ENSEMBL_ASSEMBLIES: [(&str, &str, &str, &str); 6] = [
+    ("hg38", "GRCh38", "www.ensembl.org", "Homo_sapiens"),
+    ("hg19", "GRCh37", "grch37.ensembl.org", "Homo_sapiens"),
+    ("mm39", "GRCm39", "www.ensembl.org", "Mus_musculus"),
+    ("danRer11", "GRCz11", "www.ensembl.org", "Danio_rerio"),
+    ("rn7", "mRatBN7.2", "www.ensembl.org", "Rattus_norvegicus"),
+    ("galGal6", "GRCg6a", "www.ensembl.org", "Gallus_gallus"),
+];
+
+/// UCSC, Ensembl and IGV (web app) locus URLs on a sequence of an assembly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrowserLinks<'a> {
+    /// UCSC (`hg38`) or Genome Reference Consortium (`GRCh38`) name of the assembly.
+    pub assembly: &'a str,
+    /// Name of the sequence, with or without the UCSC `chr` prefix.
+    pub chrom: &'a str,
+}
+
+impl<'a> BrowserLinks<'a> {
+    pub const fn new(assembly: &'a str, chrom: &'a str) -> Self {
+        Self { assembly, chrom }
+    }
+
+    /// UCSC name of the assembly, and its Ensembl site and species if Ensembl hosts it.
+    fn resolve(&self) -> (&'a str, Option<(&'static str, &'static str)>) {
+        ENSEMBL_ASSEMBLIES
+            .iter()
+            .find(|(ucsc, grc, _, _)| {
+                self.assembly.eq_ignore_ascii_case(ucsc) || self.assembly.eq_ignore_ascii_case(grc)
+            })
+            .map_or((self.assembly, None), |&(ucsc, _, site, species)| {
+                (ucsc, Some((site, species)))
+            })
+    }
+
+    /// UCSC naming of the sequence (`chr1`), for the UCSC browser and IGV.
+    fn ucsc_chrom(&self) -> String {
+        if self.chrom.starts_with("chr") {
+            self.chrom.to_string()
+        } else {
+            format!("chr{}", self.chrom)
+        }
+    }
+
+    /// URLs of the 1-based inclusive span `start..=end`: UCSC, Ensembl (`None` for an
+    /// assembly Ensembl does not host) and IGV.
+    pub fn urls(&self, start: usize, end: usize) -> [Option<String>; 3] {
+        let (ucsc_db, ensembl) = self.resolve();
+        let locus = format!("{}:{start}-{end}", self.ucsc_chrom());
+        [
+            Some(format!(
+                "https://genome.ucsc.edu/cgi-bin/hgTracks?db={ucsc_db}&position={locus}"
+            )),
+            ensembl.map(|(site, species)| {
+                let chrom = self.chrom.strip_prefix("chr").unwrap_or(self.chrom);
+                format!("https://{site}/{species}/Location/View?r={chrom}:{start}-{end}")
+            }),
+            Some(format!(
+                "https://igv.org/app/?genome={ucsc_db}&locus={locus}"
+            )),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_browser_links() {
+        let [ucsc, ensembl, igv] = BrowserLinks::new("GRCh38", "1").urls(11, 20);
+        assert_eq!(
+            ucsc.unwrap(),
+            "https://genome.ucsc.edu/cgi-bin/hgTracks?db=hg38&position=chr1:11-20"
+        );
+        assert_eq!(
+            ensembl.unwrap(),
+            "https://www.ensembl.org/Homo_sapiens/Location/View?r=1:11-20"
+        );
+        assert_eq!(
+            igv.unwrap(),
+            "https://igv.org/app/?genome=hg38&locus=chr1:11-20"
+        );
+
+        let [_, ensembl, _] = BrowserLinks::new("hg19", "chrX").urls(1, 2);
+        assert_eq!(
+            ensembl.unwrap(),
+            "https://grch37.ensembl.org/Homo_sapiens/Location/View?r=X:1-2"
+        );
+        let [ucsc, ensembl, _] = BrowserLinks::new("sacCer3", "chrIV").urls(1, 2);
+        assert!(ucsc.unwrap().contains("db=sacCer3&position=chrIV:1-2"));
+        assert_eq!(ensembl, None);
+    }
+}
@@ -1,31 +1,757 @@
 extern crate elapsed_time;
 
-use iirs::Cli;
-use iirs::{find_irs, stringify_irs};
+use iirs::{
+    BinaryResults, ChainArgs, Cli, Command, CompareArgs, Config, ConvertArgs, EnrichmentArgs,
+    IrMatch, Manifest, ManifestEntry, MaskArgs, OutputFormat, ParamSet, ResultFile, ShuffleArgs,
+    StrataOutput, chain_irs, compare_irs, find_irs, find_irs_multi, fmt_chains_header,
+    fmt_enrichment_header, fmt_ir_diffs_header, ir_enrichment, is_binary_results, mask_irs,
+    pipeline_records, set_operation, shuffle_sequence, sort_irs_by, stringify_irs,
+};
+use iirs::{
+    EinvertedSeqSink, FASTA_LINE_WIDTH, FormatSink, PerfCounters, PerfReport, ProgressReporter,
+    SearchSummary, StopHandle, StopReason, itr_qc, read_feature_tsv, transparent_huge_pages,
+    write_irs_counted, write_irs_stratified, write_irs_to_sinks, write_npy,
+};
+
+#[cfg(feature = "numa")]
+use iirs::NumaTopology;
 
 use anyhow::Result;
-use std::fs::File;
-use std::io::Write;
+use seq_io::fasta::OwnedRecord;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Exit code of --itr-qc when a construct fails.
+const ITR_QC_FAILED: i32 = 4;
+
+const MAX_HITS_HINT: &str = "Consider stricter parameters: a larger --min-len, \
+    a smaller --max-gap or fewer --mismatches.";
 
 #[elapsed_time::elapsed]
 fn main() -> Result<()> {
     let args = Cli::parse_args();
-    let check_bounds = true;
+    match &args.command {
+        Some(Command::Mask(mask_args)) => return mask(&args, mask_args),
+        Some(Command::Shuffle(shuffle_args)) => return shuffle(&args, shuffle_args),
+        Some(Command::Enrichment(enrichment_args)) => return enrichment(&args, enrichment_args),
+        Some(Command::Chain(chain_args)) => return chain(&args, chain_args),
+        Some(Command::Convert(convert_args)) => return convert(&args, convert_args),
+        Some(Command::Compare(compare_args)) => return compare(&args, compare_args),
+        Some(command) => return run_set_operation(command),
+        None => (),
+    }
+    if let Some(reads) = &args.reads {
+        return scan_reads(&args, reads);
+    }
+    if args.pipeline {
+        // The next sequences are read and normalized while the current one is searched.
+        return std::thread::scope(|scope| {
+            let reading = Instant::now();
+            let (sharded, config_records) = pipeline_records(scope, &args, true)?;
+            let read = reading.elapsed();
+            let config_records = config_records.into_iter().inspect(|pair| {
+                if let Ok((config, record)) = pair {
+                    advise(&args, config, record);
+                }
+            });
+            search(&args, sharded, config_records, read)
+        });
+    }
+    // The bounds of every parameter set are checked instead.
+    let check_bounds = args.param_sets.is_none();
+    let reading = Instant::now();
     let config_record_pairs = args.try_from_args(check_bounds)?;
+    let read = reading.elapsed();
+    if args.itr_qc {
+        return check_itrs(&args, config_record_pairs);
+    }
+    if let Some(param_sets) = &args.param_sets {
+        return run_param_sets(&args, param_sets, config_record_pairs);
+    }
+    for (config, record) in &config_record_pairs {
+        advise(&args, config, record);
+    }
+    let sharded = config_record_pairs.len() > 1;
+    search(
+        &args,
+        sharded,
+        config_record_pairs.into_iter().map(Ok),
+        read,
+    )
+}
+
+/// Warn about parameters unsuited to a sequence.
+fn advise(args: &Cli, config: &Config, record: &OwnedRecord) {
+    if !args.quiet {
+        for warning in config.params.advise(record.seq.len()) {
+            eprintln!("Warning for {}: {warning}", config.seq_name);
+        }
+    }
+}
+
+/// Search every sequence and write its IRs. `read` is the time spent on the input before.
+fn search<'a>(
+    args: &Cli,
+    sharded: bool,
+    config_records: impl IntoIterator<Item = Result<(Config<'a>, OwnedRecord)>> + Send,
+    read: Duration,
+) -> Result<()> {
+    let searching = Instant::now();
+    if args.huge_pages && !args.quiet {
+        match transparent_huge_pages().as_deref() {
+            Some("always" | "madvise") => (),
+            Some(mode) => eprintln!("Warning: transparent huge pages are disabled ({mode})."),
+            None => eprintln!("Warning: this system has no transparent huge pages."),
+        }
+    }
+
+    // On Ctrl-C / SIGTERM (or when running out of time) finish the current window of centers
+    // and write what was found. The time limit applies to the whole run, not to each sequence.
+    let mut stop = StopHandle::new();
+    if let Some(seconds) = args.max_time {
+        stop = stop.with_time_limit(Duration::from_secs(seconds));
+    }
+    if let Some(max_hits) = args.max_hits {
+        stop = stop.with_max_hits(max_hits, args.on_max_hits);
+    }
+    let handler_stop = stop.clone();
+    ctrlc::set_handler(move || handler_stop.stop())?;
+    let counters = PerfCounters::new();
+
+    // Progress is reported from another thread, which stops once `done` is dropped.
+    let progress = args.progress.map(|_| ProgressReporter::new(&counters));
+    let (done, done_receiver) = mpsc::channel::<()>();
+    std::thread::scope(|scope| -> Result<()> {
+        if let Some(progress) = &progress {
+            scope.spawn(move || progress.run(PROGRESS_INTERVAL, &done_receiver));
+        }
+        in_thread_pool(args, || {
+            run(
+                args,
+                config_records,
+                sharded,
+                &stop,
+                &counters,
+                progress.as_ref(),
+            )
+        })?;
+        drop(done);
+        Ok(())
+    })?;
+
+    if args.stats {
+        eprintln!("{counters}");
+    }
+    if args.perf_report {
+        let report = PerfReport::new(&counters, read, read + searching.elapsed());
+        eprintln!("{report}");
+    }
+
+    Ok(())
+}
+
+fn run<'a>(
+    args: &Cli,
+    config_records: impl IntoIterator<Item = Result<(Config<'a>, OwnedRecord)>>,
+    sharded: bool,
+    stop: &StopHandle,
+    counters: &PerfCounters,
+    progress: Option<&ProgressReporter>,
+) -> Result<()> {
+    // One file per sequence goes to the output folder.
+    if sharded {
+        fs::create_dir_all(&args.output_file)?;
+    }
+    let mut manifest: Option<Manifest> = None;
+    let manifest_file = if sharded {
+        format!("{}/manifest.json", args.output_file)
+    } else {
+        format!("{}.manifest.json", args.output_file)
+    };
+
+    let mut report =
+        |config: &Config, record: &OwnedRecord, summary: SearchSummary| -> Result<()> {
+            if args.manifest && manifest.is_none() {
+                manifest = Some(Manifest::new(
+                    config.params.clone(),
+                    config.output_format.clone(),
+                ));
+            }
+            report_record(args, config, record, &summary, counters)?;
+
+            // Rewritten after every sequence, so that it lists the files complete so far.
+            if let Some(manifest) = &mut manifest {
+                let paths = output_files(config);
+                let hits = (paths.len() == 1).then_some(summary.kept);
+                for path in &paths {
+                    let entry = ManifestEntry::new(config, record.seq.len(), path, hits)?;
+                    manifest.entries.push(entry);
+                }
+                fs::write(&manifest_file, manifest.to_json())?;
+            }
+            Ok(())
+        };
+
+    if args.threads > 1 {
+        // Searched in parallel, one record per thread, and reported in the order of the records.
+        let config_records = config_records.into_iter().map(|pair| {
+            pair.map(|(mut config, record)| {
+                // Every search shares the threads of the sequences.
+                config.params.threads = 0;
+                (config, record)
+            })
+        });
+        par_map_ordered(
+            args.threads,
+            config_records,
+            |(config, record)| search_record(args, config, record, stop, counters, progress),
+            |(config, record), summary| report(&config, &record, summary),
+        )?;
+    } else {
+        for pair in config_records {
+            let (config, record) = pair?;
+            let summary = search_record(args, &config, &record, stop, counters, progress)?;
+            report(&config, &record, summary)?;
+        }
+    }
+
+    Ok(())
+}
 
+/// Search a sequence and write its IRs to its output files, followed by a footer if the search
+/// was stopped or only counted some IRs.
+fn search_record(
+    args: &Cli,
+    config: &Config,
+    record: &OwnedRecord,
+    stop: &StopHandle,
+    counters: &PerfCounters,
+    progress: Option<&ProgressReporter>,
+) -> Result<SearchSummary> {
+    // IRs are written as soon as they are found.
+    let paths = output_files(config);
+    let mut files = paths
+        .iter()
+        .map(|path| File::create(path).map(BufWriter::new))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    if let Some(progress) = progress {
+        progress.start_sequence(config.seq_name, record.seq.len(), config.params.min_len);
+    }
+    let summary = if config.output_format == OutputFormat::Einverted {
+        // einverted also writes the sequences of the IRs.
+        let seq_files = paths
+            .iter()
+            .map(|path| File::create(format!("{path}.fasta")).map(BufWriter::new))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let mut sinks: Vec<_> = files
+            .iter_mut()
+            .zip(seq_files)
+            .map(|(file, seq_file)| (FormatSink::new(file), EinvertedSeqSink::new(seq_file)))
+            .collect();
+        let summary = write_irs_to_sinks(config, &record.seq, stop, counters, &mut sinks)?;
+        for (_, seq_sink) in sinks {
+            seq_sink.into_inner().flush()?;
+        }
+        summary
+    } else {
+        match files.as_mut_slice() {
+            [file] => write_irs_counted(config, &record.seq, stop, counters, file)?,
+            files => write_irs_stratified(config, &record.seq, stop, counters, files)?,
+        }
+    };
+    if let Some(progress) = progress {
+        progress.finish_sequence(&summary);
+    }
+
+    if let Some(footer) = search_footer(config, &summary, record.seq.len()) {
+        for file in &mut files {
+            writeln!(file, "{footer}")?;
+        }
+    }
+    for file in &mut files {
+        file.flush()?;
+    }
+    if summary.stopped.is_some() {
+        return Ok(summary);
+    }
+    drop(files);
+    for path in &paths {
+        counters.add_bytes_written(fs::metadata(path)?.len());
+    }
+
+    if args.npy && config.output_format == OutputFormat::Features {
+        for path in &paths {
+            let mut npy = BufWriter::new(File::create(format!("{path}.npy"))?);
+            write_npy(&mut npy, &read_feature_tsv(path)?)?;
+            npy.flush()?;
+        }
+    }
+    Ok(summary)
+}
+
+/// Footer of a search that was stopped or only counted some IRs, in the form of the output
+/// format: a `#` line, or a last object of JSON Lines. A JSON file holds a single array, so it
+/// gets none: `report_record` tells it on the standard error.
+fn search_footer(config: &Config, summary: &SearchSummary, seq_len: usize) -> Option<String> {
+    match config.output_format {
+        OutputFormat::Json => None,
+        OutputFormat::Jsonl => match summary.stopped {
+            Some(reason) => Some(format!(
+                r#"{{"truncated":{{"reason":"{}","covered":{},"seq_len":{}}}}}"#,
+                reason, summary.covered, seq_len
+            )),
+            None => (summary.found > summary.kept).then(|| {
+                format!(
+                    r#"{{"count_only":{{"kept":{},"found":{}}}}}"#,
+                    summary.kept, summary.found
+                )
+            }),
+        },
+        _ => truncation_footer(summary, seq_len).or_else(|| {
+            (summary.found > summary.kept).then(|| {
+                format!(
+                    "# COUNT ONLY: wrote the first {} out of {} inverted repeats",
+                    summary.kept, summary.found
+                )
+            })
+        }),
+    }
+}
+
+fn truncation_footer(summary: &SearchSummary, seq_len: usize) -> Option<String> {
+    summary.stopped.map(|reason| {
+        format!(
+            "# TRUNCATED ({}): only positions 1..{} out of {} were searched",
+            reason, summary.covered, seq_len
+        )
+    })
+}
+
+/// Tell how the search of a sequence went, and exit if it was stopped.
+fn report_record(
+    args: &Cli,
+    config: &Config,
+    record: &OwnedRecord,
+    summary: &SearchSummary,
+    counters: &PerfCounters,
+) -> Result<()> {
+    if let Some(reason) = summary.stopped {
+        let footer = truncation_footer(summary, record.seq.len()).unwrap_or_default();
+        eprintln!(
+            "{} for {}: {}",
+            footer, &config.seq_name, config.output_file
+        );
+        if reason == StopReason::MaxHits {
+            eprintln!("{MAX_HITS_HINT}");
+        }
+        if args.stats {
+            eprintln!("{counters}");
+        }
+        std::process::exit(reason.exit_code());
+    }
+
+    if summary.found > summary.kept {
+        eprintln!(
+            "Found n={} inverted repeats for {}, but only the first {} were written.\n{}",
+            summary.found, &config.seq_name, summary.kept, MAX_HITS_HINT
+        );
+    }
+    if !args.quiet {
+        println!("\n{config}");
+        println!("Search complete for {}!", &config.seq_name);
+        println!("Found n={} inverted repeats\n", summary.found);
+    }
+    Ok(())
+}
+
+/// Map `f` over `items` with `threads` worker threads, and hand every item and its result to
+/// `consume` in the order of the items. An item is started as soon as a thread is free, and at
+/// most `2 * threads` items are held in memory (searched, or waiting for an earlier one).
+#[cfg(feature = "parallel")]
+fn par_map_ordered<T: Send, R: Send>(
+    threads: usize,
+    mut items: impl Iterator<Item = Result<T>>,
+    f: impl Fn(&T) -> Result<R> + Sync,
+    mut consume: impl FnMut(T, R) -> Result<()>,
+) -> Result<()> {
+    use std::collections::BTreeMap;
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|err| anyhow::anyhow!("Could not start {threads} threads: {err}"))?;
+    let window = 2 * threads;
+    let (tx, rx) = mpsc::channel();
+    let f = &f;
+    pool.in_place_scope(|scope| {
+        // Results received before the ones of the earlier items, by index.
+        let mut done = BTreeMap::new();
+        let (mut started, mut next) = (0, 0);
+        loop {
+            while started - next < window {
+                let Some(item) = items.next().transpose()? else {
+                    break;
+                };
+                let tx = tx.clone();
+                let idx = started;
+                scope.spawn(move |_| {
+                    let result = catch_unwind(AssertUnwindSafe(|| f(&item)))
+                        .unwrap_or_else(|_| Err(anyhow::anyhow!("A search thread panicked.")));
+                    // The receiver outlives the scope.
+                    let _ = tx.send((idx, item, result));
+                });
+                started += 1;
+            }
+            if started == next {
+                return Ok(());
+            }
+            let (idx, item, result) = rx
+                .recv()
+                .map_err(|_| anyhow::anyhow!("A search thread panicked."))?;
+            done.insert(idx, (item, result));
+            while let Some((item, result)) = done.remove(&next) {
+                next += 1;
+                consume(item, result?)?;
+            }
+        }
+    })
+}
+
+#[cfg(not(feature = "parallel"))]
+fn par_map_ordered<T: Send, R: Send>(
+    _threads: usize,
+    _items: impl Iterator<Item = Result<T>>,
+    _f: impl Fn(&T) -> Result<R> + Sync,
+    _consume: impl FnMut(T, R) -> Result<()>,
+) -> Result<()> {
+    anyhow::bail!("--threads requires iirs to be built with the `parallel` feature.");
+}
+
+/// The output file, or one file per gap stratum (e.g. IUPACpal.out.gap1-3).
+fn output_files(config: &Config) -> Vec<String> {
+    match &config.gap_strata {
+        Some(strata) if strata.output == StrataOutput::Files => (0..strata.count())
+            .map(|idx| format!("{}.gap{}", config.output_file, strata.label(idx)))
+            .collect(),
+        _ => vec![config.output_file.to_string()],
+    }
+}
+
+/// Search every sequence once per parameter set, sharing the index of the sequence.
+fn run_param_sets(
+    args: &Cli,
+    param_sets: &[ParamSet],
+    config_record_pairs: Vec<(Config, OwnedRecord)>,
+) -> Result<()> {
     for (config, record) in config_record_pairs {
-        let irs = find_irs(&config.params, &record.seq)?;
+        let params_list = param_sets
+            .iter()
+            .map(|set| set.apply(&config.params))
+            .collect::<Result<Vec<_>>>()?;
+        for params in &params_list {
+            params.check_bounds(record.seq.len())?;
+        }
+        let results = find_irs_multi(&params_list, &record.seq)?;
+
+        for ((set, params), irs) in param_sets.iter().zip(params_list).zip(results) {
+            let mut irs: Vec<_> = irs.iter().map(IrMatch::span).collect();
+            let output_file = format!("{}.{}", config.output_file, set.label());
+            let mut set_config = config.clone();
+            set_config.params = params;
+            set_config.output_file = &output_file;
+
+            sort_irs_by(&mut irs, set_config.sort_by);
+            let (header, irs_str) = stringify_irs(&set_config, &irs, &record.seq);
+            let mut file = BufWriter::new(File::create(&output_file)?);
+            write_stringified(&mut file, &set_config, &header, &irs_str)?;
+            file.flush()?;
+
+            if !args.quiet {
+                println!(
+                    "Found n={} inverted repeats for {} with {}",
+                    irs.len(),
+                    config.seq_name,
+                    set.label()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn run_set_operation(command: &Command) -> Result<()> {
+    let (operation, args) = command
+        .operation()
+        .expect("other subcommands are handled before set operations");
+    let a = ResultFile::read(&args.a)?;
+    let b = ResultFile::read(&args.b)?;
+    let mut result = set_operation(operation, &a, &b, args.tolerance)?;
+    for path in &args.more {
+        result = set_operation(operation, &result, &ResultFile::read(path)?, args.tolerance)?;
+    }
+    match &args.output_file {
+        Some(path) => fs::write(path, result.to_string())?,
+        None => print!("{result}"),
+    }
+    Ok(())
+}
+
+fn mask(args: &Cli, mask_args: &MaskArgs) -> Result<()> {
+    let check_bounds = true;
+    let mut out = file_or_stdout(mask_args.output_file.as_deref())?;
+    for (config, record) in args.try_from_args(check_bounds)? {
+        let irs: Vec<_> = find_irs(&config.params, &record.seq)?
+            .iter()
+            .map(IrMatch::span)
+            .collect();
+        let masked = mask_irs(&record.seq, &irs, mask_args.mode);
+        write_fasta(&mut out, &record.head, &masked)?;
+
+        if !args.quiet && mask_args.output_file.is_some() {
+            println!(
+                "Masked n={} inverted repeats in {}",
+                irs.len(),
+                config.seq_name
+            );
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn shuffle(args: &Cli, shuffle_args: &ShuffleArgs) -> Result<()> {
+    let check_bounds = false;
+    let mut out = file_or_stdout(shuffle_args.output_file.as_deref())?;
+    let mut seed = args.seed;
+    for (config, record) in args.try_from_args(check_bounds)? {
+        for copy in 1..=shuffle_args.copies {
+            let shuffled = shuffle_sequence(&record.seq, shuffle_args.mode, seed);
+            let head = format!("{}_shuffled_{} seed={}", config.seq_name, copy, seed);
+            write_fasta(&mut out, head.as_bytes(), &shuffled)?;
+            seed = seed.wrapping_add(1);
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn enrichment(args: &Cli, enrichment_args: &EnrichmentArgs) -> Result<()> {
+    let check_bounds = true;
+    let mut out = file_or_stdout(enrichment_args.output_file.as_deref())?;
+    writeln!(out, "seq_name,{}", fmt_enrichment_header())?;
+    for (config, record) in args.try_from_args(check_bounds)? {
+        let windows = ir_enrichment(
+            &config.params,
+            &record.seq,
+            enrichment_args.window,
+            enrichment_args.mode,
+            enrichment_args.copies,
+            args.seed,
+        )?;
+        for window in &windows {
+            writeln!(out, "{},{window}", config.seq_name)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn compare(args: &Cli, compare_args: &CompareArgs) -> Result<()> {
+    let check_bounds = true;
+    let mut alternates = compare_args.alternate_records(&args.seq_names)?;
+    let mut out = file_or_stdout(compare_args.output_file.as_deref())?;
+    writeln!(out, "seq_name,{}", fmt_ir_diffs_header())?;
+    for (config, record) in args.try_from_args(check_bounds)? {
+        let Some(alternate) = alternates.remove(config.seq_name) else {
+            anyhow::bail!(
+                "Sequence {} not found in {}.",
+                config.seq_name,
+                compare_args.alternate
+            );
+        };
+        let diffs = compare_irs(
+            &config.params,
+            &record.seq,
+            &alternate.seq,
+            compare_args.context,
+        )?;
+        for diff in &diffs {
+            writeln!(out, "{},{diff}", config.seq_name)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn chain(args: &Cli, chain_args: &ChainArgs) -> Result<()> {
+    let check_bounds = false;
+    let mut config_record_pairs = args.try_from_args(check_bounds)?;
+    if config_record_pairs.len() != 1 {
+        anyhow::bail!(
+            "Select the sequence of {} with --seq-names.",
+            chain_args.results
+        );
+    }
+    let (config, record) = config_record_pairs.remove(0);
+    let irs: Vec<_> = ResultFile::read(&chain_args.results)?.irs().collect();
+    let chains = chain_irs(&irs, &record.seq, &config.params, chain_args.max_distance)?;
+
+    let mut out = file_or_stdout(chain_args.output_file.as_deref())?;
+    writeln!(out, "{}", fmt_chains_header())?;
+    for chain in &chains {
+        writeln!(out, "{chain}")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn convert(args: &Cli, convert_args: &ConvertArgs) -> Result<()> {
+    let check_bounds = false;
+    let mut config_record_pairs = args.try_from_args(check_bounds)?;
+    if config_record_pairs.len() != 1 {
+        anyhow::bail!(
+            "Select the sequence of {} with --seq-names.",
+            convert_args.results
+        );
+    }
+    let (mut config, record) = config_record_pairs.remove(0);
+    let bytes = fs::read(&convert_args.results)?;
+
+    let mut out = file_or_stdout(convert_args.output_file.as_deref())?;
+    if is_binary_results(&bytes) {
+        let results = BinaryResults::read(&mut bytes.as_slice())?;
+        if results.seq_len != record.seq.len() {
+            anyhow::bail!(
+                "{} was found on a sequence of {} bp, but {} has {} bp.",
+                convert_args.results,
+                results.seq_len,
+                config.seq_name,
+                record.seq.len()
+            );
+        }
+        // The header of the classic output format shows the parameters of the run.
+        config.params.min_len = results.min_len;
+        config.params.max_len = results.max_len;
+        config.params.max_gap = results.max_gap;
+        config.params.mismatches = results.mismatches;
+
+        let mut irs = results.irs;
+        sort_irs_by(&mut irs, config.sort_by);
         let (header, irs_str) = stringify_irs(&config, &irs, &record.seq);
+        write_stringified(&mut out, &config, &header, &irs_str)?;
+    } else {
+        let irs = ResultFile::read(&convert_args.results)?.irs().collect();
+        let results = BinaryResults::new(config.seq_name, record.seq.len(), &config.params, irs);
+        results.write(&mut out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Write the output of `stringify_irs`, as `FormatSink` would.
+fn write_stringified(
+    out: &mut impl Write,
+    config: &Config,
+    header: &str,
+    irs_str: &str,
+) -> Result<()> {
+    if config.output_format != OutputFormat::Jsonl {
+        writeln!(out, "{header}")?;
+    }
+    write!(out, "{irs_str}")?;
+    if config.output_format.has_trailing_line() {
+        writeln!(out)?;
+    }
+    Ok(())
+}
 
-        let mut file = File::create(config.output_file)?;
-        writeln!(&mut file, "{}\n{}", &header, &irs_str)?;
+/// The given file, or the standard output.
+fn file_or_stdout(output_file: Option<&str>) -> Result<Box<dyn Write>> {
+    Ok(match output_file {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    })
+}
+
+fn write_fasta(out: &mut impl Write, head: &[u8], seq: &[u8]) -> Result<()> {
+    out.write_all(b">")?;
+    out.write_all(head)?;
+    out.write_all(b"\n")?;
+    for line in seq.chunks(FASTA_LINE_WIDTH) {
+        out.write_all(line)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+fn check_itrs(args: &Cli, config_record_pairs: Vec<(Config, OwnedRecord)>) -> Result<()> {
+    let mut all_passed = true;
+    for (config, record) in config_record_pairs {
+        let qc = itr_qc(&config.params, args.internal_min_len, &record.seq)?;
+        let report = format!("# ITR QC for {}\n{}", config.seq_name, qc);
+        let mut file = BufWriter::new(File::create(config.output_file)?);
+        writeln!(&mut file, "{report}")?;
+        file.flush()?;
 
         if !args.quiet {
-            println!("\n{config}");
-            println!("Search complete for {}!", &config.seq_name);
-            println!("Found n={} inverted repeats\n", irs.len());
+            println!("{report}\n");
         }
+        all_passed &= qc.passed();
     }
 
+    if !all_passed {
+        std::process::exit(ITR_QC_FAILED);
+    }
     Ok(())
 }
+
+/// Run `search` in the worker threads of the machine: pinned to its NUMA nodes with --numa,
+/// or in the default thread pool.
+#[cfg(feature = "numa")]
+fn in_thread_pool<R: Send>(args: &Cli, search: impl FnOnce() -> Result<R> + Send) -> Result<R> {
+    if !args.numa {
+        return search();
+    }
+    let topology = NumaTopology::detect();
+    if !args.quiet {
+        println!(
+            "Pinning {} worker threads to {} NUMA node(s)",
+            topology.num_cpus(),
+            topology.nodes().len()
+        );
+    }
+    topology.thread_pool()?.install(search)
+}
+
+#[cfg(not(feature = "numa"))]
+fn in_thread_pool<R: Send>(args: &Cli, search: impl FnOnce() -> Result<R> + Send) -> Result<R> {
+    if args.numa {
+        anyhow::bail!("--numa requires iirs to be built with the `numa` feature.");
+    }
+    search()
+}
+
+#[cfg(feature = "bam")]
+fn scan_reads(args: &Cli, reads: &str) -> Result<()> {
+    let params = args.search_params()?;
+    let mut file = BufWriter::new(File::create(&args.output_file)?);
+    let found = iirs::scan_reads(reads, args.reference.as_deref(), &params, &mut file)?;
+    file.flush()?;
+
+    if !args.quiet {
+        println!("Search complete for the reads of {reads}!");
+        println!("Found n={found} inverted repeats\n");
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "bam"))]
+fn scan_reads(_args: &Cli, reads: &str) -> Result<()> {
+    anyhow::bail!("'{reads}' can only be scanned if iirs is built with the `bam` feature.")
+}
@@ -0,0 +1,161 @@
+//! JSON manifest of the output files of a run (one file per sequence for multi-sequence
+//! inputs), for distributed processing and integrity checks.
+use anyhow::{Context, Result};
+use std::fmt::Write;
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+use crate::config::{Config, OutputFormat, SearchParams};
+use crate::progress::json_string;
+
+/// CRC-32 (IEEE, as in gzip and zlib) lookup table.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 of the content of a file.
+pub fn crc32_file(path: &str) -> Result<u32> {
+    let file = File::open(path).with_context(|| format!("Could not read {path}"))?;
+    let mut reader = BufReader::new(file);
+    let mut buf = [0u8; 1 << 16];
+    let mut crc = !0u32;
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        for &byte in &buf[..read] {
+            crc = CRC32_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8);
+        }
+    }
+    Ok(!crc)
+}
+
+/// An output file of the run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub seq_name: String,
+    pub seq_len: usize,
+    pub file: String,
+    /// Number of IRs written to the file (unknown when the IRs of a sequence are split among
+    /// several files, e.g. by gap stratum).
+    pub hits: Option<usize>,
+    pub crc32: u32,
+}
+
+impl ManifestEntry {
+    /// Entry of a file already written (its checksum is computed).
+    pub fn new(config: &Config, seq_len: usize, file: &str, hits: Option<usize>) -> Result<Self> {
+        Ok(Self {
+            seq_name: config.seq_name.to_string(),
+            seq_len,
+            file: file.to_string(),
+            hits,
+            crc32: crc32_file(file)?,
+        })
+    }
+}
+
+/// Parameters and output files of a run.
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub params: SearchParams,
+    pub output_format: OutputFormat,
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub const fn new(params: SearchParams, output_format: OutputFormat) -> Self {
+        Self {
+            params,
+            output_format,
+            entries: Vec::new(),
+        }
+    }
+
+    /// The manifest as a JSON object, with one line per output file.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\n");
+        writeln!(
+            &mut out,
+            "  \"params\": {{\"min_len\":{},\"max_len\":{},\"max_gap\":{},\"mismatches\":{},\"output_format\":{}}},",
+            self.params.min_len,
+            self.params.max_len,
+            self.params.max_gap,
+            self.params.mismatches,
+            json_string(&self.output_format.to_string())
+        )
+        .unwrap();
+        out.push_str("  \"files\": [");
+        for (idx, entry) in self.entries.iter().enumerate() {
+            let hits = entry
+                .hits
+                .map_or(String::from("null"), |hits| hits.to_string());
+            write!(
+                &mut out,
+                "{}\n    {{\"file\":{},\"seq_name\":{},\"seq_len\":{},\"hits\":{},\"crc32\":\"{:08x}\"}}",
+                if idx == 0 { "" } else { "," },
+                json_string(&entry.file),
+                json_string(&entry.seq_name),
+                entry.seq_len,
+                hits,
+                entry.crc32
+            )
+            .unwrap();
+        }
+        if !self.entries.is_empty() {
+            out.push_str("\n  ");
+        }
+        out.push_str("]\n}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let path = std::env::temp_dir().join(format!("iirs-manifest-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "123456789").unwrap();
+        // Check value of CRC-32
+        assert_eq!(crc32_file(path).unwrap(), 0xcbf4_3926);
+
+        let config = Config {
+            seq_name: "chr1",
+            ..Default::default()
+        };
+        let mut manifest = Manifest::new(SearchParams::default(), OutputFormat::Csv);
+        manifest
+            .entries
+            .push(ManifestEntry::new(&config, 100, path, Some(3)).unwrap());
+        std::fs::remove_file(path).unwrap();
+
+        let json = manifest.to_json();
+        assert!(json.starts_with("{\n  \"params\": {\"min_len\":10,"));
+        assert!(
+            json.contains(
+                "\"seq_name\":\"chr1\",\"seq_len\":100,\"hits\":3,\"crc32\":\"cbf43926\""
+            )
+        );
+        assert!(json.ends_with("}\n  ]\n}\n"));
+    }
+}
@@ -0,0 +1,54 @@
+//! Masking of the IRs of a sequence, e.g. to design probes or primers away from them.
+use crate::IrSpan;
+
+/// How the spans of the IRs are masked.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MaskMode {
+    /// Lowercase, the rest of the sequence being uppercase.
+    #[default]
+    Soft,
+    /// Replaced by N.
+    Hard,
+}
+
+/// Copy of a (sanitized) sequence in uppercase, with the whole span of every IR masked
+/// (gap included).
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{MaskMode, mask_irs};
+///
+/// let seq = "ttacbbgtaa".as_bytes();
+/// assert_eq!(mask_irs(seq, &[(2, 7, 0)], MaskMode::Soft), b"TTacbbgtAA");
+/// assert_eq!(mask_irs(seq, &[(2, 7, 0)], MaskMode::Hard), b"TTNNNNNNAA");
+/// ```
+pub fn mask_irs(seq: &[u8], irs: &[IrSpan], mode: MaskMode) -> Vec<u8> {
+    let mut masked = vec![false; seq.len()];
+    for &(left, right, _) in irs {
+        masked[left..=right].fill(true);
+    }
+
+    seq.iter()
+        .zip(masked)
+        .map(|(&base, masked)| match (masked, mode) {
+            (false, _) => base.to_ascii_uppercase(),
+            (true, MaskMode::Soft) => base.to_ascii_lowercase(),
+            (true, MaskMode::Hard) => b'N',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_overlapping_irs() {
+        let seq = b"acgtacgtacgt";
+        let irs = [(1, 4, 0), (3, 6, 2), (10, 11, 0)];
+        assert_eq!(mask_irs(seq, &irs, MaskMode::Soft), b"AcgtacgTACgt");
+        assert_eq!(mask_irs(seq, &irs, MaskMode::Hard), b"ANNNNNNTACNN");
+        assert_eq!(mask_irs(seq, &[], MaskMode::Hard), b"ACGTACGTACGT");
+    }
+}
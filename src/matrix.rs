@@ -1,5 +1,6 @@
+use crate::config::SearchParams;
 use crate::constants::{ALL_SYMBOLS_COUNT, build_iupac_rules};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 /// Struct to check if two IUPAC characters match.
 pub struct MatchMatrix {
@@ -13,17 +14,35 @@ pub struct MatchMatrix {
     /// Ex. `iupac_char_to_index[36]` = 19
     /// since ord('$') = 36, which is the 19th key in `iupac_rules`.
     iupac_char_to_index: [usize; 128],
+    /// Whether two plain bases (a, c, g, t) only match when they are equal, so that they can
+    /// be compared with vector instructions.
+    exact_bases: bool,
+}
+
+impl Default for MatchMatrix {
+    fn default() -> Self {
+        Self::build(false, false)
+    }
 }
 
 impl MatchMatrix {
-    pub fn new() -> Self {
+    /// Build the matrix that corresponds to the matching rules of the given parameters.
+    pub fn from_params(params: &SearchParams) -> Self {
+        Self::build(params.bisulfite, params.wobble)
+    }
+
+    fn build(bisulfite: bool, wobble: bool) -> Self {
         let iupac_rules = build_iupac_rules();
 
         // HashMap from an IUPAC char to the set of its complements.
         let mut iupac_map = HashMap::new();
         let mut iupac_char_to_index = [0; 128];
         for (index, (iupac_char, mapped_chars)) in iupac_rules.iter().enumerate() {
-            iupac_map.insert(*iupac_char, mapped_chars.to_owned());
+            let mut mapped_chars = mapped_chars.to_owned();
+            if bisulfite {
+                bisulfite_expand(&mut mapped_chars);
+            }
+            iupac_map.insert(*iupac_char, mapped_chars);
             iupac_char_to_index[*iupac_char as usize] = index;
         }
 
@@ -32,15 +51,28 @@ impl MatchMatrix {
             let i = iupac_char_to_index[*it1_char as usize];
             for (it2_char, it2_set) in &iupac_map {
                 let j = iupac_char_to_index[*it2_char as usize];
-                let matching = !it1_set.is_disjoint(it2_set);
+                let matching =
+                    !it1_set.is_disjoint(it2_set) || (wobble && is_wobble(it1_set, it2_set));
                 match_matrix[i * ALL_SYMBOLS_COUNT + j] = matching;
             }
         }
 
-        Self {
+        let mut matrix = Self {
             match_matrix,
             iupac_char_to_index,
-        }
+            exact_bases: false,
+        };
+        let bases = *b"acgt";
+        matrix.exact_bases = bases.iter().all(|&b1| {
+            bases
+                .iter()
+                .all(|&b2| matrix.match_u8(b1, b2) == (b1 == b2))
+        });
+        matrix
+    }
+
+    pub const fn exact_bases(&self) -> bool {
+        self.exact_bases
     }
 
     pub fn match_u8(&self, b1: u8, b2: u8) -> bool {
@@ -52,6 +84,60 @@ impl MatchMatrix {
     }
 }
 
+/// Matching rule of the characters compared while extending the arms of IRs.
+pub trait Matcher: Sync {
+    fn match_u8(&self, b1: u8, b2: u8) -> bool;
+}
+
+impl Matcher for MatchMatrix {
+    #[inline(always)]
+    fn match_u8(&self, b1: u8, b2: u8) -> bool {
+        Self::match_u8(self, b1, b2)
+    }
+}
+
+/// Same rules as the default [`MatchMatrix`] for sequences of plain bases and N only (and the
+/// separators of the index), without table lookups.
+pub struct PlainMatcher;
+
+impl PlainMatcher {
+    /// Whether `seq` only has plain bases (a, c, g, t) and N.
+    pub fn accepts(seq: &[u8]) -> bool {
+        seq.iter()
+            .all(|b| matches!(b, b'a' | b'c' | b'g' | b't' | b'n'))
+    }
+}
+
+impl Matcher for PlainMatcher {
+    #[inline(always)]
+    fn match_u8(&self, b1: u8, b2: u8) -> bool {
+        // N matches every base, but not the separators ($ and #).
+        b1 == b2 || ((b1 == b'n' || b2 == b'n') && b1.min(b2) >= b'a')
+    }
+}
+
+/// Whether a base of `left` pairs with the base whose complement is in `complemented` through
+/// a G:U wobble pair: G against the complement of U (A), or U against the complement of G (C).
+/// The other way around would let A pair with C.
+fn is_wobble(left: &BTreeSet<char>, complemented: &BTreeSet<char>) -> bool {
+    (left.contains(&'g') && complemented.contains(&'a'))
+        || (left.contains(&'t') && complemented.contains(&'c'))
+}
+
+/// Bisulfite conversion turns unmethylated C into T, which shows up as G into A
+/// when looking at the other strand.
+///
+/// Since the right arm is compared after complementing, both conversions are
+/// absorbed by letting C match T and G match A.
+fn bisulfite_expand(set: &mut BTreeSet<char>) {
+    for (x, y) in [('c', 't'), ('g', 'a')] {
+        if set.contains(&x) || set.contains(&y) {
+            set.insert(x);
+            set.insert(y);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -87,6 +173,25 @@ mod tests {
         format!("{}{}\n\n", &header, &matrix_str)
     }
 
+    #[test]
+    fn test_plain_matcher() {
+        let matrix = MatchMatrix::default();
+        let chars = b"acgtn$#";
+        for &b1 in chars {
+            for &b2 in chars {
+                assert_eq!(
+                    Matcher::match_u8(&PlainMatcher, b1, b2),
+                    matrix.match_u8(b1, b2),
+                    "{} {}",
+                    b1 as char,
+                    b2 as char
+                );
+            }
+        }
+        assert!(PlainMatcher::accepts(b"acgtnnac"));
+        assert!(!PlainMatcher::accepts(b"acgtr"));
+    }
+
     #[test]
     fn test_display() {
         let expected_output = "Match Matrix:\n  \
@@ -112,7 +217,7 @@ mod tests {
             $ 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 \n\
             # 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0";
 
-        let matrix = MatchMatrix::new();
+        let matrix = MatchMatrix::default();
         let complement = build_complement_array();
         let output = display_matrix(&matrix, &complement);
 
@@ -121,7 +226,7 @@ mod tests {
 
     #[test]
     fn test_matches() {
-        let matrix = MatchMatrix::new();
+        let matrix = MatchMatrix::default();
         assert!(matrix.match_u8(b'a', b'a'));
         assert!(matrix.match_u8(b'k', b'u'));
         assert!(matrix.match_u8(b'k', b'g'));
@@ -130,20 +235,47 @@ mod tests {
     #[test]
     fn test_matches_special_chars() {
         // Because these match, we have to deal with this edge case in test::display
-        let matrix = MatchMatrix::new();
+        let matrix = MatchMatrix::default();
         assert!(matrix.match_u8(b'$', b'$'));
         assert!(matrix.match_u8(b'#', b'#'));
     }
 
     #[test]
     fn test_matches_not() {
-        let matrix = MatchMatrix::new();
+        let matrix = MatchMatrix::default();
         assert!(!matrix.match_u8(b'a', b't'));
     }
 
+    #[test]
+    fn test_matches_bisulfite() {
+        let matrix = MatchMatrix::build(true, false);
+        assert!(matrix.match_u8(b'c', b't'));
+        assert!(matrix.match_u8(b't', b'c'));
+        assert!(matrix.match_u8(b'g', b'a'));
+        assert!(matrix.match_u8(b'u', b'c'));
+        assert!(matrix.match_u8(b'y', b'c'));
+        assert!(!matrix.match_u8(b'c', b'a'));
+        assert!(!matrix.match_u8(b'g', b't'));
+        assert!(!matrix.match_u8(b'$', b'#'));
+    }
+
+    #[test]
+    fn test_matches_wobble() {
+        let matrix = MatchMatrix::build(false, true);
+        // G:U, against the complement of U (A)
+        assert!(matrix.match_u8(b'g', b'a'));
+        // U:G, against the complement of G (C)
+        assert!(matrix.match_u8(b'u', b'c'));
+        assert!(matrix.match_u8(b't', b'c'));
+        // A:C and C:A are still mismatches
+        assert!(!matrix.match_u8(b'a', b'g'));
+        assert!(!matrix.match_u8(b'c', b't'));
+        assert!(!matrix.exact_bases());
+    }
+
     #[test]
     fn test_matches_complementary() {
-        let matrix = MatchMatrix::new();
+        let matrix = MatchMatrix::default();
         let complement = build_complement_array();
         assert!(!matrix.match_u8(b'k', complement['u' as usize]));
     }
@@ -0,0 +1,299 @@
+//! Estimate of the peak memory of a search, e.g. to request the right allocation from a
+//! cluster scheduler before launching a job.
+use anyhow::{Result, anyhow};
+use std::fmt;
+
+use crate::chunked::{chunk_overlap, margin};
+use crate::config::{RmqBackend, SearchParams, SymmetryMode};
+use crate::seeds::{prefilter_seed_len, seeds_memory};
+use crate::{IndexInt, IrSpan};
+
+/// Bytes of the structures of a search (see [`SearchParams::estimate_memory`]).
+///
+/// The suffix array only lives while the index is built, and the seeds and the IRs only
+/// while it is searched: [`MemoryEstimate::peak`] takes the larger of the two phases.
+/// Displayed as a single line of JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    /// Sanitized sequence (and its three copies if circular) and doubled sequence
+    /// (`seq + '$' + revcomp + '#'`).
+    pub sequence: usize,
+    /// Suffix array of the doubled sequence (32-bit).
+    pub suffix_array: usize,
+    /// Inverse suffix array.
+    pub inverse_suffix_array: usize,
+    /// LCP array.
+    pub lcp: usize,
+    /// Range minimum query structure over the LCP array.
+    pub rmq: usize,
+    /// Seeds of the heuristic search or of the prefilter.
+    pub seeds: usize,
+    /// IRs of the search, as many as in a uniform random sequence of the same length: repeats
+    /// add more.
+    pub output: usize,
+}
+
+impl MemoryEstimate {
+    /// Peak of the search, in bytes.
+    pub const fn peak(&self) -> usize {
+        let build = self.suffix_array;
+        let search = self.seeds + self.output;
+        self.sequence
+            + self.inverse_suffix_array
+            + self.lcp
+            + self.rmq
+            + if build > search { build } else { search }
+    }
+}
+
+impl fmt::Display for MemoryEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{{\"sequence_bytes\":{},\"suffix_array_bytes\":{},\"inverse_suffix_array_bytes\":{},\
+             \"lcp_bytes\":{},\"rmq_bytes\":{},\"seeds_bytes\":{},\"output_bytes\":{},\
+             \"peak_bytes\":{}}}",
+            self.sequence,
+            self.suffix_array,
+            self.inverse_suffix_array,
+            self.lcp,
+            self.rmq,
+            self.seeds,
+            self.output,
+            self.peak()
+        )
+    }
+}
+
+/// Values of a sparse table over `len` values.
+fn sparse_table_len(len: usize) -> usize {
+    let mut total = 0;
+    let mut width = 1;
+    while width <= len {
+        total += len + 1 - width;
+        width *= 2;
+    }
+    total
+}
+
+/// Bytes of the range minimum query structure over an LCP array of `s_n` values. The
+/// `tabulation` feature is counted as a sparse table, an upper bound.
+fn rmq_memory(s_n: usize, backend: RmqBackend) -> usize {
+    match backend {
+        // Values of the size of the LCP array (u32 with the `index32` feature).
+        RmqBackend::Sparse => sparse_table_len(s_n) * size_of::<IndexInt>(),
+        RmqBackend::Blocks => {
+            s_n * (size_of::<IndexInt>() + size_of::<u32>())
+                + sparse_table_len(s_n.div_ceil(32)) * size_of::<IndexInt>()
+        }
+    }
+}
+
+/// Probability that `len` pairs of uniform random bases have at most `mismatches` mismatches.
+fn pairing_probability(len: usize, mismatches: usize) -> f64 {
+    let mut binomial = 1.0;
+    let mut total = 0.0;
+    for k in 0..=mismatches.min(len) {
+        if k > 0 {
+            binomial *= (len + 1 - k) as f64 / k as f64;
+        }
+        total += binomial * 0.75f64.powi(k as i32) * 0.25f64.powi((len - k) as i32);
+    }
+    total
+}
+
+/// Chunks searched at the same time.
+#[cfg(feature = "parallel")]
+fn chunk_threads(params: &SearchParams) -> usize {
+    if params.threads > 0 {
+        params.threads
+    } else {
+        std::thread::available_parallelism().map_or(1, usize::from)
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+const fn chunk_threads(_params: &SearchParams) -> usize {
+    1
+}
+
+/// See [`SearchParams::estimate_memory`].
+pub fn estimate(params: &SearchParams, n: usize) -> MemoryEstimate {
+    // A circular sequence is searched between two copies of itself.
+    let searched = if params.circular { 3 * n } else { n };
+    // Length of the sequences indexed, and how many at the same time.
+    let (len, indexes) = match params.chunk_len {
+        Some(chunk_len) if params.anchor.is_none() && chunk_len > 0 => {
+            let len = (chunk_len + 2 * margin(params)).min(searched);
+            (
+                len,
+                chunk_threads(params).min((searched / chunk_len).max(1)),
+            )
+        }
+        _ => (searched, 1),
+    };
+    let s_n = 2 * len + 2;
+
+    // Without mismatches, the extensions are scanned without index.
+    let indexed = params.mismatches > 0;
+    let per_index = |bytes: usize| if indexed { indexes * bytes } else { 0 };
+    let seed_len = params.seed_len.or_else(|| {
+        prefilter_seed_len(params, !params.bisulfite && !params.wobble).filter(|_| params.prefilter)
+    });
+    let wrapped = if params.circular { searched } else { 0 };
+    // Only the IRs centered in the middle copy of a circular sequence are kept.
+    let expected_irs = n as f64
+        * (params.max_gap + 1) as f64
+        * pairing_probability(params.min_len, params.mismatches);
+
+    MemoryEstimate {
+        sequence: n + wrapped + indexes * s_n,
+        suffix_array: per_index(s_n * size_of::<i32>()),
+        inverse_suffix_array: per_index(s_n * size_of::<IndexInt>()),
+        lcp: per_index(s_n * size_of::<IndexInt>()),
+        rmq: per_index(rmq_memory(s_n, params.rmq)),
+        seeds: if seed_len.is_some() {
+            indexes * seeds_memory(s_n)
+        } else {
+            0
+        },
+        output: expected_irs as usize * size_of::<IrSpan>(),
+    }
+}
+
+/// Copy of `params` whose search of a sequence of `n` bases fits in `budget` bytes: with the
+/// blocks RMQ if the default structures do not fit, then in smaller and smaller chunks
+/// (inverted symmetry modes).
+pub fn fit_memory(params: &SearchParams, n: usize, budget: usize) -> Result<SearchParams> {
+    let mut fitted = params.clone();
+    if estimate(&fitted, n).peak() <= budget {
+        return Ok(fitted);
+    }
+    fitted.rmq = RmqBackend::Blocks;
+    if estimate(&fitted, n).peak() <= budget {
+        return Ok(fitted);
+    }
+    let chunkable = params.anchor.is_none()
+        && matches!(
+            params.symmetry_mode,
+            SymmetryMode::Inverted | SymmetryMode::InvertedComplementary
+        );
+    if chunkable {
+        let overlap = chunk_overlap(params).max(1);
+        let mut chunk_len = params.chunk_len.unwrap_or(n).min(n);
+        while chunk_len / 2 >= overlap {
+            chunk_len /= 2;
+            fitted.chunk_len = Some(chunk_len);
+            if estimate(&fitted, n).peak() <= budget {
+                return Ok(fitted);
+            }
+        }
+    }
+    Err(anyhow!(
+        "the search of {n} bp needs about {} bytes even with the blocks RMQ and chunks: more than max_memory={budget}.",
+        estimate(&fitted, n).peak()
+    ))
+}
+
+/// Number of bytes, with an optional K, M, G or T suffix (powers of 1024), e.g. `16G`.
+pub fn parse_memory(s: &str) -> Result<usize> {
+    let s = s.trim();
+    let (digits, shift) = match s.char_indices().last() {
+        Some((idx, 'K' | 'k')) => (&s[..idx], 10),
+        Some((idx, 'M' | 'm')) => (&s[..idx], 20),
+        Some((idx, 'G' | 'g')) => (&s[..idx], 30),
+        Some((idx, 'T' | 't')) => (&s[..idx], 40),
+        _ => (s, 0),
+    };
+    let value: usize = digits
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("invalid memory size '{s}': expected bytes, or K, M, G or T."))?;
+    value
+        .checked_mul(1 << shift)
+        .ok_or_else(|| anyhow!("memory size '{s}' is too large."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparse_table_len() {
+        assert_eq!(sparse_table_len(1), 1);
+        // 8 + 7 + 5 + 1
+        assert_eq!(sparse_table_len(8), 21);
+    }
+
+    #[test]
+    fn test_estimate_memory() {
+        let n = 10_000_000;
+        let mut params = SearchParams::new(10, 100, 20, 2).unwrap();
+        let sparse = params.estimate_memory(n);
+        assert_eq!(sparse.sequence, 3 * n + 2);
+        assert_eq!(sparse.suffix_array, 4 * (2 * n + 2));
+        assert_eq!(sparse.lcp, sparse.inverse_suffix_array);
+        assert!(sparse.rmq > 20 * sparse.lcp);
+        assert!(sparse.peak() > sparse.rmq + sparse.suffix_array);
+
+        params.rmq = RmqBackend::Blocks;
+        let blocks = params.estimate_memory(n);
+        assert!(blocks.rmq < 3 * blocks.lcp);
+        assert!(blocks.peak() * 4 < sparse.peak());
+
+        // Chunks only index a part of the sequence at a time.
+        params.chunk_len = Some(100_000);
+        params.threads = 2;
+        assert!(params.estimate_memory(n).peak() * 10 < blocks.peak());
+
+        // Searches without mismatches have no index.
+        let exact = SearchParams::new(10, 100, 20, 0)
+            .unwrap()
+            .estimate_memory(n);
+        assert_eq!(exact.peak(), exact.sequence + exact.output);
+
+        // 1 + 5 * 3 + 10 * 9 times more IRs with 2 mismatches in arms of 5.
+        let output = |mismatches| {
+            let params = SearchParams::new(5, 100, 20, mismatches).unwrap();
+            params.estimate_memory(n).output as f64
+        };
+        assert!((output(2) / output(0) - 106.0).abs() < 1.0);
+
+        // Circular sequences are searched between two copies of themselves.
+        let mut circular = SearchParams::new(10, 100, 20, 2).unwrap();
+        circular.circular = true;
+        assert_eq!(circular.estimate_memory(n).suffix_array, 4 * (6 * n + 2));
+    }
+
+    #[test]
+    fn test_fit_memory() {
+        let n = 10_000_000;
+        let params = SearchParams::new(10, 100, 20, 2).unwrap();
+        let sparse = params.estimate_memory(n).peak();
+        let fitted = fit_memory(&params, n, sparse).unwrap();
+        assert_eq!((fitted.rmq, fitted.chunk_len), (RmqBackend::Sparse, None));
+
+        let fitted = fit_memory(&params, n, sparse / 4).unwrap();
+        assert_eq!((fitted.rmq, fitted.chunk_len), (RmqBackend::Blocks, None));
+
+        let fitted = fit_memory(&params, n, 2 * n).unwrap();
+        assert_eq!(fitted.rmq, RmqBackend::Blocks);
+        let chunk_len = fitted.chunk_len.unwrap();
+        assert!(fitted.estimate_memory(n).peak() <= 2 * n);
+        assert!(chunk_len >= chunk_overlap(&params));
+
+        assert!(fit_memory(&params, n, n).is_err());
+        let mut direct = params.clone();
+        direct.symmetry_mode = SymmetryMode::Direct;
+        assert!(fit_memory(&direct, n, 2 * n).is_err());
+    }
+
+    #[test]
+    fn test_parse_memory() {
+        assert_eq!(parse_memory("1000").unwrap(), 1000);
+        assert_eq!(parse_memory("16G").unwrap(), 16 << 30);
+        assert_eq!(parse_memory("512m").unwrap(), 512 << 20);
+        assert!(parse_memory("G").is_err());
+        assert!(parse_memory("1.5G").is_err());
+    }
+}
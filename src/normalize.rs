@@ -0,0 +1,51 @@
+//! Collapse IRs that are symmetric trimmings of one another.
+use crate::IrSpan;
+
+/// A maximal IR, together with the number of IRs found inside it with the same center
+/// (that is, obtained by trimming both arms by the same amount, at either end, or duplicates).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanonicalIr {
+    pub ir: IrSpan,
+    pub sub_forms: usize,
+}
+
+/// Keep only the maximal IRs among those sharing the same center, counting the others.
+///
+/// The result is sorted like the output of [`find_irs`](crate::find_irs).
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{CanonicalIr, normalize_irs};
+///
+/// let irs = vec![(0, 9, 2), (1, 8, 2), (2, 7, 4), (2, 9, 0)];
+/// let canonical = normalize_irs(&irs);
+/// assert_eq!(
+///     canonical,
+///     vec![
+///         CanonicalIr { ir: (0, 9, 2), sub_forms: 2 },
+///         CanonicalIr { ir: (2, 9, 0), sub_forms: 0 },
+///     ]
+/// );
+/// ```
+pub fn normalize_irs(irs: &[IrSpan]) -> Vec<CanonicalIr> {
+    // left + right identifies the center. Inside a center, a container always comes first.
+    let mut sorted = irs.to_vec();
+    sorted.sort_unstable_by_key(|&(left, right, gap)| (left + right, left, gap));
+
+    let mut canonical = Vec::new();
+    for group in sorted.chunk_by(|a, b| a.0 + a.1 == b.0 + b.1) {
+        let first = canonical.len();
+        for &ir in group {
+            let container = canonical[first..]
+                .iter_mut()
+                .find(|c: &&mut CanonicalIr| c.ir.0 <= ir.0 && c.ir.2 <= ir.2);
+            match container {
+                Some(c) => c.sub_forms += 1,
+                None => canonical.push(CanonicalIr { ir, sub_forms: 0 }),
+            }
+        }
+    }
+    canonical.sort_by(|a, b| a.ir.0.cmp(&b.ir.0).then(b.ir.1.cmp(&a.ir.1)));
+    canonical
+}
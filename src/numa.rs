@@ -0,0 +1,158 @@
+//! Placement of the worker threads on the NUMA nodes of multi-socket machines.
+//!
+//! Workers are pinned to the CPUs node by node, so that the IRs they collect stay in the memory
+//! of their node (Linux allocates pages on the node of the thread that first touches them).
+//! The index of a whole sequence is built by a single worker, so it lives on one node and is
+//! read across sockets by the others. With chunks (`SearchParams::chunk_len`), every chunk is
+//! indexed by the worker that searches it, on the node of that worker. Pinning is only done
+//! on Linux, elsewhere the workers are left to the scheduler.
+use anyhow::{Context, Result, anyhow};
+use std::fs;
+
+const NODE_DIR: &str = "/sys/devices/system/node";
+
+/// CPUs of every NUMA node of the machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumaTopology {
+    nodes: Vec<Vec<usize>>,
+}
+
+impl NumaTopology {
+    /// Nodes without CPUs are dropped.
+    pub fn new(nodes: Vec<Vec<usize>>) -> Self {
+        let nodes: Vec<_> = nodes.into_iter().filter(|cpus| !cpus.is_empty()).collect();
+        Self { nodes }
+    }
+
+    /// Topology of this machine, or a single node with every CPU when it is not exposed
+    /// (non-Linux systems, containers without `/sys`).
+    pub fn detect() -> Self {
+        let topology = Self::new(read_nodes().unwrap_or_default());
+        if !topology.nodes.is_empty() {
+            return topology;
+        }
+        let cpus = std::thread::available_parallelism().map_or(1, usize::from);
+        Self::new(vec![(0..cpus).collect()])
+    }
+
+    /// CPUs of every node.
+    pub fn nodes(&self) -> &[Vec<usize>] {
+        &self.nodes
+    }
+
+    pub fn num_cpus(&self) -> usize {
+        self.nodes.iter().map(Vec::len).sum()
+    }
+
+    /// CPU of the worker `idx` out of `threads`: the workers are split in consecutive blocks,
+    /// one per node, and spread over the CPUs of their node.
+    pub fn cpu_for(&self, idx: usize, threads: usize) -> usize {
+        let n_nodes = self.nodes.len();
+        let node = idx * n_nodes / threads;
+        // First worker of the block of the node
+        let first = (node * threads).div_ceil(n_nodes);
+        let cpus = &self.nodes[node];
+        cpus[(idx - first) % cpus.len()]
+    }
+
+    /// A thread pool with one worker per CPU, pinned as in `cpu_for`.
+    pub fn thread_pool(&self) -> Result<rayon::ThreadPool> {
+        let threads = self.num_cpus();
+        let topology = self.clone();
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .start_handler(move |idx| {
+                pin_current_thread(topology.cpu_for(idx, threads));
+            })
+            .build()
+            .map_err(|err| anyhow!("Could not start the NUMA thread pool: {err}"))
+    }
+}
+
+fn read_nodes() -> Result<Vec<Vec<usize>>> {
+    let mut nodes = Vec::new();
+    for entry in fs::read_dir(NODE_DIR)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(id) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("node"))
+            .and_then(|id| id.parse::<usize>().ok())
+        else {
+            continue;
+        };
+        let path = entry.path().join("cpulist");
+        let cpulist = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        nodes.push((id, parse_cpulist(&cpulist)?));
+    }
+    nodes.sort_unstable();
+    Ok(nodes.into_iter().map(|(_, cpus)| cpus).collect())
+}
+
+/// Parse a Linux CPU list (`0-3,8-11,16`).
+pub fn parse_cpulist(cpulist: &str) -> Result<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in cpulist.trim().split(',').filter(|range| !range.is_empty()) {
+        let error = || anyhow!("Invalid CPU list '{}'", cpulist.trim());
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let start: usize = start.parse().map_err(|_| error())?;
+        let end: usize = end.parse().map_err(|_| error())?;
+        if end < start {
+            return Err(error());
+        }
+        cpus.extend(start..=end);
+    }
+    Ok(cpus)
+}
+
+/// Restrict the calling thread to `cpu`. Whether it succeeded.
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpu: usize) -> bool {
+    use std::ffi::{c_int, c_ulong};
+
+    unsafe extern "C" {
+        fn sched_setaffinity(pid: c_int, cpusetsize: usize, mask: *const c_ulong) -> c_int;
+    }
+
+    let bits = c_ulong::BITS as usize;
+    let mut mask: Vec<c_ulong> = vec![0; cpu / bits + 1];
+    mask[cpu / bits] |= 1 << (cpu % bits);
+    // SAFETY: the mask is a valid cpu_set_t of `size_of_val(mask)` bytes, and pid 0 is the
+    // calling thread.
+    unsafe { sched_setaffinity(0, size_of_val(mask.as_slice()), mask.as_ptr()) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_cpu: usize) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpulist() {
+        assert_eq!(
+            parse_cpulist("0-3,8-9,16\n").unwrap(),
+            vec![0, 1, 2, 3, 8, 9, 16]
+        );
+        assert_eq!(parse_cpulist("\n").unwrap(), Vec::<usize>::new());
+        assert!(parse_cpulist("3-1").is_err());
+        assert!(parse_cpulist("0-a").is_err());
+    }
+
+    #[test]
+    fn test_cpu_for() {
+        // Two sockets of 4 CPUs, with interleaved numbering
+        let topology = NumaTopology::new(vec![vec![0, 2, 4, 6], vec![1, 3, 5, 7], vec![]]);
+        assert_eq!(topology.num_cpus(), 8);
+        let cpus: Vec<_> = (0..8).map(|idx| topology.cpu_for(idx, 8)).collect();
+        assert_eq!(cpus, vec![0, 2, 4, 6, 1, 3, 5, 7]);
+        // Fewer workers than CPUs still use both nodes
+        let cpus: Vec<_> = (0..3).map(|idx| topology.cpu_for(idx, 3)).collect();
+        assert_eq!(cpus, vec![0, 2, 1]);
+        assert!(NumaTopology::detect().num_cpus() > 0);
+    }
+}
@@ -0,0 +1,49 @@
+//! Hooks to instrument the search without forking it.
+//!
+//! Centers are given in the coordinates of the algorithm: the center `c` lies at `c / 2`
+//! in the sequence (between two characters for even values of `c`).
+use std::time::Duration;
+
+use crate::IrSpan;
+
+/// Something that happened inside the search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(feature = "observer"), allow(dead_code))]
+pub enum AlgoEvent {
+    /// The arms were extended from `center` for `extension` characters (kangaroo LCE),
+    /// with `steps` jumps and `lce_queries` LCE queries (range minimum queries, or scans of
+    /// the characters without mismatches, which stop past the longest IR).
+    Seed {
+        center: usize,
+        extension: usize,
+        lce_queries: usize,
+        steps: usize,
+    },
+    /// The extension from `center` stopped at `offset` characters from the center
+    /// because the mismatch budget was exhausted.
+    ExtensionTruncated { center: usize, offset: usize },
+    /// An IR longer than `max_len` was shortened by `overshoot` characters per arm.
+    MaxLenTruncated { center: usize, overshoot: usize },
+    /// An IR was found.
+    Hit { center: usize, ir: IrSpan },
+}
+
+/// Receives the [`AlgoEvent`]s of a search.
+///
+/// With the `parallel` feature, events of different centers arrive from several threads
+/// and in no particular order.
+pub trait Observer: Sync {
+    fn on_event(&self, event: AlgoEvent);
+
+    /// The index of a sequence of `seq_len` characters was built in `elapsed`, before the
+    /// events of its search.
+    fn on_index_built(&self, _seq_len: usize, _elapsed: Duration) {}
+}
+
+/// Used by every search that is not observed. The calls are optimized away.
+pub(crate) struct NoObserver;
+
+impl Observer for NoObserver {
+    #[inline(always)]
+    fn on_event(&self, _event: AlgoEvent) {}
+}
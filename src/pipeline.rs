@@ -0,0 +1,66 @@
+//! Pipelined reading of the input file: one thread reads (and decompresses) the records,
+//! another one sanitizes them and builds their `Config`, while the caller searches, so that
+//! the next sequences are ready as soon as the current one is searched.
+use anyhow::{Result, anyhow};
+use seq_io::fasta::OwnedRecord;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::Scope;
+
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::utils::{for_each_record, sanitize_sequence};
+
+/// Records waiting between two stages. Together with the record being handled by every stage,
+/// this bounds the sequences in memory.
+const STAGE_CAPACITY: usize = 1;
+
+/// Start the reading and normalization stages in `scope`, and return whether the output is
+/// sharded (more than one sequence) with the `(Config, OwnedRecord)` pairs, in the order of
+/// the input file.
+///
+/// Errors (a missing sequence, an invalid base) are sent in place of the next pair, and the
+/// stages stop. Dropping the receiver also stops them.
+pub fn pipeline_records<'scope, 'env>(
+    scope: &'scope Scope<'scope, 'env>,
+    args: &'env Cli,
+    check_bounds: bool,
+) -> Result<(bool, Receiver<Result<(Config<'env>, OwnedRecord)>>)> {
+    let inputs = args.shared_inputs()?;
+
+    let (raw_sender, raw_receiver) = mpsc::sync_channel(STAGE_CAPACITY);
+    scope.spawn(move || {
+        let send = |record| {
+            raw_sender
+                .send(Ok(record))
+                .map_err(|_| anyhow!("the search stopped"))
+        };
+        let read = match args.inline_record() {
+            Some(record) => send(record),
+            None => for_each_record(&args.input_file, &args.seq_names, send),
+        };
+        if let Err(err) = read {
+            let _ = raw_sender.send(Err(err));
+        }
+    });
+
+    // The output is sharded if a second sequence follows the first one.
+    let mut raw_records = raw_receiver.into_iter().peekable();
+    let first = raw_records.next().transpose()?;
+    let sharded = raw_records.peek().is_some_and(Result::is_ok);
+
+    let (sender, receiver) = mpsc::sync_channel(STAGE_CAPACITY);
+    scope.spawn(move || {
+        for raw_record in first.map(Ok).into_iter().chain(raw_records) {
+            let pair = raw_record.and_then(|mut record| {
+                record.seq = sanitize_sequence(&record.seq)?;
+                let config = args.config_for(&inputs, &mut record, sharded, check_bounds)?;
+                Ok((config, record))
+            });
+            let failed = pair.is_err();
+            if sender.send(pair).is_err() || failed {
+                break;
+            }
+        }
+    });
+    Ok((sharded, receiver))
+}
@@ -0,0 +1,245 @@
+//! Filters of the IRs written as WebAssembly modules, to ship custom acceptance rules without
+//! rebuilding iirs (`wasm` feature).
+//!
+//! A plugin is a `.wasm` module (or its `.wat` text form) exporting:
+//! - `memory`, where the span of every IR is written,
+//! - `iirs_alloc(len: i32) -> i32`, the offset of `len` writable bytes in `memory` (it may
+//!   return the same buffer every time),
+//! - `iirs_accept(ptr: i32, len: i32, arm_len: i32, gap: i32) -> i32`, zero to reject the IR,
+//! - and/or `iirs_score(ptr: i32, len: i32, arm_len: i32, gap: i32) -> f64`, the IR being
+//!   rejected when its score is below the minimum score of the plugin.
+//!
+//! The span (`len` bytes from `ptr`) is the sanitized sequence of the whole IR, from the start
+//! of its left arm to the end of its right arm. Plugins import nothing: they only see the IR.
+//!
+//! Every call runs on a fuel budget growing with the span, so that a plugin stuck in a loop
+//! fails on its IR instead of hanging the search. The threads of the search call their own
+//! instances of the module.
+use anyhow::{Context, Result, anyhow, bail};
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use wasmi::{Config, Engine, Linker, Memory, Module, Store, TypedFunc};
+
+use crate::IrSpan;
+use crate::score::arm_len;
+
+type HitFunc<R> = TypedFunc<(i32, i32, i32, i32), R>;
+
+/// Fuel of a call, plus `FUEL_PER_BASE` per base of the span: about as many instructions.
+const BASE_FUEL: u64 = 1_000_000;
+const FUEL_PER_BASE: u64 = 1_000;
+
+/// An instance of the module, which can only run one call at a time.
+struct Instance {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    accept: Option<HitFunc<i32>>,
+    score: Option<HitFunc<f64>>,
+}
+
+/// A loaded plugin (see the module documentation for its interface).
+pub struct WasmPlugin {
+    name: String,
+    min_score: f64,
+    engine: Engine,
+    module: Module,
+    /// Idle instances, taken by a thread for a call: there are never more of them than
+    /// threads calling the plugin at once.
+    instances: Mutex<Vec<Instance>>,
+    failures: AtomicUsize,
+}
+
+impl WasmPlugin {
+    /// Load the plugin at `path`. With an `iirs_score` export, the IRs scoring below
+    /// `min_score` are rejected.
+    pub fn load(path: &str, min_score: f64) -> Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("Could not read {path}"))?;
+        Self::from_bytes(path, &bytes, min_score)
+    }
+
+    /// Like `load`, from the content of the module (binary or text).
+    pub fn from_bytes(name: &str, bytes: &[u8], min_score: f64) -> Result<Self> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module =
+            Module::new(&engine, bytes).map_err(|err| anyhow!("Invalid plugin {name}: {err}"))?;
+        let instance = instantiate(name, &engine, &module)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            min_score,
+            engine,
+            module,
+            instances: Mutex::new(vec![instance]),
+            failures: AtomicUsize::new(0),
+        })
+    }
+
+    /// Whether the plugin keeps `ir`. IRs on which the plugin fails (e.g. a trap) are
+    /// rejected, and counted in `failures`.
+    pub fn accepts(&self, ir: &IrSpan, seq: &[u8]) -> bool {
+        self.call(ir, seq).unwrap_or_else(|err| {
+            if self.failures.fetch_add(1, Ordering::Relaxed) == 0 {
+                eprintln!(
+                    "Warning: the plugin {} failed, rejecting the IR: {err}",
+                    self.name
+                );
+            }
+            false
+        })
+    }
+
+    /// Number of IRs rejected because the plugin failed on them.
+    pub fn failures(&self) -> usize {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    fn call(&self, ir: &IrSpan, seq: &[u8]) -> Result<bool> {
+        let idle = self
+            .instances
+            .lock()
+            .map_err(|_| anyhow!("poisoned plugin instances"))?
+            .pop();
+        let mut instance = match idle {
+            Some(instance) => instance,
+            None => instantiate(&self.name, &self.engine, &self.module)?,
+        };
+        let accepted = self.call_in(&mut instance, ir, seq)?;
+        // Instances which failed (e.g. out of fuel in the middle of a call) are dropped
+        if let Ok(mut instances) = self.instances.lock() {
+            instances.push(instance);
+        }
+        Ok(accepted)
+    }
+
+    fn call_in(&self, instance: &mut Instance, ir: &IrSpan, seq: &[u8]) -> Result<bool> {
+        let &(left, right, gap) = ir;
+        let span = &seq[left..=right];
+        let Instance {
+            store,
+            memory,
+            alloc,
+            accept,
+            score,
+        } = instance;
+
+        store
+            .set_fuel(BASE_FUEL + FUEL_PER_BASE * span.len() as u64)
+            .map_err(|err| anyhow!("{err}"))?;
+        let len = i32::try_from(span.len())?;
+        let ptr = alloc.call(&mut *store, len)?;
+        memory
+            .write(&mut *store, usize::try_from(ptr)?, span)
+            .map_err(|err| anyhow!("{err}"))?;
+        let args = (ptr, len, i32::try_from(arm_len(ir))?, i32::try_from(gap)?);
+
+        if let Some(accept) = accept
+            && accept.call(&mut *store, args)? == 0
+        {
+            return Ok(false);
+        }
+        if let Some(score) = score {
+            let score = score.call(&mut *store, args)?;
+            if score.is_nan() || score < self.min_score {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// A new instance of the plugin `name`, checking its exports.
+fn instantiate(name: &str, engine: &Engine, module: &Module) -> Result<Instance> {
+    let error = |err: wasmi::Error| anyhow!("Invalid plugin {name}: {err}");
+    let mut store = Store::new(engine, ());
+    let instance = Linker::<()>::new(engine)
+        .instantiate_and_start(&mut store, module)
+        .map_err(error)?;
+
+    let memory = instance
+        .get_memory(&store, "memory")
+        .ok_or_else(|| anyhow!("The plugin {name} does not export its memory."))?;
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&store, "iirs_alloc")
+        .map_err(error)?;
+    let accept = instance.get_typed_func(&store, "iirs_accept").ok();
+    let score = instance.get_typed_func(&store, "iirs_score").ok();
+    if accept.is_none() && score.is_none() {
+        bail!("The plugin {name} exports neither iirs_accept nor iirs_score.");
+    }
+    Ok(Instance {
+        store,
+        memory,
+        alloc,
+        accept,
+        score,
+    })
+}
+
+impl fmt::Debug for WasmPlugin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WasmPlugin")
+            .field("name", &self.name)
+            .field("min_score", &self.min_score)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SearchParams;
+    use crate::find_spans;
+    use std::sync::Arc;
+
+    /// Keeps the IRs with a gap, scored by their arm length.
+    const PLUGIN: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "iirs_alloc") (param i32) (result i32) i32.const 0)
+          (func (export "iirs_accept") (param i32 i32 i32 i32) (result i32)
+            local.get 3)
+          (func (export "iirs_score") (param i32 i32 i32 i32) (result f64)
+            local.get 2
+            f64.convert_i32_s))
+    "#;
+
+    #[test]
+    fn test_wasm_plugin() {
+        let seq = b"ttacgcatgcgtaaccgatggtacgcaaccatcggtt";
+        let mut params = SearchParams::new(4, 12, 6, 1).unwrap();
+        let mut all = find_spans(&params, seq).unwrap();
+        all.sort_unstable();
+
+        let plugin = WasmPlugin::from_bytes("test", PLUGIN.as_bytes(), 5.0).unwrap();
+        params.plugin = Some(Arc::new(plugin));
+        let mut kept = find_spans(&params, seq).unwrap();
+        kept.sort_unstable();
+        let expected: Vec<_> = all
+            .iter()
+            .filter(|ir| ir.2 > 0 && arm_len(ir) >= 5)
+            .copied()
+            .collect();
+        assert_eq!(kept, expected);
+        assert_eq!(params.plugin.unwrap().failures(), 0);
+
+        let no_exports = r#"(module (memory (export "memory") 1))"#;
+        assert!(WasmPlugin::from_bytes("test", no_exports.as_bytes(), 0.0).is_err());
+
+        // A plugin stuck in a loop runs out of fuel
+        let endless = r#"
+            (module
+              (memory (export "memory") 1)
+              (func (export "iirs_alloc") (param i32) (result i32) i32.const 0)
+              (func (export "iirs_accept") (param i32 i32 i32 i32) (result i32)
+                (loop (br 0))
+                i32.const 1))
+        "#;
+        let plugin = WasmPlugin::from_bytes("test", endless.as_bytes(), 0.0).unwrap();
+        assert!(!plugin.accepts(&(0, 9, 2), seq));
+        assert_eq!(plugin.failures(), 1);
+    }
+}
@@ -0,0 +1,187 @@
+//! Machine-readable progress reports, for workflow managers and web UIs.
+use crate::control::SearchSummary;
+use crate::stats::PerfCounters;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How progress is reported on stderr.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressFormat {
+    /// One JSON object per line.
+    Json,
+}
+
+/// Stage of the search of a sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// Building the suffix array and the LCP / RMQ structures.
+    Index,
+    Search,
+    Done,
+    /// The search was stopped early (see [`StopReason`](crate::StopReason)).
+    Stopped,
+}
+
+impl Phase {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Index => "index",
+            Self::Search => "search",
+            Self::Done => "done",
+            Self::Stopped => "stopped",
+        }
+    }
+}
+
+/// The sequence being searched.
+#[derive(Debug)]
+struct Current {
+    seq_name: String,
+    seq_len: usize,
+    /// Number of centers of the sequence.
+    centers: u64,
+    /// Value of the counters when the sequence started.
+    centers_base: u64,
+    hits_base: u64,
+}
+
+/// Reports the progress of the searches counted by a [`PerfCounters`], as JSON lines:
+///
+/// ```text
+/// {"seq_name":"t1","seq_len":5000,"phase":"search","fraction":0.4213,"hits":12,"elapsed":1.002}
+/// ```
+///
+/// While searching, `hits` counts the IRs before the post-filters (`--min-score`, `--max-dust`...).
+/// The final event of every sequence gives the number of IRs found.
+pub struct ProgressReporter<'a> {
+    counters: &'a PerfCounters,
+    current: Mutex<Option<Current>>,
+    start: Instant,
+}
+
+impl<'a> ProgressReporter<'a> {
+    pub fn new(counters: &'a PerfCounters) -> Self {
+        Self {
+            counters,
+            current: Mutex::new(None),
+            start: Instant::now(),
+        }
+    }
+
+    /// To be called before searching a sequence.
+    pub fn start_sequence(&self, seq_name: &str, seq_len: usize, min_len: usize) {
+        let current = Current {
+            seq_name: seq_name.to_string(),
+            seq_len,
+            centers: (2 * seq_len + 1).saturating_sub(2 * min_len) as u64,
+            centers_base: self.counters.centers(),
+            hits_base: self.counters.hits(),
+        };
+        *self.current.lock().unwrap() = Some(current);
+        self.report();
+    }
+
+    /// To be called after searching a sequence: reports its final state.
+    pub fn finish_sequence(&self, summary: &SearchSummary) {
+        if let Some(current) = self.current.lock().unwrap().take() {
+            let (phase, fraction) = match summary.stopped {
+                None => (Phase::Done, 1.0),
+                Some(_) => (
+                    Phase::Stopped,
+                    summary.covered as f64 / current.seq_len.max(1) as f64,
+                ),
+            };
+            self.emit(&current, phase, fraction, summary.found as u64);
+        }
+    }
+
+    /// Report the current state of the search.
+    pub fn report(&self) {
+        if let Some(current) = self.current.lock().unwrap().as_ref() {
+            // No center has been examined while the index is built.
+            let examined = self.counters.centers() - current.centers_base;
+            let phase = if examined == 0 {
+                Phase::Index
+            } else {
+                Phase::Search
+            };
+            let fraction = (examined as f64 / current.centers.max(1) as f64).min(1.0);
+            let hits = self.counters.hits() - current.hits_base;
+            self.emit(current, phase, fraction, hits);
+        }
+    }
+
+    /// Report every `interval` until the sending half of `done` is dropped.
+    pub fn run(&self, interval: Duration, done: &Receiver<()>) {
+        while let Err(RecvTimeoutError::Timeout) = done.recv_timeout(interval) {
+            self.report();
+        }
+    }
+
+    fn emit(&self, current: &Current, phase: Phase, fraction: f64, hits: u64) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        eprintln!(
+            "{}",
+            progress_json(
+                &current.seq_name,
+                current.seq_len,
+                phase,
+                fraction,
+                hits,
+                elapsed
+            )
+        );
+    }
+}
+
+fn progress_json(
+    seq_name: &str,
+    seq_len: usize,
+    phase: Phase,
+    fraction: f64,
+    hits: u64,
+    elapsed: f64,
+) -> String {
+    format!(
+        "{{\"seq_name\":{},\"seq_len\":{},\"phase\":\"{}\",\"fraction\":{:.4},\"hits\":{},\"elapsed\":{:.3}}}",
+        json_string(seq_name),
+        seq_len,
+        phase.as_str(),
+        fraction,
+        hits,
+        elapsed
+    )
+}
+
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            ch if ch.is_control() => write!(&mut out, "\\u{:04x}", ch as u32).unwrap(),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_json() {
+        let line = progress_json("chr\"1\"", 100, Phase::Search, 0.5, 3, 1.25);
+        assert_eq!(
+            line,
+            r#"{"seq_name":"chr\"1\"","seq_len":100,"phase":"search","fraction":0.5000,"hits":3,"elapsed":1.250}"#
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Scanning of the reads of a SAM/BAM/CRAM file, e.g. to investigate inverted-duplication
+//! (foldback) artifacts in long-read sequencing.
+use anyhow::Result;
+use noodles_fasta as fasta;
+use noodles_sam::alignment::Record;
+use noodles_sam::alignment::record::{Cigar, Sequence};
+use std::io::Write;
+
+use crate::{IrSpan, SearchParams, find_spans};
+
+/// A CIGAR operation, as (consumes the read, consumes the reference, length).
+type CigarOp = (bool, bool, usize);
+
+/// Find the IRs of the reads of a SAM/BAM/CRAM file (detected from the content), writing one
+/// tab-separated line per IR, and return the number of IRs found.
+///
+/// The columns are the read name, the 1-based start and end of the IR and its gap in the read,
+/// followed by the reference name, start and end of the aligned part of the IR (or `.` if the
+/// read is unmapped). As in the file, the read coordinates of reverse strand alignments are
+/// those of the reverse complement of the read.
+///
+/// Secondary and supplementary alignments are skipped, so that every read is scanned once.
+/// CRAM files need the indexed FASTA `reference` they were compressed against.
+pub fn scan_reads<W: Write>(
+    path: &str,
+    reference: Option<&str>,
+    params: &SearchParams,
+    out: &mut W,
+) -> Result<usize> {
+    let repository = match reference {
+        Some(reference) => fasta::io::indexed_reader::Builder::default()
+            .build_from_path(reference)
+            .map(fasta::repository::adapters::IndexedReader::new)
+            .map(fasta::Repository::new)?,
+        None => fasta::Repository::default(),
+    };
+    let mut reader = noodles_util::alignment::io::reader::Builder::default()
+        .set_reference_sequence_repository(repository)
+        .build_from_path(path)?;
+    let header = reader.read_header()?;
+
+    writeln!(
+        out,
+        "#read_name\tread_start\tread_end\tgap\tref_name\tref_start\tref_end"
+    )?;
+    let mut found = 0;
+    for result in reader.records(&header) {
+        let record = result?;
+        let flags = record.flags()?;
+        if flags.is_secondary() || flags.is_supplementary() {
+            continue;
+        }
+
+        // '=' (identical to the reference) is not an IUPAC code.
+        let seq: Vec<u8> = record
+            .sequence()
+            .iter()
+            .map(|base| if base == b'=' { b'n' } else { base })
+            .collect();
+        let irs = read_irs(params, &seq)?;
+        if irs.is_empty() {
+            continue;
+        }
+        found += irs.len();
+
+        let read_name = record
+            .name()
+            .map_or_else(|| String::from("*"), |name| name.to_string());
+        let alignment = match (record.reference_sequence(&header), record.alignment_start()) {
+            (Some(reference_sequence), Some(start)) => {
+                let (ref_name, _) = reference_sequence?;
+                let ops = record
+                    .cigar()
+                    .iter()
+                    .map(|op| {
+                        op.map(|op| {
+                            let kind = op.kind();
+                            (kind.consumes_read(), kind.consumes_reference(), op.len())
+                        })
+                    })
+                    .collect::<std::io::Result<Vec<CigarOp>>>()?;
+                Some((ref_name.to_string(), usize::from(start?) - 1, ops))
+            }
+            _ => None,
+        };
+
+        for &(left, right, gap) in &irs {
+            write!(out, "{}\t{}\t{}\t{}", read_name, left + 1, right + 1, gap)?;
+            let projected = alignment.as_ref().and_then(|(ref_name, start, ops)| {
+                project_span(*start, ops, left, right).map(|span| (ref_name, span))
+            });
+            match projected {
+                Some((ref_name, (ref_start, ref_end))) => {
+                    writeln!(out, "\t{}\t{}\t{}", ref_name, ref_start + 1, ref_end + 1)?
+                }
+                None => writeln!(out, "\t.\t.\t.")?,
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// IRs of a single read. Unlike [`find_irs`], reads shorter than the bounds of `params`
+/// are not an error.
+fn read_irs(params: &SearchParams, seq: &[u8]) -> Result<Vec<IrSpan>> {
+    let n = seq.len();
+    if params.min_len >= n {
+        return Ok(Vec::new());
+    }
+    let mut params = params.clone();
+    params.max_gap = params.max_gap.min(n - 1);
+    params.mismatches = params.mismatches.min(n - 1);
+    find_spans(&params, seq)
+}
+
+/// Reference span (0-based, inclusive) of the aligned positions between `left` and `right`
+/// in a read aligned at `start`, or `None` if none of them is aligned
+/// (e.g. they are soft clipped or inserted).
+fn project_span(
+    start: usize,
+    ops: &[CigarOp],
+    left: usize,
+    right: usize,
+) -> Option<(usize, usize)> {
+    let mut span: Option<(usize, usize)> = None;
+    let mut read_pos = 0;
+    let mut ref_pos = start;
+
+    for &(consumes_read, consumes_reference, len) in ops {
+        if consumes_read && consumes_reference && len > 0 {
+            let first = left.max(read_pos);
+            let last = right.min(read_pos + len - 1);
+            if first <= last {
+                let ref_first = ref_pos + first - read_pos;
+                let ref_last = ref_pos + last - read_pos;
+                span = Some(span.map_or((ref_first, ref_last), |(s, _)| (s, ref_last)));
+            }
+        }
+        if consumes_read {
+            read_pos += len;
+        }
+        if consumes_reference {
+            ref_pos += len;
+        }
+    }
+
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_span() {
+        // 2S 5M 3I 4M 2D 6M
+        let ops = [
+            (true, false, 2),
+            (true, true, 5),
+            (true, false, 3),
+            (true, true, 4),
+            (false, true, 2),
+            (true, true, 6),
+        ];
+        // Read positions 2..=6 are aligned to 100..=104
+        assert_eq!(project_span(100, &ops, 0, 4), Some((100, 102)));
+        // Inserted positions are skipped
+        assert_eq!(project_span(100, &ops, 5, 12), Some((103, 107)));
+        // The deletion is inside the span
+        assert_eq!(project_span(100, &ops, 12, 15), Some((107, 112)));
+        assert_eq!(project_span(100, &ops, 0, 1), None);
+        assert_eq!(project_span(100, &ops, 7, 9), None);
+    }
+
+    #[test]
+    fn test_read_irs() {
+        let params = SearchParams::new(3, 100, 50, 0).unwrap();
+        assert!(read_irs(&params, b"acg").unwrap().is_empty());
+        assert_eq!(read_irs(&params, b"acbbgt").unwrap(), vec![(0, 5, 0)]);
+    }
+}
@@ -0,0 +1,167 @@
+//! Remote inputs (`https://` and `s3://` URIs).
+//!
+//! The file is streamed to a local cache the first time it is requested and
+//! reused from there afterwards.
+//!
+//! Requests are not signed: `s3://` URIs only reach public objects. Private objects are read
+//! through presigned urls (e.g. from `aws s3 presign s3://bucket/key`), passed as `https://`.
+use anyhow::{Context, Result, anyhow};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Overrides the cache folder (defaults to the `iirs` folder of the user cache, see
+/// [`cache_dir`]).
+const CACHE_DIR_VAR: &str = "IIRS_CACHE_DIR";
+/// Overrides the S3 endpoint (e.g. for a MinIO server or a regional endpoint).
+const S3_ENDPOINT_VAR: &str = "IIRS_S3_ENDPOINT";
+
+/// Translate an `s3://bucket/key` URI into a plain https url.
+///
+/// Only public objects are supported (or presigned urls, passed as `https://`).
+fn to_url(uri: &str) -> Result<String> {
+    let Some(path) = uri.strip_prefix("s3://") else {
+        return Ok(uri.to_string());
+    };
+    let (bucket, key) = path.split_once('/').ok_or_else(|| {
+        anyhow!(
+            "'{}' is not a valid s3 uri (expected s3://bucket/key).",
+            uri
+        )
+    })?;
+
+    Ok(match std::env::var(S3_ENDPOINT_VAR) {
+        Ok(endpoint) => format!("{}/{}/{}", endpoint.trim_end_matches('/'), bucket, key),
+        Err(_) => format!("https://{bucket}.s3.amazonaws.com/{key}"),
+    })
+}
+
+/// Cache folder of the current user, from the environment `var`: `$IIRS_CACHE_DIR`, else
+/// `$XDG_CACHE_HOME/iirs`, `~/.cache/iirs` or `%LOCALAPPDATA%\iirs`. Never a shared folder
+/// such as the temporary one, where other users could plant the files of a uri.
+fn cache_dir(var: impl Fn(&str) -> Option<OsString>) -> Result<PathBuf> {
+    if let Some(dir) = var(CACHE_DIR_VAR) {
+        return Ok(dir.into());
+    }
+    // Relative (or empty) base folders are ignored, as in the XDG specification
+    let absolute = |name: &str| var(name).map(PathBuf::from).filter(|dir| dir.is_absolute());
+    let base = absolute("XDG_CACHE_HOME")
+        .or_else(|| absolute("HOME").map(|home| home.join(".cache")))
+        .or_else(|| absolute("LOCALAPPDATA"))
+        .ok_or_else(|| anyhow!("No cache folder for the remote inputs: set {CACHE_DIR_VAR}."))?;
+    Ok(base.join("iirs"))
+}
+
+/// 64-bit FNV-1a hash, stable across runs and versions (unlike the hashers of std).
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// File name of the cached uri: a hash of the whole uri (so that distinct uris never share a
+/// file), followed by its last path segment made a valid file name (to tell the files apart).
+fn cache_name(uri: &str) -> String {
+    let path = uri.split(['?', '#']).next().unwrap_or_default();
+    let basename: String = path
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{:016x}-{}", fnv1a(uri.as_bytes()), basename)
+}
+
+/// Return the path of a local copy of the remote input, downloading it if it is not cached yet.
+pub fn fetch(uri: &str) -> Result<PathBuf> {
+    let cache_dir = cache_dir(|name| std::env::var_os(name))?;
+    // Only accessible by the current user when it is created here
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(&cache_dir)
+        .with_context(|| format!("Could not create {}", cache_dir.display()))?;
+    let local = cache_dir.join(cache_name(uri));
+    if local.is_file() {
+        return Ok(local);
+    }
+
+    let url = to_url(uri)?;
+    let response = ureq::get(&url).call().map_err(|e| match e {
+        ureq::Error::Status(403, _) if uri.starts_with("s3://") => anyhow!(
+            "could not fetch '{}': access denied. Only public objects can be read as s3://, pass a presigned https:// url (aws s3 presign) for private ones.",
+            uri
+        ),
+        e => anyhow!("could not fetch '{}': {}", uri, e),
+    })?;
+
+    // Stream into a temporary file and only move it into place once complete,
+    // so that an interrupted download is never mistaken for a cached one. The temporary
+    // file is unique to the download, as other runs may fetch the same uri concurrently.
+    static DOWNLOADS: AtomicUsize = AtomicUsize::new(0);
+    let download = DOWNLOADS.fetch_add(1, Ordering::Relaxed);
+    let mut partial = local.clone().into_os_string();
+    partial.push(format!(".{}-{download}.part", std::process::id()));
+    let mut file = File::create(&partial)?;
+    let copied = io::copy(&mut response.into_reader(), &mut file);
+    if let Err(err) = copied {
+        let _ = fs::remove_file(&partial);
+        return Err(err.into());
+    }
+    fs::rename(&partial, &local)?;
+
+    Ok(local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_s3_to_url() {
+        let url = to_url("s3://bucket/genomes/hg38.fa").unwrap();
+        assert_eq!(url, "https://bucket.s3.amazonaws.com/genomes/hg38.fa");
+        assert!(to_url("s3://bucket").is_err());
+    }
+
+    #[test]
+    fn test_cache_dir() {
+        let env = |vars: &'static [(&str, &str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| OsString::from(value))
+            }
+        };
+        let dir = cache_dir(env(&[("HOME", "/home/u"), (CACHE_DIR_VAR, "/data/cache")]));
+        assert_eq!(dir.unwrap(), PathBuf::from("/data/cache"));
+        let dir = cache_dir(env(&[("HOME", "/home/u"), ("XDG_CACHE_HOME", "/x")]));
+        assert_eq!(dir.unwrap(), PathBuf::from("/x/iirs"));
+        let dir = cache_dir(env(&[("HOME", "/home/u"), ("XDG_CACHE_HOME", "x")]));
+        assert_eq!(dir.unwrap(), PathBuf::from("/home/u/.cache/iirs"));
+        assert!(cache_dir(env(&[("TMPDIR", "/tmp")])).is_err());
+    }
+
+    #[test]
+    fn test_cache_name() {
+        let name = cache_name("https://host.org/a/b.fa?x=1");
+        assert!(name.ends_with("-b.fa"));
+        assert_eq!(name, cache_name("https://host.org/a/b.fa?x=1"));
+        assert_ne!(name, cache_name("https://host.org/a/b.fa?x=2"));
+        assert_ne!(
+            cache_name("https://host.org/a/b.fa"),
+            cache_name("https://host.org/a_b.fa")
+        );
+    }
+}
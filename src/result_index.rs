@@ -0,0 +1,153 @@
+//! Positional queries over a set of IRs.
+use crate::IrSpan;
+
+/// IRs indexed for overlap and nearest-IR queries, in 0-based inclusive coordinates like the
+/// IRs themselves.
+///
+/// The IRs are sorted by start. Overlap queries scan back at most the longest span of an IR,
+/// which is bounded by the search parameters (`2 * max_len + max_gap`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResultIndex {
+    /// Sorted by (left, right, gap).
+    irs: Vec<IrSpan>,
+    /// `reach[i]` is the index of the IR ending the furthest among the first `i + 1`.
+    reach: Vec<usize>,
+    /// Longest span of an IR.
+    max_span: usize,
+}
+
+impl ResultIndex {
+    pub fn new(mut irs: Vec<IrSpan>) -> Self {
+        irs.sort_unstable();
+        let mut reach: Vec<usize> = Vec::with_capacity(irs.len());
+        for (idx, ir) in irs.iter().enumerate() {
+            match reach.last() {
+                Some(&best) if irs[best].1 >= ir.1 => reach.push(best),
+                _ => reach.push(idx),
+            }
+        }
+        let max_span = irs
+            .iter()
+            .map(|&(left, right, _)| right - left)
+            .max()
+            .unwrap_or(0);
+        Self {
+            irs,
+            reach,
+            max_span,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.irs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.irs.is_empty()
+    }
+
+    /// The IRs, sorted by start.
+    pub fn irs(&self) -> &[IrSpan] {
+        &self.irs
+    }
+
+    /// IRs overlapping the positions `start..=end`, sorted by start.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use iirs::ResultIndex;
+    ///
+    /// let index = ResultIndex::new(vec![(50, 70, 5), (0, 9, 2), (5, 20, 0)]);
+    /// let hits: Vec<_> = index.overlapping(8, 30).collect();
+    /// assert_eq!(hits, vec![&(0, 9, 2), &(5, 20, 0)]);
+    /// assert_eq!(index.overlapping(21, 49).count(), 0);
+    /// ```
+    pub fn overlapping(&self, start: usize, end: usize) -> impl Iterator<Item = &IrSpan> {
+        // Only the IRs starting at most `max_span` before `start` can reach it.
+        let first = self
+            .irs
+            .partition_point(|&(left, _, _)| left + self.max_span < start);
+        let last = self.irs.partition_point(|&(left, _, _)| left <= end);
+        self.irs[first..last.max(first)]
+            .iter()
+            .filter(move |&&(_, right, _)| right >= start)
+    }
+
+    /// Nearest IR to a position, with the distance to it: 0 if the IR contains the position.
+    /// Ties go to the IR before.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use iirs::ResultIndex;
+    ///
+    /// let index = ResultIndex::new(vec![(0, 9, 2), (50, 70, 5)]);
+    /// assert_eq!(index.nearest(5), Some((&(0, 9, 2), 0)));
+    /// assert_eq!(index.nearest(40), Some((&(50, 70, 5), 10)));
+    /// assert_eq!(index.nearest(100), Some((&(50, 70, 5), 30)));
+    /// ```
+    pub fn nearest(&self, pos: usize) -> Option<(&IrSpan, usize)> {
+        // IRs starting at or before the position
+        let idx = self.irs.partition_point(|&(left, _, _)| left <= pos);
+
+        let before = idx.checked_sub(1).map(|i| {
+            let ir = &self.irs[self.reach[i]];
+            (ir, pos.saturating_sub(ir.1))
+        });
+        let after = self.irs.get(idx).map(|ir| (ir, ir.0 - pos));
+
+        match (before, after) {
+            (Some(before), Some(after)) if after.1 < before.1 => Some(after),
+            (before, after) => before.or(after),
+        }
+    }
+}
+
+impl FromIterator<IrSpan> for ResultIndex {
+    fn from_iter<I: IntoIterator<Item = IrSpan>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shuffle::Rng;
+
+    #[test]
+    fn test_result_index_matches_brute_force() {
+        let mut rng = Rng::new(11);
+        let irs: Vec<IrSpan> = (0..300)
+            .map(|_| {
+                let left = rng.below(1000);
+                let arm = 2 + rng.below(10);
+                let gap = rng.below(8);
+                (left, left + 2 * arm + gap - 1, gap)
+            })
+            .collect();
+        let index: ResultIndex = irs.iter().copied().collect();
+        assert_eq!(index.len(), irs.len());
+
+        for _ in 0..200 {
+            let start = rng.below(1050);
+            let end = start + rng.below(30);
+            let mut expected: Vec<_> = irs
+                .iter()
+                .filter(|&&(left, right, _)| left <= end && right >= start)
+                .collect();
+            expected.sort_unstable();
+            assert_eq!(index.overlapping(start, end).collect::<Vec<_>>(), expected);
+
+            let distance = |&(left, right, _): &IrSpan| {
+                left.saturating_sub(start) + start.saturating_sub(right)
+            };
+            let best = irs.iter().map(distance).min();
+            let (ir, received) = index.nearest(start).unwrap();
+            assert_eq!(Some(received), best);
+            assert_eq!(distance(ir), received);
+        }
+        assert_eq!(ResultIndex::default().nearest(3), None);
+        assert_eq!(ResultIndex::default().overlapping(0, 3).count(), 0);
+    }
+}
@@ -0,0 +1,62 @@
+//! Sparse table over 32-bit LCP values (`index32` feature), half the size of the one of the
+//! `rmq-tabulation` crate, which only takes `usize` values.
+use rmq::Rmq;
+
+use crate::hugepages;
+
+pub struct Sparse {
+    /// `levels[k][i]` is the minimum of `values[i..i + 2^k]`.
+    levels: Vec<Vec<u32>>,
+}
+
+impl Sparse {
+    /// Sparse table of `values`, whose levels are backed by transparent huge pages if
+    /// `huge_pages`.
+    pub fn new(values: &[u32], huge_pages: bool) -> Self {
+        let mut first = hugepages::with_capacity(values.len(), huge_pages);
+        first.extend_from_slice(values);
+        let mut levels = vec![first];
+        let mut width = 1;
+        while 2 * width <= values.len() {
+            let prev = levels.last().unwrap();
+            let len = values.len() + 1 - 2 * width;
+            let mut next = hugepages::with_capacity(len, huge_pages);
+            next.extend((0..len).map(|i| prev[i].min(prev[i + width])));
+            levels.push(next);
+            width *= 2;
+        }
+        Self { levels }
+    }
+}
+
+impl Rmq for Sparse {
+    /// Minimum of the values in `i..j`.
+    fn rmq(&self, i: usize, j: usize) -> Option<usize> {
+        if i >= j {
+            return None;
+        }
+        let k = (j - i).ilog2() as usize;
+        let level = &self.levels[k];
+        Some(level[i].min(level[j - (1 << k)]) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shuffle::Rng;
+
+    #[test]
+    fn test_sparse_minimum() {
+        let mut rng = Rng::new(3);
+        let values: Vec<u32> = (0..100).map(|_| rng.below(50) as u32).collect();
+        let sparse = Sparse::new(&values, false);
+        for i in 0..values.len() {
+            assert_eq!(sparse.rmq(i, i), None);
+            for j in i + 1..=values.len() {
+                let expected = *values[i..j].iter().min().unwrap() as usize;
+                assert_eq!(sparse.rmq(i, j), Some(expected));
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Block-decomposed range minimum queries (`RmqBackend::Blocks`), a fraction of the memory of
+//! a sparse table over the whole LCP array.
+//!
+//! The values are cut in blocks of 32. A sparse table over the minima of the blocks answers the
+//! blocks fully inside a query, and every position stores a 32-bit mask of the positions of
+//! its block that are smaller than every later one up to it (the stack of the minima), which
+//! answers the partial blocks in constant time.
+use rmq::Rmq;
+
+use crate::{IndexInt, hugepages};
+
+const BLOCK: usize = 32;
+
+pub struct Blocks {
+    values: Vec<IndexInt>,
+    /// Bit `k` of `masks[j]` is set when the `k`-th value of the block of `j` is smaller than
+    /// every later value of the block up to `j`.
+    masks: Vec<u32>,
+    /// `levels[k][b]` is the minimum of the blocks `b..b + 2^k`.
+    levels: Vec<Vec<IndexInt>>,
+}
+
+impl Blocks {
+    /// Structure of `values`, whose arrays are backed by transparent huge pages if `huge_pages`.
+    pub fn new(values: &[IndexInt], huge_pages: bool) -> Self {
+        let mut masks = hugepages::with_capacity(values.len(), huge_pages);
+        masks.resize(values.len(), 0);
+        for (block, block_values) in values.chunks(BLOCK).enumerate() {
+            let mut stack = 0u32;
+            for (k, &value) in block_values.iter().enumerate() {
+                while stack != 0 {
+                    let top = 31 - stack.leading_zeros() as usize;
+                    if block_values[top] < value {
+                        break;
+                    }
+                    stack &= !(1 << top);
+                }
+                stack |= 1 << k;
+                masks[block * BLOCK + k] = stack;
+            }
+        }
+
+        let n_blocks = values.len().div_ceil(BLOCK);
+        let mut minima = hugepages::with_capacity(n_blocks, huge_pages);
+        minima.extend(
+            values
+                .chunks(BLOCK)
+                .map(|block_values| *block_values.iter().min().unwrap()),
+        );
+        let mut levels = vec![minima];
+        let mut width = 1;
+        while 2 * width <= n_blocks {
+            let prev = levels.last().unwrap();
+            let len = n_blocks + 1 - 2 * width;
+            let mut next = hugepages::with_capacity(len, huge_pages);
+            next.extend((0..len).map(|b| prev[b].min(prev[b + width])));
+            levels.push(next);
+            width *= 2;
+        }
+
+        let mut own_values = hugepages::with_capacity(values.len(), huge_pages);
+        own_values.extend_from_slice(values);
+        Self {
+            values: own_values,
+            masks,
+            levels,
+        }
+    }
+
+    /// Minimum of the values in `i..=j`, inside a single block.
+    fn in_block(&self, i: usize, j: usize) -> IndexInt {
+        let start = j - j % BLOCK;
+        let mask = self.masks[j] & (u32::MAX << (i - start));
+        self.values[start + mask.trailing_zeros() as usize]
+    }
+
+    /// Minimum of the blocks in `a..b`.
+    fn blocks(&self, a: usize, b: usize) -> IndexInt {
+        let k = (b - a).ilog2() as usize;
+        let level = &self.levels[k];
+        level[a].min(level[b - (1 << k)])
+    }
+}
+
+impl Rmq for Blocks {
+    /// Minimum of the values in `i..j`.
+    fn rmq(&self, i: usize, j: usize) -> Option<usize> {
+        if i >= j {
+            return None;
+        }
+        let last = j - 1;
+        let (first_block, last_block) = (i / BLOCK, last / BLOCK);
+        if first_block == last_block {
+            return Some(self.in_block(i, last) as usize);
+        }
+        let mut min = self
+            .in_block(i, first_block * BLOCK + BLOCK - 1)
+            .min(self.in_block(last_block * BLOCK, last));
+        if first_block + 1 < last_block {
+            min = min.min(self.blocks(first_block + 1, last_block));
+        }
+        Some(min as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shuffle::Rng;
+
+    #[test]
+    fn test_blocks_minimum() {
+        let mut rng = Rng::new(5);
+        for len in [1, 31, 32, 33, 200] {
+            let values: Vec<IndexInt> = (0..len).map(|_| rng.below(20) as IndexInt).collect();
+            let blocks = Blocks::new(&values, false);
+            for i in 0..values.len() {
+                assert_eq!(blocks.rmq(i, i), None);
+                for j in i + 1..=values.len() {
+                    let expected = *values[i..j].iter().min().unwrap() as usize;
+                    assert_eq!(blocks.rmq(i, j), Some(expected), "{i}..{j} of {len}");
+                }
+            }
+        }
+    }
+}
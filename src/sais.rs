@@ -0,0 +1,150 @@
+//! Suffix array construction by induced sorting (SA-IS, Nong, Zhang and Chan, 2009), the
+//! `sais` feature. It runs in linear time whatever the repeats of the sequence, which pays off
+//! on large genomes when the search itself is cheap (small `max_gap`).
+//!
+//! The suffixes are sorted as if the sequence ended with a sentinel smaller than every byte,
+//! like divsufsort, so that both backends give the same suffix array.
+use crate::hugepages;
+
+/// Suffix array of `s`, 32-bit like the one of divsufsort, backed by transparent huge pages if
+/// `huge_pages`.
+pub fn suffix_array(s: &[u8], huge_pages: bool) -> Vec<i32> {
+    sa_is(s, u8::MAX as usize, huge_pages)
+}
+
+/// Suffix array of `s`, whose characters are at most `upper`.
+fn sa_is<T: Copy + Into<usize>>(s: &[T], upper: usize, huge_pages: bool) -> Vec<i32> {
+    let n = s.len();
+    let c = |i: usize| -> usize { s[i].into() };
+    match n {
+        0 => return Vec::new(),
+        1 => return vec![0],
+        2 => return if c(0) < c(1) { vec![0, 1] } else { vec![1, 0] },
+        _ => {}
+    }
+
+    // The suffix at `i` is S-type (smaller than the next one) or L-type (larger).
+    let mut ls = vec![false; n];
+    for i in (0..n - 1).rev() {
+        ls[i] = if c(i) == c(i + 1) {
+            ls[i + 1]
+        } else {
+            c(i) < c(i + 1)
+        };
+    }
+    // Start of the L-type and S-type parts of the bucket of every character.
+    let mut sum_l = vec![0; upper + 1];
+    let mut sum_s = vec![0; upper + 1];
+    for i in 0..n {
+        if ls[i] {
+            sum_l[c(i) + 1] += 1;
+        } else {
+            sum_s[c(i)] += 1;
+        }
+    }
+    for ch in 0..=upper {
+        sum_s[ch] += sum_l[ch];
+        if ch < upper {
+            sum_l[ch + 1] += sum_s[ch];
+        }
+    }
+
+    // Sort every suffix from the sorted leftmost S-type (LMS) positions `lms`.
+    let induce = |sa: &mut [i32], lms: &[usize]| {
+        sa.fill(-1);
+        let mut buf = sum_s.clone();
+        for &d in lms {
+            sa[buf[c(d)]] = d as i32;
+            buf[c(d)] += 1;
+        }
+        buf.copy_from_slice(&sum_l);
+        sa[buf[c(n - 1)]] = (n - 1) as i32;
+        buf[c(n - 1)] += 1;
+        for i in 0..n {
+            let v = sa[i];
+            if v >= 1 && !ls[v as usize - 1] {
+                let p = v as usize - 1;
+                sa[buf[c(p)]] = p as i32;
+                buf[c(p)] += 1;
+            }
+        }
+        buf.copy_from_slice(&sum_l);
+        for i in (0..n).rev() {
+            let v = sa[i];
+            if v >= 1 && ls[v as usize - 1] {
+                let p = v as usize - 1;
+                buf[c(p) + 1] -= 1;
+                sa[buf[c(p) + 1]] = p as i32;
+            }
+        }
+    };
+
+    let lms: Vec<usize> = (1..n).filter(|&i| !ls[i - 1] && ls[i]).collect();
+    let m = lms.len();
+    let mut lms_map = vec![-1i32; n];
+    for (k, &i) in lms.iter().enumerate() {
+        lms_map[i] = k as i32;
+    }
+    let mut sa = hugepages::with_capacity(n, huge_pages);
+    sa.resize(n, -1);
+    induce(&mut sa, &lms);
+
+    if m > 0 {
+        // Name the LMS substrings in their (induced) order, and sort them recursively.
+        let mut sorted_lms: Vec<usize> = sa
+            .iter()
+            .map(|&v| v as usize)
+            .filter(|&v| lms_map[v] != -1)
+            .collect();
+        let mut rec_s = vec![0usize; m];
+        let mut rec_upper = 0;
+        for i in 1..m {
+            let (mut l, mut r) = (sorted_lms[i - 1], sorted_lms[i]);
+            let end_l = lms.get(lms_map[l] as usize + 1).copied().unwrap_or(n);
+            let end_r = lms.get(lms_map[r] as usize + 1).copied().unwrap_or(n);
+            let mut same = end_l - l == end_r - r;
+            if same {
+                while l < end_l && c(l) == c(r) {
+                    l += 1;
+                    r += 1;
+                }
+                same = l < n && r < n && c(l) == c(r);
+            }
+            if !same {
+                rec_upper += 1;
+            }
+            rec_s[lms_map[sorted_lms[i]] as usize] = rec_upper;
+        }
+
+        let rec_sa = sa_is(&rec_s, rec_upper, huge_pages);
+        for (sorted, &k) in sorted_lms.iter_mut().zip(&rec_sa) {
+            *sorted = lms[k as usize];
+        }
+        induce(&mut sa, &sorted_lms);
+    }
+    sa
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libtests::random_seq;
+
+    #[test]
+    fn test_sais_matches_divsufsort() {
+        let mut seed = 17;
+        for alphabet in [&b"a"[..], b"ab", b"acgt", b"acgtnrykmswbdhv$#"] {
+            for len in [1, 2, 3, 10, 100, 5000] {
+                seed += 1;
+                let s = random_seq(seed, len, alphabet);
+                let expected: Vec<i32> = divsufsort::sort(&s).into_parts().1;
+                assert_eq!(
+                    suffix_array(&s, false),
+                    expected,
+                    "{:?}",
+                    String::from_utf8_lossy(&s)
+                );
+            }
+        }
+    }
+}
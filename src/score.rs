@@ -0,0 +1,224 @@
+//! Scoring of IRs.
+use crate::IrSpan;
+use crate::config::{SearchParams, SymmetryMode};
+use crate::matrix::MatchMatrix;
+use crate::simd;
+use crate::thermo::{extrusion_probability, hairpin_thermo};
+
+/// Which numeric score is given to IRs (for thresholds and ranking).
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScoreScheme {
+    /// Length of an arm.
+    Length,
+    /// Percentage of matching pairs between the arms.
+    Identity,
+    /// Opposite of the free energy of the hairpin (kcal/mol): more stable is higher.
+    Energy,
+    /// EMBOSS einverted: +3 per match, -4 per mismatch.
+    #[default]
+    Einverted,
+    /// Like einverted, with the weights of `SearchParams::scoring`.
+    CustomWeights,
+    /// Probability (0 to 1) of extrusion as a cruciform under `SearchParams::supercoiling`.
+    Extrusion,
+}
+
+/// Match and mismatch weights, following EMBOSS `einverted`.
+///
+/// Since the arms are always aligned without indels, `einverted`'s gap penalty never applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scoring {
+    pub match_score: i32,
+    pub mismatch_score: i32,
+}
+
+impl Default for Scoring {
+    /// Same defaults as `einverted`.
+    fn default() -> Self {
+        Self {
+            match_score: 3,
+            mismatch_score: -4,
+        }
+    }
+}
+
+impl Scoring {
+    pub fn score(&self, matches: usize, mismatches: usize) -> i32 {
+        self.match_score * matches as i32 + self.mismatch_score * mismatches as i32
+    }
+}
+
+/// Score of an IR in `seq` according to `params.score_scheme`.
+pub fn ir_score(
+    ir: &IrSpan,
+    seq: &[u8],
+    params: &SearchParams,
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+) -> f64 {
+    let len = arm_len(ir);
+    let weighted = |scoring: Scoring| {
+        let mismatches = count_mismatches(ir, seq, &params.symmetry_mode, matrix, complement);
+        f64::from(scoring.score(len - mismatches, mismatches))
+    };
+    match params.score_scheme {
+        ScoreScheme::Length => len as f64,
+        ScoreScheme::Identity => {
+            let mismatches = count_mismatches(ir, seq, &params.symmetry_mode, matrix, complement);
+            100.0 * (len - mismatches) as f64 / len as f64
+        }
+        ScoreScheme::Energy => -hairpin_thermo(ir, seq, &params.thermo).dg,
+        ScoreScheme::Einverted => weighted(Scoring::default()),
+        ScoreScheme::CustomWeights => weighted(params.scoring),
+        ScoreScheme::Extrusion => {
+            extrusion_probability(ir, seq, &params.supercoiling, &params.thermo)
+        }
+    }
+}
+
+/// Length of each of the two arms of an IR.
+pub const fn arm_len(ir: &IrSpan) -> usize {
+    let &(left, right, gap) = ir;
+    (right + 1 - left - gap) / 2
+}
+
+/// Whether an IR reaching `params.max_len` could still be extended outwards (up to a matching
+/// pair, with at most `params.mismatches` mismatches in total), i.e. it was clipped by
+/// `max_len` instead of being maximal. The arms of the direct modes are extended in the same
+/// direction, into the gap.
+pub fn is_clipped(
+    ir: &IrSpan,
+    seq: &[u8],
+    params: &SearchParams,
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+) -> bool {
+    // An IR clipped right before a mismatch is one position shorter than max_len.
+    if arm_len(ir) + 1 < params.max_len {
+        return false;
+    }
+    let &(left, right, gap) = ir;
+    let right_start = right + 1 - arm_len(ir);
+    let mode = &params.symmetry_mode;
+    let mut mismatches = count_mismatches(ir, seq, mode, matrix, complement);
+    let (reach, complemented) = match mode {
+        SymmetryMode::Inverted => (left.min(seq.len() - 1 - right), true),
+        SymmetryMode::InvertedComplementary => (left.min(seq.len() - 1 - right), false),
+        SymmetryMode::Direct => (left.min(gap), false),
+        SymmetryMode::DirectComplementary => (left.min(gap), true),
+    };
+    for k in 1..=reach {
+        let partner = match mode {
+            SymmetryMode::Inverted | SymmetryMode::InvertedComplementary => seq[right + k],
+            SymmetryMode::Direct | SymmetryMode::DirectComplementary => seq[right_start - k],
+        };
+        let partner = if complemented {
+            complement[partner as usize]
+        } else {
+            partner
+        };
+        if matrix.match_u8(seq[left - k], partner) {
+            return true;
+        }
+        mismatches += 1;
+        if mismatches > params.mismatches {
+            return false;
+        }
+    }
+    false
+}
+
+/// Number of pairs that do not match between the two arms of an IR under `mode`.
+pub fn count_mismatches(
+    ir: &IrSpan,
+    seq: &[u8],
+    mode: &SymmetryMode,
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+) -> usize {
+    mismatched_pairs(ir, seq, mode, matrix, complement).len()
+}
+
+/// 0-based positions of the pairs of bases of an IR that do not match under `mode`, from the
+/// outer pair. The arms of the direct modes are paired in the same direction.
+pub(crate) fn mismatched_pairs(
+    ir: &IrSpan,
+    seq: &[u8],
+    mode: &SymmetryMode,
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+) -> Vec<(usize, usize)> {
+    let &(left, right, _) = ir;
+    let len = arm_len(ir);
+    let right_start = right + 1 - len;
+    let mut pairs = Vec::new();
+    simd::for_each_mismatch(
+        &seq[left..left + len],
+        &seq[right_start..=right],
+        *mode,
+        matrix,
+        complement,
+        |i| {
+            pairs.push(match mode {
+                SymmetryMode::Inverted | SymmetryMode::InvertedComplementary => {
+                    (left + i, right - i)
+                }
+                SymmetryMode::Direct | SymmetryMode::DirectComplementary => {
+                    (left + i, right_start + i)
+                }
+            });
+        },
+    );
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::build_complement_array;
+
+    #[test]
+    fn test_is_clipped() {
+        let seq = "aacgtt".as_bytes();
+        let matrix = MatchMatrix::default();
+        let complement = build_complement_array();
+        let params = SearchParams::new(2, 2, 0, 0).unwrap();
+        // "ac" / "gt" could be extended with the outer pair (a, t)
+        assert!(is_clipped(&(1, 4, 0), seq, &params, &matrix, &complement));
+        assert!(!is_clipped(&(0, 5, 0), seq, &params, &matrix, &complement));
+        let params = SearchParams::new(2, 3, 0, 0).unwrap();
+        assert!(!is_clipped(&(0, 5, 0), seq, &params, &matrix, &complement));
+    }
+
+    #[test]
+    fn test_score() {
+        let matrix = MatchMatrix::default();
+        let complement = build_complement_array();
+        let seq = b"acgagt";
+        let ir = (0, 5, 0);
+        assert_eq!(arm_len(&ir), 3);
+        let mode = SymmetryMode::Inverted;
+        assert_eq!(count_mismatches(&ir, seq, &mode, &matrix, &complement), 1);
+        assert_eq!(Scoring::default().score(2, 1), 2);
+    }
+
+    #[test]
+    fn test_ir_score() {
+        let matrix = MatchMatrix::default();
+        let complement = build_complement_array();
+        let seq = b"acgagt";
+        let ir = (0, 5, 0);
+        let mut params = SearchParams::default();
+        let mut score = |scheme| {
+            params.score_scheme = scheme;
+            ir_score(&ir, seq, &params, &matrix, &complement)
+        };
+        assert_eq!(score(ScoreScheme::Length), 3.0);
+        assert_eq!(score(ScoreScheme::Identity), 200.0 / 3.0);
+        assert_eq!(score(ScoreScheme::Einverted), 2.0);
+        assert!((0.0..=1.0).contains(&score(ScoreScheme::Extrusion)));
+        params.scoring.mismatch_score = -1;
+        params.score_scheme = ScoreScheme::CustomWeights;
+        assert_eq!(ir_score(&ir, seq, &params, &matrix, &complement), 5.0);
+    }
+}
@@ -0,0 +1,189 @@
+//! Candidate centers of the search from seeds: pairs of equal k-mers of plain bases, one in
+//! each half of the doubled sequence, on the diagonal of the extension of a center.
+//!
+//! The extension of the center `c` compares `s[i + t]` with `s[j + t]`, where
+//! `i = 1 + floor(c / 2)` and `j = 2n + 1 - ceil(c / 2)` (see `add_irs_at_this_center`), so a
+//! seed at `(a, b)` lies on the extension of the center `a - b + 2n`, at `t = a - i`.
+use anyhow::{Result, anyhow};
+
+use crate::config::SearchParams;
+
+/// Longest seed: k-mers are packed in a `u64`.
+pub const MAX_SEED_LEN: usize = 32;
+
+/// Set of centers of a doubled sequence.
+pub struct Centers {
+    bits: Vec<u64>,
+}
+
+impl Centers {
+    fn new(s_n: usize) -> Self {
+        Self {
+            bits: vec![0; s_n.div_ceil(64)],
+        }
+    }
+
+    fn insert(&mut self, c: usize) {
+        self.bits[c / 64] |= 1 << (c % 64);
+    }
+
+    pub fn contains(&self, c: usize) -> bool {
+        self.bits[c / 64] & (1 << (c % 64)) != 0
+    }
+}
+
+/// The k-mers of plain bases of `seq`, 2 bits per base (U as T), with their start. The
+/// k-mers with other characters are skipped.
+fn kmers(seq: &[u8], k: usize) -> impl Iterator<Item = (u64, usize)> + '_ {
+    let mask = u64::MAX >> (64 - 2 * k);
+    let mut code = 0;
+    let mut run = 0;
+    seq.iter().enumerate().filter_map(move |(pos, &b)| {
+        let bits = match b {
+            b'a' => 0,
+            b'c' => 1,
+            b'g' => 2,
+            b't' | b'u' => 3,
+            _ => {
+                run = 0;
+                return None;
+            }
+        };
+        code = ((code << 2) | bits) & mask;
+        run += 1;
+        (run >= k).then(|| (code, pos + 1 - k))
+    })
+}
+
+/// Centers of the doubled sequence `s` with a seed of `k` bases that starts at most `reach`
+/// positions into their extension.
+pub fn seed_centers(s: &[u8], k: usize, reach: usize) -> Result<Centers> {
+    if !(1..=MAX_SEED_LEN).contains(&k) {
+        return Err(anyhow!(
+            "seed_len={k} must be between 1 and {MAX_SEED_LEN}."
+        ));
+    }
+    let mut centers = Centers::new(s.len());
+    insert_seeds(&mut centers, s, k, reach);
+    Ok(centers)
+}
+
+fn insert_seeds(centers: &mut Centers, s: &[u8], k: usize, reach: usize) {
+    let n = s.len() / 2 - 1;
+    let mut left: Vec<(u64, usize)> = kmers(&s[..n], k).collect();
+    left.sort_unstable();
+
+    for (code, offset) in kmers(&s[n + 1..2 * n + 1], k) {
+        let b = n + 1 + offset;
+        // t = ceil((a + b - 2n) / 2) - 1 must be in 0..=reach.
+        let lo = 2 * n + 1 - b;
+        let hi = lo + 2 * reach + 1;
+        let first = left.partition_point(|&seed| seed < (code, lo));
+        for &(other, a) in &left[first..] {
+            if other != code || a > hi {
+                break;
+            }
+            centers.insert(a + 2 * n - b);
+        }
+    }
+}
+
+/// Bytes used to find the seeds of a doubled sequence of `s_n` characters: the sorted k-mers
+/// of its first half and the set of centers.
+pub const fn seeds_memory(s_n: usize) -> usize {
+    (s_n / 2) * size_of::<(u64, usize)>() + s_n.div_ceil(64) * size_of::<u64>()
+}
+
+/// Shortest seed of the prefilter: shorter ones are found on most centers anyway.
+const MIN_PREFILTER_SEED: usize = 8;
+
+/// Length of the seeds of the prefilter, or `None` when it would not rule out enough centers
+/// (short `min_len`, many mismatches) or can not (`exact_bases` unset, see below).
+pub fn prefilter_seed_len(params: &SearchParams, exact_bases: bool) -> Option<usize> {
+    let k = ((params.min_len - params.mismatches) / (params.mismatches + 1)).min(MAX_SEED_LEN);
+    (k >= MIN_PREFILTER_SEED && exact_bases).then_some(k)
+}
+
+/// Whether `b` is a plain base, which the seeds are made of.
+const fn is_plain(b: u8) -> bool {
+    matches!(b, b'a' | b'c' | b'g' | b't' | b'u')
+}
+
+/// Centers of the doubled sequence `s` that may have an IR of `params`, or `None` when the
+/// prefilter is not used (see [`prefilter_seed_len`]).
+///
+/// It is exact: the first `min_len` pairs of an arm have at most `mismatches` mismatches, so
+/// they hold a run of `(min_len - mismatches) / (mismatches + 1)` equal pairs, which is a
+/// seed unless it has other characters than plain bases (which may match without being
+/// equal). Every center whose extension reaches such a character is kept. It needs plain
+/// bases to only match themselves (`exact_bases`, not the bisulfite mode).
+pub fn prefilter_centers(s: &[u8], params: &SearchParams, exact_bases: bool) -> Option<Centers> {
+    let k = prefilter_seed_len(params, exact_bases)?;
+    // The arm starts in the gap, at most max_gap / 2 + 1 pairs into the extension.
+    let reach = params.max_gap / 2 + 1 + params.min_len - k;
+    let mut centers = Centers::new(s.len());
+    insert_seeds(&mut centers, s, k, reach);
+
+    // Centers whose pairs t < reach + k of the extension hit other characters.
+    let n = s.len() / 2 - 1;
+    let len = reach + k;
+    let mut pos = 0;
+    while pos < 2 * n + 1 {
+        if pos == n || is_plain(s[pos]) {
+            pos += 1;
+            continue;
+        }
+        let start = pos;
+        while pos < 2 * n + 1 && pos != n && !is_plain(s[pos]) {
+            pos += 1;
+        }
+        // t = a - 1 - floor(c / 2) on the left half, t = b - 2n - 1 + ceil(c / 2) on the right.
+        let range = if start < n {
+            2 * start.saturating_sub(len)..(2 * pos).saturating_sub(2)
+        } else {
+            (2 * (2 * n + 2 - pos)).saturating_sub(1)..(2 * (2 * n + len - start) + 1).min(s.len())
+        };
+        for c in range {
+            centers.insert(c);
+        }
+    }
+    Some(centers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kmers() {
+        let codes: Vec<_> = kmers(b"acgtnac", 2).collect();
+        assert_eq!(
+            codes,
+            vec![(0b0001, 0), (0b0110, 1), (0b1011, 2), (0b0001, 5)]
+        );
+        assert!(kmers(b"gu", 2).eq(kmers(b"gt", 2)));
+        assert_eq!(kmers(b"tttt", 32).count(), 0);
+    }
+
+    #[test]
+    fn test_seed_centers() {
+        // The whole sequence is an IR, of center 0 + 7.
+        let seq = b"caaatttg";
+        let n = seq.len();
+        let mut s = seq.to_vec();
+        s.push(b'$');
+        s.extend(seq.iter().rev().map(|&b| match b {
+            b'a' => b't',
+            b'c' => b'g',
+            b'g' => b'c',
+            b't' => b'a',
+            _ => b,
+        }));
+        s.push(b'#');
+        let centers = seed_centers(&s, 3, 10).unwrap();
+        assert!(centers.contains(7));
+        assert!(!centers.contains(6));
+        assert_eq!((0..2 * n).filter(|&c| centers.contains(c)).count(), 1);
+        assert!(seed_centers(&s, 33, 10).is_err());
+    }
+}
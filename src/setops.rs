@@ -0,0 +1,215 @@
+//! Set operations (intersect, subtract, merge) on result files, matching IRs arm by arm.
+use anyhow::{Context, Result, anyhow};
+use std::fmt;
+use std::fs;
+
+use crate::IrSpan;
+use crate::format::{CustomColumns, fmt_csv_header, fmt_custom_header};
+use crate::score::arm_len;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOperation {
+    /// IRs of the first file matching an IR of the second.
+    Intersect,
+    /// IRs of the first file matching no IR of the second.
+    Subtract,
+    /// IRs of both files, the ones of the second file matching an IR of the first are dropped.
+    Merge,
+}
+
+/// The IRs of a result file (`csv` or `custom` output format), with their original lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResultFile {
+    header: String,
+    records: Vec<(IrSpan, String)>,
+}
+
+impl ResultFile {
+    pub fn read(path: &str) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Could not read results {path}"))?;
+        Self::parse(&content).with_context(|| format!("Invalid result file {path}"))
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let mut lines = content.lines();
+        let header = lines.next().unwrap_or_default().to_string();
+        let parse_record = if header == fmt_csv_header() {
+            parse_csv_record
+        } else if header.starts_with(&fmt_custom_header(&CustomColumns::default())) {
+            parse_custom_record
+        } else {
+            return Err(anyhow!(
+                "only the csv and custom output formats are supported"
+            ));
+        };
+
+        let mut records = Vec::new();
+        for line in lines {
+            // Footers (# TRUNCATED...) and separators
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let ir = parse_record(line).with_context(|| format!("Invalid record '{line}'"))?;
+            records.push((ir, line.to_string()));
+        }
+        Ok(Self { header, records })
+    }
+
+    pub fn irs(&self) -> impl Iterator<Item = IrSpan> + '_ {
+        self.records.iter().map(|&(ir, _)| ir)
+    }
+}
+
+impl fmt::Display for ResultFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.header)?;
+        for (_, line) in &self.records {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// `start_n,end_n,nucleotide,start_ir,end_ir,...` (1-based).
+fn parse_csv_record(line: &str) -> Result<IrSpan> {
+    let fields: Vec<&str> = line.split(',').collect();
+    if fields.len() < 5 {
+        return Err(anyhow!("expected at least 5 columns"));
+    }
+    let start: usize = fields[0].parse()?;
+    let end_left_arm: usize = fields[1].parse()?;
+    let end: usize = fields[3].parse()?;
+    let start_right_arm: usize = fields[4].parse()?;
+    Ok((start - 1, end - 1, start_right_arm - end_left_arm - 1))
+}
+
+/// `ir_start,motif,gap_motif,...` (1-based).
+fn parse_custom_record(line: &str) -> Result<IrSpan> {
+    let fields: Vec<&str> = line.split(',').collect();
+    if fields.len() < 3 {
+        return Err(anyhow!("expected at least 3 columns"));
+    }
+    let left = fields[0].parse::<usize>()? - 1;
+    let (len, gap) = (fields[1].len(), fields[2].len());
+    Ok((left, left + 2 * len + gap - 1, gap))
+}
+
+/// Whether the four ends of the arms of `a` and `b` are at most `tolerance` positions apart.
+pub fn irs_match(a: &IrSpan, b: &IrSpan, tolerance: usize) -> bool {
+    let ends = |ir: &IrSpan| {
+        let len = arm_len(ir);
+        [ir.0, ir.0 + len, ir.1 + 1 - len, ir.1]
+    };
+    ends(a)
+        .iter()
+        .zip(ends(b))
+        .all(|(&x, y)| x.abs_diff(y) <= tolerance)
+}
+
+/// Apply a set operation to two result files of the same output format.
+pub fn set_operation(
+    operation: SetOperation,
+    a: &ResultFile,
+    b: &ResultFile,
+    tolerance: usize,
+) -> Result<ResultFile> {
+    if a.header != b.header {
+        return Err(anyhow!("the result files have different output formats"));
+    }
+
+    // Whether an IR of `irs` (sorted) matches `ir`. Matching IRs start at most
+    // `tolerance` positions apart.
+    let has_match = |irs: &[IrSpan], ir: &IrSpan| {
+        let from = irs.partition_point(|other| other.0 + tolerance < ir.0);
+        irs[from..]
+            .iter()
+            .take_while(|other| other.0 <= ir.0 + tolerance)
+            .any(|other| irs_match(ir, other, tolerance))
+    };
+    let sorted = |file: &ResultFile| {
+        let mut irs: Vec<IrSpan> = file.irs().collect();
+        irs.sort_unstable();
+        irs
+    };
+
+    let mut records: Vec<(IrSpan, String)> = match operation {
+        SetOperation::Intersect | SetOperation::Subtract => {
+            let keep_matching = operation == SetOperation::Intersect;
+            let b_irs = sorted(b);
+            a.records
+                .iter()
+                .filter(|(ir, _)| has_match(&b_irs, ir) == keep_matching)
+                .cloned()
+                .collect()
+        }
+        SetOperation::Merge => {
+            let a_irs = sorted(a);
+            a.records
+                .iter()
+                .chain(b.records.iter().filter(|(ir, _)| !has_match(&a_irs, ir)))
+                .cloned()
+                .collect()
+        }
+    };
+    records.sort_by_key(|&(ir, _)| ir);
+
+    Ok(ResultFile {
+        header: a.header.clone(),
+        records,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_records() {
+        // Both describe "acb..bgt"
+        assert_eq!(parse_csv_record("1,3,acb,6,4,tgb,111").unwrap(), (0, 5, 0));
+        assert_eq!(parse_custom_record("1,acb,,tgb,3.5,2").unwrap(), (0, 5, 0));
+        assert_eq!(parse_custom_record("2,ac,gt,gt,4.5,6").unwrap(), (1, 6, 2));
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let header = fmt_custom_header(&CustomColumns::default());
+        let a = ResultFile::parse(&format!(
+            "{header}\n1,acb,,tgb,3.5,2\n11,aaaa,c,tttt,15,12\n"
+        ))
+        .unwrap();
+        let b = ResultFile::parse(&format!("{header}\n2,acb,,tgb,4.5,2\n31,ggg,,ccc,33.5,9\n"))
+            .unwrap();
+
+        let intersect = |tolerance| set_operation(SetOperation::Intersect, &a, &b, tolerance);
+        assert_eq!(intersect(0).unwrap().irs().count(), 0);
+        assert_eq!(
+            intersect(1).unwrap().irs().collect::<Vec<_>>(),
+            vec![(0, 5, 0)]
+        );
+
+        let subtract = set_operation(SetOperation::Subtract, &a, &b, 1).unwrap();
+        assert_eq!(subtract.irs().collect::<Vec<_>>(), vec![(10, 18, 1)]);
+
+        let merge = set_operation(SetOperation::Merge, &a, &b, 1).unwrap();
+        assert_eq!(
+            merge.to_string(),
+            format!("{header}\n1,acb,,tgb,3.5,2\n11,aaaa,c,tttt,15,12\n31,ggg,,ccc,33.5,9\n")
+        );
+
+        // Shards sharing the IRs at their boundary, merged in turn
+        let c = ResultFile::parse(&format!(
+            "{header}\n31,ggg,,ccc,33.5,9\n41,acb,,tgb,43.5,2\n"
+        ))
+        .unwrap();
+        let merge = set_operation(SetOperation::Merge, &merge, &c, 0).unwrap();
+        assert_eq!(
+            merge.irs().collect::<Vec<_>>(),
+            vec![(0, 5, 0), (10, 18, 1), (30, 35, 0), (40, 45, 0)]
+        );
+
+        let csv = ResultFile::parse(&format!("{}\n", fmt_csv_header())).unwrap();
+        assert!(set_operation(SetOperation::Merge, &a, &csv, 0).is_err());
+    }
+}
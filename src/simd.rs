@@ -0,0 +1,213 @@
+//! Vectorized comparison of the arms of IRs (AVX2, detected at runtime on x86_64).
+//!
+//! Pairs of plain bases (a, c, g, t) are compared 32 at a time, as long as the match matrix
+//! only pairs equal plain bases (no bisulfite rules) and the complement is the one of DNA.
+//! Pairs with another IUPAC character, and the end of the arms, go through the match matrix
+//! one pair at a time.
+use crate::config::SymmetryMode;
+use crate::matrix::MatchMatrix;
+
+/// Whether the arms of `mode` are paired from opposite ends, and whether the right arm is
+/// complemented.
+const fn pairing(mode: SymmetryMode) -> (bool, bool) {
+    match mode {
+        SymmetryMode::Inverted => (true, true),
+        SymmetryMode::InvertedComplementary => (true, false),
+        SymmetryMode::Direct => (false, false),
+        SymmetryMode::DirectComplementary => (false, true),
+    }
+}
+
+/// Whether `complement` pairs the plain bases as DNA does, as the vectorized comparison
+/// assumes.
+pub fn dna_pairs(complement: &[u8; 128]) -> bool {
+    [(b'a', b't'), (b'c', b'g'), (b'g', b'c'), (b't', b'a')]
+        .iter()
+        .all(|&(b, other)| complement[b as usize] == other)
+}
+
+/// Call `f`, in increasing order, with every offset `i` of `left_arm` whose base does not
+/// match its partner in `right_arm` under `mode`: `right_arm[len - 1 - i]` for the inverted
+/// modes, `right_arm[i]` for the direct ones.
+pub fn for_each_mismatch(
+    left_arm: &[u8],
+    right_arm: &[u8],
+    mode: SymmetryMode,
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+    mut f: impl FnMut(usize),
+) {
+    debug_assert_eq!(left_arm.len(), right_arm.len());
+    let arms = Arms {
+        left: left_arm,
+        right: right_arm,
+        mode,
+        matrix,
+        complement,
+    };
+    #[allow(unused_mut)]
+    let mut done = 0;
+    #[cfg(target_arch = "x86_64")]
+    if matrix.exact_bases() && dna_pairs(complement) && std::arch::is_x86_feature_detected!("avx2")
+    {
+        // SAFETY: AVX2 is available on this CPU.
+        done = unsafe { avx2::for_each_mismatch(&arms, &mut f) };
+    }
+    for i in done..left_arm.len() {
+        if !arms.matches(i) {
+            f(i);
+        }
+    }
+}
+
+struct Arms<'a> {
+    left: &'a [u8],
+    right: &'a [u8],
+    mode: SymmetryMode,
+    matrix: &'a MatchMatrix,
+    complement: &'a [u8; 128],
+}
+
+impl Arms<'_> {
+    /// Whether the pair of the offset `i` matches, through the match matrix.
+    fn matches(&self, i: usize) -> bool {
+        let (reversed, complemented) = pairing(self.mode);
+        let r = self.right[if reversed {
+            self.right.len() - 1 - i
+        } else {
+            i
+        }];
+        let expected = if complemented {
+            self.complement[r as usize]
+        } else {
+            r
+        };
+        self.matrix.match_u8(self.left[i], expected)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use super::{Arms, pairing};
+    use std::arch::x86_64::*;
+
+    const LANES: usize = 32;
+
+    /// Mask of the lanes holding a plain base.
+    #[target_feature(enable = "avx2")]
+    fn plain(v: __m256i) -> __m256i {
+        let a = _mm256_cmpeq_epi8(v, _mm256_set1_epi8(b'a' as i8));
+        let c = _mm256_cmpeq_epi8(v, _mm256_set1_epi8(b'c' as i8));
+        let g = _mm256_cmpeq_epi8(v, _mm256_set1_epi8(b'g' as i8));
+        let t = _mm256_cmpeq_epi8(v, _mm256_set1_epi8(b't' as i8));
+        _mm256_or_si256(_mm256_or_si256(a, c), _mm256_or_si256(g, t))
+    }
+
+    /// Same as [`super::for_each_mismatch`] for the first multiple of 32 offsets, which is
+    /// returned.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support AVX2.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn for_each_mismatch(arms: &Arms, f: &mut impl FnMut(usize)) -> usize {
+        let (reversed, complemented) = pairing(arms.mode);
+        let len = arms.left.len();
+        // Reverses the bytes of each 128-bit half, the halves are swapped afterwards.
+        let reverse = _mm256_setr_epi8(
+            15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 15, 14, 13, 12, 11, 10, 9, 8, 7,
+            6, 5, 4, 3, 2, 1, 0,
+        );
+        let mut i = 0;
+        while i + LANES <= len {
+            // SAFETY: both blocks are inside the arms.
+            let (l, r) = unsafe {
+                let l = _mm256_loadu_si256(arms.left.as_ptr().add(i).cast());
+                let r = if reversed {
+                    let r = _mm256_loadu_si256(arms.right.as_ptr().add(len - i - LANES).cast());
+                    _mm256_permute4x64_epi64(_mm256_shuffle_epi8(r, reverse), 0b01_00_11_10)
+                } else {
+                    _mm256_loadu_si256(arms.right.as_ptr().add(i).cast())
+                };
+                (l, r)
+            };
+            let matching = if complemented {
+                // Among the plain bases, only a + t and c + g have these sums.
+                let sum = _mm256_add_epi8(l, r);
+                _mm256_or_si256(
+                    _mm256_cmpeq_epi8(sum, _mm256_set1_epi8((b'a' + b't') as i8)),
+                    _mm256_cmpeq_epi8(sum, _mm256_set1_epi8((b'c' + b'g') as i8)),
+                )
+            } else {
+                _mm256_cmpeq_epi8(l, r)
+            };
+            let both_plain = _mm256_and_si256(plain(l), plain(r));
+            let mismatching = _mm256_movemask_epi8(_mm256_andnot_si256(matching, both_plain));
+            let other = !_mm256_movemask_epi8(both_plain);
+            let mut lanes = (mismatching | other) as u32;
+            while lanes != 0 {
+                let lane = lanes.trailing_zeros() as usize;
+                lanes &= lanes - 1;
+                if (other as u32) & (1 << lane) == 0 || !arms.matches(i + lane) {
+                    f(i + lane);
+                }
+            }
+            i += LANES;
+        }
+        i
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SearchParams;
+    use crate::constants::build_complement_array;
+    use crate::libtests::random_seq;
+    use crate::shuffle::Rng;
+
+    #[test]
+    fn test_for_each_mismatch_matches_matrix() {
+        let complement = build_complement_array();
+        let mut rng = Rng::new(17);
+        let mut seed = 17;
+        let mut bisulfite = SearchParams::new(3, 6, 2, 0).unwrap();
+        bisulfite.bisulfite = true;
+        for (matrix, alphabet) in [
+            (MatchMatrix::default(), &b"acgt"[..]),
+            (MatchMatrix::default(), &b"acgtacgtacgtnrykmu"[..]),
+            (MatchMatrix::from_params(&bisulfite), &b"acgt"[..]),
+        ] {
+            for len in [0, 5, 31, 32, 33, 100] {
+                seed += 1;
+                let left = random_seq(seed, len, alphabet);
+                // Mostly matching arms
+                let mut right: Vec<u8> =
+                    left.iter().rev().map(|&b| complement[b as usize]).collect();
+                for _ in 0..len / 4 {
+                    right[rng.below(len)] = alphabet[rng.below(alphabet.len())];
+                }
+                for mode in [
+                    SymmetryMode::Inverted,
+                    SymmetryMode::InvertedComplementary,
+                    SymmetryMode::Direct,
+                    SymmetryMode::DirectComplementary,
+                ] {
+                    let arms = Arms {
+                        left: &left,
+                        right: &right,
+                        mode,
+                        matrix: &matrix,
+                        complement: &complement,
+                    };
+                    let expected: Vec<usize> = (0..len).filter(|&i| !arms.matches(i)).collect();
+                    let mut offsets = Vec::new();
+                    for_each_mismatch(&left, &right, mode, &matrix, &complement, |i| {
+                        offsets.push(i);
+                    });
+                    assert_eq!(offsets, expected, "{mode:?} {len}");
+                }
+            }
+        }
+    }
+}
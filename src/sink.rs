@@ -0,0 +1,189 @@
+//! Destinations of the IRs found by the streaming search.
+use anyhow::Result;
+use std::io::Write;
+
+use crate::IrSpan;
+use crate::complement::complement_array;
+use crate::config::{Config, OutputFormat};
+use crate::constants::FASTA_LINE_WIDTH;
+use crate::control::SearchSummary;
+use crate::format;
+use crate::matrix::MatchMatrix;
+
+/// Where the search writes the IRs of a sequence, as soon as they are final, e.g. a database
+/// or a network connection (see [`crate::write_irs_to_sink`]).
+///
+/// The IRs are handed in batches, in the order of `config.sort_by`. `seq` is the sanitized
+/// sequence (lowercase, without newlines).
+pub trait ResultSink {
+    /// Called once, before the IRs.
+    fn begin(&mut self, _config: &Config, _seq: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_irs(&mut self, config: &Config, irs: &[IrSpan], seq: &[u8]) -> Result<()>;
+
+    /// Called once, after the IRs (also when the search was stopped early).
+    fn end(&mut self, _config: &Config, _summary: &SearchSummary) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes the IRs in the output format of the config (the output of [`crate::write_irs`]).
+pub struct FormatSink<W: Write> {
+    out: W,
+    matrix: Option<MatchMatrix>,
+    complement: Option<[u8; 128]>,
+    /// Whether IRs of the current sequence were written (to separate the objects of JSON).
+    written: bool,
+}
+
+impl<W: Write> FormatSink<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            matrix: None,
+            complement: None,
+            written: false,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> ResultSink for FormatSink<W> {
+    fn begin(&mut self, config: &Config, seq: &[u8]) -> Result<()> {
+        self.matrix = Some(MatchMatrix::from_params(&config.params));
+        self.complement = Some(complement_array(&config.params));
+        self.written = false;
+        // JSON Lines has no header line
+        if config.output_format != OutputFormat::Jsonl {
+            writeln!(self.out, "{}", format::fmt_header(config, seq.len()))?;
+        }
+        Ok(())
+    }
+
+    fn write_irs(&mut self, config: &Config, irs: &[IrSpan], seq: &[u8]) -> Result<()> {
+        let matrix = self
+            .matrix
+            .get_or_insert_with(|| MatchMatrix::from_params(&config.params));
+        let complement = self
+            .complement
+            .get_or_insert_with(|| complement_array(&config.params));
+        let irs_str = format::fmt_irs(config, irs, seq, matrix, complement);
+        if config.output_format == OutputFormat::Json && self.written && !irs.is_empty() {
+            self.out.write_all(b",\n")?;
+        }
+        self.written |= !irs.is_empty();
+        self.out.write_all(irs_str.as_bytes())?;
+        Ok(())
+    }
+
+    fn end(&mut self, config: &Config, _summary: &SearchSummary) -> Result<()> {
+        if config.output_format.has_trailing_line() {
+            writeln!(self.out)?;
+        }
+        if let Some(footer) = format::fmt_footer(config) {
+            writeln!(self.out, "{footer}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the IRs.
+impl ResultSink for Vec<IrSpan> {
+    fn write_irs(&mut self, _config: &Config, irs: &[IrSpan], _seq: &[u8]) -> Result<()> {
+        self.extend_from_slice(irs);
+        Ok(())
+    }
+}
+
+/// Sends the IRs to both sinks.
+impl<A: ResultSink, B: ResultSink> ResultSink for (A, B) {
+    fn begin(&mut self, config: &Config, seq: &[u8]) -> Result<()> {
+        self.0.begin(config, seq)?;
+        self.1.begin(config, seq)
+    }
+
+    fn write_irs(&mut self, config: &Config, irs: &[IrSpan], seq: &[u8]) -> Result<()> {
+        self.0.write_irs(config, irs, seq)?;
+        self.1.write_irs(config, irs, seq)
+    }
+
+    fn end(&mut self, config: &Config, summary: &SearchSummary) -> Result<()> {
+        self.0.end(config, summary)?;
+        self.1.end(config, summary)
+    }
+}
+
+/// Writes the sequence file of EMBOSS `einverted`, next to its report (`-F einverted`): one
+/// FASTA record per IR, from the start of its left arm to the end of its right arm, named
+/// `{seq_name}_{start}_{end}` (1-based).
+pub struct EinvertedSeqSink<W: Write> {
+    out: W,
+}
+
+impl<W: Write> EinvertedSeqSink<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> ResultSink for EinvertedSeqSink<W> {
+    fn write_irs(&mut self, config: &Config, irs: &[IrSpan], seq: &[u8]) -> Result<()> {
+        for &(left, right, _) in irs {
+            writeln!(self.out, ">{}_{}_{}", config.seq_name, left + 1, right + 1)?;
+            for line in seq[left..=right].chunks(FASTA_LINE_WIDTH) {
+                self.out.write_all(line)?;
+                self.out.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SearchParams;
+    use crate::constants::build_complement_array;
+    use crate::{StopHandle, find_spans, write_irs_to_sink};
+
+    #[test]
+    fn test_einverted_seq_sink() {
+        let config = Config {
+            params: SearchParams::new(4, 80, 6, 0).unwrap(),
+            output_format: OutputFormat::Einverted,
+            seq_name: "seq0",
+            ..Default::default()
+        };
+        let arm = "acgtgcatgg".repeat(4);
+        let rc: String = arm
+            .bytes()
+            .rev()
+            .map(|b| build_complement_array()[b as usize] as char)
+            .collect();
+        let seq = format!("tt{arm}cc{rc}tt");
+        let irs = find_spans(&config.params, seq.as_bytes()).unwrap();
+        assert!(irs.contains(&(2, 83, 2)));
+
+        let mut sinks = (
+            FormatSink::new(Vec::new()),
+            EinvertedSeqSink::new(Vec::new()),
+        );
+        write_irs_to_sink(&config, seq.as_bytes(), &StopHandle::new(), &mut sinks).unwrap();
+        let fasta = String::from_utf8(sinks.1.into_inner()).unwrap();
+        assert_eq!(fasta.matches('>').count(), irs.len());
+        let record = format!(">seq0_3_84\n{}\n{}\n", &seq[2..62], &seq[62..84]);
+        assert!(fasta.contains(&record), "{fasta}");
+        // The report is the same as without the sequence file
+        let report = String::from_utf8(sinks.0.into_inner()).unwrap();
+        assert_eq!(report.matches("seq0: Score").count(), irs.len());
+    }
+}
@@ -0,0 +1,338 @@
+//! Spilling of the pending IRs of the streaming writer to temporary files, so that
+//! pathological parameters (e.g. a huge `max_gap`) degrade to disk usage instead of
+//! running out of memory.
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::config::SortBy;
+use crate::{IrSpan, sort_irs_by};
+
+/// Number of spill directories and runs created by this process, to name them.
+static SPILL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of runs of a same level merged into one run of the next level, which bounds the
+/// number of open files (and of heads compared for every IR written) to about this number
+/// per level.
+const FAN_IN: usize = 16;
+
+/// A new directory of the temporary directory, only accessible by the current user, for the
+/// runs of a buffer. Created anew so that no one else can have placed files or links in it.
+fn spill_dir() -> Result<PathBuf> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    loop {
+        let id = SPILL_COUNT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("iirs-spill-{}-{}", std::process::id(), id));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("Could not create {}", dir.display()));
+            }
+        }
+    }
+}
+
+/// A sorted run of IRs in a temporary file, removed when dropped.
+struct Run {
+    path: PathBuf,
+    reader: BufReader<File>,
+    head: Option<IrSpan>,
+    /// Number of merges that led to this run (0 for the runs spilled from memory).
+    level: usize,
+}
+
+impl Run {
+    /// A new run of `dir`, whose IRs are written in order by `fill`.
+    fn create<F>(dir: &Path, level: usize, fill: F) -> Result<Self>
+    where
+        F: FnOnce(&mut dyn FnMut(IrSpan) -> Result<()>) -> Result<()>,
+    {
+        let id = SPILL_COUNT.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("run-{id}"));
+        let context = || format!("Could not spill IRs to {}", path.display());
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .with_context(context)?;
+        let mut writer = BufWriter::new(file);
+        fill(&mut |(left, right, gap)| {
+            for value in [left, right, gap] {
+                writer.write_all(&(value as u64).to_le_bytes())?;
+            }
+            Ok(())
+        })?;
+        writer.flush().with_context(context)?;
+
+        let reader = BufReader::new(File::open(&path).with_context(context)?);
+        let mut run = Self {
+            path,
+            reader,
+            head: None,
+            level,
+        };
+        run.advance()?;
+        Ok(run)
+    }
+
+    /// Merge the IRs left in `runs` into a single run of `dir`.
+    fn merge(dir: &Path, mut runs: Vec<Self>, sort_by: SortBy) -> Result<Self> {
+        let level = runs.iter().map(|run| run.level).max().unwrap_or(0) + 1;
+        Self::create(dir, level, |write| {
+            while let Some(idx) = (0..runs.len())
+                .filter(|&idx| runs[idx].head.is_some())
+                .min_by_key(|&idx| runs[idx].head.map(|ir| sort_by.key(&ir)))
+            {
+                if let Some(ir) = runs[idx].head {
+                    write(ir)?;
+                }
+                runs[idx].advance()?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Read the next IR into `head`.
+    fn advance(&mut self) -> Result<()> {
+        let mut bytes = [0u8; 24];
+        self.head = match self.reader.read_exact(&mut bytes) {
+            Ok(()) => {
+                let value = |i: usize| {
+                    u64::from_le_bytes(bytes[8 * i..8 * i + 8].try_into().unwrap()) as usize
+                };
+                Some((value(0), value(1), value(2)))
+            }
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => None,
+            Err(err) => return Err(err.into()),
+        };
+        Ok(())
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// IRs waiting to be written, in memory up to `threshold` IRs and in sorted runs on disk
+/// beyond that.
+pub(crate) struct SpillBuffer {
+    sort_by: SortBy,
+    threshold: Option<usize>,
+    /// IRs of a same window must be written together (see [`crate::RegionCap`]).
+    window: Option<usize>,
+    memory: Vec<IrSpan>,
+    runs: Vec<Run>,
+    /// Directory of the runs, created at the first spill.
+    dir: Option<PathBuf>,
+}
+
+impl SpillBuffer {
+    pub(crate) const fn new(
+        sort_by: SortBy,
+        threshold: Option<usize>,
+        window: Option<usize>,
+    ) -> Self {
+        Self {
+            sort_by,
+            threshold,
+            window,
+            memory: Vec::new(),
+            runs: Vec::new(),
+            dir: None,
+        }
+    }
+
+    pub(crate) fn extend(&mut self, irs: Vec<IrSpan>) -> Result<()> {
+        self.memory.extend(irs);
+        if self
+            .threshold
+            .is_some_and(|threshold| self.memory.len() > threshold)
+        {
+            let sort_by = self.sort_by;
+            self.memory.sort_unstable_by_key(|ir| sort_by.key(ir));
+            if self.dir.is_none() {
+                self.dir = Some(spill_dir()?);
+            }
+            if let Some(dir) = &self.dir {
+                let memory = std::mem::take(&mut self.memory);
+                self.runs.push(Run::create(dir, 0, |write| {
+                    memory.into_iter().try_for_each(write)
+                })?);
+            }
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Merge the runs of a same level once there are [`FAN_IN`] of them, level by level.
+    fn compact(&mut self) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let mut level = 0;
+        loop {
+            let (merged, rest): (Vec<Run>, Vec<Run>) = std::mem::take(&mut self.runs)
+                .into_iter()
+                .partition(|run| run.level == level);
+            self.runs = rest;
+            if merged.len() < FAN_IN {
+                self.runs.extend(merged);
+                return Ok(());
+            }
+            self.runs.push(Run::merge(dir, merged, self.sort_by)?);
+            level += 1;
+        }
+    }
+
+    /// Hand the IRs positioned before `watermark` to `emit` in order, and forget them.
+    ///
+    /// Without spilled runs, they are handed at once (sorted as [`sort_irs_by`] does). Otherwise
+    /// they are merged from the runs and handed in batches of about `threshold` IRs.
+    pub(crate) fn drain<F>(&mut self, watermark: usize, mut emit: F) -> Result<()>
+    where
+        F: FnMut(&[IrSpan]) -> Result<()>,
+    {
+        let sort_by = self.sort_by;
+        let (mut ready, rest): (Vec<_>, Vec<_>) = self
+            .memory
+            .drain(..)
+            .partition(|ir: &IrSpan| sort_by.position(ir) < watermark);
+        self.memory = rest;
+
+        if self.runs.is_empty() {
+            sort_irs_by(&mut ready, sort_by);
+            return emit(&ready);
+        }
+
+        ready.sort_unstable_by_key(|ir| sort_by.key(ir));
+        let mut ready = ready.into_iter().peekable();
+        let batch_size = self.threshold.unwrap_or(usize::MAX).max(1);
+        let mut batch: Vec<IrSpan> = Vec::new();
+        loop {
+            // The smallest IR among the memory and the heads of the runs
+            let mut next = ready.peek().copied().map(|ir| (ir, None));
+            for (idx, run) in self.runs.iter().enumerate() {
+                if let Some(head) = run.head.filter(|ir| sort_by.position(ir) < watermark)
+                    && next.is_none_or(|(ir, _)| sort_by.key(&head) < sort_by.key(&ir))
+                {
+                    next = Some((head, Some(idx)));
+                }
+            }
+            let Some((ir, source)) = next else {
+                break;
+            };
+            match source {
+                Some(idx) => self.runs[idx].advance()?,
+                None => {
+                    ready.next();
+                }
+            }
+
+            let same_window = |other: &IrSpan| {
+                self.window.is_some_and(|window| {
+                    sort_by.position(other) / window == sort_by.position(&ir) / window
+                })
+            };
+            if batch.len() >= batch_size && !batch.last().is_some_and(same_window) {
+                emit(&batch)?;
+                batch.clear();
+            }
+            batch.push(ir);
+        }
+        self.runs.retain(|run| run.head.is_some());
+
+        if batch.is_empty() {
+            Ok(())
+        } else {
+            emit(&batch)
+        }
+    }
+}
+
+impl Drop for SpillBuffer {
+    fn drop(&mut self) {
+        self.runs.clear();
+        if let Some(dir) = &self.dir {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain_all(buffer: &mut SpillBuffer, watermark: usize) -> (Vec<IrSpan>, usize) {
+        let mut irs = Vec::new();
+        let mut batches = 0;
+        buffer
+            .drain(watermark, |batch| {
+                irs.extend_from_slice(batch);
+                batches += 1;
+                Ok(())
+            })
+            .unwrap();
+        (irs, batches)
+    }
+
+    #[test]
+    fn test_spill_buffer() {
+        let irs: Vec<IrSpan> = (0..50).rev().map(|i| (i % 25, 60 + i, i % 3)).collect();
+        let mut expected = irs.clone();
+        sort_irs_by(&mut expected, SortBy::Start);
+
+        let mut buffer = SpillBuffer::new(SortBy::Start, Some(8), None);
+        for chunk in irs.chunks(5) {
+            buffer.extend(chunk.to_vec()).unwrap();
+        }
+        assert!(!buffer.runs.is_empty());
+        let (first, _) = drain_all(&mut buffer, 10);
+        let (rest, batches) = drain_all(&mut buffer, usize::MAX);
+        assert!(batches > 1);
+        assert!(buffer.runs.is_empty());
+        assert_eq!([first, rest].concat(), expected);
+    }
+
+    #[test]
+    fn test_spill_buffer_compaction() {
+        let irs: Vec<IrSpan> = (0..1000).rev().map(|i| (i, 2000 - i, i % 7)).collect();
+        let mut expected = irs.clone();
+        sort_irs_by(&mut expected, SortBy::Start);
+
+        let mut buffer = SpillBuffer::new(SortBy::Start, Some(1), None);
+        for chunk in irs.chunks(2) {
+            buffer.extend(chunk.to_vec()).unwrap();
+        }
+        // 500 runs, merged by FAN_IN into a few levels.
+        assert!(buffer.runs.len() < 3 * FAN_IN);
+        let dir = buffer.dir.clone().unwrap();
+        assert_eq!(drain_all(&mut buffer, usize::MAX).0, expected);
+        drop(buffer);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_spill_buffer_windows() {
+        // Batches are only cut between windows of 10 positions
+        let irs: Vec<IrSpan> = (0..30).map(|i| (i, 100, 0)).collect();
+        let mut buffer = SpillBuffer::new(SortBy::Start, Some(4), Some(10));
+        buffer.extend(irs.clone()).unwrap();
+        let mut batches = Vec::new();
+        buffer
+            .drain(usize::MAX, |batch| {
+                batches.push(batch.to_vec());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches.concat(), irs);
+    }
+}
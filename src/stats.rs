@@ -0,0 +1,253 @@
+//! Performance counters of a search.
+use crate::observer::{AlgoEvent, Observer};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Counters accumulated over one or several searches, to discuss performance with numbers.
+///
+/// They can be shared between threads and searches, and read at any time.
+#[derive(Debug, Default)]
+pub struct PerfCounters {
+    centers: AtomicU64,
+    lce_queries: AtomicU64,
+    extension_steps: AtomicU64,
+    hits: AtomicU64,
+    sequences: AtomicU64,
+    bases: AtomicU64,
+    index_nanos: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+impl PerfCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Centers examined.
+    pub fn centers(&self) -> u64 {
+        self.centers.load(Ordering::Relaxed)
+    }
+
+    /// Range minimum queries done to compute longest common extensions.
+    pub fn lce_queries(&self) -> u64 {
+        self.lce_queries.load(Ordering::Relaxed)
+    }
+
+    /// Jumps of the kangaroo extension (one per mismatch, plus the last one).
+    pub fn extension_steps(&self) -> u64 {
+        self.extension_steps.load(Ordering::Relaxed)
+    }
+
+    /// IRs found, before the post-filters and the `--max-hits` limit.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Sequences indexed.
+    pub fn sequences(&self) -> u64 {
+        self.sequences.load(Ordering::Relaxed)
+    }
+
+    /// Bases of the sequences indexed.
+    pub fn bases(&self) -> u64 {
+        self.bases.load(Ordering::Relaxed)
+    }
+
+    /// Time spent building the indexes.
+    pub fn index_time(&self) -> Duration {
+        Duration::from_nanos(self.index_nanos.load(Ordering::Relaxed))
+    }
+
+    /// Bytes of output, as reported with `add_bytes_written`.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    pub fn add_bytes_written(&self, bytes: u64) {
+        self.bytes_written.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+impl Observer for PerfCounters {
+    fn on_event(&self, event: AlgoEvent) {
+        match event {
+            AlgoEvent::Seed {
+                lce_queries, steps, ..
+            } => {
+                self.centers.fetch_add(1, Ordering::Relaxed);
+                self.lce_queries
+                    .fetch_add(lce_queries as u64, Ordering::Relaxed);
+                self.extension_steps
+                    .fetch_add(steps as u64, Ordering::Relaxed);
+            }
+            AlgoEvent::Hit { .. } => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+            }
+            AlgoEvent::ExtensionTruncated { .. } | AlgoEvent::MaxLenTruncated { .. } => {}
+        }
+    }
+
+    fn on_index_built(&self, seq_len: usize, elapsed: Duration) {
+        self.sequences.fetch_add(1, Ordering::Relaxed);
+        self.bases.fetch_add(seq_len as u64, Ordering::Relaxed);
+        self.index_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Display for PerfCounters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Centers examined: {}", self.centers())?;
+        writeln!(f, "LCE queries: {}", self.lce_queries())?;
+        writeln!(f, "Extension steps: {}", self.extension_steps())?;
+        write!(f, "Hits emitted: {}", self.hits())
+    }
+}
+
+/// Throughput of a run, normalized by the bases searched, to track performance across versions
+/// and machines. Displayed as a single line of JSON whose keys do not change.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{PerfCounters, PerfReport, SearchParams, StopHandle, write_irs_counted};
+/// use std::time::Duration;
+///
+/// let counters = PerfCounters::new();
+/// let config = iirs::Config {
+///     params: SearchParams::new(3, 8, 2, 0).unwrap(),
+///     ..Default::default()
+/// };
+/// let mut out = Vec::new();
+/// write_irs_counted(&config, b"acbbgtacgaat", &StopHandle::new(), &counters, &mut out).unwrap();
+/// counters.add_bytes_written(out.len() as u64);
+///
+/// let report = PerfReport::new(&counters, Duration::from_millis(1), Duration::from_secs(1));
+/// assert_eq!(report.bases, 12);
+/// assert!(report.to_string().starts_with("{\"version\":1,\"sequences\":1,\"bases\":12,"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PerfReport {
+    pub sequences: u64,
+    pub bases: u64,
+    pub hits: u64,
+    pub bytes_written: u64,
+    /// Reading and normalizing the input before the first search.
+    pub read: Duration,
+    /// Building the indexes.
+    pub index: Duration,
+    /// Searching and writing the IRs.
+    pub search: Duration,
+    pub total: Duration,
+    /// Peak resident set size of the process, in bytes (Linux).
+    pub peak_rss: Option<u64>,
+}
+
+impl PerfReport {
+    /// Version of the keys of the JSON line.
+    pub const VERSION: u32 = 1;
+
+    /// Report of a run that took `total`, of which `read` was spent on the input.
+    pub fn new(counters: &PerfCounters, read: Duration, total: Duration) -> Self {
+        let index = counters.index_time();
+        Self {
+            sequences: counters.sequences(),
+            bases: counters.bases(),
+            hits: counters.hits(),
+            bytes_written: counters.bytes_written(),
+            read,
+            index,
+            search: total.saturating_sub(read).saturating_sub(index),
+            total,
+            peak_rss: peak_rss(),
+        }
+    }
+
+    /// Millions of bases per second of `phase`.
+    fn mbp_per_s(&self, phase: Duration) -> Option<f64> {
+        per_second(self.bases as f64 / 1e6, phase)
+    }
+}
+
+/// `amount / elapsed`, if the time is not zero.
+fn per_second(amount: f64, elapsed: Duration) -> Option<f64> {
+    let seconds = elapsed.as_secs_f64();
+    (seconds > 0.0).then(|| amount / seconds)
+}
+
+/// JSON value of an optional number.
+fn json_number(value: Option<f64>) -> String {
+    value.map_or_else(|| "null".to_string(), |value| format!("{value:.3}"))
+}
+
+impl fmt::Display for PerfReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let peak_rss_per_gbp = self
+            .peak_rss
+            .filter(|_| self.bases > 0)
+            .map(|bytes| bytes as f64 / (self.bases as f64 / 1e9));
+        write!(
+            f,
+            "{{\"version\":{},\"sequences\":{},\"bases\":{},\"hits\":{},\"bytes_written\":{},\
+             \"read_s\":{:.3},\"index_s\":{:.3},\"search_s\":{:.3},\"total_s\":{:.3},\
+             \"read_mbp_per_s\":{},\"index_mbp_per_s\":{},\"search_mbp_per_s\":{},\
+             \"total_mbp_per_s\":{},\"hits_per_s\":{},\"bytes_written_per_s\":{},\
+             \"peak_rss_bytes\":{},\"peak_rss_bytes_per_gbp\":{}}}",
+            Self::VERSION,
+            self.sequences,
+            self.bases,
+            self.hits,
+            self.bytes_written,
+            self.read.as_secs_f64(),
+            self.index.as_secs_f64(),
+            self.search.as_secs_f64(),
+            self.total.as_secs_f64(),
+            json_number(self.mbp_per_s(self.read)),
+            json_number(self.mbp_per_s(self.index)),
+            json_number(self.mbp_per_s(self.search)),
+            json_number(self.mbp_per_s(self.total)),
+            json_number(per_second(self.hits as f64, self.total)),
+            json_number(per_second(self.bytes_written as f64, self.total)),
+            self.peak_rss
+                .map_or_else(|| "null".to_string(), |bytes| bytes.to_string()),
+            json_number(peak_rss_per_gbp),
+        )
+    }
+}
+
+/// Peak resident set size of the process, in bytes (`VmHWM` of `/proc/self/status`).
+pub fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line["VmHWM:".len()..]
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perf_report_json() {
+        let counters = PerfCounters::new();
+        counters.on_index_built(2_000_000, Duration::from_millis(500));
+        counters.add_bytes_written(300);
+        let report = PerfReport::new(&counters, Duration::ZERO, Duration::from_secs(2));
+        assert_eq!(report.search, Duration::from_millis(1500));
+
+        let json = report.to_string();
+        assert!(json.contains("\"read_mbp_per_s\":null,\"index_mbp_per_s\":4.000,"));
+        assert!(json.contains("\"total_mbp_per_s\":1.000,\"hits_per_s\":0.000,"));
+        assert!(json.contains("\"bytes_written_per_s\":150.000,"));
+        assert_eq!(json.matches(':').count(), 17);
+        if cfg!(target_os = "linux") {
+            assert!(peak_rss().unwrap() > 0);
+        }
+    }
+}
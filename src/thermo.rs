@@ -0,0 +1,275 @@
+//! Estimated stability of the hairpin formed by an IR (single-stranded folding).
+//!
+//! Nearest-neighbor model with the unified parameters of SantaLucia (1998), hairpin loop
+//! initiation of SantaLucia & Hicks (2004), and the sodium-equivalent correction of
+//! von Ahsen et al. (2001) for magnesium. Stacks involving a mismatch (or an ambiguous
+//! base) are simply skipped, so the estimates are only meaningful for nearly perfect stems.
+//!
+//! Cruciform extrusion under negative supercoiling follows a two-state model in the spirit of
+//! SIDD (Benham): every IR is considered independently of the others.
+use crate::IrSpan;
+use crate::score::arm_len;
+
+const KELVIN: f64 = 273.15;
+const GAS_CONSTANT: f64 = 1.987; // cal / (K mol)
+
+/// Base pairs per helical turn of B-DNA.
+const HELICAL_REPEAT: f64 = 10.5;
+/// Dimensionless constant of the superhelical free energy (Depew & Wang, 1975).
+const SUPERHELICAL_K: f64 = 1100.0;
+/// Free energy (kcal/mol) of nucleating a cruciform with short loops (Courey & Wang, 1983).
+const CRUCIFORM_NUCLEATION: f64 = 18.0;
+/// Free energy (kcal/mol) of a mismatched pair in each of the two hairpins of a cruciform.
+const HAIRPIN_MISMATCH: f64 = 2.0;
+
+/// Ionic conditions and temperature of the hairpin estimates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermoConditions {
+    /// Na+ concentration (mM).
+    pub na_mm: f64,
+    /// Mg2+ concentration (mM).
+    pub mg_mm: f64,
+    /// Temperature (Celsius) at which the free energy is given.
+    pub temperature_c: f64,
+}
+
+impl Default for ThermoConditions {
+    fn default() -> Self {
+        Self {
+            na_mm: 50.0,
+            mg_mm: 0.0,
+            temperature_c: 37.0,
+        }
+    }
+}
+
+/// Superhelical state of the topological domain of a sequence (e.g. a plasmid).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Supercoiling {
+    /// Superhelical density (sigma), negative for underwound DNA.
+    pub density: f64,
+    /// Length of the domain (bp), or the length of the sequence if `None`.
+    pub domain_len: Option<usize>,
+}
+
+impl Default for Supercoiling {
+    /// Typical density of plasmids purified from E. coli.
+    fn default() -> Self {
+        Self {
+            density: -0.06,
+            domain_len: None,
+        }
+    }
+}
+
+/// Thermodynamics of a hairpin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HairpinThermo {
+    /// Melting temperature (Celsius).
+    pub tm: f64,
+    /// Free energy of folding (kcal/mol) at the temperature of the conditions.
+    pub dg: f64,
+}
+
+/// (enthalpy kcal/mol, entropy cal/(K mol)) of the stack 5'-xy-3' / 3'-x'y'-5'.
+fn nearest_neighbor(x: u8, y: u8) -> (f64, f64) {
+    match (x, y) {
+        (b'a', b'a') | (b't', b't') => (-7.9, -22.2),
+        (b'a', b't') => (-7.2, -20.4),
+        (b't', b'a') => (-7.2, -21.3),
+        (b'c', b'a') | (b't', b'g') => (-8.5, -22.7),
+        (b'g', b't') | (b'a', b'c') => (-8.4, -22.4),
+        (b'c', b't') | (b'a', b'g') => (-7.8, -21.0),
+        (b'g', b'a') | (b't', b'c') => (-8.2, -22.2),
+        (b'c', b'g') => (-10.6, -27.2),
+        (b'g', b'c') => (-9.8, -24.4),
+        _ => (-8.0, -19.9), // gg / cc
+    }
+}
+
+/// Free energy (kcal/mol, 37 C) of closing a hairpin loop of `len` unpaired bases.
+/// Loops shorter than 3 bases cannot form and are given the value of 3.
+fn loop_initiation(len: usize) -> f64 {
+    const TABLE: [(usize, f64); 15] = [
+        (3, 3.5),
+        (4, 3.5),
+        (5, 3.3),
+        (6, 4.0),
+        (7, 4.2),
+        (8, 4.3),
+        (9, 4.5),
+        (10, 4.6),
+        (12, 5.0),
+        (14, 5.1),
+        (16, 5.3),
+        (18, 5.5),
+        (20, 5.7),
+        (25, 6.1),
+        (30, 6.3),
+    ];
+    let len = len.max(3);
+    if len > 30 {
+        // Jacobson-Stockmayer extrapolation
+        return 6.3 + 2.44 * GAS_CONSTANT * 310.15 / 1000.0 * (len as f64 / 30.0).ln();
+    }
+    let idx = TABLE.partition_point(|&(l, _)| l < len);
+    let (l1, g1) = TABLE[idx];
+    if l1 == len || idx == 0 {
+        return g1;
+    }
+    // Linear interpolation for the missing lengths
+    let (l0, g0) = TABLE[idx - 1];
+    g0 + (g1 - g0) * (len - l0) as f64 / (l1 - l0) as f64
+}
+
+fn is_watson_crick(x: u8, y: u8) -> bool {
+    matches!(
+        (x, y),
+        (b'a', b't' | b'u') | (b't' | b'u', b'a') | (b'c', b'g') | (b'g', b'c')
+    )
+}
+
+/// Estimated melting temperature and free energy of the hairpin formed by `ir` in `seq`
+/// (sanitized, i.e. lowercase).
+pub fn hairpin_thermo(ir: &IrSpan, seq: &[u8], conditions: &ThermoConditions) -> HairpinThermo {
+    let &(left, right, gap) = ir;
+    let len = arm_len(ir);
+    let normalize = |c: u8| if c == b'u' { b't' } else { c };
+    let pair = |i: usize| (normalize(seq[left + i]), normalize(seq[right - i]));
+
+    let mut dh = 0.0;
+    let mut ds = 0.0;
+    let mut stacks = 0;
+    for i in 0..len.saturating_sub(1) {
+        let ((x1, y1), (x2, y2)) = (pair(i), pair(i + 1));
+        if is_watson_crick(x1, y1) && is_watson_crick(x2, y2) {
+            let (h, s) = nearest_neighbor(x1, x2);
+            dh += h;
+            ds += s;
+            stacks += 1;
+        }
+    }
+
+    // Terminal pair at the open end of the stem
+    let (x, _) = pair(0);
+    let (h, s) = if matches!(x, b'g' | b'c') {
+        (0.1, -2.8)
+    } else {
+        (2.3, 4.1)
+    };
+    dh += h;
+    ds += s;
+
+    // The loop is purely entropic
+    ds -= loop_initiation(gap) * 1000.0 / 310.15;
+
+    // Salt correction, with Mg2+ converted into a Na+ equivalent
+    let na_eq = (conditions.na_mm + 120.0 * conditions.mg_mm.sqrt()) / 1000.0;
+    ds += 0.368 * stacks as f64 * na_eq.ln();
+
+    HairpinThermo {
+        tm: dh * 1000.0 / ds - KELVIN,
+        dg: dh - (conditions.temperature_c + KELVIN) * ds / 1000.0,
+    }
+}
+
+/// Estimated probability that `ir` extrudes as a cruciform in `seq` (sanitized, i.e.
+/// lowercase) under the given supercoiling, between 0 and 1.
+///
+/// Extruding the IR unwinds its whole span, which relaxes the superhelical free energy
+/// `K * RT / N * dLk^2` of the domain (N bp, linking number difference `dLk = sigma * N / 10.5`),
+/// against the cost of nucleating the cruciform, of its two loops and of its mismatches.
+/// The stacking of the arms is the same in both states and cancels out.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{Supercoiling, ThermoConditions, extrusion_probability};
+///
+/// // A perfect 14 bp IR with a 4 base loop in a 3 kb plasmid
+/// let seq = "gcgcggccatcgatttttatcgatggccgcgc".repeat(100);
+/// let conditions = ThermoConditions::default();
+/// let relaxed = Supercoiling { density: -0.02, domain_len: None };
+/// let supercoiled = Supercoiling { density: -0.07, domain_len: None };
+/// let ir = (0, 31, 4);
+/// assert!(extrusion_probability(&ir, seq.as_bytes(), &relaxed, &conditions) < 0.01);
+/// assert!(extrusion_probability(&ir, seq.as_bytes(), &supercoiled, &conditions) > 0.99);
+/// ```
+pub fn extrusion_probability(
+    ir: &IrSpan,
+    seq: &[u8],
+    supercoiling: &Supercoiling,
+    conditions: &ThermoConditions,
+) -> f64 {
+    let &(left, right, gap) = ir;
+    let normalize = |c: u8| if c == b'u' { b't' } else { c };
+    let mismatches = (0..arm_len(ir))
+        .filter(|i| !is_watson_crick(normalize(seq[left + i]), normalize(seq[right - i])))
+        .count();
+
+    let rt = GAS_CONSTANT * (conditions.temperature_c + KELVIN) / 1000.0;
+    let domain_len = supercoiling.domain_len.unwrap_or(seq.len()).max(1) as f64;
+    let superhelical = |dlk: f64| SUPERHELICAL_K * rt / domain_len * dlk * dlk;
+    let dlk = supercoiling.density * domain_len / HELICAL_REPEAT;
+    // The twist lost by unwinding the span goes to the linking number difference.
+    let unwound_turns = (right + 1 - left) as f64 / HELICAL_REPEAT;
+    let relaxation = superhelical(dlk) - superhelical(dlk + unwound_turns);
+
+    let cost = CRUCIFORM_NUCLEATION
+        + 2.0 * (loop_initiation(gap) - loop_initiation(4))
+        + 2.0 * HAIRPIN_MISMATCH * mismatches as f64;
+    let dg = cost - relaxation;
+    1.0 / (1.0 + (dg / rt).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hairpin_thermo() {
+        let conditions = ThermoConditions::default();
+        // GC-rich stem of 8 pairs closed by a 4 base loop
+        let gc = hairpin_thermo(&(0, 19, 4), b"gcgcggccttttggccgcgc", &conditions);
+        // AT-rich stem of the same length
+        let at = hairpin_thermo(&(0, 19, 4), b"atattaatttttattaatat", &conditions);
+        assert!(gc.tm > at.tm);
+        assert!(gc.dg < 0.0);
+        assert!(gc.tm > 60.0 && gc.tm < 110.0, "{}", gc.tm);
+
+        // More salt stabilizes the hairpin
+        let salty = ThermoConditions {
+            mg_mm: 10.0,
+            ..conditions
+        };
+        assert!(hairpin_thermo(&(0, 19, 4), b"gcgcggccttttggccgcgc", &salty).tm > gc.tm);
+    }
+
+    #[test]
+    fn test_extrusion_probability() {
+        let conditions = ThermoConditions::default();
+        let seq = "gcgcggccatcgatttttatcgatggccgcgc".repeat(100);
+        let mismatched = seq.replacen("gcgcgg", "acgcgg", 1);
+        let probability = |seq: &str, ir, density| {
+            let supercoiling = Supercoiling {
+                density,
+                domain_len: None,
+            };
+            extrusion_probability(&ir, seq.as_bytes(), &supercoiling, &conditions)
+        };
+        // More supercoiling, longer arms and fewer mismatches favor extrusion
+        let reference = probability(&seq, (0, 31, 4), -0.05);
+        assert!(reference > probability(&seq, (0, 31, 4), -0.04));
+        assert!(reference > probability(&seq, (6, 25, 4), -0.05));
+        assert!(reference > probability(&mismatched, (0, 31, 4), -0.05));
+        // Positive supercoiling prevents it
+        assert!(probability(&seq, (0, 31, 4), 0.05) < 1e-6);
+    }
+
+    #[test]
+    fn test_loop_initiation() {
+        assert_eq!(loop_initiation(0), 3.5);
+        assert_eq!(loop_initiation(11), 4.8);
+        assert!(loop_initiation(60) > loop_initiation(30));
+    }
+}
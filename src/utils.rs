@@ -1,7 +1,19 @@
-use crate::constants::IUPAC_SYMBOLS;
-use anyhow::{Result, anyhow};
+use crate::bed::Intervals;
+use crate::constants::{ALL_SEQUENCES, IUPAC_SYMBOLS};
+use crate::flatfile;
+use anyhow::{Context, Result, anyhow};
+use flate2::read::MultiGzDecoder;
 use seq_io::fasta::{OwnedRecord, Reader, Record};
-use std::fs;
+use seq_io::fastq;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+
+/// Offset of the Phred quality scores of FASTQ files (Sanger / Illumina 1.8+).
+const PHRED_OFFSET: u8 = 33;
+
+/// First bytes of a gzip file (and of every block of a bgzip file).
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
 /// Just some clearer error handling.
 pub fn check_file_exist(path: &str) -> Result<()> {
@@ -15,9 +27,53 @@ pub fn check_file_exist(path: &str) -> Result<()> {
     }
 }
 
+/// Map remote inputs (`https://`, `s3://`) to a locally cached copy.
+///
+/// Local paths are returned unchanged.
+fn resolve_input_file(input_file: &str) -> Result<String> {
+    let is_remote = ["https://", "http://", "s3://"]
+        .iter()
+        .any(|scheme| input_file.starts_with(scheme));
+    if !is_remote {
+        return Ok(input_file.to_string());
+    }
+
+    #[cfg(feature = "remote")]
+    return Ok(crate::remote::fetch(input_file)?
+        .to_string_lossy()
+        .into_owned());
+
+    #[cfg(not(feature = "remote"))]
+    Err(anyhow!(
+        "'{}' is a remote input: iirs must be built with the `remote` feature.",
+        input_file
+    ))
+}
+
+/// Open an input file, decompressed on the fly if it is gzip or bgzip compressed (detected from
+/// its content, whatever its extension).
+pub fn open_input(path: &str) -> Result<Box<dyn BufRead>> {
+    let mut file =
+        BufReader::new(File::open(path).with_context(|| format!("Could not read {path}"))?);
+    let is_gzip = file.fill_buf()?.starts_with(&GZIP_MAGIC);
+    Ok(if is_gzip {
+        // bgzip files are a series of gzip members
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(file)
+    })
+}
+
 /// Remove newlines, cast to lowercase and check that all the character are in IUPAC.
 pub fn sanitize_sequence(seq: &[u8]) -> Result<Vec<u8>> {
     let mut sanitized_seq = Vec::new();
+    sanitize_sequence_into(seq, &mut sanitized_seq)?;
+    Ok(sanitized_seq)
+}
+
+/// Same as [`sanitize_sequence`], into `sanitized_seq` (cleared first).
+pub fn sanitize_sequence_into(seq: &[u8], sanitized_seq: &mut Vec<u8>) -> Result<()> {
+    sanitized_seq.clear();
 
     for &byte in seq {
         if byte != b'\n' && byte != b'\r' {
@@ -31,7 +87,7 @@ pub fn sanitize_sequence(seq: &[u8]) -> Result<Vec<u8>> {
         }
     }
 
-    Ok(sanitized_seq)
+    Ok(())
 }
 
 /// Attempts to extract the record of every sequence with id in `seq_ids` from the input file.
@@ -41,29 +97,67 @@ pub fn sanitize_sequence(seq: &[u8]) -> Result<Vec<u8>> {
 ///
 /// `iirs -s ALL_SEQUENCES -m 5`
 ///
+/// The input file can be FASTA, FASTQ (the qualities are ignored), GenBank or EMBL (detected
+/// from its content), optionally gzip or bgzip compressed, and it can also be an `https://` or
+/// `s3://` uri if the `remote` feature is enabled.
+///
 /// If at least one sequence is not found, returns an error with the list of missing
 /// sequences, together with a list of all the sequences present in the input file.
 pub fn safe_extract_records(input_file: &str, seq_ids: &[String]) -> Result<Vec<OwnedRecord>> {
-    check_file_exist(input_file)?;
+    let mut records = Vec::new();
+    for_each_record(input_file, seq_ids, |mut owned_record| {
+        owned_record.seq = sanitize_sequence(&owned_record.seq)?;
+        records.push(owned_record);
+        Ok(())
+    })?;
+    Ok(records)
+}
+
+/// Like `safe_extract_records`, but hands every selected record (not sanitized) to `f` as soon
+/// as it is read. Missing sequences are only reported once the whole file is read.
+pub fn for_each_record(
+    input_file: &str,
+    seq_ids: &[String],
+    mut f: impl FnMut(OwnedRecord) -> Result<()>,
+) -> Result<()> {
+    let local_file = resolve_input_file(input_file)?;
+    check_file_exist(&local_file)?;
 
-    let do_all_sequences = seq_ids.len() == 1 && seq_ids[0] == "ALL_SEQUENCES";
+    let do_all_sequences = seq_ids.len() == 1 && seq_ids[0] == ALL_SEQUENCES;
 
-    let mut reader = Reader::from_path(input_file)?;
     let mut all_seq_ids_found = Vec::new();
     let mut seq_ids_not_found = seq_ids.to_vec();
-    let mut records = Vec::new();
 
-    while let Some(record) = reader.next() {
-        let record = record.expect("Error reading record");
-        let mut owned_record = record.to_owned_record();
+    let mut select = |owned_record: OwnedRecord| -> Result<()> {
         let record_id = owned_record.id()?.to_string();
         if do_all_sequences || seq_ids.contains(&record_id) {
-            owned_record.seq = sanitize_sequence(record.seq())?;
-            records.push(owned_record);
+            f(owned_record)?;
             seq_ids_not_found.retain(|id| id != &record_id);
         }
 
         all_seq_ids_found.push(record_id);
+        Ok(())
+    };
+
+    if let Some(format) = flatfile::sniff(&local_file)? {
+        for owned_record in flatfile::read_records(&local_file, format)? {
+            select(owned_record)?;
+        }
+    } else if is_fastq(&local_file)? {
+        let mut reader = fastq::Reader::new(open_input(&local_file)?);
+        while let Some(record) = reader.next() {
+            let record = record?;
+            select(OwnedRecord {
+                head: fastq::Record::head(&record).to_vec(),
+                seq: fastq::Record::seq(&record).to_vec(),
+            })?;
+        }
+    } else {
+        let mut reader = Reader::new(open_input(&local_file)?);
+        while let Some(record) = reader.next() {
+            let record = record.expect("Error reading record");
+            select(record.to_owned_record())?;
+        }
     }
 
     if !seq_ids_not_found.is_empty() && !do_all_sequences {
@@ -75,7 +169,49 @@ pub fn safe_extract_records(input_file: &str, seq_ids: &[String]) -> Result<Vec<
         ));
     }
 
-    Ok(records)
+    Ok(())
+}
+
+/// Whether the first non-empty line of the file is a FASTQ header (`@`).
+fn is_fastq(path: &str) -> Result<bool> {
+    for line in open_input(path)?.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            return Ok(line.starts_with('@'));
+        }
+    }
+    Ok(false)
+}
+
+/// Positions of every record of a FASTQ file whose Phred quality is below `min_quality`, as
+/// merged intervals per record (to be masked like the regions of a BED file).
+pub fn read_quality_masks(input_file: &str, min_quality: u8) -> Result<HashMap<String, Intervals>> {
+    let local_file = resolve_input_file(input_file)?;
+    check_file_exist(&local_file)?;
+    if !is_fastq(&local_file)? {
+        return Err(anyhow!(
+            "'{}' has no qualities: --min-quality needs a FASTQ input.",
+            input_file
+        ));
+    }
+
+    let mut masks = HashMap::new();
+    let mut reader = fastq::Reader::new(open_input(&local_file)?);
+    while let Some(record) = reader.next() {
+        let record = record?;
+        let mut low_quality: Intervals = Vec::new();
+        for (pos, &qual) in fastq::Record::qual(&record).iter().enumerate() {
+            if qual.saturating_sub(PHRED_OFFSET) >= min_quality {
+                continue;
+            }
+            match low_quality.last_mut() {
+                Some(last) if last.1 == pos => last.1 = pos + 1,
+                _ => low_quality.push((pos, pos + 1)),
+            }
+        }
+        masks.insert(fastq::Record::id(&record)?.to_string(), low_quality);
+    }
+    Ok(masks)
 }
 
 #[cfg(test)]
@@ -109,4 +245,83 @@ mod tests {
         let seq = b"de".to_vec();
         assert!(sanitize_sequence(&seq).is_err());
     }
+
+    #[test]
+    fn test_for_each_record_streams() {
+        let path = std::env::temp_dir().join(format!("iirs-records-{}.fasta", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, ">a\nACGT\n>b\nac\ngt\n>c\nt\n").unwrap();
+
+        let mut seen = Vec::new();
+        let seq_ids = ["c".to_string(), "a".to_string()];
+        for_each_record(path, &seq_ids, |record| {
+            seen.push((record.id().unwrap().to_string(), record.seq));
+            Ok(())
+        })
+        .unwrap();
+        // In the order of the file, not sanitized
+        assert_eq!(
+            seen,
+            vec![
+                ("a".to_string(), b"ACGT".to_vec()),
+                ("c".to_string(), b"t".to_vec())
+            ]
+        );
+
+        // The records found are handed over before the missing ones are reported
+        let mut found = 0;
+        let seq_ids = ["b".to_string(), "d".to_string()];
+        let result = for_each_record(path, &seq_ids, |_| {
+            found += 1;
+            Ok(())
+        });
+        fs::remove_file(path).unwrap();
+        assert!(result.unwrap_err().to_string().contains("'d' not found"));
+        assert_eq!(found, 1);
+    }
+
+    #[test]
+    fn test_fastq_input() {
+        let path = std::env::temp_dir().join(format!("iirs-reads-{}.fastq", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(
+            path,
+            "@r1 amplicon\nACGTACGT\n+\nII#II##I\n@r2\nGGCC\n+\nIIII\n",
+        )
+        .unwrap();
+
+        let records = safe_extract_records(path, &[ALL_SEQUENCES.to_string()]).unwrap();
+        let ids: Vec<_> = records.iter().map(|record| record.id().unwrap()).collect();
+        assert_eq!(ids, ["r1", "r2"]);
+        assert_eq!(records[0].seq, b"acgtacgt");
+
+        // '#' is quality 2, 'I' is 40
+        let masks = read_quality_masks(path, 20).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(masks["r1"], vec![(2, 3), (5, 7)]);
+        assert!(masks["r2"].is_empty());
+    }
+
+    #[test]
+    fn test_gzip_input() {
+        // Two gzip members, as written by bgzip: ">a\nACGTAC\n" and "GTAC\n>b\nTTGA\n"
+        let compressed = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xb3, 0x4b, 0xe4, 0x72,
+            0x74, 0x76, 0x0f, 0x71, 0x74, 0xe6, 0x02, 0x00, 0x5e, 0x5b, 0xbd, 0xc0, 0x0a, 0x00,
+            0x00, 0x00, 0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x73, 0x0f,
+            0x71, 0x74, 0xe6, 0xb2, 0x4b, 0xe2, 0x0a, 0x09, 0x71, 0x77, 0xe4, 0x02, 0x00, 0x14,
+            0x82, 0xf9, 0xdf, 0x0d, 0x00, 0x00, 0x00,
+        ];
+        // Detected from the content, not from the extension
+        let path = std::env::temp_dir().join(format!("iirs-gzip-{}.fasta", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, compressed).unwrap();
+
+        let records = safe_extract_records(path, &[ALL_SEQUENCES.to_string()]);
+        fs::remove_file(path).unwrap();
+        let records = records.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].seq, b"acgtacgtac");
+        assert_eq!(records[1].seq, b"ttga");
+    }
 }
@@ -0,0 +1,162 @@
+//! Validation of IRs proposed from outside the search (another tool, a curated database...).
+use anyhow::{Result, bail};
+
+use crate::IrSpan;
+use crate::algo::passes_filters;
+use crate::complement::complement_array;
+use crate::config::SearchParams;
+use crate::matrix::MatchMatrix;
+use crate::score::mismatched_pairs;
+use crate::utils::sanitize_sequence;
+
+/// A reason for a proposed IR not to be valid under some `SearchParams`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrViolation {
+    /// The arms are shorter than `min_len`.
+    TooShort,
+    /// The arms are longer than `max_len`.
+    TooLong,
+    /// The gap is longer than `max_gap`.
+    GapTooLong,
+    /// More mismatches than `mismatches` (or than `max_mismatch_rate` of the arm length).
+    TooManyMismatches,
+    /// Discarded by one of the post-filters of the search (score, complexity, masked
+    /// regions...).
+    Filtered,
+}
+
+/// Outcome of [`is_ir`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrCheck {
+    pub ir: IrSpan,
+    /// 0-based positions of the pairs of bases that do not match, from the outer pair.
+    pub mismatches: Vec<(usize, usize)>,
+    pub violations: Vec<IrViolation>,
+}
+
+impl IrCheck {
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Check whether the arms `left_start..=left_end` and `right_start..=right_end` (0-based) of
+/// `seq` make a valid IR under `params`, with the pairs of `params.symmetry_mode`.
+///
+/// Unlike the search, the IR does not have to be maximal: arms that could be extended are
+/// still valid. Errors if the arms are out of the sequence, overlap or differ in length.
+///
+/// # Examples
+///
+/// ```rust
+/// use iirs::{IrViolation, SearchParams, is_ir};
+///
+/// let seq = "acgtaacgttacgt".as_bytes();
+/// let params = SearchParams::new(4, 10, 6, 1).unwrap();
+/// // acgt / acgt: the reverse complement of acgt is acgt
+/// let check = is_ir(&params, seq, 0, 3, 10, 13).unwrap();
+/// assert!(check.is_valid());
+/// assert_eq!(check.ir, (0, 13, 6));
+///
+/// // aacg / acgt: only the outer pair (a, t) matches
+/// let check = is_ir(&params, seq, 4, 7, 10, 13).unwrap();
+/// assert_eq!(check.mismatches, vec![(5, 12), (6, 11), (7, 10)]);
+/// assert_eq!(check.violations, vec![IrViolation::TooManyMismatches]);
+/// ```
+pub fn is_ir(
+    params: &SearchParams,
+    seq: &[u8],
+    left_start: usize,
+    left_end: usize,
+    right_start: usize,
+    right_end: usize,
+) -> Result<IrCheck> {
+    let seq = sanitize_sequence(seq)?;
+    if left_start > left_end || right_start > right_end {
+        bail!("The arms must start before they end.");
+    }
+    if left_end >= right_start {
+        bail!("The left arm must end before the right arm starts.");
+    }
+    if right_end >= seq.len() {
+        bail!(
+            "The right arm ends at {}, past the end of the sequence ({} bp).",
+            right_end,
+            seq.len()
+        );
+    }
+    let len = left_end + 1 - left_start;
+    if right_end + 1 - right_start != len {
+        bail!("The arms must have the same length.");
+    }
+    let ir = (left_start, right_end, right_start - left_end - 1);
+
+    let matrix = MatchMatrix::from_params(params);
+    let complement = complement_array(params);
+    let mismatches = mismatched_pairs(&ir, &seq, &params.symmetry_mode, &matrix, &complement);
+
+    let mut violations = Vec::new();
+    if len < params.min_len {
+        violations.push(IrViolation::TooShort);
+    }
+    if len > params.max_len {
+        violations.push(IrViolation::TooLong);
+    }
+    if ir.2 > params.max_gap {
+        violations.push(IrViolation::GapTooLong);
+    }
+    let too_many_mismatches = match params.max_mismatch_rate {
+        Some(rate) => mismatches.len() as f64 > rate * len as f64,
+        None => mismatches.len() > params.mismatches,
+    };
+    if too_many_mismatches {
+        violations.push(IrViolation::TooManyMismatches);
+    }
+    if !passes_filters(&ir, &seq, params, &matrix, &complement) {
+        violations.push(IrViolation::Filtered);
+    }
+
+    Ok(IrCheck {
+        ir,
+        mismatches,
+        violations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SymmetryMode;
+    use crate::find_spans;
+
+    #[test]
+    fn test_is_ir_agrees_with_search() {
+        let seq = b"ttacgcatgcgtaaccgatggtacgcaaccatcggtt";
+        let params = SearchParams::new(4, 12, 6, 1).unwrap();
+        let irs = find_spans(&params, seq).unwrap();
+        assert!(!irs.is_empty());
+        for &(left, right, gap) in &irs {
+            let len = (right + 1 - left - gap) / 2;
+            let check = is_ir(&params, seq, left, left + len - 1, right + 1 - len, right).unwrap();
+            assert_eq!(check.ir, (left, right, gap));
+            assert!(check.is_valid(), "{check:?}");
+            assert!(check.mismatches.len() <= params.mismatches);
+        }
+
+        let check = is_ir(&params, seq, 0, 1, 4, 5).unwrap();
+        assert!(check.violations.contains(&IrViolation::TooShort));
+        assert!(is_ir(&params, seq, 0, 3, 3, 6).is_err());
+        assert!(is_ir(&params, seq, 0, 3, 5, 9).is_err());
+        assert!(is_ir(&params, seq, 0, 3, 40, 43).is_err());
+    }
+
+    #[test]
+    fn test_is_ir_direct() {
+        let mut params = SearchParams::new(3, 6, 2, 0).unwrap();
+        params.symmetry_mode = SymmetryMode::Direct;
+        let check = is_ir(&params, b"acgtacg", 0, 2, 4, 6).unwrap();
+        assert!(check.is_valid());
+        let check = is_ir(&params, b"acgtacc", 0, 2, 4, 6).unwrap();
+        assert_eq!(check.mismatches, vec![(2, 6)]);
+    }
+}
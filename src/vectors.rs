@@ -0,0 +1,210 @@
+//! Numeric feature vectors of IRs, to train classifiers on IR calls.
+use anyhow::{Context, Result, anyhow};
+use std::io::{self, Write};
+
+use crate::IrSpan;
+use crate::complexity::arms;
+use crate::config::SearchParams;
+use crate::matrix::MatchMatrix;
+use crate::score::{arm_len, count_mismatches};
+use crate::thermo::hairpin_thermo;
+
+/// Bases on each side of an IR used for its flanking composition.
+pub const FLANK_LEN: usize = 20;
+
+/// Names of the features, in the order of `FeatureVector::to_array`.
+pub const FEATURE_NAMES: [&str; 11] = [
+    "arm_len",
+    "gap",
+    "mismatches",
+    "gc",
+    "dg",
+    "entropy",
+    "degeneracy",
+    "flank_a",
+    "flank_c",
+    "flank_g",
+    "flank_t",
+];
+
+/// Features of an IR.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeatureVector {
+    pub arm_len: usize,
+    pub gap: usize,
+    pub mismatches: usize,
+    /// GC fraction of the arms.
+    pub gc: f64,
+    /// Free energy of the hairpin (kcal/mol), see `hairpin_thermo`.
+    pub dg: f64,
+    /// Shannon entropy (bits, from 0 to 2) of the bases of the arms.
+    pub entropy: f64,
+    /// Mean number of bases (from 1 to 4) that the IUPAC codes of the arms stand for.
+    pub degeneracy: f64,
+    /// Fractions of a, c, g and t (or u) in the `FLANK_LEN` bases on both sides of the IR.
+    pub flank: [f64; 4],
+}
+
+impl FeatureVector {
+    pub fn to_array(&self) -> [f64; FEATURE_NAMES.len()] {
+        [
+            self.arm_len as f64,
+            self.gap as f64,
+            self.mismatches as f64,
+            self.gc,
+            self.dg,
+            self.entropy,
+            self.degeneracy,
+            self.flank[0],
+            self.flank[1],
+            self.flank[2],
+            self.flank[3],
+        ]
+    }
+}
+
+/// Number of bases that an IUPAC code stands for.
+const fn degeneracy(base: u8) -> usize {
+    match base.to_ascii_lowercase() {
+        b'r' | b'y' | b's' | b'w' | b'k' | b'm' => 2,
+        b'b' | b'd' | b'h' | b'v' => 3,
+        b'n' => 4,
+        _ => 1,
+    }
+}
+
+/// Fractions of a, c, g and t (or u) in `seq`.
+fn composition(seq: &[u8]) -> [f64; 4] {
+    let mut counts = [0usize; 4];
+    for &base in seq {
+        match base {
+            b'a' => counts[0] += 1,
+            b'c' => counts[1] += 1,
+            b'g' => counts[2] += 1,
+            b't' | b'u' => counts[3] += 1,
+            _ => (),
+        }
+    }
+    counts.map(|count| count as f64 / seq.len().max(1) as f64)
+}
+
+/// Features of `ir` in `seq` (sanitized, i.e. lowercase).
+pub fn feature_vector(
+    ir: &IrSpan,
+    seq: &[u8],
+    params: &SearchParams,
+    matrix: &MatchMatrix,
+    complement: &[u8; 128],
+) -> FeatureVector {
+    let &(left, right, gap) = ir;
+    let (left_arm, right_arm) = arms(ir, seq);
+    let arm_bases = || left_arm.iter().chain(right_arm);
+    let n_arm_bases = (2 * arm_len(ir)) as f64;
+
+    let [a, c, g, t] = composition(&[left_arm, right_arm].concat());
+    let entropy = [a, c, g, t]
+        .iter()
+        .filter(|&&p| p > 0.0)
+        .map(|p| -p * p.log2())
+        .sum();
+    let flanks = [
+        &seq[left.saturating_sub(FLANK_LEN)..left],
+        &seq[right + 1..(right + 1 + FLANK_LEN).min(seq.len())],
+    ]
+    .concat();
+
+    FeatureVector {
+        arm_len: arm_len(ir),
+        gap,
+        mismatches: count_mismatches(ir, seq, &params.symmetry_mode, matrix, complement),
+        gc: c + g,
+        dg: hairpin_thermo(ir, seq, &params.thermo).dg,
+        entropy,
+        degeneracy: arm_bases().map(|&base| degeneracy(base)).sum::<usize>() as f64 / n_arm_bases,
+        flank: composition(&flanks),
+    }
+}
+
+/// Read the feature vectors of a result file in the `features` output format.
+pub fn read_feature_tsv(path: &str) -> Result<Vec<[f64; FEATURE_NAMES.len()]>> {
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Could not read {path}"))?;
+    let mut vectors = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') || line.starts_with("start\t") {
+            continue;
+        }
+        let error = || anyhow!("{}:{}: invalid feature vector", path, line_number + 1);
+        // Skip the coordinates
+        let values = line
+            .split('\t')
+            .skip(2)
+            .map(|value| value.parse::<f64>().map_err(|_| error()))
+            .collect::<Result<Vec<_>>>()?;
+        vectors.push(values.try_into().map_err(|_| error())?);
+    }
+    Ok(vectors)
+}
+
+/// Write feature vectors as a NumPy `.npy` (version 1.0) matrix of `float64`, with one row per
+/// IR and the columns of `FEATURE_NAMES`.
+pub fn write_npy<W: Write>(out: &mut W, vectors: &[[f64; FEATURE_NAMES.len()]]) -> io::Result<()> {
+    let mut header = format!(
+        "{{'descr': '<f8', 'fortran_order': False, 'shape': ({}, {}), }}",
+        vectors.len(),
+        FEATURE_NAMES.len()
+    );
+    // Magic string, version and header length take 10 bytes, and the data must be 64-aligned.
+    let padding = 63 - (10 + header.len()) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    out.write_all(b"\x93NUMPY\x01\x00")?;
+    out.write_all(&(header.len() as u16).to_le_bytes())?;
+    out.write_all(header.as_bytes())?;
+    for value in vectors.iter().flatten() {
+        out.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::build_complement_array;
+
+    #[test]
+    fn test_feature_vector() {
+        let seq = b"aaaaccgcgntttttcgcggttttt";
+        let params = SearchParams::new(5, 10, 5, 1).unwrap();
+        let matrix = MatchMatrix::from_params(&params);
+        let complement = build_complement_array();
+        let features = feature_vector(&(4, 19, 6), seq, &params, &matrix, &complement);
+        assert_eq!(
+            (features.arm_len, features.gap, features.mismatches),
+            (5, 6, 0)
+        );
+        assert_eq!(features.gc, 1.0);
+        assert!(features.dg < 0.0);
+        assert_eq!(features.degeneracy, 1.0);
+        assert_eq!(features.entropy, 1.0);
+        // 4 a's on the left and 5 t's on the right
+        assert_eq!(features.flank, [4.0 / 9.0, 0.0, 0.0, 5.0 / 9.0]);
+        assert_eq!(features.to_array()[..3], [5.0, 6.0, 0.0]);
+    }
+
+    #[test]
+    fn test_write_npy() {
+        let mut out = Vec::new();
+        let vectors = [[1.0; FEATURE_NAMES.len()], [2.0; FEATURE_NAMES.len()]];
+        write_npy(&mut out, &vectors).unwrap();
+        assert!(out.starts_with(b"\x93NUMPY\x01\x00"));
+        let header_len = u16::from_le_bytes([out[8], out[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&out[10..10 + header_len]).unwrap();
+        assert!(header.contains("'shape': (2, 11)"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(out.len(), 10 + header_len + 2 * 11 * 8);
+        assert_eq!(out[out.len() - 8..], 2.0f64.to_le_bytes());
+    }
+}